@@ -0,0 +1,19 @@
+// The sqlx query macros check every query at compile time, against
+// `DATABASE_URL` when it is set and against the cached query data otherwise.
+// SQLite and Postgres describe the same query differently, so each backend
+// keeps its own cache: `.sqlx` for SQLite and `.sqlx-postgres` for Postgres.
+// To refresh a cache, build with `DATABASE_URL` pointing at a migrated
+// database; the macros rewrite the files in the selected directory.
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=migrations");
+    println!("cargo:rerun-if-env-changed=SQLX_OFFLINE_DIR");
+
+    if std::env::var_os("CARGO_FEATURE_POSTGRES").is_some()
+        && std::env::var_os("SQLX_OFFLINE_DIR").is_none()
+    {
+        let manifest_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+        println!("cargo:rustc-env=SQLX_OFFLINE_DIR={}/.sqlx-postgres", manifest_dir);
+    }
+}
@@ -10,7 +10,7 @@ from pathlib import Path
 
 PROJECT_ROOT = Path(__file__).parent.resolve()
 ROCKET_TOML = PROJECT_ROOT / "Rocket.toml"
-MIGRATIONS_DIR = PROJECT_ROOT / "migrations"
+MIGRATIONS_DIR = PROJECT_ROOT / "migrations" / "sqlite"
 
 
 def _print(msg: str) -> None:
@@ -99,9 +99,10 @@ def cmd_migrate(_: argparse.Namespace) -> None:
     conn = sqlite3.connect(str(db_path))
     try:
         cur = conn.cursor()
-        sql_files = sorted(p for p in MIGRATIONS_DIR.glob("*.sql"))
+        # Down scripts only run through `todoctl migrate down`
+        sql_files = sorted(p for p in MIGRATIONS_DIR.glob("*.sql") if not p.name.endswith(".down.sql"))
         if not sql_files:
-            _print("No .sql files found in migrations/")
+            _print("No .sql files found in migrations/sqlite/")
         for p in sql_files:
             _print(f"Applying migration: {p.name}")
             script = p.read_text(encoding="utf-8")
@@ -0,0 +1,5 @@
+-- The indexes and trigger go with the table
+DROP TABLE IF EXISTS todos;
+
+-- Only todos uses it by now; users was dropped by the down script of 002
+DROP FUNCTION IF EXISTS set_updated_at();
@@ -0,0 +1,11 @@
+-- Todos become shared by everyone again
+DROP INDEX IF EXISTS idx_todos_user_id;
+ALTER TABLE todos DROP COLUMN user_id;
+
+-- Pending email changes are abandoned
+ALTER TABLE users DROP COLUMN email_verification_expires_at;
+ALTER TABLE users DROP COLUMN email_verification_hash;
+ALTER TABLE users DROP COLUMN pending_email;
+
+-- Every issued JWT stops verifying
+DROP TABLE IF EXISTS sessions;
@@ -0,0 +1,2 @@
+-- Export files already written are left on disk
+DROP TABLE IF EXISTS data_exports;
@@ -0,0 +1,9 @@
+-- The restored constraints only know three statuses: blocked todos go back
+-- to pending and cancelled ones count as completed
+UPDATE todos SET status = 'pending' WHERE status = 'blocked';
+UPDATE todos SET status = 'completed' WHERE status = 'cancelled';
+
+ALTER TABLE todos ADD CONSTRAINT todos_status_check
+    CHECK (status IN ('pending', 'in_progress', 'completed'));
+ALTER TABLE todos ADD CONSTRAINT todos_priority_check
+    CHECK (priority IN ('low', 'medium', 'high'));
@@ -0,0 +1,5 @@
+-- Status and priority values are defined once, by the Rust enums, so drop
+-- the CHECK constraints that repeated them. This also admits the new
+-- 'blocked' and 'cancelled' statuses.
+ALTER TABLE todos DROP CONSTRAINT IF EXISTS todos_status_check;
+ALTER TABLE todos DROP CONSTRAINT IF EXISTS todos_priority_check;
@@ -0,0 +1,3 @@
+-- Also drops any roles and disabled flags set since
+ALTER TABLE users DROP COLUMN disabled_at;
+ALTER TABLE users DROP COLUMN role;
@@ -0,0 +1,5 @@
+-- Admins can be told apart from regular users
+ALTER TABLE users ADD COLUMN role TEXT NOT NULL DEFAULT 'user';
+
+-- Disabled accounts keep their data but can no longer sign in
+ALTER TABLE users ADD COLUMN disabled_at TIMESTAMP;
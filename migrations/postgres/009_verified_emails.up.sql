@@ -0,0 +1,7 @@
+-- Set once the user has proven control of `email`, through the verification
+-- flow or a verified identity provider. Registration does not set it.
+ALTER TABLE users ADD COLUMN email_verified_at TIMESTAMP;
+
+-- Users created by an identity provider got their email from it
+UPDATE users SET email_verified_at = created_at
+WHERE id IN (SELECT user_id FROM user_identities);
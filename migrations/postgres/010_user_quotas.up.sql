@@ -0,0 +1,9 @@
+-- Per-user replacements for the [app.quotas] defaults, set by administrators.
+-- A NULL column keeps the default.
+CREATE TABLE IF NOT EXISTS user_quotas (
+    user_id BIGINT PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    max_todos BIGINT,
+    max_description_len BIGINT,
+    max_storage_bytes BIGINT,
+    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
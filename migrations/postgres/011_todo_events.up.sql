@@ -0,0 +1,13 @@
+-- The change feed behind GET /api/events. Rows are pruned after
+-- events.retention_hours.
+CREATE TABLE IF NOT EXISTS todo_events (
+    id BIGSERIAL PRIMARY KEY,
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    kind TEXT NOT NULL CHECK (kind IN ('created', 'updated', 'deleted')),
+    todo_id BIGINT NOT NULL,
+    -- The todo after the change as JSON; NULL for deletions
+    todo TEXT,
+    created_at TIMESTAMP NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_todo_events_user_id ON todo_events(user_id, id);
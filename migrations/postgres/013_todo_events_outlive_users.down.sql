@@ -0,0 +1,4 @@
+-- Events of deleted accounts cannot keep the foreign key and are dropped
+DELETE FROM todo_events WHERE user_id NOT IN (SELECT id FROM users);
+ALTER TABLE todo_events
+    ADD CONSTRAINT todo_events_user_id_fkey FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE;
@@ -0,0 +1,3 @@
+-- Deleting an account logs a todo.deleted event for each of its todos once
+-- the user row is gone, so the log no longer references users.
+ALTER TABLE todo_events DROP CONSTRAINT IF EXISTS todo_events_user_id_fkey;
@@ -0,0 +1,2 @@
+-- The indexes and trigger go with the table
+DROP TABLE IF EXISTS todos;
@@ -0,0 +1,16 @@
+-- Create user_identities table (external OIDC identities linked to users)
+CREATE TABLE IF NOT EXISTS user_identities (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    provider TEXT NOT NULL,
+    subject TEXT NOT NULL,
+    email TEXT,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    last_login_at DATETIME
+);
+
+-- An external identity can only be linked to one user
+CREATE UNIQUE INDEX IF NOT EXISTS idx_user_identities_provider_subject ON user_identities(provider, subject);
+
+-- Create index on user_id for faster queries
+CREATE INDEX IF NOT EXISTS idx_user_identities_user_id ON user_identities(user_id);
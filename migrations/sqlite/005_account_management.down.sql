@@ -0,0 +1,39 @@
+-- Todos become shared by everyone again. SQLite cannot drop a column that
+-- references another table, so the table is rebuilt; the CHECK constraints
+-- are those in force before 007.
+CREATE TABLE todos_old (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    title TEXT NOT NULL,
+    description TEXT,
+    status TEXT NOT NULL DEFAULT 'pending' CHECK (status IN ('pending', 'in_progress', 'completed')),
+    priority TEXT NOT NULL DEFAULT 'medium' CHECK (priority IN ('low', 'medium', 'high')),
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+INSERT INTO todos_old (id, title, description, status, priority, created_at, updated_at)
+SELECT id, title, description, status, priority, created_at, updated_at FROM todos;
+
+DROP TABLE todos;
+
+ALTER TABLE todos_old RENAME TO todos;
+
+-- Recreate the indexes and trigger that were dropped with the old table
+CREATE INDEX IF NOT EXISTS idx_todos_status ON todos(status);
+
+CREATE INDEX IF NOT EXISTS idx_todos_priority ON todos(priority);
+
+CREATE TRIGGER IF NOT EXISTS update_todos_updated_at
+    AFTER UPDATE ON todos
+    FOR EACH ROW
+BEGIN
+    UPDATE todos SET updated_at = CURRENT_TIMESTAMP WHERE id = NEW.id;
+END;
+
+-- Pending email changes are abandoned
+ALTER TABLE users DROP COLUMN email_verification_expires_at;
+ALTER TABLE users DROP COLUMN email_verification_hash;
+ALTER TABLE users DROP COLUMN pending_email;
+
+-- Every issued JWT stops verifying
+DROP TABLE IF EXISTS sessions;
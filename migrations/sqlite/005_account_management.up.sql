@@ -0,0 +1,25 @@
+-- Create sessions table so issued JWTs can be revoked
+CREATE TABLE IF NOT EXISTS sessions (
+    id TEXT PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    revoked_at DATETIME
+);
+
+-- Create index on user_id for revoking all of a user's sessions
+CREATE INDEX IF NOT EXISTS idx_sessions_user_id ON sessions(user_id);
+
+-- Email changes stay pending until the new address is verified
+ALTER TABLE users ADD COLUMN pending_email TEXT;
+ALTER TABLE users ADD COLUMN email_verification_hash TEXT;
+ALTER TABLE users ADD COLUMN email_verification_expires_at DATETIME;
+
+-- Todos belong to the user who created them
+ALTER TABLE todos ADD COLUMN user_id INTEGER REFERENCES users(id) ON DELETE SET NULL;
+
+-- Todos used to be shared by everyone. Hand the existing ones to the oldest
+-- account so they stay reachable; only that user sees them from now on.
+UPDATE todos SET user_id = (SELECT MIN(id) FROM users) WHERE user_id IS NULL;
+
+-- Create index on user_id for faster queries
+CREATE INDEX IF NOT EXISTS idx_todos_user_id ON todos(user_id);
@@ -0,0 +1,14 @@
+-- Create data_exports table (background personal data exports)
+CREATE TABLE IF NOT EXISTS data_exports (
+    id TEXT PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    status TEXT NOT NULL DEFAULT 'pending' CHECK (status IN ('pending', 'running', 'ready', 'failed', 'expired')),
+    file_path TEXT,
+    error TEXT,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    completed_at DATETIME,
+    expires_at DATETIME
+);
+
+-- Create index on user_id for faster queries
+CREATE INDEX IF NOT EXISTS idx_data_exports_user_id ON data_exports(user_id);
@@ -0,0 +1,37 @@
+-- Puts the CHECK constraints back, rebuilding the table as the up script
+-- did. They only know three statuses: blocked todos go back to pending and
+-- cancelled ones count as completed.
+CREATE TABLE todos_old (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    title TEXT NOT NULL,
+    description TEXT,
+    status TEXT NOT NULL DEFAULT 'pending' CHECK (status IN ('pending', 'in_progress', 'completed')),
+    priority TEXT NOT NULL DEFAULT 'medium' CHECK (priority IN ('low', 'medium', 'high')),
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    user_id INTEGER REFERENCES users(id) ON DELETE SET NULL
+);
+
+INSERT INTO todos_old (id, title, description, status, priority, created_at, updated_at, user_id)
+SELECT id, title, description,
+       CASE status WHEN 'blocked' THEN 'pending' WHEN 'cancelled' THEN 'completed' ELSE status END,
+       priority, created_at, updated_at, user_id
+FROM todos;
+
+DROP TABLE todos;
+
+ALTER TABLE todos_old RENAME TO todos;
+
+-- Recreate the indexes and trigger that were dropped with the old table
+CREATE INDEX IF NOT EXISTS idx_todos_status ON todos(status);
+
+CREATE INDEX IF NOT EXISTS idx_todos_priority ON todos(priority);
+
+CREATE INDEX IF NOT EXISTS idx_todos_user_id ON todos(user_id);
+
+CREATE TRIGGER IF NOT EXISTS update_todos_updated_at
+    AFTER UPDATE ON todos
+    FOR EACH ROW
+BEGIN
+    UPDATE todos SET updated_at = CURRENT_TIMESTAMP WHERE id = NEW.id;
+END;
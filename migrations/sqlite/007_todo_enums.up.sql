@@ -0,0 +1,35 @@
+-- Status and priority values are defined once, by the Rust enums, so drop
+-- the CHECK constraints that repeated them. This also admits the new
+-- 'blocked' and 'cancelled' statuses. SQLite cannot drop a constraint in
+-- place, so the table is rebuilt.
+CREATE TABLE todos_new (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    title TEXT NOT NULL,
+    description TEXT,
+    status TEXT NOT NULL DEFAULT 'pending',
+    priority TEXT NOT NULL DEFAULT 'medium',
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    user_id INTEGER REFERENCES users(id) ON DELETE SET NULL
+);
+
+INSERT INTO todos_new (id, title, description, status, priority, created_at, updated_at, user_id)
+SELECT id, title, description, status, priority, created_at, updated_at, user_id FROM todos;
+
+DROP TABLE todos;
+
+ALTER TABLE todos_new RENAME TO todos;
+
+-- Recreate the indexes and trigger that were dropped with the old table
+CREATE INDEX IF NOT EXISTS idx_todos_status ON todos(status);
+
+CREATE INDEX IF NOT EXISTS idx_todos_priority ON todos(priority);
+
+CREATE INDEX IF NOT EXISTS idx_todos_user_id ON todos(user_id);
+
+CREATE TRIGGER IF NOT EXISTS update_todos_updated_at
+    AFTER UPDATE ON todos
+    FOR EACH ROW
+BEGIN
+    UPDATE todos SET updated_at = CURRENT_TIMESTAMP WHERE id = NEW.id;
+END;
@@ -0,0 +1,9 @@
+-- Per-user replacements for the [app.quotas] defaults, set by administrators.
+-- A NULL column keeps the default.
+CREATE TABLE IF NOT EXISTS user_quotas (
+    user_id INTEGER PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    max_todos INTEGER,
+    max_description_len INTEGER,
+    max_storage_bytes INTEGER,
+    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,14 @@
+-- The change feed behind GET /api/events. Rows are pruned after
+-- events.retention_hours; AUTOINCREMENT keeps ids from being reused, so a
+-- Last-Event-ID never points at a newer event.
+CREATE TABLE IF NOT EXISTS todo_events (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    kind TEXT NOT NULL CHECK (kind IN ('created', 'updated', 'deleted')),
+    todo_id INTEGER NOT NULL,
+    -- The todo after the change as JSON; NULL for deletions
+    todo TEXT,
+    created_at DATETIME NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_todo_events_user_id ON todo_events(user_id, id);
@@ -0,0 +1,49 @@
+-- Outgoing webhooks (/api/webhooks). Deliveries are queued from todo_events
+-- after webhook_cursor.last_event_id and retried with backoff until they
+-- succeed or run out of attempts.
+
+-- The status before an update that changed it, so completions stand out
+ALTER TABLE todo_events ADD COLUMN previous_status TEXT;
+
+CREATE TABLE IF NOT EXISTS webhooks (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    url TEXT NOT NULL,
+    -- Signs every delivery, so it is kept as issued rather than hashed
+    secret TEXT NOT NULL,
+    event_types TEXT NOT NULL, -- Space separated, e.g. "todo.created todo.completed"
+    -- Receives every user's events, for as long as the owner is an administrator
+    all_users BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at DATETIME NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_webhooks_user_id ON webhooks(user_id);
+
+CREATE TABLE IF NOT EXISTS webhook_deliveries (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    webhook_id INTEGER NOT NULL REFERENCES webhooks(id) ON DELETE CASCADE,
+    event_id INTEGER NOT NULL,
+    event_type TEXT NOT NULL,
+    -- The request body, fixed when queued so a redelivery sends the same
+    payload TEXT NOT NULL,
+    status TEXT NOT NULL CHECK (status IN ('pending', 'succeeded', 'failed')),
+    attempts INTEGER NOT NULL DEFAULT 0,
+    next_attempt_at DATETIME,
+    last_attempt_at DATETIME,
+    response_status INTEGER,
+    last_error TEXT,
+    -- The delivery a redelivery repeats
+    redelivery_of INTEGER,
+    created_at DATETIME NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_webhook_deliveries_due ON webhook_deliveries(status, next_attempt_at);
+CREATE INDEX IF NOT EXISTS idx_webhook_deliveries_webhook_id ON webhook_deliveries(webhook_id, id);
+
+-- A single row: the newest event deliveries have been queued for
+CREATE TABLE IF NOT EXISTS webhook_cursor (
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    last_event_id INTEGER NOT NULL
+);
+
+INSERT INTO webhook_cursor (id, last_event_id) SELECT 1, COALESCE(MAX(id), 0) FROM todo_events;
@@ -0,0 +1,22 @@
+-- Events of deleted accounts cannot keep the foreign key and are dropped
+CREATE TABLE todo_events_old (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    kind TEXT NOT NULL CHECK (kind IN ('created', 'updated', 'deleted')),
+    todo_id INTEGER NOT NULL,
+    todo TEXT,
+    previous_status TEXT,
+    created_at DATETIME NOT NULL
+);
+
+INSERT INTO todo_events_old (id, user_id, kind, todo_id, todo, previous_status, created_at)
+SELECT id, user_id, kind, todo_id, todo, previous_status, created_at FROM todo_events
+WHERE user_id IN (SELECT id FROM users);
+
+DELETE FROM sqlite_sequence WHERE name = 'todo_events_old';
+INSERT INTO sqlite_sequence (name, seq) SELECT 'todo_events_old', seq FROM sqlite_sequence WHERE name = 'todo_events';
+
+DROP TABLE todo_events;
+ALTER TABLE todo_events_old RENAME TO todo_events;
+
+CREATE INDEX IF NOT EXISTS idx_todo_events_user_id ON todo_events(user_id, id);
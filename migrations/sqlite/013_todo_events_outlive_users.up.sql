@@ -0,0 +1,25 @@
+-- Deleting an account logs a todo.deleted event for each of its todos once
+-- the user row is gone, so the log no longer references users. The table is
+-- rebuilt without the foreign key; the AUTOINCREMENT counter is carried
+-- over so no event id is ever reused.
+CREATE TABLE todo_events_new (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id INTEGER NOT NULL,
+    kind TEXT NOT NULL CHECK (kind IN ('created', 'updated', 'deleted')),
+    todo_id INTEGER NOT NULL,
+    -- The todo after the change as JSON; NULL for deletions
+    todo TEXT,
+    previous_status TEXT,
+    created_at DATETIME NOT NULL
+);
+
+INSERT INTO todo_events_new (id, user_id, kind, todo_id, todo, previous_status, created_at)
+SELECT id, user_id, kind, todo_id, todo, previous_status, created_at FROM todo_events;
+
+DELETE FROM sqlite_sequence WHERE name = 'todo_events_new';
+INSERT INTO sqlite_sequence (name, seq) SELECT 'todo_events_new', seq FROM sqlite_sequence WHERE name = 'todo_events';
+
+DROP TABLE todo_events;
+ALTER TABLE todo_events_new RENAME TO todo_events;
+
+CREATE INDEX IF NOT EXISTS idx_todo_events_user_id ON todo_events(user_id, id);
@@ -0,0 +1,4 @@
+-- A worker claims a due delivery by setting locked_until before sending it,
+-- so instances sharing the database do not send it twice. A claim left by a
+-- worker that died mid-attempt lapses at locked_until.
+ALTER TABLE webhook_deliveries ADD COLUMN locked_until DATETIME;
@@ -0,0 +1,366 @@
+//! The operations behind `todoctl`. The binary parses arguments and prints;
+//! everything that touches the database lives here so it can be tested.
+
+use std::path::Path;
+
+use serde::Deserialize;
+use sqlx::migrate::MigrateError;
+
+use crate::auth::jwt::create_token;
+use crate::config::JwtConfig;
+use crate::auth::password::hash_password;
+use crate::database::{self, DbPool, MigrationStatus};
+use crate::models::{
+    CreateTodoRequest, CreateUserRequest, PASSWORD_MIN_LEN, Priority, Role, Status, TodoResponse,
+    User,
+};
+use crate::repository::{
+    NewTodo, NewUser, RepositoryError, TodoFilter, TodoRepository, UserRepository,
+};
+use crate::validation::{FieldError, Validate};
+
+/// Password of every account created by `seed`
+pub const SEED_PASSWORD: &str = "password123";
+
+#[derive(Debug)]
+pub enum AdminError {
+    /// The request cannot be carried out as given; the message says why
+    Invalid(String),
+    Repository(RepositoryError),
+    Migrate(MigrateError),
+    Io(std::io::Error),
+    Json(serde_json::Error),
+}
+
+impl std::fmt::Display for AdminError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AdminError::Invalid(message) => write!(f, "{}", message),
+            AdminError::Repository(e) => write!(f, "{}", e),
+            AdminError::Migrate(e) => write!(f, "{}", e),
+            AdminError::Io(e) => write!(f, "{}", e),
+            AdminError::Json(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for AdminError {}
+
+impl From<RepositoryError> for AdminError {
+    fn from(e: RepositoryError) -> Self {
+        AdminError::Repository(e)
+    }
+}
+
+impl From<sqlx::Error> for AdminError {
+    fn from(e: sqlx::Error) -> Self {
+        AdminError::Repository(e.into())
+    }
+}
+
+impl From<MigrateError> for AdminError {
+    fn from(e: MigrateError) -> Self {
+        AdminError::Migrate(e)
+    }
+}
+
+impl From<std::io::Error> for AdminError {
+    fn from(e: std::io::Error) -> Self {
+        AdminError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for AdminError {
+    fn from(e: serde_json::Error) -> Self {
+        AdminError::Json(e)
+    }
+}
+
+pub type AdminResult<T = ()> = Result<T, AdminError>;
+
+/// The fields of an imported todo; anything else in the entry is ignored
+#[derive(Debug, Deserialize)]
+struct ImportedTodo {
+    title: String,
+    description: Option<String>,
+    priority: Option<Priority>,
+    status: Option<Status>,
+}
+
+fn generate_password() -> String {
+    uuid::Uuid::new_v4().simple().to_string()
+}
+
+fn hash(password: &str) -> AdminResult<String> {
+    hash_password(password).map_err(|e| AdminError::Invalid(format!("failed to hash password: {}", e)))
+}
+
+fn describe(errors: &[FieldError]) -> String {
+    errors
+        .iter()
+        .map(|e| format!("{}: {}", e.field, e.message))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn user_id(user: &User) -> i64 {
+    user.id.expect("User ID should be set")
+}
+
+pub async fn find_user(users: &dyn UserRepository, username: &str) -> AdminResult<User> {
+    users
+        .find_by_username(username)
+        .await?
+        .ok_or_else(|| AdminError::Invalid(format!("no user named '{}'", username)))
+}
+
+/// Number of migrations not yet applied
+pub async fn pending_migrations(pool: &DbPool) -> AdminResult<usize> {
+    let status = database::migration_status(pool).await?;
+    Ok(status.iter().filter(|m| !m.applied).count())
+}
+
+/// Applies every pending migration and returns the ones it applied
+pub async fn migrate_up(pool: &DbPool) -> AdminResult<Vec<MigrationStatus>> {
+    let before = database::migration_status(pool).await?;
+    database::migrator().run(pool).await?;
+
+    Ok(before.into_iter().filter(|m| !m.applied).collect())
+}
+
+/// Reverts the most recently applied migration and returns it, or `None`
+/// when nothing is applied
+pub async fn migrate_down(pool: &DbPool) -> AdminResult<Option<MigrationStatus>> {
+    let migrator = database::migrator();
+    let mut applied: Vec<_> = database::migration_status(pool)
+        .await?
+        .into_iter()
+        .filter(|m| m.applied)
+        .collect();
+    let Some(latest) = applied.pop() else {
+        return Ok(None);
+    };
+
+    if !latest.reversible {
+        return Err(AdminError::Invalid(format!(
+            "migration {} ({}) has no down script",
+            latest.version, latest.description
+        )));
+    }
+
+    let target = applied.last().map_or(0, |m| m.version);
+    migrator.undo(pool, target).await?;
+    Ok(Some(latest))
+}
+
+/// Creates an account. Returns the user and, when none was given, the
+/// generated password.
+pub async fn create_user(
+    users: &dyn UserRepository,
+    username: String,
+    email: String,
+    password: Option<String>,
+    admin: bool,
+) -> AdminResult<(User, Option<String>)> {
+    let generated = password.is_none();
+    let mut request = CreateUserRequest {
+        username,
+        email,
+        password: password.unwrap_or_else(generate_password),
+    };
+    request
+        .validate()
+        .map_err(|errors| AdminError::Invalid(describe(&errors)))?;
+
+    let password_hash = hash(&request.password)?;
+    let mut user = users
+        .create(NewUser {
+            username: request.username,
+            email: request.email,
+            password_hash,
+        })
+        .await?;
+    if admin {
+        users.set_role(user_id(&user), Role::Admin).await?;
+        user.role = Role::Admin;
+    }
+
+    Ok((user, generated.then_some(request.password)))
+}
+
+/// Blocks or restores sign-in. Disabling also ends every session.
+pub async fn set_disabled(users: &dyn UserRepository, username: &str, disabled: bool) -> AdminResult {
+    let user = find_user(users, username).await?;
+    users.set_disabled(user_id(&user), disabled).await?;
+    if disabled {
+        users.revoke_all_sessions(user_id(&user)).await?;
+    }
+    Ok(())
+}
+
+pub async fn set_role(users: &dyn UserRepository, username: &str, role: Role) -> AdminResult {
+    let user = find_user(users, username).await?;
+    users.set_role(user_id(&user), role).await?;
+    Ok(())
+}
+
+/// Replaces the password and ends every session. Returns the password when
+/// it was generated.
+pub async fn reset_password(
+    users: &dyn UserRepository,
+    username: &str,
+    password: Option<String>,
+) -> AdminResult<Option<String>> {
+    let user = find_user(users, username).await?;
+    let generated = password.is_none();
+    let password = password.unwrap_or_else(generate_password);
+    if password.chars().count() < PASSWORD_MIN_LEN {
+        return Err(AdminError::Invalid(format!(
+            "password must be at least {} characters",
+            PASSWORD_MIN_LEN
+        )));
+    }
+
+    users
+        .set_password_hash(user_id(&user), &hash(&password)?)
+        .await?;
+    users.revoke_all_sessions(user_id(&user)).await?;
+
+    Ok(generated.then_some(password))
+}
+
+/// The user's todos, oldest first
+pub async fn export_todos(
+    users: &dyn UserRepository,
+    todos: &dyn TodoRepository,
+    username: &str,
+) -> AdminResult<Vec<TodoResponse>> {
+    let user = find_user(users, username).await?;
+    let mut list = todos.list(user_id(&user), TodoFilter::default()).await?;
+    list.reverse();
+
+    Ok(list.into_iter().map(TodoResponse::from).collect())
+}
+
+/// Adds the todos in a JSON array to the user's list. Only title,
+/// description, status and priority are read, so an export can be imported
+/// as is. Nothing is imported if any entry is invalid. Returns the number of
+/// todos added.
+pub async fn import_todos(
+    users: &dyn UserRepository,
+    todos: &dyn TodoRepository,
+    username: &str,
+    json: &str,
+) -> AdminResult<usize> {
+    let user = find_user(users, username).await?;
+    let entries: Vec<ImportedTodo> = serde_json::from_str(json)?;
+
+    let mut requests = Vec::with_capacity(entries.len());
+    for (i, entry) in entries.into_iter().enumerate() {
+        let mut request = CreateTodoRequest {
+            title: entry.title,
+            description: entry.description,
+            priority: entry.priority,
+            status: entry.status,
+        };
+        request
+            .validate()
+            .map_err(|errors| AdminError::Invalid(format!("entry {}: {}", i, describe(&errors))))?;
+        requests.push(request);
+    }
+
+    for request in &requests {
+        let new = NewTodo {
+            title: request.title.clone(),
+            description: request.description.clone(),
+            status: request.status.unwrap_or_default(),
+            priority: request.priority.unwrap_or_default(),
+        };
+        todos.create(user_id(&user), new).await?;
+    }
+    Ok(requests.len())
+}
+
+/// Creates users `seed1..=seedN` with `todos` todos each, signing in with
+/// [`SEED_PASSWORD`]. Existing users are left alone. Returns the usernames
+/// that were created.
+pub async fn seed(
+    users: &dyn UserRepository,
+    todos: &dyn TodoRepository,
+    count: u32,
+    todos_per_user: u32,
+) -> AdminResult<Vec<String>> {
+    const PRIORITIES: [Priority; 3] = [Priority::Low, Priority::Medium, Priority::High];
+    const STATUSES: [Status; 3] = [Status::Pending, Status::InProgress, Status::Completed];
+
+    // Hashing is deliberately slow, so every seeded user shares one hash
+    let password_hash = hash(SEED_PASSWORD)?;
+
+    let mut created = Vec::new();
+    for i in 1..=count {
+        let username = format!("seed{}", i);
+        if users.find_by_username(&username).await?.is_some() {
+            continue;
+        }
+
+        let user = users
+            .create(NewUser {
+                username: username.clone(),
+                email: format!("{}@example.com", username),
+                password_hash: password_hash.clone(),
+            })
+            .await?;
+
+        for j in 0..todos_per_user as usize {
+            let new = NewTodo {
+                title: format!("Seed todo {}", j + 1),
+                description: None,
+                status: STATUSES[j % STATUSES.len()],
+                priority: PRIORITIES[j % PRIORITIES.len()],
+            };
+            todos.create(user_id(&user), new).await?;
+        }
+        created.push(username);
+    }
+
+    Ok(created)
+}
+
+/// Copies the SQLite database to `output` while the server keeps running.
+/// Unlike [`crate::backup::create`] the copy is not checksummed or rotated.
+#[cfg(not(feature = "postgres"))]
+pub async fn backup(pool: &DbPool, output: &Path) -> AdminResult {
+    if output.exists() {
+        return Err(AdminError::Invalid(format!("{} already exists", output.display())));
+    }
+    if let Some(dir) = output.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir)?;
+    }
+
+    // A consistent copy without blocking writers for long
+    sqlx::query("VACUUM INTO $1")
+        .bind(output.to_string_lossy().into_owned())
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+#[cfg(feature = "postgres")]
+pub async fn backup(_pool: &DbPool, _output: &Path) -> AdminResult {
+    Err(AdminError::Invalid(
+        "backups of PostgreSQL databases are made with pg_dump".to_string(),
+    ))
+}
+
+/// Starts a session for the user and returns a JWT for it
+pub async fn issue_token(users: &dyn UserRepository, jwt: &JwtConfig, username: &str) -> AdminResult<String> {
+    let user = find_user(users, username).await?;
+    if user.disabled_at.is_some() {
+        return Err(AdminError::Invalid(format!("user '{}' is disabled", username)));
+    }
+
+    let session_id = users.create_session(user_id(&user)).await?;
+    create_token(jwt, &user_id(&user).to_string(), &user.email, &user.username, &session_id)
+        .map_err(|e| AdminError::Invalid(format!("failed to sign token: {}", e)))
+}
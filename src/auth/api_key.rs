@@ -0,0 +1,69 @@
+use crate::auth::jwt::{JwtAuth, JwtError};
+use crate::auth::password::{generate_token, hash_token};
+use crate::database::Db;
+
+/// Every personal access token starts with this prefix, which is how
+/// `JwtAuth` tells API keys apart from session JWTs.
+pub const API_KEY_PREFIX: &str = "pat_";
+
+/// Scopes that can be granted to an API key
+pub const SCOPES: &[&str] = &["todos:read", "todos:write", "user:read"];
+
+pub fn generate_api_key() -> String {
+    format!("{}{}", API_KEY_PREFIX, generate_token())
+}
+
+pub fn hash_api_key(token: &str) -> String {
+    hash_token(token)
+}
+
+/// The leading characters of a token, safe to show in key listings
+pub fn display_prefix(token: &str) -> String {
+    token.chars().take(API_KEY_PREFIX.len() + 8).collect()
+}
+
+pub fn is_known_scope(scope: &str) -> bool {
+    SCOPES.contains(&scope)
+}
+
+pub async fn authenticate(db: &Db, token: &str) -> Result<JwtAuth, JwtError> {
+    let key_hash = hash_api_key(token);
+
+    let key = sqlx::query!(
+        "SELECT k.id, k.scopes, k.expires_at, k.revoked_at, u.id AS user_id, u.email, u.username
+         FROM api_keys k JOIN users u ON u.id = k.user_id
+         WHERE k.key_hash = $1 AND u.disabled_at IS NULL",
+        key_hash
+    )
+    .fetch_optional(&**db)
+    .await
+    .map_err(|_| JwtError::DatabaseUnavailable)?
+    .ok_or(JwtError::InvalidToken)?;
+
+    if key.revoked_at.is_some() {
+        return Err(JwtError::InvalidToken);
+    }
+
+    if key
+        .expires_at
+        .is_some_and(|exp| exp <= chrono::Utc::now().naive_utc())
+    {
+        return Err(JwtError::ExpiredToken);
+    }
+
+    // Last-used tracking is best effort and must not fail the request
+    let _ = sqlx::query!(
+        "UPDATE api_keys SET last_used_at = CURRENT_TIMESTAMP WHERE id = $1",
+        key.id
+    )
+    .execute(&**db)
+    .await;
+
+    Ok(JwtAuth {
+        user_id: key.user_id.to_string(),
+        email: key.email,
+        username: key.username,
+        scopes: Some(key.scopes.split_whitespace().map(str::to_string).collect()),
+        session_id: None,
+    })
+}
@@ -0,0 +1,35 @@
+//! CSRF protection for browsers that authenticate with the `auth_token`
+//! cookie. Every session has a token derived from its id, handed out in the
+//! readable `csrf_token` cookie and the login response. State-changing
+//! requests authenticated by the cookie must echo it in `X-CSRF-Token`; a
+//! cross-site form can send the cookie, but cannot read or set the header.
+//! Bearer tokens are never sent by the browser on its own and need no check.
+
+use hmac::{Hmac, Mac};
+use rocket::http::Method;
+use sha2::Sha256;
+
+pub const CSRF_COOKIE: &str = "csrf_token";
+pub const CSRF_HEADER: &str = "X-CSRF-Token";
+
+fn mac(secret: &str, session_id: &str) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC takes keys of any length");
+    mac.update(b"csrf:");
+    mac.update(session_id.as_bytes());
+    mac
+}
+
+/// The CSRF token of a session
+pub fn token(secret: &str, session_id: &str) -> String {
+    hex::encode(mac(secret, session_id).finalize().into_bytes())
+}
+
+/// Whether `presented` is the session's token, compared in constant time
+pub fn verify(secret: &str, session_id: &str, presented: &str) -> bool {
+    hex::decode(presented).is_ok_and(|bytes| mac(secret, session_id).verify_slice(&bytes).is_ok())
+}
+
+/// Methods that must not change anything, so need no token
+pub fn is_safe(method: Method) -> bool {
+    matches!(method, Method::Get | Method::Head | Method::Options)
+}
@@ -1,21 +1,84 @@
+use jsonwebtoken::errors::ErrorKind;
 use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
 use rocket::http::Status;
 use rocket::request::{FromRequest, Outcome};
 use rocket::Request;
+use rocket_db_pools::Database;
 
-use crate::models::Claims;
+use crate::auth::api_key::{self, API_KEY_PREFIX};
+use crate::auth::csrf::{self, CSRF_HEADER};
+use crate::auth::session;
+use crate::config::{AppConfig, JwtConfig};
+use crate::database::Db;
+use crate::error::ApiError;
+use crate::metrics::{self, AuthMethod};
+use crate::models::{Claims, Role};
 
+#[derive(Clone)]
 pub struct JwtAuth {
     pub user_id: String,
     pub email: String,
     pub username: String,
+    /// `None` for session tokens, which carry every scope.
+    /// `Some` for API keys, limited to the scopes they were created with.
+    pub scopes: Option<Vec<String>>,
+    /// The session behind a JWT; `None` for API keys
+    pub session_id: Option<String>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum JwtError {
     MissingToken,
     InvalidToken,
     ExpiredToken,
+    DatabaseUnavailable,
+    /// No `AppConfig` is managed, so tokens cannot be checked
+    Misconfigured,
+    /// A state-changing request authenticated by cookie came without the
+    /// session's CSRF token
+    CsrfMismatch,
+}
+
+impl JwtAuth {
+    /// The numeric user id carried in the token subject
+    pub fn numeric_user_id(&self) -> Result<i64, ApiError> {
+        self.user_id.parse::<i64>().map_err(|_| ApiError::InvalidToken)
+    }
+
+    pub fn is_api_key(&self) -> bool {
+        self.scopes.is_some()
+    }
+
+    /// Account and key management only accept interactive sessions, so a
+    /// leaked API key cannot be used to take over the account.
+    pub fn require_session(&self) -> Result<i64, ApiError> {
+        if self.is_api_key() {
+            return Err(ApiError::SessionRequired);
+        }
+
+        self.numeric_user_id()
+    }
+
+    pub fn has_scope(&self, scope: &str) -> bool {
+        match &self.scopes {
+            None => true,
+            Some(scopes) => scopes.iter().any(|s| s == scope),
+        }
+    }
+
+    pub fn require_scope(&self, scope: &str) -> Result<(), ApiError> {
+        if self.has_scope(scope) {
+            return Ok(());
+        }
+
+        Err(ApiError::InsufficientScope(scope.to_string()))
+    }
+}
+
+/// Fails the guard and remembers why, for the 401 catcher
+fn fail<T>(request: &Request<'_>, status: Status, error: JwtError) -> Outcome<T, JwtError> {
+    request.local_cache(|| Some(error.clone()));
+    Outcome::Error((status, error))
 }
 
 #[rocket::async_trait]
@@ -23,43 +86,185 @@ impl<'r> FromRequest<'r> for JwtAuth {
     type Error = JwtError;
 
     async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
-        // Get token from Cookie or Authorization header
-        let token = if let Some(cookie) = request.cookies().get("auth_token") {
-            cookie.value()
-        } else if let Some(auth_header) = request.headers().get_one("Authorization") {
-            if auth_header.starts_with("Bearer ") {
-                &auth_header[7..]
-            } else {
-                return Outcome::Error((Status::BadRequest, JwtError::MissingToken));
+        // Once per request: the rate limiter authenticates before the route does
+        request.local_cache_async(authenticate(request)).await.clone()
+    }
+}
+
+/// The token a request presents, and whether it came in the cookie. The
+/// Authorization header wins: only the cookie is sent by the browser on
+/// its own, so only the cookie needs CSRF protection.
+fn presented<'a>(request: &'a Request<'_>) -> Option<Result<(&'a str, bool), JwtError>> {
+    if let Some(auth_header) = request.headers().get_one("Authorization") {
+        Some(auth_header.strip_prefix("Bearer ").map(|token| (token, false)).ok_or(JwtError::MissingToken))
+    } else {
+        request.cookies().get("auth_token").map(|cookie| Ok((cookie.value(), true)))
+    }
+}
+
+/// The token a request authenticated with, if any
+pub fn presented_token<'a>(request: &'a Request<'_>) -> Option<&'a str> {
+    presented(request).and_then(Result::ok).map(|(token, _)| token)
+}
+
+async fn authenticate(request: &Request<'_>) -> Outcome<JwtAuth, JwtError> {
+    let (token, from_cookie) = match presented(request) {
+        Some(Ok(presented)) => presented,
+        Some(Err(e)) => return fail(request, Status::BadRequest, e),
+        None => return Outcome::Forward(Status::Unauthorized),
+    };
+
+    let Some(db) = Db::fetch(request.rocket()) else {
+        return fail(request, Status::InternalServerError, JwtError::DatabaseUnavailable);
+    };
+
+    // Personal access tokens are looked up in the database
+    if token.starts_with(API_KEY_PREFIX) {
+        let authenticated = api_key::authenticate(db, token).await;
+        if !matches!(authenticated, Err(JwtError::DatabaseUnavailable)) {
+            metrics::record_auth(AuthMethod::ApiKey, authenticated.is_ok());
+        }
+        return match authenticated {
+            Ok(auth) => Outcome::Success(auth),
+            Err(JwtError::DatabaseUnavailable) => {
+                fail(request, Status::InternalServerError, JwtError::DatabaseUnavailable)
             }
-        } else {
-            return Outcome::Forward(Status::Ok);
+            Err(e) => fail(request, Status::Unauthorized, e),
+        };
+    }
+
+    // Decode and validate token
+    let Some(config) = request.rocket().state::<AppConfig>() else {
+        return fail(request, Status::InternalServerError, JwtError::Misconfigured);
+    };
+    let secret = config.jwt.secret();
+    let validation = Validation::default();
+    let decoded = decode::<Claims>(
+        token,
+        &DecodingKey::from_secret(secret.as_ref()),
+        &validation,
+    );
+
+    let claims = match decoded {
+        Ok(token_data) => token_data.claims,
+        Err(e) if *e.kind() == ErrorKind::ExpiredSignature => {
+            metrics::record_auth(AuthMethod::Jwt, false);
+            return fail(request, Status::Unauthorized, JwtError::ExpiredToken);
+        }
+        Err(_) => {
+            metrics::record_auth(AuthMethod::Jwt, false);
+            return fail(request, Status::Unauthorized, JwtError::InvalidToken);
+        }
+    };
+
+    let Ok(user_id) = claims.sub.parse::<i64>() else {
+        metrics::record_auth(AuthMethod::Jwt, false);
+        return fail(request, Status::Unauthorized, JwtError::InvalidToken);
+    };
+
+    if from_cookie && !csrf::is_safe(request.method()) {
+        let presented = request.headers().get_one(CSRF_HEADER).unwrap_or_default();
+        if !csrf::verify(secret, &claims.sid, presented) {
+            tracing::warn!(event = "csrf_rejected", user_id, method = %request.method(), path = %request.uri().path());
+            return fail(request, Status::Forbidden, JwtError::CsrfMismatch);
+        }
+    }
+
+    // Tokens die with their session (logout, password change, ...)
+    match session::is_active(db, &claims.sid, user_id).await {
+        Ok(true) => {
+            metrics::record_auth(AuthMethod::Jwt, true);
+            Outcome::Success(JwtAuth {
+                user_id: claims.sub,
+                email: claims.email,
+                username: claims.username,
+                scopes: None,
+                session_id: Some(claims.sid),
+            })
+        }
+        Ok(false) => {
+            metrics::record_auth(AuthMethod::Jwt, false);
+            fail(request, Status::Unauthorized, JwtError::InvalidToken)
+        }
+        Err(_) => fail(request, Status::InternalServerError, JwtError::DatabaseUnavailable),
+    }
+}
+
+/// A signed-in administrator. API keys never qualify, whatever their
+/// owner's role.
+pub struct AdminAuth(pub JwtAuth);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for AdminAuth {
+    type Error = JwtError;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let auth = match request.guard::<JwtAuth>().await {
+            Outcome::Success(auth) => auth,
+            Outcome::Error(e) => return Outcome::Error(e),
+            Outcome::Forward(status) => return Outcome::Forward(status),
+        };
+        let Ok(user_id) = auth.require_session() else {
+            return Outcome::Error((Status::Forbidden, JwtError::InvalidToken));
+        };
+        let Some(db) = Db::fetch(request.rocket()) else {
+            return fail(request, Status::InternalServerError, JwtError::DatabaseUnavailable);
         };
 
-        // Decode and validate token
-        let secret = std::env::var("JWT_SECRET").unwrap_or_else(|_| "default-secret".to_string());
-        let validation = Validation::default();
-        let decoded = decode::<Claims>(
-            token,
-            &DecodingKey::from_secret(secret.as_ref()),
-            &validation,
-        );
-
-        match decoded {
-            Ok(token_data) => Outcome::Success(JwtAuth {
-                user_id: token_data.claims.sub,
-                email: token_data.claims.email,
-                username: token_data.claims.username,
-            }),
-            Err(_) => Outcome::Error((Status::Unauthorized, JwtError::InvalidToken)),
+        let role = sqlx::query_scalar!(r#"SELECT role AS "role: Role" FROM users WHERE id = $1"#, user_id)
+            .fetch_optional(&**db)
+            .await;
+        match role {
+            Ok(Some(Role::Admin)) => Outcome::Success(AdminAuth(auth)),
+            Ok(_) => Outcome::Error((Status::Forbidden, JwtError::InvalidToken)),
+            Err(_) => fail(request, Status::InternalServerError, JwtError::DatabaseUnavailable),
         }
     }
 }
 
-pub fn create_token(user_id: &str, email: &str, username: &str) -> Result<String, jsonwebtoken::errors::Error> {
-    let secret = std::env::var("JWT_SECRET").unwrap_or_else(|_| "default-secret".to_string());
+/// The session a signed token belongs to, even once it has expired, so it
+/// can be revoked without authenticating the request
+pub fn session_id(jwt: &JwtConfig, token: &str) -> Option<String> {
+    let mut validation = Validation::default();
+    validation.validate_exp = false;
+    decode::<Claims>(token, &DecodingKey::from_secret(jwt.secret().as_ref()), &validation)
+        .ok()
+        .map(|token_data| token_data.claims.sid)
+}
+
+/// Whether a token accepted earlier still would be: it has not expired,
+/// and neither its session nor its API key has been revoked. Connections
+/// that outlive a request check this from time to time.
+pub async fn still_valid(db: &Db, jwt: &JwtConfig, token: &str) -> Result<bool, JwtError> {
+    if token.starts_with(API_KEY_PREFIX) {
+        return match api_key::authenticate(db, token).await {
+            Ok(_) => Ok(true),
+            Err(JwtError::DatabaseUnavailable) => Err(JwtError::DatabaseUnavailable),
+            Err(_) => Ok(false),
+        };
+    }
+
+    let key = DecodingKey::from_secret(jwt.secret().as_ref());
+    let Ok(token_data) = decode::<Claims>(token, &key, &Validation::default()) else {
+        return Ok(false);
+    };
+    let Ok(user_id) = token_data.claims.sub.parse::<i64>() else {
+        return Ok(false);
+    };
+    session::is_active(db, &token_data.claims.sid, user_id)
+        .await
+        .map_err(|_| JwtError::DatabaseUnavailable)
+}
+
+pub fn create_token(
+    jwt: &JwtConfig,
+    user_id: &str,
+    email: &str,
+    username: &str,
+    session_id: &str,
+) -> Result<String, jsonwebtoken::errors::Error> {
     let expiration = chrono::Utc::now()
-        .checked_add_signed(chrono::Duration::hours(24))
+        .checked_add_signed(chrono::Duration::hours(jwt.ttl_hours))
         .expect("valid timestamp")
         .timestamp() as usize;
 
@@ -67,6 +272,7 @@ pub fn create_token(user_id: &str, email: &str, username: &str) -> Result<String
         sub: user_id.to_string(),
         email: email.to_string(),
         username: username.to_string(),
+        sid: session_id.to_string(),
         exp: expiration,
         iat: chrono::Utc::now().timestamp() as usize,
     };
@@ -74,6 +280,6 @@ pub fn create_token(user_id: &str, email: &str, username: &str) -> Result<String
     encode(
         &Header::default(),
         &claims,
-        &EncodingKey::from_secret(secret.as_ref()),
+        &EncodingKey::from_secret(jwt.secret().as_ref()),
     )
 }
\ No newline at end of file
@@ -1,3 +1,6 @@
+pub mod api_key;
+pub mod csrf;
 pub mod jwt;
-
-pub use jwt::*;
\ No newline at end of file
+pub mod oidc;
+pub mod password;
+pub mod session;
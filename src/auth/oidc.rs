@@ -0,0 +1,328 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{decode, decode_header, DecodingKey, Validation};
+use rocket::fairing::AdHoc;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::sync::Mutex;
+
+/// Name of the short-lived cookie holding the state of an in-flight login
+pub const FLOW_COOKIE: &str = "oidc_flow";
+
+/// One `[default.oidc.providers.<name>]` table in Rocket.toml
+#[derive(Debug, Clone, Deserialize)]
+pub struct OidcProviderConfig {
+    pub issuer: String,
+    pub client_id: String,
+    pub client_secret: Option<String>,
+    pub redirect_uri: String,
+    #[serde(default = "default_scopes")]
+    pub scopes: Vec<String>,
+    pub display_name: Option<String>,
+}
+
+fn default_scopes() -> Vec<String> {
+    vec!["openid".into(), "email".into(), "profile".into()]
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct OidcConfig {
+    pub providers: HashMap<String, OidcProviderConfig>,
+    /// Longest wait to connect to a provider
+    pub connect_timeout_secs: u64,
+    /// Longest a discovery, token or JWKS request may take in total
+    pub timeout_secs: u64,
+}
+
+impl Default for OidcConfig {
+    fn default() -> Self {
+        OidcConfig {
+            providers: HashMap::new(),
+            connect_timeout_secs: 5,
+            timeout_secs: 10,
+        }
+    }
+}
+
+/// The subset of the discovery document we rely on
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProviderMetadata {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub jwks_uri: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct IdTokenClaims {
+    pub sub: String,
+    pub email: Option<String>,
+    #[serde(default)]
+    pub email_verified: bool,
+    pub preferred_username: Option<String>,
+    pub nonce: Option<String>,
+}
+
+/// Everything needed to finish a login, kept in the `oidc_flow` cookie
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LoginFlow {
+    pub provider: String,
+    pub state: String,
+    pub nonce: String,
+    pub code_verifier: String,
+}
+
+impl LoginFlow {
+    pub fn new(provider: &str) -> Self {
+        LoginFlow {
+            provider: provider.to_string(),
+            state: random_token(),
+            nonce: random_token(),
+            code_verifier: random_token(),
+        }
+    }
+
+    pub fn code_challenge(&self) -> String {
+        URL_SAFE_NO_PAD.encode(Sha256::digest(self.code_verifier.as_bytes()))
+    }
+
+    pub fn encode(&self) -> String {
+        let json = serde_json::to_string(self).expect("login flow serializes");
+        URL_SAFE_NO_PAD.encode(json)
+    }
+
+    pub fn decode(value: &str) -> Option<Self> {
+        let json = URL_SAFE_NO_PAD.decode(value).ok()?;
+        serde_json::from_slice(&json).ok()
+    }
+}
+
+#[derive(Debug)]
+pub enum OidcError {
+    UnknownProvider,
+    Discovery(String),
+    TokenExchange(String),
+    InvalidIdToken(String),
+}
+
+impl std::fmt::Display for OidcError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OidcError::UnknownProvider => write!(f, "unknown identity provider"),
+            OidcError::Discovery(e) => write!(f, "provider discovery failed: {}", e),
+            OidcError::TokenExchange(e) => write!(f, "code exchange failed: {}", e),
+            OidcError::InvalidIdToken(e) => write!(f, "invalid id_token: {}", e),
+        }
+    }
+}
+
+fn random_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// Configured providers plus a cache of their discovery documents
+pub struct Oidc {
+    providers: HashMap<String, OidcProviderConfig>,
+    http: reqwest::Client,
+    metadata: Mutex<HashMap<String, ProviderMetadata>>,
+}
+
+impl Oidc {
+    /// Requests to providers are bounded by the configured timeouts, so a
+    /// slow provider fails the login instead of holding the request
+    pub fn new(config: OidcConfig) -> Result<Self, reqwest::Error> {
+        let http = reqwest::Client::builder()
+            .connect_timeout(Duration::from_secs(config.connect_timeout_secs))
+            .timeout(Duration::from_secs(config.timeout_secs))
+            .build()?;
+
+        Ok(Oidc {
+            providers: config.providers,
+            http,
+            metadata: Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub fn provider_names(&self) -> Vec<(&str, &str)> {
+        let mut names: Vec<(&str, &str)> = self
+            .providers
+            .iter()
+            .map(|(name, cfg)| (name.as_str(), cfg.display_name.as_deref().unwrap_or(name)))
+            .collect();
+        names.sort();
+        names
+    }
+
+    pub fn provider(&self, name: &str) -> Result<&OidcProviderConfig, OidcError> {
+        self.providers.get(name).ok_or(OidcError::UnknownProvider)
+    }
+
+    pub async fn metadata(&self, name: &str) -> Result<ProviderMetadata, OidcError> {
+        if let Some(metadata) = self.metadata.lock().await.get(name) {
+            return Ok(metadata.clone());
+        }
+
+        let provider = self.provider(name)?;
+        let url = format!(
+            "{}/.well-known/openid-configuration",
+            provider.issuer.trim_end_matches('/')
+        );
+        let metadata: ProviderMetadata = self
+            .http
+            .get(&url)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| OidcError::Discovery(e.to_string()))?
+            .json()
+            .await
+            .map_err(|e| OidcError::Discovery(e.to_string()))?;
+
+        if metadata.issuer.trim_end_matches('/') != provider.issuer.trim_end_matches('/') {
+            return Err(OidcError::Discovery("issuer mismatch".into()));
+        }
+
+        self.metadata
+            .lock()
+            .await
+            .insert(name.to_string(), metadata.clone());
+        Ok(metadata)
+    }
+
+    pub async fn authorization_url(&self, flow: &LoginFlow) -> Result<String, OidcError> {
+        let provider = self.provider(&flow.provider)?;
+        let metadata = self.metadata(&flow.provider).await?;
+
+        let mut url = reqwest::Url::parse(&metadata.authorization_endpoint)
+            .map_err(|e| OidcError::Discovery(e.to_string()))?;
+        url.query_pairs_mut()
+            .append_pair("response_type", "code")
+            .append_pair("client_id", &provider.client_id)
+            .append_pair("redirect_uri", &provider.redirect_uri)
+            .append_pair("scope", &provider.scopes.join(" "))
+            .append_pair("state", &flow.state)
+            .append_pair("nonce", &flow.nonce)
+            .append_pair("code_challenge", &flow.code_challenge())
+            .append_pair("code_challenge_method", "S256");
+
+        Ok(url.to_string())
+    }
+
+    /// Exchanges the authorization code and returns the verified id_token claims
+    pub async fn complete(&self, flow: &LoginFlow, code: &str) -> Result<IdTokenClaims, OidcError> {
+        let provider = self.provider(&flow.provider)?;
+        let metadata = self.metadata(&flow.provider).await?;
+
+        let mut form = vec![
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", provider.redirect_uri.as_str()),
+            ("client_id", provider.client_id.as_str()),
+            ("code_verifier", flow.code_verifier.as_str()),
+        ];
+        if let Some(secret) = &provider.client_secret {
+            form.push(("client_secret", secret.as_str()));
+        }
+
+        let tokens: TokenResponse = self
+            .http
+            .post(&metadata.token_endpoint)
+            .form(&form)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| OidcError::TokenExchange(e.to_string()))?
+            .json()
+            .await
+            .map_err(|e| OidcError::TokenExchange(e.to_string()))?;
+
+        let claims = self.verify_id_token(provider, &metadata, &tokens.id_token).await?;
+
+        if claims.nonce.as_deref() != Some(flow.nonce.as_str()) {
+            return Err(OidcError::InvalidIdToken("nonce mismatch".into()));
+        }
+
+        Ok(claims)
+    }
+
+    async fn verify_id_token(
+        &self,
+        provider: &OidcProviderConfig,
+        metadata: &ProviderMetadata,
+        id_token: &str,
+    ) -> Result<IdTokenClaims, OidcError> {
+        let header =
+            decode_header(id_token).map_err(|e| OidcError::InvalidIdToken(e.to_string()))?;
+
+        let jwks: JwkSet = self
+            .http
+            .get(&metadata.jwks_uri)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| OidcError::InvalidIdToken(e.to_string()))?
+            .json()
+            .await
+            .map_err(|e| OidcError::InvalidIdToken(e.to_string()))?;
+
+        let jwk = match &header.kid {
+            Some(kid) => jwks.find(kid),
+            None => jwks.keys.first(),
+        }
+        .ok_or_else(|| OidcError::InvalidIdToken("no matching signing key".into()))?;
+
+        let key =
+            DecodingKey::from_jwk(jwk).map_err(|e| OidcError::InvalidIdToken(e.to_string()))?;
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_audience(&[&provider.client_id]);
+        validation.set_issuer(&[&metadata.issuer]);
+
+        decode::<IdTokenClaims>(id_token, &key, &validation)
+            .map(|data| data.claims)
+            .map_err(|e| OidcError::InvalidIdToken(e.to_string()))
+    }
+}
+
+pub fn stage() -> AdHoc {
+    AdHoc::try_on_ignite("OIDC Providers", |rocket| async {
+        let config = match rocket.figment().find_value("oidc") {
+            Ok(_) => match rocket.figment().extract_inner::<OidcConfig>("oidc") {
+                Ok(config) => config,
+                Err(e) => {
+                    tracing::error!(error = %e, "invalid [oidc] configuration");
+                    return Err(rocket);
+                }
+            },
+            Err(_) => OidcConfig::default(),
+        };
+        if config.connect_timeout_secs == 0 || config.timeout_secs == 0 {
+            tracing::error!(
+                event = "invalid_config",
+                problem = "oidc.connect_timeout_secs and oidc.timeout_secs must be at least 1"
+            );
+            return Err(rocket);
+        }
+
+        match Oidc::new(config) {
+            Ok(oidc) => Ok(rocket.manage(oidc)),
+            Err(e) => {
+                tracing::error!(error = %e, "failed to build the OIDC HTTP client");
+                Err(rocket)
+            }
+        }
+    })
+}
@@ -0,0 +1,42 @@
+use argon2::{
+    password_hash::{
+        rand_core::{OsRng, RngCore},
+        PasswordHash, PasswordHasher, PasswordVerifier, SaltString,
+    },
+    Argon2,
+};
+use sha2::{Digest, Sha256};
+
+use crate::models::User;
+
+pub fn hash_password(password: &str) -> Result<String, argon2::password_hash::Error> {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+}
+
+/// Accounts without a password (see `User::NO_PASSWORD`) never verify
+pub fn verify_password(password: &str, password_hash: &str) -> Result<bool, argon2::password_hash::Error> {
+    if password_hash == User::NO_PASSWORD {
+        return Ok(false);
+    }
+
+    let parsed_hash = PasswordHash::new(password_hash)?;
+    Ok(Argon2::default()
+        .verify_password(password.as_bytes(), &parsed_hash)
+        .is_ok())
+}
+
+/// A random 256-bit secret, hex encoded
+pub fn generate_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+/// Random tokens are high-entropy, so a plain SHA-256 digest is enough to
+/// store them safely while still allowing an indexed lookup.
+pub fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
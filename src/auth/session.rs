@@ -0,0 +1,74 @@
+use crate::database::{Db, DbConnection};
+
+/// Every JWT references a row in `sessions` through its `sid` claim, so a
+/// token stops working as soon as its session is revoked.
+pub async fn create_session(conn: &mut DbConnection, user_id: i64) -> Result<String, sqlx::Error> {
+    let session_id = uuid::Uuid::new_v4().to_string();
+
+    sqlx::query!(
+        "INSERT INTO sessions (id, user_id) VALUES ($1, $2)",
+        session_id,
+        user_id
+    )
+    .execute(conn)
+    .await?;
+
+    Ok(session_id)
+}
+
+pub async fn is_active(db: &Db, session_id: &str, user_id: i64) -> Result<bool, sqlx::Error> {
+    let session = sqlx::query!(
+        "SELECT id FROM sessions WHERE id = $1 AND user_id = $2 AND revoked_at IS NULL",
+        session_id,
+        user_id
+    )
+    .fetch_optional(&**db)
+    .await?;
+
+    Ok(session.is_some())
+}
+
+/// Whether the session was started within the last `minutes`, for actions
+/// that require a fresh login
+pub async fn is_recent(
+    conn: &mut DbConnection,
+    session_id: &str,
+    minutes: i64,
+) -> Result<bool, sqlx::Error> {
+    let session = sqlx::query!("SELECT created_at FROM sessions WHERE id = $1", session_id)
+        .fetch_optional(conn)
+        .await?;
+
+    let cutoff = chrono::Utc::now().naive_utc() - chrono::Duration::minutes(minutes);
+    Ok(session
+        .and_then(|s| s.created_at)
+        .is_some_and(|created_at| created_at >= cutoff))
+}
+
+pub async fn revoke(conn: &mut DbConnection, session_id: &str) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "UPDATE sessions SET revoked_at = CURRENT_TIMESTAMP WHERE id = $1 AND revoked_at IS NULL",
+        session_id
+    )
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
+
+/// Revoke every session of the user except `keep`
+pub async fn revoke_others(
+    conn: &mut DbConnection,
+    user_id: i64,
+    keep: &str,
+) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query!(
+        "UPDATE sessions SET revoked_at = CURRENT_TIMESTAMP WHERE user_id = $1 AND id != $2 AND revoked_at IS NULL",
+        user_id,
+        keep
+    )
+    .execute(conn)
+    .await?;
+
+    Ok(result.rows_affected())
+}
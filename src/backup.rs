@@ -0,0 +1,523 @@
+//! Snapshots of the SQLite database. A snapshot is written with `VACUUM
+//! INTO`, checked with `PRAGMA integrity_check`, optionally gzipped, and
+//! stored next to a `sha256sum`-style checksum file. Old snapshots are
+//! rotated out after every backup.
+
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use rocket::fairing::AdHoc;
+use rocket::figment::Figment;
+use rocket_db_pools::Database;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::migrate::MigrateError;
+use utoipa::ToSchema;
+
+use crate::config::InvalidConfig;
+use crate::database::{Db, DbPool};
+use crate::health::Health;
+
+const PREFIX: &str = "todos-";
+const CHECKSUM_SUFFIX: &str = ".sha256";
+
+/// `[default.backup]` in Rocket.toml
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct BackupConfig {
+    /// Where snapshots are written
+    pub dir: PathBuf,
+    /// Minutes between scheduled backups; 0 turns the schedule off
+    pub interval_minutes: u64,
+    /// Number of snapshots kept; older ones are deleted
+    pub keep: usize,
+    /// Store snapshots as `.db.gz`
+    pub gzip: bool,
+    /// How long a restore waits for running requests before giving up
+    pub drain_seconds: u64,
+}
+
+impl Default for BackupConfig {
+    fn default() -> Self {
+        BackupConfig {
+            dir: PathBuf::from("backups"),
+            interval_minutes: 0,
+            keep: 7,
+            gzip: false,
+            drain_seconds: 10,
+        }
+    }
+}
+
+impl BackupConfig {
+    /// Reads and checks the `[backup]` section; the defaults when it is missing
+    pub fn load(figment: &Figment) -> Result<Self, InvalidConfig> {
+        let config: BackupConfig = figment.extract_inner("backup").or_else(|e| {
+            if e.missing() {
+                Ok(BackupConfig::default())
+            } else {
+                Err(InvalidConfig(e.into_iter().map(|e| e.to_string()).collect()))
+            }
+        })?;
+        let problems = config.problems();
+        if !problems.is_empty() {
+            return Err(InvalidConfig(problems));
+        }
+        Ok(config)
+    }
+
+    fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        // Rotation would otherwise delete the snapshot it runs after
+        if self.keep < 1 {
+            problems.push("backup.keep must be at least 1".to_string());
+        }
+        if self.dir.as_os_str().is_empty() {
+            problems.push("backup.dir must not be empty".to_string());
+        } else if let Ok(metadata) = std::fs::metadata(&self.dir) {
+            if !metadata.is_dir() {
+                problems.push(format!("backup.dir {} is not a directory", self.dir.display()));
+            } else if metadata.permissions().readonly() {
+                problems.push(format!("backup.dir {} is not writable", self.dir.display()));
+            }
+        }
+        problems
+    }
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct Snapshot {
+    #[schema(example = "todos-20240101T020000123Z.db.gz")]
+    pub name: String,
+    /// Size on disk, after compression
+    pub size: u64,
+    /// Hex SHA-256 of the file as stored; `None` if the checksum file is missing
+    pub sha256: Option<String>,
+    pub compressed: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug)]
+pub enum BackupError {
+    /// No snapshot with that name
+    NotFound(String),
+    /// The snapshot's checksum file is missing or does not match
+    ChecksumMismatch(String),
+    /// The snapshot is not a healthy SQLite database
+    Corrupt(String),
+    /// Only SQLite databases are backed up this way
+    Unsupported,
+    Io(std::io::Error),
+    Database(sqlx::Error),
+    Migrate(MigrateError),
+}
+
+impl std::fmt::Display for BackupError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BackupError::NotFound(name) => write!(f, "no snapshot named '{}'", name),
+            BackupError::ChecksumMismatch(name) => write!(f, "checksum of '{}' does not match", name),
+            BackupError::Corrupt(name) => write!(f, "'{}' failed the integrity check", name),
+            BackupError::Unsupported => write!(f, "backups of PostgreSQL databases are made with pg_dump"),
+            BackupError::Io(e) => write!(f, "{}", e),
+            BackupError::Database(e) => write!(f, "{}", e),
+            BackupError::Migrate(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for BackupError {}
+
+impl From<std::io::Error> for BackupError {
+    fn from(e: std::io::Error) -> Self {
+        BackupError::Io(e)
+    }
+}
+
+impl From<sqlx::Error> for BackupError {
+    fn from(e: sqlx::Error) -> Self {
+        BackupError::Database(e)
+    }
+}
+
+impl From<MigrateError> for BackupError {
+    fn from(e: MigrateError) -> Self {
+        BackupError::Migrate(e)
+    }
+}
+
+pub type BackupResult<T> = Result<T, BackupError>;
+
+fn is_snapshot(name: &str) -> bool {
+    name.starts_with(PREFIX) && (name.ends_with(".db") || name.ends_with(".db.gz"))
+}
+
+fn checksum_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(CHECKSUM_SUFFIX);
+    path.with_file_name(name)
+}
+
+fn sha256_file(path: &Path) -> std::io::Result<String> {
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = [0u8; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(hex::encode(hasher.finalize()))
+}
+
+/// The hash recorded for a snapshot; the checksum file uses the `sha256sum`
+/// format so operators can check it with `sha256sum -c`
+fn recorded_checksum(path: &Path) -> Option<String> {
+    let line = std::fs::read_to_string(checksum_path(path)).ok()?;
+    line.split_whitespace().next().map(str::to_string)
+}
+
+#[cfg(not(feature = "postgres"))]
+fn gzip(from: &Path, to: &Path) -> std::io::Result<()> {
+    let mut input = std::fs::File::open(from)?;
+    let output = std::fs::File::create(to)?;
+    let mut encoder = flate2::write::GzEncoder::new(output, flate2::Compression::default());
+    std::io::copy(&mut input, &mut encoder)?;
+    encoder.finish()?.sync_all()
+}
+
+#[cfg(not(feature = "postgres"))]
+fn gunzip(from: &Path, to: &Path) -> std::io::Result<()> {
+    let mut decoder = flate2::read::GzDecoder::new(std::fs::File::open(from)?);
+    let mut output = std::fs::File::create(to)?;
+    std::io::copy(&mut decoder, &mut output)?;
+    output.sync_all()
+}
+
+async fn blocking<T: Send + 'static>(
+    work: impl FnOnce() -> std::io::Result<T> + Send + 'static,
+) -> BackupResult<T> {
+    tokio::task::spawn_blocking(work)
+        .await
+        .map_err(|e| BackupError::Io(std::io::Error::other(e)))?
+        .map_err(BackupError::Io)
+}
+
+/// Snapshots in the backup directory, newest first
+pub fn list(config: &BackupConfig) -> BackupResult<Vec<Snapshot>> {
+    let entries = match std::fs::read_dir(&config.dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut snapshots = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if !is_snapshot(&name) {
+            continue;
+        }
+
+        let metadata = entry.metadata()?;
+        snapshots.push(Snapshot {
+            sha256: recorded_checksum(&entry.path()),
+            compressed: name.ends_with(".gz"),
+            size: metadata.len(),
+            created_at: metadata.modified().map(DateTime::<Utc>::from).unwrap_or_else(|_| Utc::now()),
+            name,
+        });
+    }
+
+    // Names embed the creation time, so they sort chronologically
+    snapshots.sort_by(|a, b| b.name.cmp(&a.name));
+    Ok(snapshots)
+}
+
+/// Deletes all but the newest `keep` snapshots and returns their names
+pub fn rotate(config: &BackupConfig) -> BackupResult<Vec<String>> {
+    let mut removed = Vec::new();
+    // The newest snapshot is never rotated out, whatever `keep` says
+    for snapshot in list(config)?.into_iter().skip(config.keep.max(1)) {
+        let path = config.dir.join(&snapshot.name);
+        std::fs::remove_file(&path)?;
+        let _ = std::fs::remove_file(checksum_path(&path));
+        removed.push(snapshot.name);
+    }
+    Ok(removed)
+}
+
+/// Looks a snapshot up by name and checks it against its checksum file
+pub async fn verify(config: &BackupConfig, name: &str) -> BackupResult<PathBuf> {
+    // Only names from the listing are accepted, so `name` cannot point
+    // outside the backup directory
+    if !list(config)?.iter().any(|s| s.name == name) {
+        return Err(BackupError::NotFound(name.to_string()));
+    }
+
+    let path = config.dir.join(name);
+    let Some(expected) = recorded_checksum(&path) else {
+        return Err(BackupError::ChecksumMismatch(name.to_string()));
+    };
+    let actual = blocking({
+        let path = path.clone();
+        move || sha256_file(&path)
+    })
+    .await?;
+    if actual != expected {
+        return Err(BackupError::ChecksumMismatch(name.to_string()));
+    }
+
+    Ok(path)
+}
+
+#[cfg(not(feature = "postgres"))]
+async fn integrity_check(conn: &mut sqlx::SqliteConnection, schema: &str) -> Result<bool, sqlx::Error> {
+    let result: String = sqlx::query_scalar(&format!("PRAGMA {}.integrity_check(1)", schema))
+        .fetch_one(conn)
+        .await?;
+    Ok(result == "ok")
+}
+
+/// Writes a new snapshot, then rotates old ones out
+#[cfg(not(feature = "postgres"))]
+pub async fn create(pool: &DbPool, config: &BackupConfig) -> BackupResult<Snapshot> {
+    use sqlx::{ConnectOptions, Connection};
+
+    std::fs::create_dir_all(&config.dir)?;
+
+    let name = format!("{}{}.db", PREFIX, Utc::now().format("%Y%m%dT%H%M%S%3fZ"));
+    // Written under a hidden name and renamed at the end, so a half-written
+    // snapshot never shows up in the listing
+    let partial = config.dir.join(format!(".{}.partial", name));
+
+    // A consistent copy without blocking writers for long
+    sqlx::query("VACUUM INTO $1")
+        .bind(partial.to_string_lossy().into_owned())
+        .execute(pool)
+        .await?;
+
+    let healthy = async {
+        let mut conn = sqlx::sqlite::SqliteConnectOptions::new()
+            .filename(&partial)
+            .read_only(true)
+            .connect()
+            .await?;
+        let healthy = integrity_check(&mut conn, "main").await?;
+        conn.close().await?;
+        Ok::<_, sqlx::Error>(healthy)
+    }
+    .await;
+    if !matches!(healthy, Ok(true)) {
+        let _ = std::fs::remove_file(&partial);
+        healthy?;
+        return Err(BackupError::Corrupt(name));
+    }
+
+    let (name, path) = if config.gzip {
+        let name = format!("{}.gz", name);
+        let compressed = config.dir.join(format!(".{}.partial", name));
+        blocking({
+            let (partial, compressed) = (partial.clone(), compressed.clone());
+            move || {
+                gzip(&partial, &compressed)?;
+                std::fs::remove_file(&partial)
+            }
+        })
+        .await?;
+        (name, compressed)
+    } else {
+        (name, partial)
+    };
+
+    let sha256 = blocking({
+        let path = path.clone();
+        move || sha256_file(&path)
+    })
+    .await?;
+    let target = config.dir.join(&name);
+    std::fs::write(checksum_path(&target), format!("{}  {}\n", sha256, name))?;
+    std::fs::rename(&path, &target)?;
+
+    let snapshot = Snapshot {
+        size: std::fs::metadata(&target)?.len(),
+        sha256: Some(sha256),
+        compressed: config.gzip,
+        created_at: Utc::now(),
+        name,
+    };
+    tracing::info!(
+        event = "backup_created",
+        snapshot = %snapshot.name,
+        size = snapshot.size,
+        sha256 = snapshot.sha256.as_deref().unwrap_or_default(),
+        "backup written"
+    );
+
+    for removed in rotate(config)? {
+        tracing::info!(event = "backup_rotated", snapshot = %removed, "old backup removed");
+    }
+
+    Ok(snapshot)
+}
+
+#[cfg(feature = "postgres")]
+pub async fn create(_pool: &DbPool, _config: &BackupConfig) -> BackupResult<Snapshot> {
+    Err(BackupError::Unsupported)
+}
+
+/// Replaces the live database with a snapshot, then applies any migrations
+/// the snapshot predates. The copy runs in one transaction, so a failed
+/// restore leaves the database as it was. The server should be in
+/// maintenance mode while this runs.
+#[cfg(not(feature = "postgres"))]
+pub async fn restore(pool: &DbPool, config: &BackupConfig, name: &str) -> BackupResult<()> {
+    let path = verify(config, name).await?;
+
+    // ATTACH needs a plain database file
+    let (source, scratch) = if name.ends_with(".gz") {
+        let scratch = config.dir.join(format!(".restore-{}.db", uuid::Uuid::new_v4().simple()));
+        blocking({
+            let (path, scratch) = (path.clone(), scratch.clone());
+            move || gunzip(&path, &scratch)
+        })
+        .await?;
+        (scratch.clone(), Some(scratch))
+    } else {
+        (path, None)
+    };
+
+    let result = copy_from(pool, &source, name).await;
+    if let Some(scratch) = scratch {
+        let _ = std::fs::remove_file(scratch);
+    }
+    result?;
+
+    crate::database::migrator().run(pool).await?;
+    tracing::info!(event = "backup_restored", snapshot = %name, "database restored");
+    Ok(())
+}
+
+/// Drops every table in the live database and recreates it from the
+/// attached snapshot
+#[cfg(not(feature = "postgres"))]
+async fn copy_from(pool: &DbPool, source: &Path, name: &str) -> BackupResult<()> {
+    use sqlx::Connection;
+
+    // The connection's settings are changed below, so it is not returned to
+    // the pool
+    let conn = &mut pool.acquire().await?.detach();
+
+    sqlx::query("PRAGMA foreign_keys = OFF").execute(&mut *conn).await?;
+    sqlx::query("ATTACH DATABASE $1 AS snapshot")
+        .bind(source.to_string_lossy().into_owned())
+        .execute(&mut *conn)
+        .await?;
+    if !integrity_check(conn, "snapshot").await? {
+        return Err(BackupError::Corrupt(name.to_string()));
+    }
+
+    let mut tx = conn.begin().await?;
+
+    let existing: Vec<(String, String)> = sqlx::query_as(
+        "SELECT type, name FROM main.sqlite_master WHERE type IN ('table', 'view') AND name NOT LIKE 'sqlite_%'",
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+    // Indexes and triggers go with their tables
+    for (kind, object) in existing {
+        sqlx::query(&format!("DROP {} IF EXISTS main.\"{}\"", kind.to_uppercase(), object))
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    // Tables first, each filled as it is created, then everything else
+    let schema: Vec<(String, String, String)> = sqlx::query_as(
+        "SELECT type, name, sql FROM snapshot.sqlite_master WHERE sql IS NOT NULL AND name NOT LIKE 'sqlite_%' ORDER BY type != 'table', rowid",
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+    for (kind, object, sql) in schema {
+        sqlx::query(&sql).execute(&mut *tx).await?;
+        if kind == "table" {
+            sqlx::query(&format!("INSERT INTO main.\"{0}\" SELECT * FROM snapshot.\"{0}\"", object))
+                .execute(&mut *tx)
+                .await?;
+        }
+    }
+
+    // AUTOINCREMENT counters, so new ids do not reuse old ones
+    let has_sequence: bool = sqlx::query_scalar(
+        "SELECT EXISTS (SELECT 1 FROM snapshot.sqlite_master WHERE name = 'sqlite_sequence')",
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+    if has_sequence {
+        sqlx::query("DELETE FROM main.sqlite_sequence").execute(&mut *tx).await?;
+        sqlx::query("INSERT INTO main.sqlite_sequence SELECT * FROM snapshot.sqlite_sequence")
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    tx.commit().await?;
+    sqlx::query("DETACH DATABASE snapshot").execute(&mut *conn).await?;
+    Ok(())
+}
+
+#[cfg(feature = "postgres")]
+pub async fn restore(_pool: &DbPool, _config: &BackupConfig, _name: &str) -> BackupResult<()> {
+    Err(BackupError::Unsupported)
+}
+
+pub fn stage() -> AdHoc {
+    AdHoc::try_on_ignite("Backups", |rocket| async {
+        let config = match BackupConfig::load(rocket.figment()) {
+            Ok(config) => config,
+            Err(InvalidConfig(problems)) => {
+                for problem in &problems {
+                    tracing::error!(event = "invalid_config", problem = %problem);
+                }
+                return Err(rocket);
+            }
+        };
+
+        Ok(rocket
+            .manage(config.clone())
+            .attach(AdHoc::on_liftoff("Backup Schedule", |rocket| {
+                Box::pin(async move {
+                    if config.interval_minutes == 0 || cfg!(feature = "postgres") {
+                        return;
+                    }
+                    let Some(db) = Db::fetch(rocket) else {
+                        return;
+                    };
+                    let pool = (**db).clone();
+                    let mut shutdown = rocket.shutdown();
+
+                    let job = tokio::spawn(async move {
+                        // The first backup runs one interval after startup,
+                        // so restarts do not pile up snapshots
+                        let period = Duration::from_secs(config.interval_minutes * 60);
+                        let mut interval = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+                        loop {
+                            tokio::select! {
+                                _ = interval.tick() => {
+                                    if let Err(e) = create(&pool, &config).await {
+                                        tracing::error!(event = "backup_failed", error = %e, "scheduled backup failed");
+                                    }
+                                }
+                                _ = &mut shutdown => break,
+                            }
+                        }
+                    });
+                    if let Some(health) = rocket.state::<Health>() {
+                        health.watch("backup_schedule", job);
+                    }
+                })
+            })))
+    })
+}
@@ -0,0 +1,306 @@
+//! Administration tool for the todo API. Reads the database settings from the
+//! same Rocket.toml / `ROCKET_*` environment as the server.
+//!
+//! ```text
+//! cargo run --bin todoctl -- migrate status
+//! cargo run --bin todoctl -- user create alice alice@example.com --admin
+//! cargo run --bin todoctl -- todos export alice --output alice.json
+//! ```
+
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use clap::{Parser, Subcommand};
+
+use my_rocket_app::admin::{self, AdminResult};
+use my_rocket_app::backup::{self, BackupConfig};
+use my_rocket_app::config::{AppConfig, InvalidConfig};
+use my_rocket_app::database::{self, DbPool};
+use my_rocket_app::models::Role;
+use my_rocket_app::repository::{SqlTodoRepository, SqlUserRepository, TodoRepository, UserRepository};
+
+#[derive(Parser)]
+#[command(name = "todoctl", about = "Manage the todo API database")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Apply, inspect or revert schema migrations
+    #[command(subcommand)]
+    Migrate(MigrateCommand),
+    /// Manage user accounts
+    #[command(subcommand)]
+    User(UserCommand),
+    /// Move a user's todos in and out as JSON
+    #[command(subcommand)]
+    Todos(TodosCommand),
+    /// Create demo users and todos; existing users are left alone
+    Seed {
+        #[arg(long, default_value_t = 3)]
+        users: u32,
+        /// Todos per user
+        #[arg(long, default_value_t = 10)]
+        todos: u32,
+    },
+    /// Snapshot the SQLite database while the server keeps running, using
+    /// the `[backup]` settings (checksum, gzip, rotation)
+    Backup {
+        /// Write a plain copy to this file instead, outside the rotation
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+    /// List snapshots, newest first
+    Backups,
+    /// Replace the database with a snapshot. Stop the server first, or use
+    /// POST /api/admin/backups/<name>/restore, which puts it into
+    /// maintenance mode while restoring.
+    Restore {
+        name: String,
+        /// Confirm that the current data may be overwritten
+        #[arg(long)]
+        yes: bool,
+    },
+    /// Debugging helpers for tokens
+    #[command(subcommand)]
+    Token(TokenCommand),
+}
+
+#[derive(Subcommand)]
+enum MigrateCommand {
+    /// Apply all pending migrations
+    Up,
+    /// List migrations and whether they are applied
+    Status,
+    /// Revert the most recently applied migration
+    Down,
+}
+
+#[derive(Subcommand)]
+enum UserCommand {
+    /// Create an account; a password is generated unless one is given
+    Create {
+        username: String,
+        email: String,
+        #[arg(long)]
+        password: Option<String>,
+        #[arg(long)]
+        admin: bool,
+    },
+    /// Block sign-in and end all of the user's sessions
+    Disable { username: String },
+    /// Allow a disabled user to sign in again
+    Enable { username: String },
+    SetRole {
+        username: String,
+        #[arg(value_parser = parse_role)]
+        role: Role,
+    },
+    /// Replace the password and end all of the user's sessions
+    ResetPassword {
+        username: String,
+        /// Generated when omitted
+        #[arg(long)]
+        password: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum TodosCommand {
+    /// Write the user's todos as a JSON array, oldest first
+    Export {
+        username: String,
+        /// Defaults to stdout
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+    /// Add the todos in a JSON array to the user's list. Only title,
+    /// description, status and priority are read, so an export can be
+    /// imported as is. Nothing is imported if any entry is invalid.
+    Import { username: String, file: PathBuf },
+}
+
+#[derive(Subcommand)]
+enum TokenCommand {
+    /// Start a session for the user and print a JWT for it
+    Issue { username: String },
+}
+
+fn backup_config() -> Result<BackupConfig, InvalidConfig> {
+    BackupConfig::load(&rocket::Config::figment())
+}
+
+fn parse_role(value: &str) -> Result<Role, String> {
+    serde_json::from_value(serde_json::Value::String(value.to_string()))
+        .map_err(|_| format!("unknown role '{}' (expected user or admin)", value))
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    match run(cli).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn run(cli: Cli) -> Result<(), Box<dyn std::error::Error>> {
+    let pool = database::connect(&rocket::Config::figment()).await?;
+
+    // Everything but the migration and restore commands needs the current
+    // schema; a restore brings its own and migrates it afterwards
+    if !matches!(cli.command, Command::Migrate(_) | Command::Restore { .. } | Command::Backups) {
+        let pending = admin::pending_migrations(&pool).await?;
+        if pending > 0 {
+            return Err(format!("{} pending migration(s); run `todoctl migrate up` first", pending).into());
+        }
+    }
+
+    let users = SqlUserRepository::new(pool.clone());
+    let todos = SqlTodoRepository::new(pool.clone());
+
+    match cli.command {
+        Command::Migrate(command) => migrate(&pool, command).await?,
+        Command::User(command) => user(&users, command).await?,
+        Command::Todos(command) => todos_command(&users, &todos, command).await?,
+        Command::Seed { users: n, todos: m } => {
+            let created = admin::seed(&users, &todos, n, m).await?;
+            for username in &created {
+                println!("Created {} with {} todos", username, m);
+            }
+            if created.len() < n as usize {
+                println!("Skipped {} existing user(s)", n as usize - created.len());
+            }
+            println!("Seeded users sign in with password '{}'", admin::SEED_PASSWORD);
+        }
+        Command::Backup { output: Some(output) } => {
+            admin::backup(&pool, &output).await?;
+            println!("Backed up to {}", output.display());
+        }
+        Command::Backup { output: None } => {
+            let snapshot = backup::create(&pool, &backup_config()?).await?;
+            println!("Backed up to {}", snapshot.name);
+            println!("sha256 {}", snapshot.sha256.unwrap_or_default());
+        }
+        Command::Backups => {
+            for snapshot in backup::list(&backup_config()?)? {
+                let checksum = if snapshot.sha256.is_some() { "" } else { "  (no checksum)" };
+                println!("{:<36} {:>12} bytes  {}{}", snapshot.name, snapshot.size, snapshot.created_at, checksum);
+            }
+        }
+        Command::Restore { name, yes } => {
+            if !yes {
+                return Err("restoring replaces all current data; pass --yes to confirm".into());
+            }
+            backup::restore(&pool, &backup_config()?, &name).await?;
+            println!("Restored {}", name);
+        }
+        Command::Token(TokenCommand::Issue { username }) => {
+            let config = AppConfig::load(&rocket::Config::figment())?;
+            println!("{}", admin::issue_token(&users, &config.jwt, &username).await?);
+        }
+    }
+
+    Ok(())
+}
+
+async fn migrate(pool: &DbPool, command: MigrateCommand) -> AdminResult {
+    match command {
+        MigrateCommand::Up => {
+            let applied = admin::migrate_up(pool).await?;
+            if applied.is_empty() {
+                println!("Already up to date");
+            }
+            for m in applied {
+                println!("Applied {} {}", m.version, m.description);
+            }
+        }
+        MigrateCommand::Status => {
+            for m in database::migration_status(pool).await? {
+                let state = match (m.applied, m.checksum_mismatch) {
+                    (true, true) => "applied, file changed since",
+                    (true, false) => "applied",
+                    (false, _) => "pending",
+                };
+                println!("{:>4}  {:<40} {}", m.version, m.description, state);
+            }
+        }
+        MigrateCommand::Down => match admin::migrate_down(pool).await? {
+            Some(m) => println!("Reverted {} {}", m.version, m.description),
+            None => println!("No migrations applied"),
+        },
+    }
+
+    Ok(())
+}
+
+async fn user(users: &dyn UserRepository, command: UserCommand) -> AdminResult {
+    match command {
+        UserCommand::Create {
+            username,
+            email,
+            password,
+            admin,
+        } => {
+            let (user, generated) = admin::create_user(users, username, email, password, admin).await?;
+            println!("Created user {} ({})", user.username, user.id.unwrap_or_default());
+            if let Some(password) = generated {
+                println!("Password: {}", password);
+            }
+        }
+        UserCommand::Disable { username } => {
+            admin::set_disabled(users, &username, true).await?;
+            println!("Disabled {}", username);
+        }
+        UserCommand::Enable { username } => {
+            admin::set_disabled(users, &username, false).await?;
+            println!("Enabled {}", username);
+        }
+        UserCommand::SetRole { username, role } => {
+            admin::set_role(users, &username, role).await?;
+            println!("{} is now {}", username, format!("{:?}", role).to_lowercase());
+        }
+        UserCommand::ResetPassword { username, password } => {
+            let generated = admin::reset_password(users, &username, password).await?;
+            println!("Password of {} reset; existing sessions were ended", username);
+            if let Some(password) = generated {
+                println!("Password: {}", password);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn todos_command(
+    users: &dyn UserRepository,
+    todos: &dyn TodoRepository,
+    command: TodosCommand,
+) -> AdminResult {
+    match command {
+        TodosCommand::Export { username, output } => {
+            let list = admin::export_todos(users, todos, &username).await?;
+            let json = serde_json::to_string_pretty(&list)?;
+            match output {
+                Some(path) => {
+                    std::fs::write(&path, json)?;
+                    eprintln!("Exported {} todos to {}", list.len(), path.display());
+                }
+                None => println!("{}", json),
+            }
+        }
+        TodosCommand::Import { username, file } => {
+            let json = std::fs::read_to_string(&file)?;
+            let count = admin::import_todos(users, todos, &username, &json).await?;
+            println!("Imported {} todos for {}", count, username);
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,339 @@
+//! Application settings from the `[app]` section of Rocket.toml. They are
+//! checked when the server ignites, so a bad value stops it from starting
+//! instead of surfacing on the first request that reads it.
+
+use std::net::IpAddr;
+
+use rocket::fairing::AdHoc;
+use rocket::figment::Figment;
+use rocket::http::SameSite;
+use rocket::serde::{Deserialize, Serialize};
+use rocket::Request;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::cors::CorsConfig;
+use crate::error::ApiError;
+use crate::quota::QuotaConfig;
+use crate::security_headers::SecurityHeadersConfig;
+use crate::validation::FieldError;
+
+/// Signs tokens when no secret is configured, outside the release profile
+const DEV_JWT_SECRET: &str = "default-secret";
+
+/// Shortest secret accepted in the release profile
+const MIN_SECRET_LEN: usize = 32;
+
+/// The `[app]` section of Rocket.toml
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct AppConfig {
+    pub name: String,
+    pub jwt: JwtConfig,
+    pub cookies: CookieConfig,
+    pub pagination: PaginationConfig,
+    pub features: Features,
+    pub cors: CorsConfig,
+    pub security_headers: SecurityHeadersConfig,
+    pub quotas: QuotaConfig,
+    /// Reverse proxies whose `X-Real-IP` header is believed. Requests from
+    /// anywhere else are attributed to the address they connect from.
+    pub trusted_proxies: Vec<IpAddr>,
+}
+
+impl Default for AppConfig {
+    fn default() -> Self {
+        AppConfig {
+            name: "Todo List API".to_string(),
+            jwt: JwtConfig::default(),
+            cookies: CookieConfig::default(),
+            pagination: PaginationConfig::default(),
+            features: Features::default(),
+            cors: CorsConfig::default(),
+            security_headers: SecurityHeadersConfig::default(),
+            quotas: QuotaConfig::default(),
+            trusted_proxies: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct JwtConfig {
+    /// `JWT_SECRET` in the environment takes precedence
+    pub secret: Option<String>,
+    /// Lifetime of session tokens and the `auth_token` cookie
+    pub ttl_hours: i64,
+}
+
+impl Default for JwtConfig {
+    fn default() -> Self {
+        JwtConfig {
+            secret: None,
+            ttl_hours: 24,
+        }
+    }
+}
+
+impl JwtConfig {
+    pub fn secret(&self) -> &str {
+        self.secret.as_deref().unwrap_or(DEV_JWT_SECRET)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct CookieConfig {
+    /// Only send cookies over HTTPS
+    pub secure: bool,
+    /// `strict`, `lax` or `none`
+    pub same_site: String,
+}
+
+impl Default for CookieConfig {
+    fn default() -> Self {
+        CookieConfig {
+            secure: false,
+            same_site: "lax".to_string(),
+        }
+    }
+}
+
+impl CookieConfig {
+    pub fn same_site(&self) -> SameSite {
+        match self.same_site.to_ascii_lowercase().as_str() {
+            "strict" => SameSite::Strict,
+            "none" => SameSite::None,
+            _ => SameSite::Lax,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(default)]
+pub struct PaginationConfig {
+    /// Page size when a list request has no `limit`
+    pub default_limit: usize,
+    /// Largest `limit` a list request may ask for
+    pub max_limit: usize,
+}
+
+impl Default for PaginationConfig {
+    fn default() -> Self {
+        PaginationConfig {
+            default_limit: 1000,
+            max_limit: 1000,
+        }
+    }
+}
+
+/// `limit` and `offset` of a list request
+#[derive(Debug, Clone, Copy, Default, FromForm, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct Page {
+    /// Items to return, up to the server's `max_limit`
+    pub limit: Option<usize>,
+    /// Items to skip
+    pub offset: Option<usize>,
+}
+
+impl PaginationConfig {
+    /// The requested page of `items`
+    pub fn apply<T>(&self, page: Page, items: Vec<T>) -> Result<Vec<T>, FieldError> {
+        let limit = match page.limit {
+            Some(0) => return Err(FieldError::new("limit", "too_small", "must be at least 1")),
+            Some(limit) if limit > self.max_limit => {
+                return Err(FieldError::new(
+                    "limit",
+                    "too_large",
+                    format!("must be at most {}", self.max_limit),
+                ));
+            }
+            Some(limit) => limit,
+            None => self.default_limit,
+        };
+        Ok(items.into_iter().skip(page.offset.unwrap_or(0)).take(limit).collect())
+    }
+}
+
+/// Parts of the API that can be switched off
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(default)]
+pub struct Features {
+    /// `POST /api/auth/register`
+    pub registration: bool,
+    /// Creating personal access tokens
+    pub api_keys: bool,
+    /// `GET /api/users/me/export`
+    pub data_export: bool,
+}
+
+impl Default for Features {
+    fn default() -> Self {
+        Features {
+            registration: true,
+            api_keys: true,
+            data_export: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Feature {
+    Registration,
+    ApiKeys,
+    DataExport,
+}
+
+impl Features {
+    pub fn require(&self, feature: Feature) -> Result<(), ApiError> {
+        let (enabled, name) = match feature {
+            Feature::Registration => (self.registration, "registration"),
+            Feature::ApiKeys => (self.api_keys, "api_keys"),
+            Feature::DataExport => (self.data_export, "data_export"),
+        };
+        if enabled { Ok(()) } else { Err(ApiError::FeatureDisabled(name)) }
+    }
+}
+
+/// Everything wrong with a configuration, one problem per entry
+#[derive(Debug)]
+pub struct InvalidConfig(pub Vec<String>);
+
+impl std::fmt::Display for InvalidConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid configuration: {}", self.0.join("; "))
+    }
+}
+
+impl std::error::Error for InvalidConfig {}
+
+impl AppConfig {
+    /// Reads and checks the `[app]` section. In the release profile a JWT
+    /// secret of at least 32 bytes is required.
+    pub fn load(figment: &Figment) -> Result<Self, InvalidConfig> {
+        let mut config: AppConfig = figment
+            .extract_inner("app")
+            .or_else(|e| {
+                if e.missing() {
+                    Ok(AppConfig::default())
+                } else {
+                    Err(InvalidConfig(e.into_iter().map(|e| e.to_string()).collect()))
+                }
+            })?;
+        if let Ok(secret) = std::env::var("JWT_SECRET") {
+            config.jwt.secret = Some(secret);
+        }
+        config.validate(figment.profile() == rocket::Config::RELEASE_PROFILE)?;
+        Ok(config)
+    }
+
+    pub fn validate(&self, release: bool) -> Result<(), InvalidConfig> {
+        let mut problems = Vec::new();
+
+        match self.jwt.secret.as_deref() {
+            None if release => problems.push("app.jwt.secret (or JWT_SECRET) must be set".to_string()),
+            Some(secret) if release && secret.len() < MIN_SECRET_LEN => problems.push(format!(
+                "app.jwt.secret must be at least {} bytes, got {}",
+                MIN_SECRET_LEN,
+                secret.len()
+            )),
+            Some("") => problems.push("app.jwt.secret must not be empty".to_string()),
+            _ => {}
+        }
+        if !(1..=720).contains(&self.jwt.ttl_hours) {
+            problems.push(format!("app.jwt.ttl_hours must be between 1 and 720, got {}", self.jwt.ttl_hours));
+        }
+
+        match self.cookies.same_site.to_ascii_lowercase().as_str() {
+            "strict" | "lax" => {}
+            // Browsers drop SameSite=None cookies that are not Secure
+            "none" if !self.cookies.secure => {
+                problems.push("app.cookies.same_site = \"none\" requires app.cookies.secure = true".to_string())
+            }
+            "none" => {}
+            other => problems.push(format!(
+                "app.cookies.same_site must be strict, lax or none, got '{}'",
+                other
+            )),
+        }
+
+        let pagination = &self.pagination;
+        if pagination.default_limit == 0 {
+            problems.push("app.pagination.default_limit must be at least 1".to_string());
+        }
+        if pagination.max_limit < pagination.default_limit {
+            problems.push(format!(
+                "app.pagination.max_limit ({}) must be at least default_limit ({})",
+                pagination.max_limit, pagination.default_limit
+            ));
+        }
+
+        problems.extend(self.cors.problems());
+        problems.extend(self.security_headers.problems());
+        problems.extend(self.quotas.problems());
+
+        if problems.is_empty() { Ok(()) } else { Err(InvalidConfig(problems)) }
+    }
+
+    /// The address a request came from. The client-supplied `X-Real-IP`
+    /// header only counts when the connection is from a trusted proxy.
+    pub fn client_ip(&self, request: &Request<'_>) -> Option<IpAddr> {
+        let remote = request.remote()?.ip().to_canonical();
+        if self.trusted_proxies.contains(&remote) {
+            request.real_ip().map(|ip| ip.to_canonical()).or(Some(remote))
+        } else {
+            Some(remote)
+        }
+    }
+
+    /// What `/config` shows: nothing secret, nothing about the deployment
+    pub fn public(&self) -> PublicConfig {
+        PublicConfig {
+            app_name: self.name.clone(),
+            version: env!("CARGO_PKG_VERSION"),
+            token_ttl_hours: self.jwt.ttl_hours,
+            pagination: self.pagination.clone(),
+            features: self.features.clone(),
+        }
+    }
+}
+
+/// The settings clients may need to know about
+#[derive(Debug, Serialize, ToSchema)]
+pub struct PublicConfig {
+    #[schema(example = "Todo List API")]
+    pub app_name: String,
+    #[schema(example = "0.1.0")]
+    pub version: &'static str,
+    pub token_ttl_hours: i64,
+    pub pagination: PaginationConfig,
+    pub features: Features,
+}
+
+/// [`AppConfig::client_ip`] with the managed settings; without them no
+/// proxy is trusted
+pub fn client_ip(request: &Request<'_>) -> Option<IpAddr> {
+    match request.rocket().state::<AppConfig>() {
+        Some(config) => config.client_ip(request),
+        None => request.remote().map(|remote| remote.ip().to_canonical()),
+    }
+}
+
+pub fn stage() -> AdHoc {
+    AdHoc::try_on_ignite("Application Config", |rocket| async {
+        match AppConfig::load(rocket.figment()) {
+            Ok(config) => {
+                if config.jwt.secret.is_none() {
+                    tracing::warn!(event = "insecure_jwt_secret", "no JWT secret configured; using the development default");
+                }
+                Ok(rocket.manage(config))
+            }
+            Err(InvalidConfig(problems)) => {
+                for problem in &problems {
+                    tracing::error!(event = "invalid_config", problem = %problem);
+                }
+                Err(rocket)
+            }
+        }
+    })
+}
@@ -0,0 +1,214 @@
+//! Cross-origin access for browser clients, configured by `[app.cors]`.
+//!
+//! Allowed origins are exact (`https://app.example.com`), a subdomain
+//! pattern (`https://*.example.com`) or `*`. Preflight requests to any
+//! `/api` route are answered by [`preflight`]; the [`Cors`] fairing adds
+//! the headers to every response, or refuses preflights it cannot allow.
+
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::{Header, Method, Status};
+use rocket::serde::Deserialize;
+use rocket::{Request, Response};
+
+use crate::config::AppConfig;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct CorsConfig {
+    /// Origins such as `https://app.example.com`, patterns such as
+    /// `https://*.example.com`, or `*` for any
+    pub allowed_origins: Vec<String>,
+    pub allowed_methods: Vec<String>,
+    /// Request headers a browser may send; `*` allows any
+    pub allowed_headers: Vec<String>,
+    /// Response headers scripts may read
+    pub expose_headers: Vec<String>,
+    /// Lets browsers send the `auth_token` cookie
+    pub allow_credentials: bool,
+    /// How long browsers may cache a preflight response
+    pub max_age_secs: u64,
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        CorsConfig {
+            allowed_origins: Vec::new(),
+            allowed_methods: ["GET", "POST", "PUT", "PATCH", "DELETE"].map(String::from).to_vec(),
+            allowed_headers: [
+                "Authorization",
+                "Content-Type",
+                "X-CSRF-Token",
+                "X-Request-Id",
+                "Last-Event-ID",
+                "traceparent",
+                "tracestate",
+            ]
+            .map(String::from)
+            .to_vec(),
+            expose_headers: [
+                "X-Request-Id",
+                "traceresponse",
+                "RateLimit-Limit",
+                "RateLimit-Remaining",
+                "RateLimit-Reset",
+                "Retry-After",
+            ]
+            .map(String::from)
+            .to_vec(),
+            allow_credentials: false,
+            max_age_secs: 3600,
+        }
+    }
+}
+
+/// `scheme://host[:port]` with nothing after it
+fn is_origin(origin: &str) -> bool {
+    let Some((scheme, authority)) = origin.split_once("://") else {
+        return false;
+    };
+    matches!(scheme, "http" | "https")
+        && !authority.is_empty()
+        && !authority.contains(['/', '?', '#', '@', ' ', '*'])
+}
+
+/// Whether `origin` is covered by the configured `allowed`, which is an
+/// exact origin or has a `*.` wildcard in place of the leftmost labels
+fn matches(allowed: &str, origin: &str) -> bool {
+    match allowed.split_once("://*.") {
+        Some((scheme, domain)) => origin
+            .strip_prefix(scheme)
+            .and_then(|rest| rest.strip_prefix("://"))
+            .and_then(|host| host.strip_suffix(domain))
+            .and_then(|sub| sub.strip_suffix('.'))
+            .is_some_and(|sub| !sub.is_empty() && is_origin(&format!("{}://{}", scheme, sub))),
+        None => allowed == origin,
+    }
+}
+
+impl CorsConfig {
+    /// One entry per setting that could not work
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+
+        for origin in &self.allowed_origins {
+            let valid = origin == "*" || is_origin(&origin.replacen("://*.", "://wildcard.", 1));
+            if !valid {
+                problems.push(format!(
+                    "app.cors.allowed_origins: '{}' is not an origin like https://app.example.com or https://*.example.com",
+                    origin
+                ));
+            }
+        }
+        // Browsers refuse credentialed responses to a wildcard origin
+        if self.allow_credentials && self.allowed_origins.iter().any(|o| o == "*") {
+            problems.push("app.cors.allow_credentials cannot be combined with allowed_origins = [\"*\"]".to_string());
+        }
+        for method in &self.allowed_methods {
+            if method.parse::<Method>().is_err() {
+                problems.push(format!("app.cors.allowed_methods: '{}' is not an HTTP method", method));
+            }
+        }
+
+        problems
+    }
+
+    pub fn allows_origin(&self, origin: &str) -> bool {
+        let origin = origin.to_ascii_lowercase();
+        self.allowed_origins
+            .iter()
+            .any(|allowed| allowed == "*" || matches(&allowed.to_ascii_lowercase(), &origin))
+    }
+
+    fn allows_method(&self, method: &str) -> bool {
+        self.allowed_methods.iter().any(|m| m.eq_ignore_ascii_case(method))
+    }
+
+    fn allows_headers(&self, requested: &str) -> bool {
+        self.allowed_headers.iter().any(|h| h == "*")
+            || requested
+                .split(',')
+                .map(str::trim)
+                .filter(|h| !h.is_empty())
+                .all(|h| self.allowed_headers.iter().any(|a| a.eq_ignore_ascii_case(h)))
+    }
+
+    fn allow_origin_value(&self, origin: &str) -> String {
+        if !self.allow_credentials && self.allowed_origins.iter().any(|o| o == "*") {
+            "*".to_string()
+        } else {
+            origin.to_string()
+        }
+    }
+}
+
+/// Answers preflight requests; [`Cors`] decides what they are allowed
+#[options("/<_..>")]
+pub fn preflight() -> Status {
+    Status::NoContent
+}
+
+pub struct Cors;
+
+#[rocket::async_trait]
+impl Fairing for Cors {
+    fn info(&self) -> Info {
+        Info {
+            name: "CORS",
+            kind: Kind::Response,
+        }
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let Some(origin) = request.headers().get_one("Origin") else {
+            return;
+        };
+        let Some(config) = request.rocket().state::<AppConfig>() else {
+            return;
+        };
+        let cors = &config.cors;
+        // Whether the response depends on the origin, for caches
+        response.adjoin_header(Header::new("Vary", "Origin"));
+
+        let requested_method = request.headers().get_one("Access-Control-Request-Method");
+        let is_preflight = request.method() == Method::Options && requested_method.is_some();
+
+        if !cors.allows_origin(origin) {
+            if is_preflight {
+                tracing::debug!(event = "cors_refused", origin, "origin not allowed");
+                response.set_status(Status::Forbidden);
+            }
+            return;
+        }
+
+        response.set_header(Header::new("Access-Control-Allow-Origin", cors.allow_origin_value(origin)));
+        if cors.allow_credentials {
+            response.set_header(Header::new("Access-Control-Allow-Credentials", "true"));
+        }
+
+        if let Some(method) = requested_method.filter(|_| is_preflight) {
+            let requested_headers = request.headers().get_one("Access-Control-Request-Headers").unwrap_or("");
+            if !cors.allows_method(method) || !cors.allows_headers(requested_headers) {
+                tracing::debug!(event = "cors_refused", origin, method, headers = requested_headers);
+                response.remove_header("Access-Control-Allow-Origin");
+                response.remove_header("Access-Control-Allow-Credentials");
+                response.set_status(Status::Forbidden);
+                return;
+            }
+            response.adjoin_header(Header::new("Vary", "Access-Control-Request-Method"));
+            response.adjoin_header(Header::new("Vary", "Access-Control-Request-Headers"));
+            response.set_header(Header::new("Access-Control-Allow-Methods", cors.allowed_methods.join(", ")));
+            // A `*` list is echoed, since browsers ignore `*` on credentialed requests
+            let allowed_headers = if cors.allowed_headers.iter().any(|h| h == "*") {
+                requested_headers.to_string()
+            } else {
+                cors.allowed_headers.join(", ")
+            };
+            if !allowed_headers.is_empty() {
+                response.set_header(Header::new("Access-Control-Allow-Headers", allowed_headers));
+            }
+            response.set_header(Header::new("Access-Control-Max-Age", cors.max_age_secs.to_string()));
+        } else if !cors.expose_headers.is_empty() {
+            response.set_header(Header::new("Access-Control-Expose-Headers", cors.expose_headers.join(", ")));
+        }
+    }
+}
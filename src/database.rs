@@ -1,27 +1,389 @@
 use rocket::fairing::{self, AdHoc};
+use rocket::figment::Figment;
+use rocket::serde::{Deserialize, Serialize};
 use rocket::{Build, Rocket};
 use rocket_db_pools::{Database, sqlx};
+use sqlx::ConnectOptions;
+use sqlx::migrate::{Migrate, MigrateError, Migrator};
+use utoipa::ToSchema;
 
-#[derive(Database)]
+// The backend is chosen at compile time: SQLite by default, Postgres with
+// the `postgres` feature. Queries are written with `$N` placeholders and
+// `RETURNING`, which both understand, and stick to types both map the same
+// way, so handlers share one copy of the SQL. What does differ per backend:
+// the pool type, the migrations (`migrations/sqlite`, `migrations/postgres`),
+// the compile-time query data (`.sqlx`, `.sqlx-postgres`, see build.rs),
+// connection setup in `connect` (pragmas are SQLite only), and `todoctl
+// backup`, which only knows SQLite.
+
+#[cfg(not(feature = "postgres"))]
+#[derive(Database, Clone)]
 #[database("sqlite_db")]
 pub struct Db(sqlx::SqlitePool);
 
-pub type DbResult<T, E = rocket::response::Debug<sqlx::Error>> = std::result::Result<T, E>;
+#[cfg(feature = "postgres")]
+#[derive(Database, Clone)]
+#[database("postgres_db")]
+pub struct Db(sqlx::PgPool);
 
-pub async fn run_migrations(rocket: Rocket<Build>) -> fairing::Result {
-    match Db::fetch(&rocket) {
-        Some(db) => match sqlx::migrate!("./migrations").run(&**db).await {
-            Ok(_) => {
-                println!("Database migrations completed successfully");
-                Ok(rocket)
+/// Key of the pool under `databases` in Rocket.toml
+#[cfg(not(feature = "postgres"))]
+pub const DB_NAME: &str = "sqlite_db";
+#[cfg(feature = "postgres")]
+pub const DB_NAME: &str = "postgres_db";
+
+/// The backend as OpenTelemetry names it, for `db.system` span attributes
+#[cfg(not(feature = "postgres"))]
+pub const DB_SYSTEM: &str = "sqlite";
+#[cfg(feature = "postgres")]
+pub const DB_SYSTEM: &str = "postgresql";
+
+#[cfg(not(feature = "postgres"))]
+pub type DbPool = sqlx::SqlitePool;
+#[cfg(feature = "postgres")]
+pub type DbPool = sqlx::PgPool;
+
+#[cfg(not(feature = "postgres"))]
+pub type DbConnection = sqlx::SqliteConnection;
+#[cfg(feature = "postgres")]
+pub type DbConnection = sqlx::PgConnection;
+
+/// The single row of an `INSERT ... RETURNING`, fetched with `fetch_all`.
+/// SQLite only commits once the statement has run to the end, so with
+/// `fetch_one` another connection might not see the new row yet.
+pub fn returned<T>(rows: Vec<T>) -> Result<T, sqlx::Error> {
+    rows.into_iter().next().ok_or(sqlx::Error::RowNotFound)
+}
+
+pub fn migrator() -> Migrator {
+    #[cfg(not(feature = "postgres"))]
+    return sqlx::migrate!("./migrations/sqlite");
+    #[cfg(feature = "postgres")]
+    return sqlx::migrate!("./migrations/postgres");
+}
+
+/// The `databases.<DB_NAME>` section of Rocket.toml
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct PoolConfig {
+    pub url: String,
+    /// Connections kept open even when idle
+    pub min_connections: u32,
+    /// Defaults to four per Rocket worker
+    pub max_connections: Option<u32>,
+    /// Seconds a request waits for a free connection before failing
+    #[serde(alias = "connect_timeout")]
+    pub acquire_timeout: u64,
+    /// Seconds before an idle connection above `min_connections` is closed
+    pub idle_timeout: Option<u64>,
+    #[cfg(not(feature = "postgres"))]
+    #[serde(flatten)]
+    pub sqlite: SqlitePragmas,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        PoolConfig {
+            url: String::new(),
+            min_connections: 0,
+            max_connections: None,
+            acquire_timeout: 5,
+            idle_timeout: None,
+            #[cfg(not(feature = "postgres"))]
+            sqlite: SqlitePragmas::default(),
+        }
+    }
+}
+
+/// Set on every SQLite connection the pool opens
+#[cfg(not(feature = "postgres"))]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SqlitePragmas {
+    /// `wal` lets readers carry on while one connection writes
+    pub journal_mode: String,
+    /// `normal` is safe with WAL and avoids a sync per commit
+    pub synchronous: String,
+    /// Milliseconds a writer waits for the lock before `database is locked`
+    pub busy_timeout_ms: u64,
+    pub foreign_keys: bool,
+    /// Page cache per connection, in pages, or in KiB when negative
+    pub cache_size: i64,
+}
+
+#[cfg(not(feature = "postgres"))]
+impl Default for SqlitePragmas {
+    fn default() -> Self {
+        SqlitePragmas {
+            journal_mode: "wal".to_string(),
+            synchronous: "normal".to_string(),
+            busy_timeout_ms: 5000,
+            foreign_keys: true,
+            cache_size: -16000,
+        }
+    }
+}
+
+fn config_error(e: impl std::error::Error + Send + Sync + 'static) -> sqlx::Error {
+    sqlx::Error::Configuration(Box::new(e))
+}
+
+/// Opens a pool as configured under `databases.<DB_NAME>`. The server and
+/// the tools that run without a Rocket instance both open theirs here. A
+/// missing SQLite file is created.
+pub async fn connect(figment: &Figment) -> Result<DbPool, sqlx::Error> {
+    use std::time::Duration;
+
+    let config: PoolConfig = figment
+        .extract_inner(&format!("databases.{}", DB_NAME))
+        .map_err(config_error)?;
+    if config.url.is_empty() {
+        return Err(sqlx::Error::Configuration(
+            format!("databases.{}.url is not set", DB_NAME).into(),
+        ));
+    }
+    let workers: usize = figment
+        .extract_inner(rocket::Config::WORKERS)
+        .unwrap_or_else(|_| rocket::Config::default().workers);
+
+    #[cfg(not(feature = "postgres"))]
+    let options = {
+        use std::str::FromStr;
+        use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqliteSynchronous};
+
+        let pragmas = &config.sqlite;
+        SqliteConnectOptions::from_str(&config.url)?
+            .create_if_missing(true)
+            .journal_mode(SqliteJournalMode::from_str(&pragmas.journal_mode)?)
+            .synchronous(SqliteSynchronous::from_str(&pragmas.synchronous)?)
+            .busy_timeout(Duration::from_millis(pragmas.busy_timeout_ms))
+            .foreign_keys(pragmas.foreign_keys)
+            .pragma("cache_size", pragmas.cache_size.to_string())
+    };
+    #[cfg(feature = "postgres")]
+    let options = {
+        use std::str::FromStr;
+
+        sqlx::postgres::PgConnectOptions::from_str(&config.url)?
+    };
+
+    sqlx::pool::PoolOptions::new()
+        .min_connections(config.min_connections)
+        .max_connections(config.max_connections.unwrap_or(workers as u32 * 4))
+        .acquire_timeout(Duration::from_secs(config.acquire_timeout))
+        .idle_timeout(config.idle_timeout.map(Duration::from_secs))
+        .connect_with(options.disable_statement_logging())
+        .await
+}
+
+/// The file behind the pool's main database, if it has one
+#[cfg(not(feature = "postgres"))]
+pub async fn database_file(pool: &DbPool) -> Option<std::path::PathBuf> {
+    let file: String = sqlx::query_scalar("SELECT file FROM pragma_database_list WHERE name = 'main'")
+        .fetch_one(pool)
+        .await
+        .ok()?;
+    // Empty for in-memory databases
+    (!file.is_empty()).then(|| file.into())
+}
+
+/// Opens the pool in place of `Db::init()`, which cannot set pragmas, and
+/// closes it at shutdown
+fn pool_stage() -> AdHoc {
+    AdHoc::try_on_ignite("Database Pool", |rocket| async {
+        match connect(rocket.figment()).await {
+            Ok(pool) => {
+                tracing::info!(
+                    event = "database_pool_opened",
+                    max_connections = pool.options().get_max_connections(),
+                    min_connections = pool.options().get_min_connections()
+                );
+                Ok(rocket
+                    .manage(Db(pool))
+                    .attach(AdHoc::on_shutdown("Database Pool Close", |rocket| {
+                        Box::pin(async move {
+                            if let Some(db) = rocket.state::<Db>() {
+                                db.close().await;
+                            }
+                        })
+                    })))
             }
             Err(e) => {
-                eprintln!("Failed to run database migrations: {}", e);
+                tracing::error!(event = "database_pool_failed", error = %e, "could not open the database pool");
                 Err(rocket)
             }
+        }
+    })
+}
+
+/// One known migration and whether it has been applied to the database
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct MigrationStatus {
+    pub version: i64,
+    pub description: String,
+    pub applied: bool,
+    /// The file changed after it was applied
+    pub checksum_mismatch: bool,
+    /// A down script exists, so `todoctl migrate down` can revert it
+    pub reversible: bool,
+}
+
+pub async fn migration_status(pool: &DbPool) -> Result<Vec<MigrationStatus>, MigrateError> {
+    let mut conn = pool.acquire().await?;
+    conn.ensure_migrations_table().await?;
+    let applied = conn.list_applied_migrations().await?;
+
+    let migrator = migrator();
+    Ok(migrator
+        .iter()
+        .filter(|m| !m.migration_type.is_down_migration())
+        .map(|m| {
+            let applied = applied.iter().find(|a| a.version == m.version);
+            MigrationStatus {
+                version: m.version,
+                description: m.description.to_string(),
+                applied: applied.is_some(),
+                checksum_mismatch: applied.is_some_and(|a| a.checksum != m.checksum),
+                reversible: migrator
+                    .iter()
+                    .any(|down| down.version == m.version && down.migration_type.is_down_migration()),
+            }
+        })
+        .collect())
+}
+
+/// The gist of [`migration_status`], as reported by `/ready`
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct MigrationSummary {
+    pub applied: usize,
+    /// Versions not yet applied
+    pub pending: Vec<i64>,
+    /// Versions whose file changed after they were applied
+    pub checksum_mismatches: Vec<i64>,
+}
+
+impl MigrationSummary {
+    pub fn of(status: &[MigrationStatus]) -> Self {
+        MigrationSummary {
+            applied: status.iter().filter(|m| m.applied).count(),
+            pending: status.iter().filter(|m| !m.applied).map(|m| m.version).collect(),
+            checksum_mismatches: status
+                .iter()
+                .filter(|m| m.checksum_mismatch)
+                .map(|m| m.version)
+                .collect(),
+        }
+    }
+
+    /// The schema is exactly the one this build expects
+    pub fn is_current(&self) -> bool {
+        self.pending.is_empty() && self.checksum_mismatches.is_empty()
+    }
+}
+
+/// The `[migrations]` section of Rocket.toml
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct MigrationConfig {
+    /// Apply pending migrations at startup. When off, the server refuses to
+    /// start until they have been applied with `todoctl migrate up`.
+    pub auto: bool,
+}
+
+impl Default for MigrationConfig {
+    fn default() -> Self {
+        MigrationConfig { auto: true }
+    }
+}
+
+/// The version a failed `Migrator::run` stopped at: named by the error, or
+/// else the first migration that is still pending
+async fn failed_version(pool: &DbPool, error: &MigrateError) -> Option<i64> {
+    match error {
+        MigrateError::VersionMissing(v) | MigrateError::VersionMismatch(v) | MigrateError::Dirty(v) => {
+            Some(*v)
+        }
+        _ => migration_status(pool)
+            .await
+            .ok()?
+            .into_iter()
+            .find(|m| !m.applied)
+            .map(|m| m.version),
+    }
+}
+
+async fn migrate(pool: &DbPool, config: &MigrationConfig) -> Result<(), ()> {
+    let before = migration_status(pool).await.map_err(|e| {
+        tracing::error!(event = "migration_status_failed", error = %e, "could not read applied migrations");
+    })?;
+    let summary = MigrationSummary::of(&before);
+
+    if !summary.checksum_mismatches.is_empty() {
+        for m in before.iter().filter(|m| m.checksum_mismatch) {
+            tracing::error!(
+                event = "migration_checksum_mismatch",
+                version = m.version,
+                description = %m.description,
+                "applied migration differs from the one in this build"
+            );
+        }
+        return Err(());
+    }
+    if summary.pending.is_empty() {
+        tracing::info!(event = "migrations_current", applied = summary.applied, "database schema is up to date");
+        return Ok(());
+    }
+    if !config.auto {
+        for m in before.iter().filter(|m| !m.applied) {
+            tracing::error!(
+                event = "migration_pending",
+                version = m.version,
+                description = %m.description,
+                "refusing to start with pending migrations; run `todoctl migrate up`"
+            );
+        }
+        return Err(());
+    }
+
+    if let Err(e) = migrator().run(pool).await {
+        let version = failed_version(pool, &e).await;
+        let description = before
+            .iter()
+            .find(|m| Some(m.version) == version)
+            .map(|m| m.description.as_str());
+        tracing::error!(
+            event = "migration_failed",
+            version,
+            description,
+            error = %e,
+            "database migration failed"
+        );
+        return Err(());
+    }
+    for m in before.iter().filter(|m| !m.applied) {
+        tracing::info!(event = "migration_applied", version = m.version, description = %m.description);
+    }
+    Ok(())
+}
+
+pub async fn run_migrations(rocket: Rocket<Build>) -> fairing::Result {
+    // A section that does not parse must not fall back to `auto = true`
+    let config: MigrationConfig = match rocket.figment().extract_inner("migrations") {
+        Ok(config) => config,
+        Err(e) if e.missing() => MigrationConfig::default(),
+        Err(e) => {
+            tracing::error!(event = "invalid_config", problem = %e);
+            return Err(rocket);
+        }
+    };
+
+    match Db::fetch(&rocket) {
+        Some(db) => match migrate(db, &config).await {
+            Ok(()) => Ok(rocket),
+            Err(()) => Err(rocket),
         },
         None => {
-            eprintln!("Database connection not found");
+            tracing::error!(event = "migration_failed", "database pool not found");
             Err(rocket)
         }
     }
@@ -30,7 +392,8 @@ pub async fn run_migrations(rocket: Rocket<Build>) -> fairing::Result {
 pub fn stage() -> AdHoc {
     AdHoc::on_ignite("Database Stage", |rocket| async {
         rocket
-            .attach(Db::init())
+            .attach(pool_stage())
             .attach(AdHoc::try_on_ignite("Database Migrations", run_migrations))
+            .attach(crate::repository::stage())
     })
 }
@@ -0,0 +1,324 @@
+use std::io::Cursor;
+
+use rocket::http::{ContentType, Status};
+use rocket::response::{self, Responder};
+use rocket::{Catcher, Request, Response};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::auth::jwt::JwtError;
+use crate::quota::Quota;
+use crate::rate_limit;
+use crate::validation::{FieldError, ValidationFailure};
+
+pub type ApiResult<T> = Result<T, ApiError>;
+
+/// Every error an endpoint can return. Rendered as an RFC 7807
+/// `application/problem+json` document with a stable `code`.
+#[derive(Debug)]
+pub enum ApiError {
+    BadRequest(String),
+    Unauthorized(String),
+    InvalidCredentials,
+    MissingToken,
+    InvalidToken,
+    TokenExpired,
+    Forbidden(String),
+    /// API keys cannot be used for account management
+    SessionRequired,
+    /// The API key lacks the named scope
+    InsufficientScope(String),
+    NotFound(String),
+    Conflict(String),
+    Gone(String),
+    UnprocessableEntity(String),
+    /// One or more fields failed validation
+    Validation(Vec<FieldError>),
+    /// An upstream service (e.g. an identity provider) failed
+    BadGateway(String),
+    /// Temporarily unavailable, e.g. while a backup is being restored
+    ServiceUnavailable(String),
+    /// Switched off in `[app.features]`
+    FeatureDisabled(&'static str),
+    /// A cookie-authenticated write without the session's CSRF token
+    CsrfTokenInvalid,
+    /// The caller's bucket is empty; retry after this many seconds
+    RateLimited(u64),
+    /// The request would take the user past a quota with this limit
+    QuotaExceeded(Quota, i64),
+    /// Logged in full, never shown to the client
+    Database(sqlx::Error),
+    /// Logged in full, never shown to the client
+    Internal(String),
+}
+
+/// Body of every error response
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ProblemDetails {
+    /// URI reference identifying the problem type
+    #[serde(rename = "type")]
+    #[schema(example = "/problems/not_found")]
+    pub problem_type: String,
+    #[schema(example = "Not Found")]
+    pub title: String,
+    #[schema(example = 404)]
+    pub status: u16,
+    #[schema(example = "Todo not found")]
+    pub detail: String,
+    /// Stable, machine-readable error code
+    #[schema(example = "not_found")]
+    pub code: String,
+    /// The request path that produced the error
+    #[schema(example = "/api/todos/42")]
+    pub instance: String,
+    /// Matches the `x-request-id` response header and the server logs
+    pub request_id: Option<String>,
+    /// Field-level details for `validation_failed`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<FieldError>,
+}
+
+impl ApiError {
+    pub fn status(&self) -> Status {
+        match self {
+            ApiError::BadRequest(_) => Status::BadRequest,
+            ApiError::Unauthorized(_)
+            | ApiError::InvalidCredentials
+            | ApiError::MissingToken
+            | ApiError::InvalidToken
+            | ApiError::TokenExpired => Status::Unauthorized,
+            ApiError::Forbidden(_)
+            | ApiError::SessionRequired
+            | ApiError::InsufficientScope(_)
+            | ApiError::FeatureDisabled(_)
+            | ApiError::CsrfTokenInvalid
+            | ApiError::QuotaExceeded(..) => Status::Forbidden,
+            ApiError::NotFound(_) => Status::NotFound,
+            ApiError::Conflict(_) => Status::Conflict,
+            ApiError::Gone(_) => Status::Gone,
+            ApiError::UnprocessableEntity(_) | ApiError::Validation(_) => {
+                Status::UnprocessableEntity
+            }
+            ApiError::RateLimited(_) => Status::TooManyRequests,
+            ApiError::BadGateway(_) => Status::BadGateway,
+            ApiError::ServiceUnavailable(_) => Status::ServiceUnavailable,
+            ApiError::Database(_) | ApiError::Internal(_) => Status::InternalServerError,
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::BadRequest(_) => "bad_request",
+            ApiError::Unauthorized(_) => "unauthorized",
+            ApiError::InvalidCredentials => "invalid_credentials",
+            ApiError::MissingToken => "missing_token",
+            ApiError::InvalidToken => "invalid_token",
+            ApiError::TokenExpired => "token_expired",
+            ApiError::Forbidden(_) => "forbidden",
+            ApiError::SessionRequired => "session_required",
+            ApiError::InsufficientScope(_) => "insufficient_scope",
+            ApiError::NotFound(_) => "not_found",
+            ApiError::Conflict(_) => "conflict",
+            ApiError::Gone(_) => "gone",
+            ApiError::UnprocessableEntity(_) => "unprocessable_entity",
+            ApiError::Validation(_) => "validation_failed",
+            ApiError::BadGateway(_) => "upstream_error",
+            ApiError::ServiceUnavailable(_) => "service_unavailable",
+            ApiError::FeatureDisabled(_) => "feature_disabled",
+            ApiError::CsrfTokenInvalid => "csrf_token_invalid",
+            ApiError::RateLimited(_) => "rate_limited",
+            ApiError::QuotaExceeded(Quota::Todos, _) => "todo_quota_exceeded",
+            ApiError::QuotaExceeded(Quota::DescriptionLength, _) => "description_quota_exceeded",
+            ApiError::QuotaExceeded(Quota::Storage, _) => "storage_quota_exceeded",
+            ApiError::Database(_) => "database_error",
+            ApiError::Internal(_) => "internal_error",
+        }
+    }
+
+    pub fn detail(&self) -> String {
+        match self {
+            ApiError::BadRequest(detail)
+            | ApiError::Unauthorized(detail)
+            | ApiError::Forbidden(detail)
+            | ApiError::NotFound(detail)
+            | ApiError::Conflict(detail)
+            | ApiError::Gone(detail)
+            | ApiError::UnprocessableEntity(detail)
+            | ApiError::BadGateway(detail)
+            | ApiError::ServiceUnavailable(detail) => detail.clone(),
+            ApiError::Validation(errors) => match errors.as_slice() {
+                [error] => format!("{} {}", error.field, error.message),
+                errors => format!("{} fields are invalid", errors.len()),
+            },
+            ApiError::InvalidCredentials => "Invalid email or password".to_string(),
+            ApiError::MissingToken => "Authentication is required".to_string(),
+            ApiError::InvalidToken => "The token is invalid or has been revoked".to_string(),
+            ApiError::TokenExpired => "The token has expired".to_string(),
+            ApiError::SessionRequired => "API keys cannot be used for this endpoint".to_string(),
+            ApiError::InsufficientScope(scope) => {
+                format!("This API key is missing the '{}' scope", scope)
+            }
+            ApiError::FeatureDisabled(feature) => {
+                format!("The '{}' feature is disabled on this server", feature)
+            }
+            ApiError::CsrfTokenInvalid => {
+                "Requests authenticated by cookie must send the csrf_token cookie's value in X-CSRF-Token".to_string()
+            }
+            ApiError::RateLimited(seconds) => {
+                format!("Too many requests; retry in {} seconds", seconds)
+            }
+            ApiError::QuotaExceeded(Quota::Todos, limit) => {
+                format!("You have reached your limit of {} todos", limit)
+            }
+            ApiError::QuotaExceeded(Quota::DescriptionLength, limit) => {
+                format!("Descriptions may be at most {} characters", limit)
+            }
+            ApiError::QuotaExceeded(Quota::Storage, limit) => {
+                format!("This would take your todos past your storage limit of {} bytes", limit)
+            }
+            ApiError::Database(_) | ApiError::Internal(_) => {
+                "An unexpected error occurred".to_string()
+            }
+        }
+    }
+
+    pub fn to_problem(&self, request: &Request<'_>) -> ProblemDetails {
+        let status = self.status();
+
+        ProblemDetails {
+            problem_type: format!("/problems/{}", self.code()),
+            title: status.reason_lossy().to_string(),
+            status: status.code,
+            detail: self.detail(),
+            code: self.code().to_string(),
+            instance: request.uri().path().to_string(),
+            request_id: request.headers().get_one("x-request-id").map(str::to_string),
+            errors: match self {
+                ApiError::Validation(errors) => errors.clone(),
+                _ => Vec::new(),
+            },
+        }
+    }
+}
+
+impl From<sqlx::Error> for ApiError {
+    fn from(e: sqlx::Error) -> Self {
+        match e {
+            sqlx::Error::RowNotFound => ApiError::NotFound("Resource not found".to_string()),
+            e => ApiError::Database(e),
+        }
+    }
+}
+
+impl From<FieldError> for ApiError {
+    fn from(e: FieldError) -> Self {
+        ApiError::Validation(vec![e])
+    }
+}
+
+impl From<JwtError> for ApiError {
+    fn from(e: JwtError) -> Self {
+        match e {
+            JwtError::MissingToken => ApiError::MissingToken,
+            JwtError::InvalidToken => ApiError::InvalidToken,
+            JwtError::ExpiredToken => ApiError::TokenExpired,
+            JwtError::DatabaseUnavailable => {
+                ApiError::Internal("Database unavailable during authentication".to_string())
+            }
+            JwtError::Misconfigured => {
+                ApiError::Internal("Token settings are not loaded".to_string())
+            }
+            JwtError::CsrfMismatch => ApiError::CsrfTokenInvalid,
+        }
+    }
+}
+
+impl<'r> Responder<'r, 'static> for ApiError {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        let problem = self.to_problem(request);
+
+        match &self {
+            ApiError::Database(e) => tracing::error!(
+                request_id = problem.request_id.as_deref().unwrap_or_default(),
+                error = %e,
+                "database error"
+            ),
+            ApiError::Internal(e) => tracing::error!(
+                request_id = problem.request_id.as_deref().unwrap_or_default(),
+                error = %e,
+                "internal error"
+            ),
+            _ => {}
+        }
+
+        let body = serde_json::to_string(&problem).map_err(|_| Status::InternalServerError)?;
+
+        Response::build()
+            .status(self.status())
+            .header(ContentType::new("application", "problem+json"))
+            .sized_body(body.len(), Cursor::new(body))
+            .ok()
+    }
+}
+
+#[catch(400)]
+fn bad_request(_: &Request<'_>) -> ApiError {
+    ApiError::BadRequest("The request could not be understood".to_string())
+}
+
+/// Guards record why authentication failed, so the response can say whether
+/// the token was missing, invalid or expired
+#[catch(401)]
+fn unauthorized(request: &Request<'_>) -> ApiError {
+    match request.local_cache(|| None::<JwtError>) {
+        Some(JwtError::ExpiredToken) => ApiError::TokenExpired,
+        Some(JwtError::InvalidToken) => ApiError::InvalidToken,
+        _ => ApiError::MissingToken,
+    }
+}
+
+#[catch(403)]
+fn forbidden(request: &Request<'_>) -> ApiError {
+    match request.local_cache(|| None::<JwtError>) {
+        Some(JwtError::CsrfMismatch) => ApiError::CsrfTokenInvalid,
+        _ => ApiError::Forbidden("Access denied".to_string()),
+    }
+}
+
+#[catch(404)]
+fn not_found(request: &Request<'_>) -> ApiError {
+    ApiError::NotFound(format!("No resource found at '{}'", request.uri().path()))
+}
+
+/// `Validated` bodies leave their field errors behind for this catcher
+#[catch(422)]
+fn unprocessable_entity(request: &Request<'_>) -> ApiError {
+    match request.local_cache(|| None::<ValidationFailure>) {
+        Some(ValidationFailure(errors)) => ApiError::Validation(errors.clone()),
+        None => ApiError::UnprocessableEntity(
+            "The request body is malformed or has invalid fields".to_string(),
+        ),
+    }
+}
+
+#[catch(429)]
+fn too_many_requests(request: &Request<'_>) -> ApiError {
+    ApiError::RateLimited(rate_limit::decision(request).map_or(1, |d| d.retry_after_secs))
+}
+
+#[catch(500)]
+fn internal_error(_: &Request<'_>) -> ApiError {
+    ApiError::Internal("Unhandled server error".to_string())
+}
+
+pub fn catchers() -> Vec<Catcher> {
+    catchers![
+        bad_request,
+        unauthorized,
+        forbidden,
+        not_found,
+        unprocessable_entity,
+        too_many_requests,
+        internal_error
+    ]
+}
@@ -0,0 +1,477 @@
+//! The todo change feed. Every todo created, updated or deleted through the
+//! repository is appended to the `todo_events` log and broadcast to its
+//! owner's open `GET /api/events` streams. A client reconnecting with
+//! `Last-Event-ID` is first sent what it missed from the log; one resuming
+//! from further back than the log reaches gets a `resync` event instead and
+//! should reload its todos.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use rocket::fairing::AdHoc;
+use rocket::request::{FromRequest, Outcome};
+use rocket::response::stream::{Event, EventStream};
+use rocket::serde::{Deserialize, Serialize};
+use rocket::tokio::sync::broadcast::{self, error::RecvError};
+use rocket::{Request, Shutdown};
+use utoipa::ToSchema;
+
+use crate::database::{returned, DbPool};
+use crate::health::Health;
+use crate::models::{Status, Todo, TodoResponse, UpdateTodoRequest};
+use crate::repository::{NewTodo, RepositoryResult, TodoFilter, TodoRepository, Usage};
+
+/// Live events buffered for each stream; a stream that falls further
+/// behind catches up from the log
+const CHANNEL_CAPACITY: usize = 1024;
+
+/// Most events replayed on resume; a client further behind must resync
+const MAX_REPLAY: usize = 1000;
+
+/// The `[events]` section of Rocket.toml
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct EventsConfig {
+    /// Comments are sent this often so proxies keep idle streams open
+    pub heartbeat_secs: u64,
+    /// How long events stay in the log for `Last-Event-ID` resumes
+    pub retention_hours: i64,
+}
+
+impl Default for EventsConfig {
+    fn default() -> Self {
+        EventsConfig {
+            heartbeat_secs: 15,
+            retention_hours: 72,
+        }
+    }
+}
+
+impl EventsConfig {
+    fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.heartbeat_secs == 0 {
+            problems.push("events.heartbeat_secs must be at least 1".to_string());
+        }
+        if self.retention_hours < 1 {
+            problems.push(format!("events.retention_hours must be at least 1, got {}", self.retention_hours));
+        }
+        problems
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type, ToSchema)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "text", rename_all = "snake_case")]
+pub enum EventKind {
+    Created,
+    Updated,
+    Deleted,
+}
+
+impl EventKind {
+    /// The SSE `event` field
+    pub fn event_type(&self) -> &'static str {
+        match self {
+            EventKind::Created => "todo.created",
+            EventKind::Updated => "todo.updated",
+            EventKind::Deleted => "todo.deleted",
+        }
+    }
+}
+
+/// One change to a todo, the `data` of an SSE event
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct TodoEvent {
+    /// Increases with every event; also the SSE `id`
+    pub id: i64,
+    #[serde(skip)]
+    pub user_id: i64,
+    pub kind: EventKind,
+    pub todo_id: i64,
+    /// The todo after the change; absent for deletions
+    pub todo: Option<TodoResponse>,
+    /// The status before an update that changed it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub previous_status: Option<Status>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl TodoEvent {
+    fn to_sse(&self) -> Event {
+        Event::json(self).id(self.id.to_string()).event(self.kind.event_type())
+    }
+}
+
+struct EventRow {
+    id: i64,
+    user_id: i64,
+    kind: EventKind,
+    todo_id: i64,
+    todo: Option<String>,
+    previous_status: Option<Status>,
+    created_at: NaiveDateTime,
+}
+
+impl From<EventRow> for TodoEvent {
+    fn from(row: EventRow) -> Self {
+        TodoEvent {
+            id: row.id,
+            user_id: row.user_id,
+            kind: row.kind,
+            todo_id: row.todo_id,
+            todo: row.todo.and_then(|json| serde_json::from_str(&json).ok()),
+            previous_status: row.previous_status,
+            created_at: DateTime::from_naive_utc_and_offset(row.created_at, Utc),
+        }
+    }
+}
+
+/// What a resuming stream is sent before live events
+enum CatchUp {
+    Events(Vec<TodoEvent>),
+    /// Events may be missing; carries the newest id to continue from
+    Resync(i64),
+}
+
+/// The persisted log and the broadcast channel every stream listens on.
+/// Each stream receives every user's events and keeps its own.
+pub struct EventLog {
+    pool: DbPool,
+    sender: broadcast::Sender<TodoEvent>,
+}
+
+impl EventLog {
+    pub fn new(pool: DbPool) -> Self {
+        EventLog {
+            pool,
+            sender: broadcast::channel(CHANNEL_CAPACITY).0,
+        }
+    }
+
+    /// Writes the event to the log, then broadcasts it
+    pub async fn append(
+        &self,
+        user_id: i64,
+        kind: EventKind,
+        todo_id: i64,
+        todo: Option<&Todo>,
+        previous_status: Option<Status>,
+    ) -> Result<TodoEvent, sqlx::Error> {
+        let todo = todo.cloned().map(TodoResponse::from);
+        let json = todo.as_ref().map(|todo| serde_json::to_string(todo).expect("todos serialize"));
+        let created_at = Utc::now().naive_utc();
+
+        let id = returned(
+            sqlx::query_scalar!(
+                r#"INSERT INTO todo_events (user_id, kind, todo_id, todo, previous_status, created_at) VALUES ($1, $2, $3, $4, $5, $6) RETURNING id AS "id!""#,
+                user_id,
+                kind as _,
+                todo_id,
+                json,
+                previous_status as _,
+                created_at
+            )
+            .fetch_all(&self.pool)
+            .await?,
+        )?;
+
+        let event = TodoEvent {
+            id,
+            user_id,
+            kind,
+            todo_id,
+            todo,
+            previous_status,
+            created_at: DateTime::from_naive_utc_and_offset(created_at, Utc),
+        };
+        // Nobody listening is fine
+        let _ = self.sender.send(event.clone());
+        Ok(event)
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<TodoEvent> {
+        self.sender.subscribe()
+    }
+
+    /// Up to `limit` of the user's events after `after`, oldest first
+    pub async fn since(&self, user_id: i64, after: i64, limit: usize) -> Result<Vec<TodoEvent>, sqlx::Error> {
+        let limit = limit as i64;
+        let rows = sqlx::query_as!(
+            EventRow,
+            r#"SELECT id AS "id!", user_id, kind AS "kind: EventKind", todo_id, todo,
+                previous_status AS "previous_status: Status", created_at
+            FROM todo_events WHERE user_id = $1 AND id > $2 ORDER BY id LIMIT $3"#,
+            user_id,
+            after,
+            limit
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.into_iter().map(TodoEvent::from).collect())
+    }
+
+    /// Up to `limit` events of every user after `after`, oldest first
+    pub async fn all_since(&self, after: i64, limit: usize) -> Result<Vec<TodoEvent>, sqlx::Error> {
+        let limit = limit as i64;
+        let rows = sqlx::query_as!(
+            EventRow,
+            r#"SELECT id AS "id!", user_id, kind AS "kind: EventKind", todo_id, todo,
+                previous_status AS "previous_status: Status", created_at
+            FROM todo_events WHERE id > $1 ORDER BY id LIMIT $2"#,
+            after,
+            limit
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.into_iter().map(TodoEvent::from).collect())
+    }
+
+    /// The id of the newest event, 0 when there is none
+    pub async fn latest_id(&self) -> Result<i64, sqlx::Error> {
+        let latest = sqlx::query_scalar!(r#"SELECT MAX(id) AS "id?: i64" FROM todo_events"#)
+            .fetch_one(&self.pool)
+            .await?;
+
+        Ok(latest.unwrap_or(0))
+    }
+
+    async fn catch_up(&self, user_id: i64, after: i64) -> Result<CatchUp, sqlx::Error> {
+        let oldest = sqlx::query_scalar!(r#"SELECT MIN(id) AS "id?: i64" FROM todo_events"#)
+            .fetch_one(&self.pool)
+            .await?;
+        // Ids only grow, so a gap before the oldest event means pruned ones
+        let pruned = match oldest {
+            Some(oldest) => oldest > after + 1,
+            None => after > 0,
+        };
+
+        let events = self.since(user_id, after, MAX_REPLAY + 1).await?;
+        if pruned || events.len() > MAX_REPLAY {
+            return Ok(CatchUp::Resync(self.latest_id().await?));
+        }
+        Ok(CatchUp::Events(events))
+    }
+
+    /// Deletes events older than `retention_hours`
+    pub async fn purge(&self, retention_hours: i64) -> Result<u64, sqlx::Error> {
+        let cutoff = (Utc::now() - chrono::Duration::hours(retention_hours)).naive_utc();
+        let result = sqlx::query!("DELETE FROM todo_events WHERE created_at < $1", cutoff)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected())
+    }
+}
+
+/// The `Last-Event-ID` header a reconnecting `EventSource` sends. Anything
+/// but an event id is ignored, as if the stream were new.
+pub struct LastEventId(pub Option<i64>);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for LastEventId {
+    type Error = std::convert::Infallible;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let id = request.headers().get_one("Last-Event-ID").and_then(|id| id.trim().parse().ok());
+        Outcome::Success(LastEventId(id))
+    }
+}
+
+/// The user's events as they happen, after those since `last_event_id`,
+/// or since the request for a new stream. Ends when the server shuts down.
+pub async fn stream(
+    log: Arc<EventLog>,
+    user_id: i64,
+    last_event_id: Option<i64>,
+    heartbeat: Duration,
+    mut shutdown: Shutdown,
+) -> Result<EventStream![], sqlx::Error> {
+    let after = match last_event_id {
+        Some(after) => after,
+        None => log.latest_id().await?,
+    };
+    // Subscribed before the log is read, so no event falls in between
+    let mut live = log.subscribe();
+
+    Ok(EventStream! {
+        // Newest id sent, or known to be older than the stream
+        let mut sent = match log.catch_up(user_id, after).await {
+            Ok(CatchUp::Events(events)) => {
+                let mut sent = after;
+                for event in events {
+                    sent = event.id;
+                    yield event.to_sse();
+                }
+                sent
+            }
+            Ok(CatchUp::Resync(latest)) => {
+                yield Event::data("resync").event("resync").id(latest.to_string());
+                latest
+            }
+            Err(e) => {
+                tracing::warn!(event = "event_log_failed", error = %e, "stream starts without catching up");
+                yield Event::data("resync").event("resync");
+                after
+            }
+        };
+
+        loop {
+            let received = rocket::tokio::select! {
+                biased;
+                received = live.recv() => received,
+                _ = &mut shutdown => break,
+            };
+
+            match received {
+                Ok(event) => {
+                    if event.user_id == user_id && event.id > sent {
+                        sent = event.id;
+                        yield event.to_sse();
+                    }
+                }
+                Err(RecvError::Lagged(_)) => match log.since(user_id, sent, MAX_REPLAY + 1).await {
+                    Ok(events) if events.len() <= MAX_REPLAY => {
+                        for event in events {
+                            sent = event.id;
+                            yield event.to_sse();
+                        }
+                    }
+                    _ => {
+                        sent = log.latest_id().await.unwrap_or(sent);
+                        yield Event::data("resync").event("resync");
+                    }
+                },
+                Err(RecvError::Closed) => break,
+            }
+        }
+    }
+    .heartbeat(heartbeat))
+}
+
+/// Appends every change made through the repository it wraps to the log.
+/// A change that cannot be logged is still made, and only missing from the feed.
+pub struct PublishingTodoRepository {
+    inner: Arc<dyn TodoRepository>,
+    log: Arc<EventLog>,
+}
+
+impl PublishingTodoRepository {
+    pub fn new(inner: Arc<dyn TodoRepository>, log: Arc<EventLog>) -> Self {
+        PublishingTodoRepository { inner, log }
+    }
+
+    async fn publish(
+        &self,
+        user_id: i64,
+        kind: EventKind,
+        todo_id: i64,
+        todo: Option<&Todo>,
+        previous_status: Option<Status>,
+    ) {
+        if let Err(e) = self.log.append(user_id, kind, todo_id, todo, previous_status).await {
+            tracing::warn!(event = "todo_event_lost", error = %e, todo_id, kind = kind.event_type());
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl TodoRepository for PublishingTodoRepository {
+    async fn create(&self, user_id: i64, todo: NewTodo) -> RepositoryResult<Todo> {
+        let todo = self.inner.create(user_id, todo).await?;
+        self.publish(user_id, EventKind::Created, todo.id.unwrap_or_default(), Some(&todo), None).await;
+        Ok(todo)
+    }
+
+    async fn find(&self, user_id: i64, id: i64) -> RepositoryResult<Option<Todo>> {
+        self.inner.find(user_id, id).await
+    }
+
+    async fn list(&self, user_id: i64, filter: TodoFilter) -> RepositoryResult<Vec<Todo>> {
+        self.inner.list(user_id, filter).await
+    }
+
+    async fn update(&self, user_id: i64, id: i64, changes: UpdateTodoRequest) -> RepositoryResult<Option<Todo>> {
+        let changed = changes.title.is_some()
+            || changes.description.is_some()
+            || changes.status.is_some()
+            || changes.priority.is_some();
+        // Read first, so the event can say what the status changed from
+        let before = match changes.status {
+            Some(_) => self.inner.find(user_id, id).await?.map(|todo| todo.status),
+            None => None,
+        };
+        let todo = self.inner.update(user_id, id, changes).await?;
+        if changed && let Some(todo) = &todo {
+            let previous_status = before.filter(|status| *status != todo.status);
+            self.publish(user_id, EventKind::Updated, id, Some(todo), previous_status).await;
+        }
+        Ok(todo)
+    }
+
+    async fn delete(&self, user_id: i64, id: i64) -> RepositoryResult<bool> {
+        let deleted = self.inner.delete(user_id, id).await?;
+        if deleted {
+            self.publish(user_id, EventKind::Deleted, id, None, None).await;
+        }
+        Ok(deleted)
+    }
+
+    async fn usage(&self, user_id: i64) -> RepositoryResult<Usage> {
+        self.inner.usage(user_id).await
+    }
+}
+
+/// Reads `[events]` and prunes the log hourly. The log itself is set up
+/// with the repositories.
+pub fn stage() -> AdHoc {
+    AdHoc::try_on_ignite("Todo Events", |rocket| async {
+        let config: EventsConfig = match rocket.figment().extract_inner("events") {
+            Ok(config) => config,
+            Err(e) if e.missing() => EventsConfig::default(),
+            Err(e) => {
+                tracing::error!(event = "invalid_config", problem = %e);
+                return Err(rocket);
+            }
+        };
+        let problems = config.problems();
+        if !problems.is_empty() {
+            for problem in &problems {
+                tracing::error!(event = "invalid_config", problem = %problem);
+            }
+            return Err(rocket);
+        }
+
+        let retention_hours = config.retention_hours;
+        Ok(rocket
+            .manage(config)
+            .attach(AdHoc::on_liftoff("Event Log Cleanup", move |rocket| {
+                Box::pin(async move {
+                    let Some(log) = rocket.state::<Arc<EventLog>>().cloned() else {
+                        return;
+                    };
+                    let mut shutdown = rocket.shutdown();
+
+                    let job = tokio::spawn(async move {
+                        let mut interval = tokio::time::interval(Duration::from_secs(3600));
+                        loop {
+                            tokio::select! {
+                                _ = interval.tick() => {
+                                    if let Err(e) = log.purge(retention_hours).await {
+                                        tracing::warn!(error = %e, "failed to purge old todo events");
+                                    }
+                                }
+                                _ = &mut shutdown => break,
+                            }
+                        }
+                    });
+                    if let Some(health) = rocket.state::<Health>() {
+                        health.watch("event_log_cleanup", job);
+                    }
+                })
+            })))
+    })
+}
@@ -0,0 +1,357 @@
+use std::io::Cursor;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use chrono::Utc;
+use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use rocket::fairing::AdHoc;
+use rocket::http::{ContentType, Status};
+use rocket::response::{self, Responder};
+use rocket::{Request, Response};
+use rocket_db_pools::Database;
+use serde::{Deserialize, Serialize};
+
+use crate::database::{Db, DbPool};
+use crate::health::Health;
+use crate::models::{Priority, Status as TodoStatus, Todo, TodoResponse};
+
+/// `[default.export]` in Rocket.toml
+#[derive(Debug, Clone, Deserialize)]
+pub struct ExportConfig {
+    #[serde(default = "default_dir")]
+    pub dir: PathBuf,
+    /// Accounts with more todos than this are exported in the background
+    #[serde(default = "default_async_threshold")]
+    pub async_threshold: i64,
+    /// How long a finished background export is kept on disk
+    #[serde(default = "default_retention_hours")]
+    pub retention_hours: i64,
+    /// Lifetime of a download link handed out by the status endpoint
+    #[serde(default = "default_link_ttl_minutes")]
+    pub link_ttl_minutes: i64,
+}
+
+fn default_dir() -> PathBuf {
+    PathBuf::from("exports")
+}
+
+fn default_async_threshold() -> i64 {
+    500
+}
+
+fn default_retention_hours() -> i64 {
+    24
+}
+
+fn default_link_ttl_minutes() -> i64 {
+    15
+}
+
+impl Default for ExportConfig {
+    fn default() -> Self {
+        ExportConfig {
+            dir: default_dir(),
+            async_threshold: default_async_threshold(),
+            retention_hours: default_retention_hours(),
+            link_ttl_minutes: default_link_ttl_minutes(),
+        }
+    }
+}
+
+/// Gathers everything stored about a user into one JSON document
+pub async fn collect(pool: &DbPool, user_id: i64) -> Result<serde_json::Value, sqlx::Error> {
+    let profile = sqlx::query!(
+        "SELECT id, username, email, pending_email, created_at, updated_at FROM users WHERE id = $1",
+        user_id
+    )
+    .fetch_one(pool)
+    .await?;
+
+    let todos: Vec<TodoResponse> = sqlx::query_as!(
+        Todo,
+        r#"SELECT id AS "id?", title, description, status AS "status: TodoStatus", priority AS "priority: Priority", created_at, updated_at FROM todos WHERE user_id = $1 ORDER BY created_at"#,
+        user_id
+    )
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(TodoResponse::from)
+    .collect();
+
+    let sessions: Vec<serde_json::Value> = sqlx::query!(
+        "SELECT id, created_at, revoked_at FROM sessions WHERE user_id = $1 ORDER BY created_at",
+        user_id
+    )
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(|row| {
+        serde_json::json!({
+            "id": row.id,
+            "created_at": row.created_at,
+            "revoked_at": row.revoked_at
+        })
+    })
+    .collect();
+
+    let api_keys: Vec<serde_json::Value> = sqlx::query!(
+        "SELECT id, name, prefix, scopes, created_at, expires_at, last_used_at, revoked_at FROM api_keys WHERE user_id = $1 ORDER BY created_at",
+        user_id
+    )
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(|row| {
+        serde_json::json!({
+            "id": row.id,
+            "name": row.name,
+            "prefix": row.prefix,
+            "scopes": row.scopes.split_whitespace().collect::<Vec<_>>(),
+            "created_at": row.created_at,
+            "expires_at": row.expires_at,
+            "last_used_at": row.last_used_at,
+            "revoked_at": row.revoked_at
+        })
+    })
+    .collect();
+
+    let identities: Vec<serde_json::Value> = sqlx::query!(
+        "SELECT provider, subject, email, created_at, last_login_at FROM user_identities WHERE user_id = $1 ORDER BY created_at",
+        user_id
+    )
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(|row| {
+        serde_json::json!({
+            "provider": row.provider,
+            "subject": row.subject,
+            "email": row.email,
+            "created_at": row.created_at,
+            "last_login_at": row.last_login_at
+        })
+    })
+    .collect();
+
+    Ok(serde_json::json!({
+        "format_version": 1,
+        "exported_at": Utc::now(),
+        "profile": {
+            "id": profile.id,
+            "username": profile.username,
+            "email": profile.email,
+            "pending_email": profile.pending_email,
+            "created_at": profile.created_at,
+            "updated_at": profile.updated_at
+        },
+        "todos": todos,
+        "sessions": sessions,
+        "api_keys": api_keys,
+        "identities": identities
+    }))
+}
+
+pub async fn count_todos(pool: &DbPool, user_id: i64) -> Result<i64, sqlx::Error> {
+    let row = sqlx::query!(
+        "SELECT COUNT(*) AS \"count!: i64\" FROM todos WHERE user_id = $1",
+        user_id
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(row.count)
+}
+
+/// Builds the archive for a queued export and records the outcome
+/// What a failed job reports to its owner; the cause is only logged
+pub const FAILED_MESSAGE: &str = "The export could not be created; please request a new one";
+
+pub async fn run_job(pool: DbPool, config: ExportConfig, export_id: String, user_id: i64) {
+    let _ = sqlx::query!(
+        "UPDATE data_exports SET status = 'running' WHERE id = $1",
+        export_id
+    )
+    .execute(&pool)
+    .await;
+
+    let path = config.dir.join(format!("{}.json", export_id));
+    let result = async {
+        let archive = collect(&pool, user_id).await.map_err(|e| e.to_string())?;
+        let body = serde_json::to_vec_pretty(&archive).map_err(|e| e.to_string())?;
+        tokio::fs::create_dir_all(&config.dir)
+            .await
+            .map_err(|e| e.to_string())?;
+        tokio::fs::write(&path, body).await.map_err(|e| e.to_string())
+    }
+    .await;
+
+    match result {
+        Ok(()) => {
+            let file_path = path.to_string_lossy().to_string();
+            let expires_at =
+                Utc::now().naive_utc() + chrono::Duration::hours(config.retention_hours);
+            let _ = sqlx::query!(
+                "UPDATE data_exports SET status = 'ready', file_path = $1, completed_at = CURRENT_TIMESTAMP, expires_at = $2 WHERE id = $3",
+                file_path,
+                expires_at,
+                export_id
+            )
+            .execute(&pool)
+            .await;
+            tracing::info!(export_id = %export_id, user_id, event = "export_ready");
+        }
+        Err(error) => {
+            let _ = sqlx::query!(
+                "UPDATE data_exports SET status = 'failed', error = $1, completed_at = CURRENT_TIMESTAMP WHERE id = $2",
+                FAILED_MESSAGE,
+                export_id
+            )
+            .execute(&pool)
+            .await;
+            tracing::error!(export_id = %export_id, user_id, error = %error, event = "export_failed");
+        }
+    }
+}
+
+/// Jobs only live in the task that runs them, so ones cut short by a restart
+/// are started again
+pub async fn resume_unfinished(pool: &DbPool, config: &ExportConfig) -> Result<usize, sqlx::Error> {
+    let unfinished = sqlx::query!(
+        r#"SELECT id AS "id!", user_id FROM data_exports WHERE status IN ('pending', 'running')"#
+    )
+    .fetch_all(pool)
+    .await?;
+
+    for job in &unfinished {
+        tracing::info!(export_id = %job.id, user_id = job.user_id, event = "export_resumed");
+        tokio::spawn(run_job(pool.clone(), config.clone(), job.id.clone(), job.user_id));
+    }
+
+    Ok(unfinished.len())
+}
+
+/// Deletes archives past their retention window
+pub async fn purge_expired(pool: &DbPool) -> Result<u64, sqlx::Error> {
+    let now = Utc::now().naive_utc();
+    let expired = sqlx::query!(
+        "SELECT id, file_path FROM data_exports WHERE status = 'ready' AND expires_at <= $1",
+        now
+    )
+    .fetch_all(pool)
+    .await?;
+
+    for export in &expired {
+        if let Some(path) = &export.file_path {
+            let _ = tokio::fs::remove_file(path).await;
+        }
+        sqlx::query!(
+            "UPDATE data_exports SET status = 'expired', file_path = NULL WHERE id = $1",
+            export.id
+        )
+        .execute(pool)
+        .await?;
+    }
+
+    Ok(expired.len() as u64)
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct DownloadClaims {
+    sub: String,
+    purpose: String,
+    exp: usize,
+}
+
+const DOWNLOAD_PURPOSE: &str = "export_download";
+
+/// A signed, short-lived token that lets a browser fetch the archive without
+/// sending credentials
+pub fn create_download_token(
+    secret: &str,
+    export_id: &str,
+    ttl_minutes: i64,
+) -> Result<String, jsonwebtoken::errors::Error> {
+    let claims = DownloadClaims {
+        sub: export_id.to_string(),
+        purpose: DOWNLOAD_PURPOSE.to_string(),
+        exp: (Utc::now() + chrono::Duration::minutes(ttl_minutes)).timestamp() as usize,
+    };
+
+    encode(
+        &Header::default(),
+        &claims,
+        &EncodingKey::from_secret(secret.as_ref()),
+    )
+}
+
+pub fn verify_download_token(secret: &str, token: &str, export_id: &str) -> bool {
+    decode::<DownloadClaims>(
+        token,
+        &DecodingKey::from_secret(secret.as_ref()),
+        &Validation::default(),
+    )
+    .map(|data| data.claims.sub == export_id && data.claims.purpose == DOWNLOAD_PURPOSE)
+    .unwrap_or(false)
+}
+
+/// A JSON body served as a file download
+pub struct Attachment {
+    pub filename: String,
+    pub body: Vec<u8>,
+}
+
+impl<'r> Responder<'r, 'static> for Attachment {
+    fn respond_to(self, _: &'r Request<'_>) -> response::Result<'static> {
+        Response::build()
+            .status(Status::Ok)
+            .header(ContentType::JSON)
+            .raw_header(
+                "Content-Disposition",
+                format!("attachment; filename=\"{}\"", self.filename),
+            )
+            .sized_body(self.body.len(), Cursor::new(self.body))
+            .ok()
+    }
+}
+
+pub fn stage() -> AdHoc {
+    AdHoc::on_ignite("Data Exports", |rocket| async {
+        let config = rocket
+            .figment()
+            .extract_inner::<ExportConfig>("export")
+            .unwrap_or_default();
+
+        rocket
+            .manage(config.clone())
+            .attach(AdHoc::on_liftoff("Export Cleanup", |rocket| {
+                Box::pin(async move {
+                    let Some(db) = Db::fetch(rocket) else {
+                        return;
+                    };
+                    let pool = (**db).clone();
+                    if let Err(e) = resume_unfinished(&pool, &config).await {
+                        tracing::warn!(error = %e, "failed to resume unfinished exports");
+                    }
+
+                    let mut shutdown = rocket.shutdown();
+
+                    let job = tokio::spawn(async move {
+                        let mut interval = tokio::time::interval(Duration::from_secs(3600));
+                        loop {
+                            tokio::select! {
+                                _ = interval.tick() => {
+                                    if let Err(e) = purge_expired(&pool).await {
+                                        tracing::warn!(error = %e, "failed to purge expired exports");
+                                    }
+                                }
+                                _ = &mut shutdown => break,
+                            }
+                        }
+                    });
+                    if let Some(health) = rocket.state::<Health>() {
+                        health.watch("export_cleanup", job);
+                    }
+                })
+            }))
+    })
+}
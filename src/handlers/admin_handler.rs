@@ -0,0 +1,159 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use rocket::State;
+use rocket::response::status;
+use rocket::serde::json::Json;
+
+use crate::auth::jwt::AdminAuth;
+use crate::backup::{self, BackupConfig, BackupError, Snapshot};
+use crate::config::AppConfig;
+use crate::database::{self, Db, MigrationStatus};
+use crate::error::{ApiError, ApiResult};
+use crate::maintenance::Maintenance;
+use crate::models::{QuotaOverrides, UserQuotaResponse};
+use crate::repository::{TodoRepository, UserRepository};
+use crate::telemetry::logging::{LogLevelError, LogLevelResponse, LogLevels, SetLogLevelRequest};
+
+impl From<BackupError> for ApiError {
+    fn from(e: BackupError) -> Self {
+        match e {
+            BackupError::NotFound(_) => ApiError::NotFound(e.to_string()),
+            BackupError::ChecksumMismatch(_) | BackupError::Corrupt(_) => {
+                ApiError::UnprocessableEntity(e.to_string())
+            }
+            BackupError::Unsupported => ApiError::BadRequest(e.to_string()),
+            BackupError::Database(e) => ApiError::Database(e),
+            BackupError::Io(_) | BackupError::Migrate(_) => ApiError::Internal(e.to_string()),
+        }
+    }
+}
+
+pub async fn list_backups(_admin: AdminAuth, config: &State<BackupConfig>) -> ApiResult<Json<Vec<Snapshot>>> {
+    Ok(Json(backup::list(config)?))
+}
+
+pub async fn create_backup(
+    db: &Db,
+    admin: AdminAuth,
+    config: &State<BackupConfig>,
+) -> ApiResult<status::Created<Json<Snapshot>>> {
+    let snapshot = backup::create(db, config).await?;
+    tracing::info!(event = "backup_requested", admin = %admin.0.username, snapshot = %snapshot.name);
+
+    Ok(status::Created::new(format!("/api/admin/backups/{}", snapshot.name)).body(Json(snapshot)))
+}
+
+pub async fn restore_backup(
+    db: &Db,
+    admin: AdminAuth,
+    config: &State<BackupConfig>,
+    maintenance: &State<Maintenance>,
+    name: &str,
+) -> ApiResult<Json<serde_json::Value>> {
+    // Checked before anyone is locked out
+    backup::verify(config, name).await?;
+
+    let Some(_window) = maintenance
+        .begin(Duration::from_secs(config.drain_seconds))
+        .await
+    else {
+        return Err(ApiError::ServiceUnavailable(
+            "Other requests are still running; try again shortly".to_string(),
+        ));
+    };
+    tracing::warn!(event = "restore_started", admin = %admin.0.username, snapshot = %name);
+    backup::restore(db, config, name).await?;
+
+    Ok(Json(serde_json::json!({
+        "message": "Database restored",
+        "snapshot": name
+    })))
+}
+
+pub async fn list_migrations(db: &Db, _admin: AdminAuth) -> ApiResult<Json<Vec<MigrationStatus>>> {
+    database::migration_status(db)
+        .await
+        .map(Json)
+        .map_err(|e| ApiError::Internal(format!("could not read applied migrations: {}", e)))
+}
+
+impl From<LogLevelError> for ApiError {
+    fn from(e: LogLevelError) -> Self {
+        match e {
+            LogLevelError::Invalid(_) => ApiError::UnprocessableEntity(e.to_string()),
+            LogLevelError::Detached => ApiError::ServiceUnavailable(e.to_string()),
+        }
+    }
+}
+
+fn log_levels() -> ApiResult<&'static LogLevels> {
+    LogLevels::installed().ok_or_else(|| {
+        ApiError::ServiceUnavailable("Log levels are not adjustable in this process".to_string())
+    })
+}
+
+fn log_level_response(levels: &LogLevels) -> ApiResult<Json<LogLevelResponse>> {
+    Ok(Json(LogLevelResponse {
+        filter: levels.current()?,
+        configured: levels.configured().to_string(),
+    }))
+}
+
+pub async fn get_log_level(_admin: AdminAuth) -> ApiResult<Json<LogLevelResponse>> {
+    log_level_response(log_levels()?)
+}
+
+pub async fn set_log_level(admin: AdminAuth, request: SetLogLevelRequest) -> ApiResult<Json<LogLevelResponse>> {
+    let levels = log_levels()?;
+    levels.set(&request.filter)?;
+    tracing::warn!(event = "log_level_changed", admin = %admin.0.username, filter = %request.filter);
+
+    log_level_response(levels)
+}
+
+async fn user_quota(
+    todos: &dyn TodoRepository,
+    users: &dyn UserRepository,
+    config: &AppConfig,
+    user_id: i64,
+) -> ApiResult<Json<UserQuotaResponse>> {
+    let overrides = users.quota_overrides(user_id).await?.ok_or_else(|| user_not_found(user_id))?;
+    let usage = todos.usage(user_id).await?;
+
+    Ok(Json(UserQuotaResponse {
+        user_id,
+        usage: config.quotas.limits(&overrides).usage_response(usage),
+        overrides,
+    }))
+}
+
+fn user_not_found(user_id: i64) -> ApiError {
+    ApiError::NotFound(format!("User {} not found", user_id))
+}
+
+pub async fn get_user_quota(
+    _admin: AdminAuth,
+    todos: &State<Arc<dyn TodoRepository>>,
+    users: &State<Arc<dyn UserRepository>>,
+    config: &State<AppConfig>,
+    user_id: i64,
+) -> ApiResult<Json<UserQuotaResponse>> {
+    user_quota(todos.inner().as_ref(), users.inner().as_ref(), config, user_id).await
+}
+
+pub async fn set_user_quota(
+    admin: AdminAuth,
+    todos: &State<Arc<dyn TodoRepository>>,
+    users: &State<Arc<dyn UserRepository>>,
+    config: &State<AppConfig>,
+    user_id: i64,
+    overrides: QuotaOverrides,
+) -> ApiResult<Json<UserQuotaResponse>> {
+    if !users.set_quota_overrides(user_id, &overrides).await? {
+        return Err(user_not_found(user_id));
+    }
+    tracing::warn!(event = "quota_changed", admin = %admin.0.username, user_id, overrides = ?overrides);
+
+    user_quota(todos.inner().as_ref(), users.inner().as_ref(), config, user_id).await
+}
@@ -0,0 +1,124 @@
+use rocket::response::status;
+use rocket::serde::json::Json;
+use rocket_db_pools::Connection;
+
+use crate::auth::api_key::{self, SCOPES};
+use crate::auth::jwt::JwtAuth;
+use crate::database::{returned, Db};
+use crate::error::{ApiError, ApiResult};
+use crate::models::{ApiKey, ApiKeyResponse, CreateApiKeyRequest, CreatedApiKeyResponse};
+
+pub async fn create_api_key(
+    mut db: Connection<Db>,
+    auth: JwtAuth,
+    request: Json<CreateApiKeyRequest>,
+) -> ApiResult<status::Created<Json<CreatedApiKeyResponse>>> {
+    let user_id = auth.require_session()?;
+
+    let name = request.name.trim();
+    if name.is_empty() {
+        return Err(ApiError::BadRequest("API key name must not be empty".to_string()));
+    }
+
+    let mut scopes: Vec<String> = match &request.scopes {
+        Some(scopes) => scopes.clone(),
+        None => SCOPES.iter().map(|s| s.to_string()).collect(),
+    };
+    scopes.sort();
+    scopes.dedup();
+
+    if scopes.is_empty() {
+        return Err(ApiError::BadRequest("At least one scope is required".to_string()));
+    }
+    if let Some(unknown) = scopes.iter().find(|s| !api_key::is_known_scope(s)) {
+        return Err(ApiError::BadRequest(format!(
+            "Unknown scope '{}'; expected one of: {}",
+            unknown,
+            SCOPES.join(", ")
+        )));
+    }
+
+    let expires_at = match request.expires_in_days {
+        Some(days) if days <= 0 => {
+            return Err(ApiError::BadRequest("expires_in_days must be positive".to_string()));
+        }
+        Some(days) => Some(chrono::Utc::now().naive_utc() + chrono::Duration::days(days)),
+        None => None,
+    };
+
+    let token = api_key::generate_api_key();
+    let key_hash = api_key::hash_api_key(&token);
+    let prefix = api_key::display_prefix(&token);
+    let scopes_str = scopes.join(" ");
+
+    let id = sqlx::query_scalar!(
+        r#"INSERT INTO api_keys (user_id, name, prefix, key_hash, scopes, expires_at) VALUES ($1, $2, $3, $4, $5, $6) RETURNING id AS "id!""#,
+        user_id,
+        name,
+        prefix,
+        key_hash,
+        scopes_str,
+        expires_at
+    )
+    .fetch_all(&mut **db)
+    .await
+    .and_then(returned)
+    .map_err(|_| ApiError::Internal("Failed to create API key".to_string()))?;
+
+    let created = sqlx::query_as!(
+        ApiKey,
+        "SELECT id, user_id, name, prefix, scopes, expires_at, last_used_at, revoked_at, created_at FROM api_keys WHERE id = $1",
+        id
+    )
+    .fetch_one(&mut **db)
+    .await
+    .map_err(|_| ApiError::Internal("Failed to fetch created API key".to_string()))?;
+
+    Ok(status::Created::new(format!("/api/auth/api-keys/{}", id)).body(Json(
+        CreatedApiKeyResponse {
+            api_key: ApiKeyResponse::from(created),
+            token,
+        },
+    )))
+}
+
+pub async fn list_api_keys(
+    mut db: Connection<Db>,
+    auth: JwtAuth,
+) -> ApiResult<Json<Vec<ApiKeyResponse>>> {
+    let user_id = auth.require_session()?;
+
+    let keys = sqlx::query_as!(
+        ApiKey,
+        "SELECT id, user_id, name, prefix, scopes, expires_at, last_used_at, revoked_at, created_at FROM api_keys WHERE user_id = $1 AND revoked_at IS NULL ORDER BY created_at DESC",
+        user_id
+    )
+    .fetch_all(&mut **db)
+    .await
+    .map_err(|_| ApiError::Internal("Failed to fetch API keys".to_string()))?;
+
+    Ok(Json(keys.into_iter().map(ApiKeyResponse::from).collect()))
+}
+
+pub async fn revoke_api_key(
+    mut db: Connection<Db>,
+    auth: JwtAuth,
+    id: i64,
+) -> ApiResult<status::NoContent> {
+    let user_id = auth.require_session()?;
+
+    let result = sqlx::query!(
+        "UPDATE api_keys SET revoked_at = CURRENT_TIMESTAMP WHERE id = $1 AND user_id = $2 AND revoked_at IS NULL",
+        id,
+        user_id
+    )
+    .execute(&mut **db)
+    .await
+    .map_err(|_| ApiError::Internal("Failed to revoke API key".to_string()))?;
+
+    if result.rows_affected() == 0 {
+        return Err(ApiError::NotFound("API key not found".to_string()));
+    }
+
+    Ok(status::NoContent)
+}
@@ -1,160 +1,183 @@
-use argon2::{
-    password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
-    Argon2,
-};
-use rocket::http::{Cookie, SameSite, Status};
-use rocket::response::status;
+use rocket::http::Cookie;
 use rocket::serde::json::Json;
-use rocket_db_pools::Connection;
 
-use crate::database::Db;
+use crate::config::{AppConfig, JwtConfig};
+use crate::error::{ApiError, ApiResult};
 use crate::models::user::{CreateUserRequest, LoginRequest, User};
-use crate::auth::jwt::create_token;
+use crate::auth::csrf::{self, CSRF_COOKIE};
+use crate::auth::jwt::{self, create_token, JwtAuth};
+use crate::auth::password::{hash_password, verify_password};
+use crate::metrics::{self, AuthMethod};
+use crate::repository::{NewUser, RepositoryError, UserRepository};
 use rocket::http::CookieJar;
 
+/// A user who just registered or logged in, with the token for their new
+/// session
+#[derive(Debug)]
+pub struct SignedIn {
+    pub user_id: i64,
+    pub username: String,
+    pub email: String,
+    pub token: String,
+    /// For cookie clients; see `auth::csrf`
+    pub csrf_token: String,
+}
+
+impl SignedIn {
+    pub fn body(&self, message: &str) -> serde_json::Value {
+        serde_json::json!({
+            "message": message,
+            "user": {
+                "id": self.user_id,
+                "username": self.username,
+                "email": self.email
+            },
+            "token": self.token
+        })
+    }
+}
+
 pub async fn register(
-    mut db: Connection<Db>,
-    request: Json<CreateUserRequest>,
-) -> Result<status::Created<Json<serde_json::Value>>, status::Custom<Json<serde_json::Value>>> {
+    users: &dyn UserRepository,
+    jwt: &JwtConfig,
+    request: CreateUserRequest,
+) -> ApiResult<SignedIn> {
     // Check if user exists
-    let existing_user = sqlx::query!("SELECT id FROM users WHERE email = ? OR username = ?",
-        request.email, request.username)
-        .fetch_optional(&mut **db)
-        .await;
-
-    if existing_user.is_ok() && existing_user.unwrap().is_some() {
-        return Err(status::Custom(
-            Status::Conflict,
-            Json(serde_json::json!({
-                "error": "User already exists"
-            })),
-        ));
+    if users.exists(&request.username, &request.email).await? {
+        return Err(ApiError::Conflict("User already exists".to_string()));
     }
 
     // Hash password
-    let salt = SaltString::generate(&mut OsRng);
-    let argon2 = Argon2::default();
-    let password_hash = argon2
-        .hash_password(request.password.as_bytes(), &salt)
-        .map_err(|_| status::Custom(
-            Status::InternalServerError,
-            Json(serde_json::json!({"error": "Failed to hash password"})),
-        ))?;
-
-    // Create user
-    let password_hash_str = password_hash.to_string();
-    let result = sqlx::query!(
-        "INSERT INTO users (username, email, password_hash) VALUES (?, ?, ?)",
-        request.username,
-        request.email,
-        password_hash_str
-    )
-    .execute(&mut **db)
-    .await
-    .map_err(|_| status::Custom(
-        Status::InternalServerError,
-        Json(serde_json::json!({"error": "Failed to create user"})),
-    ))?;
-
-    let user_id = result.last_insert_rowid();
-
-    // Create token
-    let token = create_token(&user_id.to_string(), &request.email, &request.username)
-        .map_err(|_| status::Custom(
-            Status::InternalServerError,
-            Json(serde_json::json!({"error": "Failed to create token"})),
-        ))?;
-
-    let response = status::Created::new("/users")
-        .body(Json(serde_json::json!({
-            "message": "User created successfully",
-            "user": {
-                "id": user_id,
-                "username": request.username,
-                "email": request.email
-            },
-            "token": token
-        })));
+    let password_hash = hash_password(&request.password)
+        .map_err(|_| ApiError::Internal("Failed to hash password".to_string()))?;
+
+    // Create user; a concurrent registration may still take the name first
+    let user = users
+        .create(NewUser {
+            username: request.username,
+            email: request.email,
+            password_hash,
+        })
+        .await
+        .map_err(|e| match e {
+            RepositoryError::Conflict => ApiError::Conflict("User already exists".to_string()),
+            e => e.into(),
+        })?;
+
+    sign_in(users, jwt, user).await
+}
 
-    Ok(response)
+pub async fn login(users: &dyn UserRepository, jwt: &JwtConfig, request: LoginRequest) -> ApiResult<SignedIn> {
+    let signed_in = password_login(users, jwt, request).await;
+    metrics::record_auth(AuthMethod::Password, signed_in.is_ok());
+    signed_in
 }
 
-pub async fn login(
-    mut db: Connection<Db>,
-    request: Json<LoginRequest>,
-    cookies: &CookieJar<'_>,
-) -> Result<Json<serde_json::Value>, status::Custom<Json<serde_json::Value>>> {
+async fn password_login(users: &dyn UserRepository, jwt: &JwtConfig, request: LoginRequest) -> ApiResult<SignedIn> {
     // Find user
-    let user = sqlx::query!(
-        "SELECT id, username, email, password_hash FROM users WHERE email = ?",
-        request.email
-    )
-    .fetch_optional(&mut **db)
-    .await
-    .map_err(|_| status::Custom(
-        Status::InternalServerError,
-        Json(serde_json::json!({"error": "Database error"})),
-    ))?;
-
-    let user = user.ok_or_else(|| status::Custom(
-        Status::Unauthorized,
-        Json(serde_json::json!({"error": "Invalid credentials"})),
-    ))?;
-
-    // Verify password
-    let parsed_hash = PasswordHash::new(&user.password_hash)
-        .map_err(|_| status::Custom(
-            Status::InternalServerError,
-            Json(serde_json::json!({"error": "Password hash error"})),
-        ))?;
-
-    if Argon2::default()
-        .verify_password(request.password.as_bytes(), &parsed_hash)
-        .is_err()
-    {
-        return Err(status::Custom(
-            Status::Unauthorized,
-            Json(serde_json::json!({"error": "Invalid credentials"})),
-        ));
+    let user = users
+        .find_by_email(&request.email)
+        .await?
+        .ok_or(ApiError::InvalidCredentials)?;
+
+    // Verify password (accounts created through an identity provider have none)
+    let valid = verify_password(&request.password, &user.password_hash)
+        .map_err(|_| ApiError::Internal("Password hash error".to_string()))?;
+
+    if !valid {
+        return Err(ApiError::InvalidCredentials);
     }
 
-    // Create token
+    // Checked after the password so the response does not reveal the account
+    if user.disabled_at.is_some() {
+        return Err(account_disabled());
+    }
+
+    sign_in(users, jwt, user).await
+}
+
+pub fn account_disabled() -> ApiError {
+    ApiError::Forbidden("Account is disabled".to_string())
+}
+
+/// Create session and token
+async fn sign_in(users: &dyn UserRepository, jwt: &JwtConfig, user: User) -> ApiResult<SignedIn> {
     let user_id = user.id.expect("User ID should be set");
-    let token = create_token(&user_id.to_string(), &user.email, &user.username)
-        .map_err(|_| status::Custom(
-            Status::InternalServerError,
-            Json(serde_json::json!({"error": "Failed to create token"})),
-        ))?;
-
-    // Set the cookie with the token
-    let cookie = Cookie::build(("auth_token", token.clone()))
+    let session_id = users.create_session(user_id).await?;
+    let token = create_token(jwt, &user_id.to_string(), &user.email, &user.username, &session_id)
+        .map_err(|e| ApiError::Internal(format!("Failed to create token: {}", e)))?;
+
+    Ok(SignedIn {
+        user_id,
+        username: user.username,
+        email: user.email,
+        token,
+        csrf_token: csrf::token(jwt.secret(), &session_id),
+    })
+}
+
+/// Set the cookie with the token, for as long as the token lasts, and the
+/// script-readable cookie with the session's CSRF token next to it
+pub fn set_auth_cookie(cookies: &CookieJar<'_>, config: &AppConfig, token: &str, csrf_token: &str) {
+    let cookie = Cookie::build(("auth_token", token.to_string()))
         .http_only(true)
-        .secure(false) // Set to true in production with HTTPS
-        .same_site(SameSite::Lax)
+        .secure(config.cookies.secure)
+        .same_site(config.cookies.same_site())
         .path("/")
-        .max_age(rocket::time::Duration::days(1));
+        .max_age(rocket::time::Duration::hours(config.jwt.ttl_hours));
+    cookies.add(cookie);
 
+    let cookie = Cookie::build((CSRF_COOKIE, csrf_token.to_string()))
+        .http_only(false)
+        .secure(config.cookies.secure)
+        .same_site(config.cookies.same_site())
+        .path("/")
+        .max_age(rocket::time::Duration::hours(config.jwt.ttl_hours));
     cookies.add(cookie);
+}
+
+pub fn clear_auth_cookies(cookies: &CookieJar<'_>) {
+    cookies.remove(Cookie::build(("auth_token", "")));
+    cookies.remove(Cookie::build((CSRF_COOKIE, "")));
+}
+
+/// The CSRF token of the caller's session, for clients that cannot read
+/// the `csrf_token` cookie (e.g. a SPA on another origin)
+pub fn csrf_token(jwt: &JwtConfig, auth: &JwtAuth) -> ApiResult<Json<serde_json::Value>> {
+    auth.require_session()?;
+    let session_id = auth.session_id.as_deref().unwrap_or_default();
 
     Ok(Json(serde_json::json!({
-        "message": "Login successful",
-        "user": {
-            "id": user.id,
-            "username": user.username,
-            "email": user.email
-        },
-        "token": token
+        "csrf_token": csrf::token(jwt.secret(), session_id)
     })))
 }
 
-pub async fn logout(cookies: &CookieJar<'_>) -> Json<serde_json::Value> {
-    cookies.remove(Cookie::build(("auth_token", "")));
+pub async fn logout(
+    users: &dyn UserRepository,
+    jwt: &JwtConfig,
+    auth: Option<JwtAuth>,
+    cookies: &CookieJar<'_>,
+) -> Json<serde_json::Value> {
+    // Revoke the sessions so copies of the tokens stop working too. A
+    // cookie sent without its CSRF header does not authenticate the
+    // request, but the session behind it must not outlive the cookie.
+    let mut sessions: Vec<String> = auth.and_then(|auth| auth.session_id).into_iter().collect();
+    if let Some(session_id) = cookies.get("auth_token").and_then(|cookie| jwt::session_id(jwt, cookie.value()))
+        && !sessions.contains(&session_id)
+    {
+        sessions.push(session_id);
+    }
+    for session_id in &sessions {
+        let _ = users.revoke_session(session_id).await;
+    }
+
+    clear_auth_cookies(cookies);
     Json(serde_json::json!({
         "message": "Logout successful"
     }))
 }
 
-pub async fn me(auth: crate::auth::JwtAuth) -> Json<serde_json::Value> {
+pub async fn me(auth: JwtAuth) -> Json<serde_json::Value> {
     Json(serde_json::json!({
         "user": {
             "id": auth.user_id,
@@ -0,0 +1,148 @@
+use chrono::{DateTime, NaiveDateTime, Utc};
+use rocket::response::status;
+use rocket::serde::json::Json;
+use rocket::{Responder, State};
+
+use crate::auth::jwt::JwtAuth;
+use crate::config::JwtConfig;
+use crate::database::Db;
+use crate::error::{ApiError, ApiResult};
+use crate::export::{self, Attachment, ExportConfig};
+use crate::models::DataExportResponse;
+
+/// Small accounts get their archive straight away; large ones are queued
+#[derive(Responder)]
+pub enum ExportOutcome {
+    Archive(Attachment),
+    Queued(status::Accepted<Json<DataExportResponse>>),
+}
+
+fn to_utc(dt: NaiveDateTime) -> DateTime<Utc> {
+    DateTime::from_naive_utc_and_offset(dt, Utc)
+}
+
+fn archive_filename(export_id: Option<&str>) -> String {
+    match export_id {
+        Some(id) => format!("todo-export-{}.json", id),
+        None => format!("todo-export-{}.json", Utc::now().format("%Y%m%d%H%M%S")),
+    }
+}
+
+pub async fn export_me(
+    db: &Db,
+    auth: JwtAuth,
+    config: &State<ExportConfig>,
+    background: bool,
+) -> ApiResult<ExportOutcome> {
+    let user_id = auth.require_session()?;
+
+    let todo_count = export::count_todos(db, user_id).await?;
+
+    if !background && todo_count <= config.async_threshold {
+        let archive = export::collect(db, user_id).await?;
+        let body = serde_json::to_vec_pretty(&archive)
+            .map_err(|_| ApiError::Internal("Failed to build export".to_string()))?;
+
+        return Ok(ExportOutcome::Archive(Attachment {
+            filename: archive_filename(None),
+            body,
+        }));
+    }
+
+    let export_id = uuid::Uuid::new_v4().to_string();
+    sqlx::query!(
+        "INSERT INTO data_exports (id, user_id) VALUES ($1, $2)",
+        export_id,
+        user_id
+    )
+    .execute(&**db)
+    .await?;
+
+    tracing::info!(export_id = %export_id, user_id, todo_count, event = "export_queued");
+    rocket::tokio::spawn(export::run_job(
+        (**db).clone(),
+        config.inner().clone(),
+        export_id.clone(),
+        user_id,
+    ));
+
+    Ok(ExportOutcome::Queued(status::Accepted(Json(DataExportResponse {
+        id: export_id,
+        status: "pending".to_string(),
+        created_at: Utc::now(),
+        completed_at: None,
+        expires_at: None,
+        download_url: None,
+        error: None,
+    }))))
+}
+
+pub async fn get_export(
+    db: &Db,
+    auth: JwtAuth,
+    config: &State<ExportConfig>,
+    jwt: &JwtConfig,
+    export_id: &str,
+) -> ApiResult<Json<DataExportResponse>> {
+    let user_id = auth.require_session()?;
+
+    let export = sqlx::query!(
+        "SELECT id AS \"id!\", status, error, created_at, completed_at, expires_at FROM data_exports WHERE id = $1 AND user_id = $2",
+        export_id,
+        user_id
+    )
+    .fetch_optional(&**db)
+    .await?
+    .ok_or_else(|| ApiError::NotFound("Export not found".to_string()))?;
+
+    let download_url = if export.status == "ready" {
+        let token = export::create_download_token(jwt.secret(), export_id, config.link_ttl_minutes)
+            .map_err(|_| ApiError::Internal("Failed to create download link".to_string()))?;
+        Some(format!("/api/exports/{}/download?token={}", export_id, token))
+    } else {
+        None
+    };
+
+    Ok(Json(DataExportResponse {
+        id: export.id,
+        status: export.status,
+        created_at: export.created_at.map(to_utc).unwrap_or_else(Utc::now),
+        completed_at: export.completed_at.map(to_utc),
+        expires_at: export.expires_at.map(to_utc),
+        download_url,
+        // Rows written before failures were redacted may hold the raw cause
+        error: export.error.map(|_| export::FAILED_MESSAGE.to_string()),
+    }))
+}
+
+pub async fn download_export(db: &Db, jwt: &JwtConfig, export_id: &str, token: &str) -> ApiResult<Attachment> {
+    if !export::verify_download_token(jwt.secret(), token, export_id) {
+        return Err(ApiError::Forbidden("Invalid or expired download link".to_string()));
+    }
+
+    let export = sqlx::query!(
+        "SELECT status, file_path, expires_at FROM data_exports WHERE id = $1",
+        export_id
+    )
+    .fetch_optional(&**db)
+    .await?
+    .ok_or_else(|| ApiError::NotFound("Export not found".to_string()))?;
+
+    let expired = export
+        .expires_at
+        .is_some_and(|exp| exp <= Utc::now().naive_utc());
+    let path = match (export.status.as_str(), export.file_path) {
+        ("ready", Some(path)) if !expired => path,
+        ("ready", _) | ("expired", _) => return Err(ApiError::Gone("Export has expired".to_string())),
+        _ => return Err(ApiError::Conflict("Export is not ready yet".to_string())),
+    };
+
+    let body = rocket::tokio::fs::read(&path)
+        .await
+        .map_err(|_| ApiError::Gone("Export has expired".to_string()))?;
+
+    Ok(Attachment {
+        filename: archive_filename(Some(export_id)),
+        body,
+    })
+}
@@ -1,5 +1,8 @@
-pub mod todo_handler;
+pub mod admin_handler;
+pub mod api_key_handler;
 pub mod auth_handler;
-
-pub use todo_handler::*;
-pub use auth_handler::*;
+pub mod export_handler;
+pub mod oidc_handler;
+pub mod todo_handler;
+pub mod user_handler;
+pub mod webhook_handler;
@@ -0,0 +1,295 @@
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use rocket::http::{Cookie, CookieJar, SameSite};
+use rocket::response::Redirect;
+use rocket::serde::json::Json;
+use rocket::State;
+use rocket_db_pools::Connection;
+use sqlx::Connection as _;
+use utoipa::IntoParams;
+
+use crate::auth::csrf;
+use crate::auth::jwt::create_token;
+use crate::auth::oidc::{IdTokenClaims, LoginFlow, Oidc, OidcError, FLOW_COOKIE};
+use crate::auth::session::create_session;
+use crate::config::AppConfig;
+use crate::database::{returned, Db};
+use crate::error::{ApiError, ApiResult};
+use crate::handlers::auth_handler::{account_disabled, set_auth_cookie};
+use crate::metrics::{self, AuthMethod};
+use crate::models::User;
+
+/// The provider's own error text only goes to the log
+fn provider_error(e: OidcError) -> ApiError {
+    tracing::warn!(error = %e, "OIDC login failed");
+
+    match e {
+        OidcError::UnknownProvider => ApiError::NotFound("Unknown identity provider".to_string()),
+        OidcError::Discovery(_) | OidcError::TokenExchange(_) => {
+            ApiError::BadGateway("External login failed: the identity provider could not be reached".to_string())
+        }
+        OidcError::InvalidIdToken(_) => {
+            ApiError::Unauthorized("External login failed: the identity provider's response was rejected".to_string())
+        }
+    }
+}
+
+pub async fn list_providers(oidc: &State<Oidc>) -> Json<serde_json::Value> {
+    let providers: Vec<serde_json::Value> = oidc
+        .provider_names()
+        .into_iter()
+        .map(|(name, display_name)| {
+            serde_json::json!({
+                "name": name,
+                "display_name": display_name,
+                "login_url": format!("/api/auth/oidc/{}/login", name)
+            })
+        })
+        .collect();
+
+    Json(serde_json::json!({ "providers": providers }))
+}
+
+pub async fn login(
+    oidc: &State<Oidc>,
+    config: &AppConfig,
+    provider: &str,
+    cookies: &CookieJar<'_>,
+) -> ApiResult<Redirect> {
+    let flow = LoginFlow::new(provider);
+    let url = oidc.authorization_url(&flow).await.map_err(provider_error)?;
+
+    // Lax so the cookie survives the top-level redirect back from the provider
+    let cookie = Cookie::build((FLOW_COOKIE, flow.encode()))
+        .http_only(true)
+        .secure(config.cookies.secure)
+        .same_site(SameSite::Lax)
+        .path("/api/auth/oidc")
+        .max_age(rocket::time::Duration::minutes(10));
+    cookies.add(cookie);
+
+    Ok(Redirect::to(url))
+}
+
+/// What the provider appends to the callback URL
+#[derive(Debug, Default, FromForm, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct CallbackParams {
+    /// Authorization code
+    pub code: Option<String>,
+    /// State issued by the login endpoint
+    pub state: Option<String>,
+    /// Error returned by the provider
+    pub error: Option<String>,
+}
+
+pub async fn callback(
+    db: Connection<Db>,
+    oidc: &State<Oidc>,
+    config: &AppConfig,
+    provider: &str,
+    params: CallbackParams,
+    cookies: &CookieJar<'_>,
+) -> ApiResult<Json<serde_json::Value>> {
+    let signed_in = complete_login(db, oidc, config, provider, params, cookies).await;
+    metrics::record_auth(AuthMethod::Oidc, signed_in.is_ok());
+    signed_in
+}
+
+async fn complete_login(
+    mut db: Connection<Db>,
+    oidc: &State<Oidc>,
+    config: &AppConfig,
+    provider: &str,
+    params: CallbackParams,
+    cookies: &CookieJar<'_>,
+) -> ApiResult<Json<serde_json::Value>> {
+    let CallbackParams { code, state, error: provider_error_code } = params;
+    let flow = cookies
+        .get(FLOW_COOKIE)
+        .and_then(|cookie| LoginFlow::decode(cookie.value()));
+    cookies.remove(Cookie::build(FLOW_COOKIE).path("/api/auth/oidc"));
+
+    if let Some(code) = provider_error_code {
+        return Err(ApiError::Unauthorized(format!(
+            "Identity provider returned '{}'",
+            code
+        )));
+    }
+
+    let flow = flow.ok_or_else(|| ApiError::BadRequest("Login session expired or missing".to_string()))?;
+    if flow.provider != provider || state.as_deref() != Some(flow.state.as_str()) {
+        return Err(ApiError::BadRequest("State mismatch".to_string()));
+    }
+    let code = code.ok_or_else(|| ApiError::BadRequest("Missing authorization code".to_string()))?;
+
+    let claims = oidc.complete(&flow, &code).await.map_err(provider_error)?;
+    let (user_id, username, email) = find_or_link_user(&mut db, provider, &claims).await?;
+
+    let session_id = create_session(&mut db, user_id)
+        .await?;
+    let token = create_token(&config.jwt, &user_id.to_string(), &email, &username, &session_id).map_err(|_| {
+        ApiError::Internal("Failed to create token".to_string())
+    })?;
+    let csrf_token = csrf::token(config.jwt.secret(), &session_id);
+    set_auth_cookie(cookies, config, &token, &csrf_token);
+
+    Ok(Json(serde_json::json!({
+        "message": "Login successful",
+        "user": {
+            "id": user_id,
+            "username": username,
+            "email": email
+        },
+        "token": token,
+        "csrf_token": csrf_token
+    })))
+}
+
+/// Resolve the external identity to a local user. Known identities log in
+/// directly; otherwise the identity is linked to the user with the same
+/// email, or a new user is created. Linking an account whose email was never
+/// verified clears its password and revokes its sessions and API keys.
+async fn find_or_link_user(
+    db: &mut Connection<Db>,
+    provider: &str,
+    claims: &IdTokenClaims,
+) -> ApiResult<(i64, String, String)> {
+    let linked = sqlx::query!(
+        r#"SELECT u.id AS "id!", u.username, u.email, u.disabled_at FROM user_identities i JOIN users u ON u.id = i.user_id
+         WHERE i.provider = $1 AND i.subject = $2"#,
+        provider,
+        claims.sub
+    )
+    .fetch_optional(&mut ***db)
+    .await?;
+
+    if let Some(user) = linked {
+        if user.disabled_at.is_some() {
+            return Err(account_disabled());
+        }
+
+        sqlx::query!(
+            "UPDATE user_identities SET last_login_at = CURRENT_TIMESTAMP WHERE provider = $1 AND subject = $2",
+            provider,
+            claims.sub
+        )
+        .execute(&mut ***db)
+        .await?;
+
+        return Ok((user.id, user.username, user.email));
+    }
+
+    let email = match (&claims.email, claims.email_verified) {
+        (Some(email), true) => email.clone(),
+        _ => {
+            return Err(ApiError::Forbidden("Identity provider did not return a verified email".to_string()));
+        }
+    };
+
+    let existing = sqlx::query!(
+        r#"SELECT id AS "id!", username, disabled_at, email_verified_at FROM users WHERE email = $1"#,
+        email
+    )
+    .fetch_optional(&mut ***db)
+    .await?;
+
+    // Picked before the transaction, which holds on to the connection
+    let new_username = match existing {
+        Some(_) => None,
+        None => Some(unique_username(db, claims, &email).await?),
+    };
+
+    let mut tx = (***db).begin().await?;
+
+    let (user_id, username) = match (existing, new_username) {
+        (Some(user), _) if user.disabled_at.is_some() => return Err(account_disabled()),
+        (Some(user), _) => {
+            // Registration never proves the email, so whoever set the password
+            // may not own the address. The identity provider did prove it:
+            // take the account over and lock out every other way in.
+            if user.email_verified_at.is_none() {
+                let no_password = User::NO_PASSWORD;
+                sqlx::query!(
+                    "UPDATE users SET password_hash = $1, email_verified_at = CURRENT_TIMESTAMP, pending_email = NULL, email_verification_hash = NULL, email_verification_expires_at = NULL WHERE id = $2",
+                    no_password,
+                    user.id
+                )
+                .execute(&mut *tx)
+                .await?;
+                sqlx::query!(
+                    "UPDATE sessions SET revoked_at = CURRENT_TIMESTAMP WHERE user_id = $1 AND revoked_at IS NULL",
+                    user.id
+                )
+                .execute(&mut *tx)
+                .await?;
+                sqlx::query!(
+                    "UPDATE api_keys SET revoked_at = CURRENT_TIMESTAMP WHERE user_id = $1 AND revoked_at IS NULL",
+                    user.id
+                )
+                .execute(&mut *tx)
+                .await?;
+            }
+
+            (user.id, user.username)
+        }
+        (None, username) => {
+            let username = username.expect("picked above");
+            let no_password = User::NO_PASSWORD;
+            let user_id = sqlx::query_scalar!(
+                r#"INSERT INTO users (username, email, password_hash, email_verified_at) VALUES ($1, $2, $3, CURRENT_TIMESTAMP) RETURNING id AS "id!""#,
+                username,
+                email,
+                no_password
+            )
+            .fetch_all(&mut *tx)
+            .await
+            .and_then(returned)
+            .map_err(|_| ApiError::Internal("Failed to create user".to_string()))?;
+
+            (user_id, username)
+        }
+    };
+
+    sqlx::query!(
+        "INSERT INTO user_identities (user_id, provider, subject, email, last_login_at) VALUES ($1, $2, $3, $4, CURRENT_TIMESTAMP)",
+        user_id,
+        provider,
+        claims.sub,
+        email
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok((user_id, username, email))
+}
+
+async fn unique_username(
+    db: &mut Connection<Db>,
+    claims: &IdTokenClaims,
+    email: &str,
+) -> ApiResult<String> {
+    let base: String = claims
+        .preferred_username
+        .as_deref()
+        .unwrap_or_else(|| email.split('@').next().unwrap_or_default())
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'))
+        .collect();
+    let base = if base.is_empty() { "user".to_string() } else { base };
+
+    let mut candidate = base.clone();
+    for _ in 0..5 {
+        let taken = sqlx::query!("SELECT id FROM users WHERE username = $1", candidate)
+            .fetch_optional(&mut ***db)
+            .await?
+            .is_some();
+        if !taken {
+            return Ok(candidate);
+        }
+        candidate = format!("{}-{:04x}", base, OsRng.next_u32() & 0xffff);
+    }
+
+    Err(ApiError::Conflict("Could not allocate a unique username".to_string()))
+}
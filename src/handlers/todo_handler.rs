@@ -1,262 +1,123 @@
-use rocket::http::Status;
-use rocket::response::status::NotFound;
-use rocket::serde::json::Json;
-
-use crate::database::{Db, DbResult};
+use crate::error::{ApiError, ApiResult};
 use crate::models::{
-    CreateTodoRequest, Priority, Status as TodoStatus, Todo, TodoResponse, UpdateTodoRequest,
+    CreateTodoRequest, Priority, Status as TodoStatus, TodoResponse, UpdateTodoRequest,
+    UsageResponse,
 };
-use rocket_db_pools::Connection;
-
-pub async fn create_todo(
-    mut db: Connection<Db>,
-    request: Json<CreateTodoRequest>,
-) -> DbResult<Json<TodoResponse>> {
-    let priority = request.priority.as_ref().unwrap_or(&Priority::Medium);
-    let status = request.status.as_ref().unwrap_or(&TodoStatus::Pending);
-
-    let status_str = status.as_str();
-    let priority_str = priority.as_str();
-
-    let result = sqlx::query!(
-        "INSERT INTO todos (title, description, status, priority) VALUES (?, ?, ?, ?)",
-        request.title,
-        request.description,
-        status_str,
-        priority_str
-    )
-    .execute(&mut **db)
-    .await?;
-
-    let id = result.last_insert_rowid();
-
-    // Get the created record
-    let created_record = sqlx::query!(
-        "SELECT id, title, description, status, priority, created_at, updated_at FROM todos WHERE id = ?",
-        id
-    )
-    .fetch_one(&mut **db)
-    .await?;
-
-    let todo = Todo {
-        id: Some(created_record.id),
-        title: created_record.title,
-        description: created_record.description,
-        status: created_record.status,
-        priority: created_record.priority,
-        created_at: created_record.created_at,
-        updated_at: created_record.updated_at,
-    };
+use crate::quota::{self, Limits};
+use crate::repository::{NewTodo, TodoFilter, TodoRepository};
 
-    Ok(Json(TodoResponse::from(todo)))
+fn not_found(id: i64) -> ApiError {
+    ApiError::NotFound(format!("Todo {} not found", id))
 }
 
-pub async fn get_todo(
-    mut db: Connection<Db>,
-    id: i64,
-) -> Result<Json<TodoResponse>, NotFound<String>> {
-    let result = sqlx::query!(
-        "SELECT id, title, description, status, priority, created_at, updated_at FROM todos WHERE id = ?",
-        id
-    )
-    .fetch_one(&mut **db)
-    .await
-    .map_err(|e| NotFound(format!("Todo not found: {}", e)))?;
-
-    let todo = Todo {
-        id: Some(result.id),
-        title: result.title,
-        description: result.description,
-        status: result.status,
-        priority: result.priority,
-        created_at: result.created_at,
-        updated_at: result.updated_at,
+pub async fn create_todo(
+    todos: &dyn TodoRepository,
+    limits: &Limits,
+    user_id: i64,
+    request: CreateTodoRequest,
+) -> ApiResult<TodoResponse> {
+    limits.check_description(request.description.as_deref())?;
+    let size = quota::size(&request.title, request.description.as_deref());
+    limits.check_growth(todos.usage(user_id).await?, 1, size)?;
+
+    let new = NewTodo {
+        title: request.title,
+        description: request.description,
+        status: request.status.unwrap_or_default(),
+        priority: request.priority.unwrap_or_default(),
     };
 
-    Ok(Json(TodoResponse::from(todo)))
+    let todo = todos.create(user_id, new).await?;
+
+    Ok(TodoResponse::from(todo))
 }
 
-pub async fn get_all_todos(mut db: Connection<Db>) -> DbResult<Json<Vec<TodoResponse>>> {
-    let results = sqlx::query!(
-        "SELECT id, title, description, status, priority, created_at, updated_at FROM todos ORDER BY created_at DESC"
-    )
-    .fetch_all(&mut **db)
-    .await?;
+pub async fn get_todo(todos: &dyn TodoRepository, user_id: i64, id: i64) -> ApiResult<TodoResponse> {
+    let todo = todos.find(user_id, id).await?.ok_or_else(|| not_found(id))?;
 
-    let todos: Vec<TodoResponse> = results
-        .into_iter()
-        .map(|row| {
-            let todo = Todo {
-                id: Some(row.id),
-                title: row.title,
-                description: row.description,
-                status: row.status,
-                priority: row.priority,
-                created_at: row.created_at,
-                updated_at: row.updated_at,
-            };
-            TodoResponse::from(todo)
-        })
-        .collect();
+    Ok(TodoResponse::from(todo))
+}
 
-    Ok(Json(todos))
+pub async fn get_all_todos(
+    todos: &dyn TodoRepository,
+    user_id: i64,
+) -> ApiResult<Vec<TodoResponse>> {
+    list(todos, user_id, TodoFilter::default()).await
 }
 
 pub async fn update_todo(
-    mut db: Connection<Db>,
+    todos: &dyn TodoRepository,
+    limits: &Limits,
+    user_id: i64,
     id: i64,
-    request: Json<UpdateTodoRequest>,
-) -> Result<Json<TodoResponse>, NotFound<String>> {
-    // First, get the existing todo
-    let existing = sqlx::query!(
-        "SELECT id, title, description, status, priority, created_at, updated_at FROM todos WHERE id = ?",
-        id
-    )
-    .fetch_one(&mut **db)
-    .await
-    .map_err(|e| NotFound(format!("Todo not found: {}", e)))?;
-
-    // Build update query dynamically
-    let mut update_fields = Vec::new();
-    let mut values: Vec<Box<dyn sqlx::Encode<'_, sqlx::Sqlite> + Send + Sync>> = Vec::new();
-
-    if let Some(title) = &request.title {
-        update_fields.push("title = ?");
-        values.push(Box::new(title.clone()));
-    }
-    if let Some(description) = &request.description {
-        update_fields.push("description = ?");
-        values.push(Box::new(description.clone()));
+    request: UpdateTodoRequest,
+) -> ApiResult<TodoResponse> {
+    if request.title.is_some() || request.description.is_some() {
+        limits.check_description(request.description.as_deref())?;
+
+        let current = todos.find(user_id, id).await?.ok_or_else(|| not_found(id))?;
+        let title = request.title.as_deref().unwrap_or(&current.title);
+        let description = request.description.as_deref().or(current.description.as_deref());
+        let growth = quota::size(title, description) - quota::size(&current.title, current.description.as_deref());
+        limits.check_growth(todos.usage(user_id).await?, 0, growth)?;
     }
-    if let Some(status) = &request.status {
-        update_fields.push("status = ?");
-        values.push(Box::new(status.as_str().to_string()));
-    }
-    if let Some(priority) = &request.priority {
-        update_fields.push("priority = ?");
-        values.push(Box::new(priority.as_str().to_string()));
-    }
-
-    if update_fields.is_empty() {
-        // No fields to update, return existing todo
-        let todo = Todo {
-            id: Some(existing.id),
-            title: existing.title,
-            description: existing.description,
-            status: existing.status,
-            priority: existing.priority,
-            created_at: existing.created_at,
-            updated_at: existing.updated_at,
-        };
-        return Ok(Json(TodoResponse::from(todo)));
-    }
-
-    update_fields.push("updated_at = CURRENT_TIMESTAMP");
 
-    let query = format!("UPDATE todos SET {} WHERE id = ?", update_fields.join(", "));
+    let todo = todos
+        .update(user_id, id, request)
+        .await?
+        .ok_or_else(|| not_found(id))?;
 
-    // Execute the update
-    let result = sqlx::query(&query)
-        .bind(id)
-        .execute(&mut **db)
-        .await
-        .map_err(|e| NotFound(format!("Failed to update todo: {}", e)))?;
+    Ok(TodoResponse::from(todo))
+}
 
-    if result.rows_affected() == 0 {
-        return Err(NotFound("Todo not found".to_string()));
+pub async fn delete_todo(todos: &dyn TodoRepository, user_id: i64, id: i64) -> ApiResult<()> {
+    if !todos.delete(user_id, id).await? {
+        return Err(not_found(id));
     }
 
-    // Get the updated record
-    let updated_record = sqlx::query!(
-        "SELECT id, title, description, status, priority, created_at, updated_at FROM todos WHERE id = ?",
-        id
-    )
-    .fetch_one(&mut **db)
-    .await
-    .map_err(|e| NotFound(format!("Failed to fetch updated todo: {}", e)))?;
+    Ok(())
+}
 
-    let result = Todo {
-        id: Some(updated_record.id),
-        title: updated_record.title,
-        description: updated_record.description,
-        status: updated_record.status,
-        priority: updated_record.priority,
-        created_at: updated_record.created_at,
-        updated_at: updated_record.updated_at,
+pub async fn get_todos_by_status(
+    todos: &dyn TodoRepository,
+    user_id: i64,
+    status: TodoStatus,
+) -> ApiResult<Vec<TodoResponse>> {
+    let filter = TodoFilter {
+        status: Some(status),
+        ..TodoFilter::default()
     };
 
-    Ok(Json(TodoResponse::from(result)))
+    list(todos, user_id, filter).await
 }
 
-pub async fn delete_todo(mut db: Connection<Db>, id: i64) -> Result<Status, NotFound<String>> {
-    let result = sqlx::query!("DELETE FROM todos WHERE id = ?", id)
-        .execute(&mut **db)
-        .await
-        .map_err(|e| NotFound(format!("Failed to delete todo: {}", e)))?;
-
-    if result.rows_affected() == 0 {
-        return Err(NotFound("Todo not found".to_string()));
-    }
+pub async fn get_todos_by_priority(
+    todos: &dyn TodoRepository,
+    user_id: i64,
+    priority: Priority,
+) -> ApiResult<Vec<TodoResponse>> {
+    let filter = TodoFilter {
+        priority: Some(priority),
+        ..TodoFilter::default()
+    };
 
-    Ok(Status::NoContent)
+    list(todos, user_id, filter).await
 }
 
-pub async fn get_todos_by_status(
-    mut db: Connection<Db>,
-    status: String,
-) -> DbResult<Json<Vec<TodoResponse>>> {
-    let results = sqlx::query!(
-        "SELECT id, title, description, status, priority, created_at, updated_at FROM todos WHERE status = ? ORDER BY created_at DESC",
-        status
-    )
-    .fetch_all(&mut **db)
-    .await?;
-
-    let todos: Vec<TodoResponse> = results
-        .into_iter()
-        .map(|row| {
-            let todo = Todo {
-                id: row.id,
-                title: row.title,
-                description: row.description,
-                status: row.status,
-                priority: row.priority,
-                created_at: row.created_at,
-                updated_at: row.updated_at,
-            };
-            TodoResponse::from(todo)
-        })
-        .collect();
+async fn list(
+    todos: &dyn TodoRepository,
+    user_id: i64,
+    filter: TodoFilter,
+) -> ApiResult<Vec<TodoResponse>> {
+    let todos = todos.list(user_id, filter).await?;
 
-    Ok(Json(todos))
+    Ok(todos.into_iter().map(TodoResponse::from).collect())
 }
 
-pub async fn get_todos_by_priority(
-    mut db: Connection<Db>,
-    priority: String,
-) -> DbResult<Json<Vec<TodoResponse>>> {
-    let results = sqlx::query!(
-        "SELECT id, title, description, status, priority, created_at, updated_at FROM todos WHERE priority = ? ORDER BY created_at DESC",
-        priority
-    )
-    .fetch_all(&mut **db)
-    .await?;
-
-    let todos: Vec<TodoResponse> = results
-        .into_iter()
-        .map(|row| {
-            let todo = Todo {
-                id: row.id,
-                title: row.title,
-                description: row.description,
-                status: row.status,
-                priority: row.priority,
-                created_at: row.created_at,
-                updated_at: row.updated_at,
-            };
-            TodoResponse::from(todo)
-        })
-        .collect();
-
-    Ok(Json(todos))
+pub async fn get_usage(
+    todos: &dyn TodoRepository,
+    limits: &Limits,
+    user_id: i64,
+) -> ApiResult<UsageResponse> {
+    Ok(limits.usage_response(todos.usage(user_id).await?))
 }
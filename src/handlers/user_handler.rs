@@ -0,0 +1,339 @@
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use rocket::http::CookieJar;
+use rocket::response::status;
+use rocket::serde::json::Json;
+use rocket_db_pools::Connection;
+use sqlx::Connection as _;
+
+use crate::auth::jwt::{create_token, JwtAuth};
+use crate::auth::password::{generate_token, hash_password, hash_token, verify_password};
+use crate::auth::session;
+use crate::config::JwtConfig;
+use crate::database::Db;
+use crate::error::{ApiError, ApiResult};
+use crate::events::{EventKind, EventLog};
+use crate::handlers::auth_handler::clear_auth_cookies;
+use crate::mailer::Mailer;
+use crate::models::{
+    ChangePasswordRequest, DeleteAccountRequest, TodoDisposition, UpdateProfileRequest,
+    UserResponse, VerifyEmailRequest,
+};
+
+/// How long an email verification token stays valid
+const EMAIL_VERIFICATION_HOURS: i64 = 24;
+
+/// Accounts without a password must have signed in this recently to delete
+/// themselves
+const RECENT_LOGIN_MINUTES: i64 = 10;
+
+async fn load_user(db: &mut Connection<Db>, user_id: i64) -> ApiResult<UserResponse> {
+    let user = sqlx::query!(
+        "SELECT id, username, email, pending_email, created_at FROM users WHERE id = $1",
+        user_id
+    )
+    .fetch_optional(&mut ***db)
+    .await?
+    .ok_or_else(|| ApiError::NotFound("User not found".to_string()))?;
+
+    Ok(UserResponse {
+        id: user.id,
+        username: user.username,
+        email: user.email,
+        pending_email: user.pending_email,
+        created_at: user
+            .created_at
+            .map(|dt| DateTime::from_naive_utc_and_offset(dt, Utc))
+            .unwrap_or_else(Utc::now),
+    })
+}
+
+/// Username and email live in the JWT claims, so profile changes come with a
+/// fresh token for the current session
+fn profile_response(jwt: &JwtConfig, auth: &JwtAuth, user: UserResponse) -> ApiResult<Json<serde_json::Value>> {
+    let session_id = auth.session_id.as_deref().unwrap_or_default();
+    let token = create_token(jwt, &user.id.to_string(), &user.email, &user.username, session_id)
+        .map_err(|_| ApiError::Internal("Failed to create token".to_string()))?;
+
+    Ok(Json(serde_json::json!({
+        "user": user,
+        "token": token
+    })))
+}
+
+pub async fn update_profile(
+    mut db: Connection<Db>,
+    mailer: &Mailer,
+    jwt: &JwtConfig,
+    auth: JwtAuth,
+    request: UpdateProfileRequest,
+) -> ApiResult<Json<serde_json::Value>> {
+    let user_id = auth.require_session()?;
+    let current = load_user(&mut db, user_id).await?;
+
+    // Everything is checked before anything is written, so a rejected email
+    // does not leave a changed username behind
+    let username = request.username.as_deref();
+    if let Some(username) = username {
+        let taken = sqlx::query!(
+            "SELECT id FROM users WHERE username = $1 AND id != $2",
+            username,
+            user_id
+        )
+        .fetch_optional(&mut **db)
+        .await?;
+        if taken.is_some() {
+            return Err(ApiError::Conflict("Username already taken".to_string()));
+        }
+    }
+
+    let email = request.email.as_deref().filter(|email| *email != current.email);
+    if let Some(email) = email {
+        let taken = sqlx::query!("SELECT id FROM users WHERE email = $1", email)
+            .fetch_optional(&mut **db)
+            .await?;
+        if taken.is_some() {
+            return Err(ApiError::Conflict("Email already in use".to_string()));
+        }
+    }
+
+    let mut tx = db.begin().await?;
+
+    if let Some(username) = username {
+        // A concurrent update may still take the name first
+        let result = sqlx::query!("UPDATE users SET username = $1 WHERE id = $2", username, user_id)
+            .execute(&mut *tx)
+            .await;
+        match result {
+            Ok(_) => {}
+            Err(sqlx::Error::Database(e)) if e.is_unique_violation() => {
+                return Err(ApiError::Conflict("Username already taken".to_string()));
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    // The address only changes once the owner proves they control it
+    let verification = email.map(|email| (email, generate_token()));
+    if let Some((email, token)) = &verification {
+        let token_hash = hash_token(token);
+        let expires_at =
+            Utc::now().naive_utc() + chrono::Duration::hours(EMAIL_VERIFICATION_HOURS);
+
+        sqlx::query!(
+            "UPDATE users SET pending_email = $1, email_verification_hash = $2, email_verification_expires_at = $3 WHERE id = $4",
+            email,
+            token_hash,
+            expires_at,
+            user_id
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await?;
+
+    if let Some((email, token)) = verification {
+        mailer
+            .send(
+                email,
+                "Confirm your new email address",
+                &format!(
+                    "Confirm this address by sending the token {} to POST /api/users/me/email/verify within {} hours.",
+                    token, EMAIL_VERIFICATION_HOURS
+                ),
+            )
+            .await;
+    }
+
+    let user = load_user(&mut db, user_id).await?;
+    profile_response(jwt, &auth, user)
+}
+
+pub async fn verify_email(
+    mut db: Connection<Db>,
+    jwt: &JwtConfig,
+    auth: JwtAuth,
+    request: VerifyEmailRequest,
+) -> ApiResult<Json<serde_json::Value>> {
+    let user_id = auth.require_session()?;
+    let token_hash = hash_token(&request.token);
+
+    let pending = sqlx::query!(
+        "SELECT pending_email, email_verification_expires_at FROM users WHERE id = $1 AND email_verification_hash = $2",
+        user_id,
+        token_hash
+    )
+    .fetch_optional(&mut **db)
+    .await?
+    .ok_or_else(|| ApiError::BadRequest("Invalid verification token".to_string()))?;
+
+    let Some(email) = pending.pending_email else {
+        return Err(ApiError::BadRequest("Invalid verification token".to_string()));
+    };
+    if pending
+        .email_verification_expires_at
+        .is_none_or(|exp| exp <= Utc::now().naive_utc())
+    {
+        return Err(ApiError::BadRequest("Verification token expired".to_string()));
+    }
+
+    let result = sqlx::query!(
+        "UPDATE users SET email = $1, email_verified_at = CURRENT_TIMESTAMP, pending_email = NULL, email_verification_hash = NULL, email_verification_expires_at = NULL WHERE id = $2",
+        email,
+        user_id
+    )
+    .execute(&mut **db)
+    .await;
+
+    // Someone else may have claimed the address since the change was requested
+    match result {
+        Ok(_) => {}
+        Err(sqlx::Error::Database(e)) if e.is_unique_violation() => {
+            return Err(ApiError::Conflict("Email already in use".to_string()));
+        }
+        Err(e) => return Err(e.into()),
+    }
+
+    let user = load_user(&mut db, user_id).await?;
+    profile_response(jwt, &auth, user)
+}
+
+pub async fn change_password(
+    mut db: Connection<Db>,
+    auth: JwtAuth,
+    request: ChangePasswordRequest,
+) -> ApiResult<Json<serde_json::Value>> {
+    let user_id = auth.require_session()?;
+
+    let user = sqlx::query!("SELECT password_hash FROM users WHERE id = $1", user_id)
+        .fetch_optional(&mut **db)
+        .await?
+        .ok_or_else(|| ApiError::NotFound("User not found".to_string()))?;
+
+    let valid = verify_password(&request.current_password, &user.password_hash)
+        .map_err(|_| ApiError::Internal("Password hash error".to_string()))?;
+    if !valid {
+        return Err(ApiError::Unauthorized("Current password is incorrect".to_string()));
+    }
+
+    let password_hash = hash_password(&request.new_password)
+        .map_err(|_| ApiError::Internal("Failed to hash password".to_string()))?;
+
+    // One transaction, so the new password never coexists with old sessions
+    let mut tx = db.begin().await?;
+    sqlx::query!(
+        "UPDATE users SET password_hash = $1 WHERE id = $2",
+        password_hash,
+        user_id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    // Everyone else holding a token for this account has to sign in again
+    let current_session = auth.session_id.as_deref().unwrap_or_default();
+    let revoked = session::revoke_others(&mut tx, user_id, current_session)
+        .await?;
+    tx.commit().await?;
+
+    Ok(Json(serde_json::json!({
+        "message": "Password changed",
+        "revoked_sessions": revoked
+    })))
+}
+
+pub async fn delete_account(
+    mut db: Connection<Db>,
+    events: &Arc<EventLog>,
+    auth: JwtAuth,
+    request: DeleteAccountRequest,
+    cookies: &CookieJar<'_>,
+) -> ApiResult<status::NoContent> {
+    let user_id = auth.require_session()?;
+
+    let user = sqlx::query!("SELECT password_hash FROM users WHERE id = $1", user_id)
+        .fetch_optional(&mut **db)
+        .await?
+        .ok_or_else(|| ApiError::NotFound("User not found".to_string()))?;
+
+    // Re-authenticate: by password when the account has one, otherwise by a
+    // recent sign-in through the identity provider
+    if user.password_hash == crate::models::User::NO_PASSWORD {
+        let session_id = auth.session_id.as_deref().unwrap_or_default();
+        let recent = session::is_recent(&mut db, session_id, RECENT_LOGIN_MINUTES)
+            .await?;
+        if !recent {
+            return Err(ApiError::Unauthorized("Please sign in again before deleting your account".to_string()));
+        }
+    } else {
+        let password = request.password.as_deref().unwrap_or_default();
+        let valid = verify_password(password, &user.password_hash)
+            .map_err(|_| ApiError::Internal("Password hash error".to_string()))?;
+        if !valid {
+            return Err(ApiError::Unauthorized("Password is incorrect".to_string()));
+        }
+    }
+
+    let export_files = sqlx::query!(
+        "SELECT file_path FROM data_exports WHERE user_id = $1 AND file_path IS NOT NULL",
+        user_id
+    )
+    .fetch_all(&mut **db)
+    .await?;
+
+    let mut tx = db.begin().await?;
+
+    let todo_ids = sqlx::query_scalar!(r#"SELECT id AS "id!" FROM todos WHERE user_id = $1"#, user_id)
+        .fetch_all(&mut *tx)
+        .await?;
+    match request.todos {
+        TodoDisposition::Delete => {
+            sqlx::query!("DELETE FROM todos WHERE user_id = $1", user_id)
+                .execute(&mut *tx)
+                .await?;
+        }
+        TodoDisposition::Anonymize => {
+            sqlx::query!("UPDATE todos SET user_id = NULL WHERE user_id = $1", user_id)
+                .execute(&mut *tx)
+                .await?;
+        }
+    }
+
+    // The account's change history goes with it
+    sqlx::query!("DELETE FROM todo_events WHERE user_id = $1", user_id)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query!("DELETE FROM api_keys WHERE user_id = $1", user_id)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query!("DELETE FROM user_identities WHERE user_id = $1", user_id)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query!("DELETE FROM data_exports WHERE user_id = $1", user_id)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query!("DELETE FROM sessions WHERE user_id = $1", user_id)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query!("DELETE FROM users WHERE id = $1", user_id)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+
+    // Deleted or given up, the todos are gone as far as the account's
+    // streams and webhooks are concerned
+    for todo_id in todo_ids {
+        if let Err(e) = events.append(user_id, EventKind::Deleted, todo_id, None, None).await {
+            tracing::warn!(event = "todo_event_lost", error = %e, todo_id, kind = EventKind::Deleted.event_type());
+        }
+    }
+
+    for export in export_files.into_iter().filter_map(|row| row.file_path) {
+        let _ = rocket::tokio::fs::remove_file(export).await;
+    }
+
+    clear_auth_cookies(cookies);
+    Ok(status::NoContent)
+}
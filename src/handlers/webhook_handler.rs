@@ -0,0 +1,173 @@
+use chrono::Utc;
+use rocket::response::status;
+use rocket::serde::json::Json;
+
+use crate::auth::jwt::{AdminAuth, JwtAuth};
+use crate::database::{returned, Db};
+use crate::error::{ApiError, ApiResult};
+use crate::models::{
+    CreateWebhookRequest, CreatedWebhookResponse, DeliveryResponse, DeliveryStatus, Webhook, WebhookDelivery,
+    WebhookResponse,
+};
+use crate::validation::{FieldError, Validated};
+use crate::webhooks::{self, WebhookConfig, EVENT_TYPES};
+
+/// Deliveries listed per webhook, newest first
+const DELIVERY_LOG_LIMIT: i64 = 100;
+
+/// The caller's webhook; other users' look the same as missing ones
+async fn owned_webhook(db: &Db, user_id: i64, id: i64) -> ApiResult<Webhook> {
+    sqlx::query_as!(
+        Webhook,
+        r#"SELECT id AS "id!", user_id, url, event_types, all_users, created_at FROM webhooks WHERE id = $1 AND user_id = $2"#,
+        id,
+        user_id
+    )
+    .fetch_optional(&**db)
+    .await?
+    .ok_or_else(|| ApiError::NotFound("Webhook not found".to_string()))
+}
+
+async fn find_delivery(db: &Db, id: i64) -> ApiResult<WebhookDelivery> {
+    let delivery = sqlx::query_as!(
+        WebhookDelivery,
+        r#"SELECT id AS "id!", webhook_id, event_id, event_type, status AS "status: DeliveryStatus", attempts,
+            next_attempt_at, last_attempt_at, response_status, last_error, redelivery_of, created_at
+        FROM webhook_deliveries WHERE id = $1"#,
+        id
+    )
+    .fetch_one(&**db)
+    .await?;
+
+    Ok(delivery)
+}
+
+pub async fn create_webhook(
+    db: &Db,
+    config: &WebhookConfig,
+    auth: JwtAuth,
+    admin: Option<AdminAuth>,
+    request: Validated<CreateWebhookRequest>,
+) -> ApiResult<status::Created<Json<CreatedWebhookResponse>>> {
+    let user_id = auth.require_session()?;
+    if request.all_users && admin.is_none() {
+        return Err(ApiError::Forbidden(
+            "Only administrators can receive every user's events".to_string(),
+        ));
+    }
+    // Deliveries check again, in case the name is pointed elsewhere later
+    if !config.allow_private_networks
+        && let Err(reason) = webhooks::check_destination(&request.url).await
+    {
+        tracing::warn!(event = "webhook_destination_refused", user_id, reason = %reason);
+        return Err(FieldError::new("url", "forbidden_destination", format!("must be a public endpoint; it {}", reason)).into());
+    }
+
+    let event_types = match &request.event_types {
+        Some(types) => types.join(" "),
+        None => EVENT_TYPES.join(" "),
+    };
+    let secret = webhooks::generate_secret();
+    let created_at = Utc::now().naive_utc();
+
+    let id = returned(
+        sqlx::query_scalar!(
+            r#"INSERT INTO webhooks (user_id, url, secret, event_types, all_users, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6) RETURNING id AS "id!""#,
+            user_id,
+            request.url,
+            secret,
+            event_types,
+            request.all_users,
+            created_at
+        )
+        .fetch_all(&**db)
+        .await?,
+    )?;
+
+    let webhook = owned_webhook(db, user_id, id).await?;
+    tracing::info!(event = "webhook_created", webhook_id = id, user_id, all_users = webhook.all_users);
+
+    Ok(status::Created::new(format!("/api/webhooks/{}", id)).body(Json(CreatedWebhookResponse {
+        webhook: WebhookResponse::from(webhook),
+        secret,
+    })))
+}
+
+pub async fn list_webhooks(db: &Db, auth: JwtAuth) -> ApiResult<Json<Vec<WebhookResponse>>> {
+    let user_id = auth.require_session()?;
+
+    let webhooks = sqlx::query_as!(
+        Webhook,
+        r#"SELECT id AS "id!", user_id, url, event_types, all_users, created_at FROM webhooks WHERE user_id = $1 ORDER BY id"#,
+        user_id
+    )
+    .fetch_all(&**db)
+    .await?;
+
+    Ok(Json(webhooks.into_iter().map(WebhookResponse::from).collect()))
+}
+
+pub async fn delete_webhook(db: &Db, auth: JwtAuth, id: i64) -> ApiResult<status::NoContent> {
+    let user_id = auth.require_session()?;
+
+    // Its deliveries go with it, pending ones included
+    let result = sqlx::query!("DELETE FROM webhooks WHERE id = $1 AND user_id = $2", id, user_id)
+        .execute(&**db)
+        .await?;
+    if result.rows_affected() == 0 {
+        return Err(ApiError::NotFound("Webhook not found".to_string()));
+    }
+
+    tracing::info!(event = "webhook_deleted", webhook_id = id, user_id);
+    Ok(status::NoContent)
+}
+
+pub async fn list_deliveries(db: &Db, auth: JwtAuth, id: i64) -> ApiResult<Json<Vec<DeliveryResponse>>> {
+    let user_id = auth.require_session()?;
+    let webhook = owned_webhook(db, user_id, id).await?;
+
+    let deliveries = sqlx::query_as!(
+        WebhookDelivery,
+        r#"SELECT id AS "id!", webhook_id, event_id, event_type, status AS "status: DeliveryStatus", attempts,
+            next_attempt_at, last_attempt_at, response_status, last_error, redelivery_of, created_at
+        FROM webhook_deliveries WHERE webhook_id = $1 ORDER BY id DESC LIMIT $2"#,
+        webhook.id,
+        DELIVERY_LOG_LIMIT
+    )
+    .fetch_all(&**db)
+    .await?;
+
+    Ok(Json(deliveries.into_iter().map(DeliveryResponse::from).collect()))
+}
+
+/// Queues the payload of an earlier delivery again, as a new delivery
+/// with a fresh set of attempts
+pub async fn redeliver(
+    db: &Db,
+    auth: JwtAuth,
+    id: i64,
+    delivery_id: i64,
+) -> ApiResult<status::Accepted<Json<DeliveryResponse>>> {
+    let user_id = auth.require_session()?;
+    let webhook = owned_webhook(db, user_id, id).await?;
+    let now = Utc::now().naive_utc();
+
+    let queued = sqlx::query_scalar!(
+        r#"INSERT INTO webhook_deliveries (webhook_id, event_id, event_type, payload, status, next_attempt_at, redelivery_of, created_at)
+        SELECT webhook_id, event_id, event_type, payload, 'pending', $1, id, $2 FROM webhook_deliveries
+        WHERE id = $3 AND webhook_id = $4 RETURNING id AS "id!""#,
+        now,
+        now,
+        delivery_id,
+        webhook.id
+    )
+    .fetch_all(&**db)
+    .await?;
+    let Some(queued) = queued.into_iter().next() else {
+        return Err(ApiError::NotFound("Delivery not found".to_string()));
+    };
+
+    tracing::info!(event = "webhook_redelivery_queued", webhook_id = webhook.id, delivery_id, redelivery_id = queued);
+    Ok(status::Accepted(Json(DeliveryResponse::from(find_delivery(db, queued).await?))))
+}
@@ -0,0 +1,457 @@
+//! Health checks behind `/health` and `/ready`. Each check reports pass,
+//! warn or fail with its timing, and to administrators what it found; a
+//! failing critical check makes the service not ready, and so does a shutdown in progress, so load balancers stop
+//! sending traffic before connections are drained.
+//!
+//! Other parts of the application add checks with [`Health::register`] and
+//! have their background tasks watched with [`Health::watch`].
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use rocket::fairing::AdHoc;
+use rocket::figment::Figment;
+use rocket::futures::future::join_all;
+use rocket::serde::{Deserialize, Serialize};
+use rocket::tokio::task::JoinHandle;
+use rocket_db_pools::Database;
+use utoipa::ToSchema;
+
+use crate::config::InvalidConfig;
+use crate::database::{self, Db, DbPool, MigrationSummary};
+use crate::telemetry::logging::{LoggingConfig, Sink};
+
+/// The `[health]` section of Rocket.toml
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct HealthConfig {
+    /// Longest a single check may run before it counts as failed
+    pub timeout_ms: u64,
+    /// Database round trips slower than this are reported as `warn`
+    pub slow_query_ms: u64,
+    /// Free space below this on the database or log volume fails the disk check
+    pub min_free_mb: u64,
+}
+
+impl HealthConfig {
+    /// Reads and checks the `[health]` section
+    pub fn load(figment: &Figment) -> Result<Self, InvalidConfig> {
+        let config: HealthConfig = figment.extract_inner("health").or_else(|e| {
+            if e.missing() {
+                Ok(HealthConfig::default())
+            } else {
+                Err(InvalidConfig(e.into_iter().map(|e| e.to_string()).collect()))
+            }
+        })?;
+        // Every check would time out, so the service would never be ready
+        if config.timeout_ms == 0 {
+            return Err(InvalidConfig(vec!["health.timeout_ms must be at least 1".to_string()]));
+        }
+        Ok(config)
+    }
+}
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        HealthConfig {
+            timeout_ms: 2000,
+            slow_query_ms: 250,
+            min_free_mb: 256,
+        }
+    }
+}
+
+/// Ordered from best to worst, so the overall status is the maximum
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Pass,
+    Warn,
+    Fail,
+}
+
+/// What one check found
+#[derive(Debug, Clone)]
+pub struct Check {
+    pub status: CheckStatus,
+    pub detail: Option<String>,
+    pub data: Option<serde_json::Value>,
+}
+
+impl Check {
+    pub fn pass() -> Self {
+        Check {
+            status: CheckStatus::Pass,
+            detail: None,
+            data: None,
+        }
+    }
+
+    pub fn warn(detail: impl Into<String>) -> Self {
+        Check {
+            status: CheckStatus::Warn,
+            detail: Some(detail.into()),
+            data: None,
+        }
+    }
+
+    pub fn fail(detail: impl Into<String>) -> Self {
+        Check {
+            status: CheckStatus::Fail,
+            detail: Some(detail.into()),
+            data: None,
+        }
+    }
+
+    pub fn with_data(self, data: impl Serialize) -> Self {
+        Check {
+            data: serde_json::to_value(data).ok(),
+            ..self
+        }
+    }
+}
+
+#[rocket::async_trait]
+pub trait HealthCheck: Send + Sync + 'static {
+    fn name(&self) -> &'static str;
+
+    /// Whether failing makes the service not ready. Non-critical checks
+    /// only show up in `/health`.
+    fn critical(&self) -> bool {
+        true
+    }
+
+    async fn check(&self) -> Check;
+}
+
+/// One check in a report
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CheckResult {
+    pub status: CheckStatus,
+    pub critical: bool,
+    pub duration_ms: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<Object>)]
+    pub data: Option<serde_json::Value>,
+}
+
+pub struct Health {
+    config: HealthConfig,
+    checks: RwLock<Vec<Arc<dyn HealthCheck>>>,
+    jobs: Arc<Jobs>,
+    shutting_down: Arc<AtomicBool>,
+}
+
+impl Health {
+    pub fn new(config: HealthConfig) -> Self {
+        let shutting_down = Arc::new(AtomicBool::new(false));
+        let jobs = Arc::new(Jobs {
+            handles: Mutex::default(),
+            shutting_down: shutting_down.clone(),
+        });
+        Health {
+            config,
+            checks: RwLock::new(vec![jobs.clone()]),
+            jobs,
+            shutting_down,
+        }
+    }
+
+    /// Adds a check, replacing any with the same name
+    pub fn register(&self, check: impl HealthCheck) {
+        let mut checks = self.checks.write().unwrap();
+        checks.retain(|c| c.name() != check.name());
+        checks.push(Arc::new(check));
+    }
+
+    /// Reports the background task as dead once it ends before shutdown
+    pub fn watch(&self, job: &'static str, handle: JoinHandle<()>) {
+        self.jobs.handles.lock().unwrap().push((job, handle));
+    }
+
+    pub fn is_shutting_down(&self) -> bool {
+        self.shutting_down.load(Ordering::SeqCst)
+    }
+
+    /// Runs the checks concurrently, each under the configured timeout
+    pub async fn run(&self, critical_only: bool) -> BTreeMap<&'static str, CheckResult> {
+        let checks: Vec<_> = self
+            .checks
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|c| c.critical() || !critical_only)
+            .cloned()
+            .collect();
+        let timeout = Duration::from_millis(self.config.timeout_ms);
+
+        let results = join_all(checks.iter().map(|check| async move {
+            let start = Instant::now();
+            let outcome = rocket::tokio::time::timeout(timeout, check.check())
+                .await
+                .unwrap_or_else(|_| Check::fail(format!("timed out after {}ms", timeout.as_millis())));
+            let result = CheckResult {
+                status: outcome.status,
+                critical: check.critical(),
+                duration_ms: start.elapsed().as_secs_f64() * 1000.0,
+                detail: outcome.detail,
+                data: outcome.data,
+            };
+            if result.status != CheckStatus::Pass {
+                tracing::warn!(
+                    event = "health_check_degraded",
+                    check = check.name(),
+                    status = ?result.status,
+                    detail = result.detail.as_deref().unwrap_or_default()
+                );
+            }
+            (check.name(), result)
+        }))
+        .await;
+        results.into_iter().collect()
+    }
+}
+
+/// The worst status among `checks`
+pub fn overall(checks: &BTreeMap<&'static str, CheckResult>) -> CheckStatus {
+    checks.values().map(|c| c.status).max().unwrap_or(CheckStatus::Pass)
+}
+
+/// What callers other than administrators see of a report: the statuses,
+/// without the details and data, which can carry database errors and
+/// filesystem paths. The specifics are in the `health_check_degraded` log.
+pub fn redact(checks: &mut BTreeMap<&'static str, CheckResult>) {
+    for check in checks.values_mut() {
+        check.data = None;
+        if check.detail.is_some() {
+            check.detail = Some("details are in the server log".to_string());
+        }
+    }
+}
+
+/// Background tasks that should run until shutdown
+struct Jobs {
+    handles: Mutex<Vec<(&'static str, JoinHandle<()>)>>,
+    shutting_down: Arc<AtomicBool>,
+}
+
+#[rocket::async_trait]
+impl HealthCheck for Jobs {
+    fn name(&self) -> &'static str {
+        "background_jobs"
+    }
+
+    fn critical(&self) -> bool {
+        false
+    }
+
+    async fn check(&self) -> Check {
+        let jobs: BTreeMap<_, _> = self
+            .handles
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(job, handle)| (*job, if handle.is_finished() { "stopped" } else { "running" }))
+            .collect();
+        let stopped: Vec<_> = jobs.iter().filter(|(_, s)| **s == "stopped").map(|(j, _)| *j).collect();
+        // They stop on purpose at shutdown
+        let check = if stopped.is_empty() || self.shutting_down.load(Ordering::SeqCst) {
+            Check::pass()
+        } else {
+            Check::fail(format!("stopped: {}", stopped.join(", ")))
+        };
+        check.with_data(jobs)
+    }
+}
+
+/// A `SELECT 1` round trip
+struct DatabaseCheck {
+    pool: DbPool,
+    slow: Duration,
+}
+
+#[rocket::async_trait]
+impl HealthCheck for DatabaseCheck {
+    fn name(&self) -> &'static str {
+        "database"
+    }
+
+    async fn check(&self) -> Check {
+        let start = Instant::now();
+        if let Err(e) = sqlx::query("SELECT 1").execute(&self.pool).await {
+            return Check::fail(e.to_string());
+        }
+        let check = match start.elapsed() {
+            elapsed if elapsed > self.slow => Check::warn(format!("round trip took {}ms", elapsed.as_millis())),
+            _ => Check::pass(),
+        };
+        check.with_data(serde_json::json!({
+            "system": database::DB_SYSTEM,
+            "connections": self.pool.size(),
+            "idle": self.pool.num_idle(),
+        }))
+    }
+}
+
+/// The schema is the one this build expects
+struct MigrationsCheck {
+    pool: DbPool,
+}
+
+#[rocket::async_trait]
+impl HealthCheck for MigrationsCheck {
+    fn name(&self) -> &'static str {
+        "migrations"
+    }
+
+    async fn check(&self) -> Check {
+        let status = match database::migration_status(&self.pool).await {
+            Ok(status) => status,
+            Err(e) => return Check::fail(format!("could not read applied migrations: {}", e)),
+        };
+        let summary = MigrationSummary::of(&status);
+        let check = if summary.is_current() {
+            Check::pass()
+        } else {
+            Check::fail(format!(
+                "{} pending, {} changed since applied",
+                summary.pending.len(),
+                summary.checksum_mismatches.len()
+            ))
+        };
+        check.with_data(summary)
+    }
+}
+
+/// Free space on the volumes the server writes to
+struct DiskCheck {
+    paths: Vec<(&'static str, PathBuf)>,
+    min_free_mb: u64,
+}
+
+#[rocket::async_trait]
+impl HealthCheck for DiskCheck {
+    fn name(&self) -> &'static str {
+        "disk"
+    }
+
+    fn critical(&self) -> bool {
+        false
+    }
+
+    async fn check(&self) -> Check {
+        let mut data = BTreeMap::new();
+        let mut low = Vec::new();
+        for (name, path) in &self.paths {
+            match free_bytes(path) {
+                Ok(free) => {
+                    let free_mb = free / (1024 * 1024);
+                    if free_mb < self.min_free_mb {
+                        low.push(format!("{} has {}MB free", name, free_mb));
+                    }
+                    data.insert(*name, serde_json::json!({ "path": path, "free_mb": free_mb }));
+                }
+                Err(e) => return Check::warn(format!("cannot read free space of {}: {}", path.display(), e)),
+            }
+        }
+        let check = if low.is_empty() {
+            Check::pass()
+        } else {
+            Check::fail(low.join(", "))
+        };
+        check.with_data(data)
+    }
+}
+
+/// Space available to unprivileged writers on the volume holding `path`,
+/// or its nearest existing ancestor
+#[cfg(unix)]
+fn free_bytes(path: &Path) -> std::io::Result<u64> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let existing = path.ancestors().find(|p| p.exists()).unwrap_or(Path::new("."));
+    let existing = if existing.as_os_str().is_empty() { Path::new(".") } else { existing };
+    let c_path = CString::new(existing.as_os_str().as_bytes())?;
+    let mut stat = std::mem::MaybeUninit::<libc::statvfs>::uninit();
+    // SAFETY: `c_path` is NUL-terminated and `stat` is only read once
+    // statvfs has filled it in
+    let stat = unsafe {
+        if libc::statvfs(c_path.as_ptr(), stat.as_mut_ptr()) != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        stat.assume_init()
+    };
+    // The field widths differ between platforms
+    #[allow(clippy::unnecessary_cast)]
+    Ok(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+#[cfg(not(unix))]
+fn free_bytes(_path: &Path) -> std::io::Result<u64> {
+    Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "not supported on this platform"))
+}
+
+pub fn stage() -> AdHoc {
+    AdHoc::try_on_ignite("Health Checks", |rocket| async {
+        let config = match HealthConfig::load(rocket.figment()) {
+            Ok(config) => config,
+            Err(InvalidConfig(problems)) => {
+                for problem in &problems {
+                    tracing::error!(event = "invalid_config", problem = %problem);
+                }
+                return Err(rocket);
+            }
+        };
+
+        Ok(rocket
+            .manage(Health::new(config))
+            .attach(AdHoc::on_liftoff("Health Checks", |rocket| {
+                Box::pin(async move {
+                    let Some(health) = rocket.state::<Health>() else {
+                        return;
+                    };
+
+                    // Flip to not ready as soon as shutdown is requested,
+                    // while the grace period lets connections drain
+                    let shutting_down = health.shutting_down.clone();
+                    let shutdown = rocket.shutdown();
+                    rocket::tokio::spawn(async move {
+                        shutdown.await;
+                        shutting_down.store(true, Ordering::SeqCst);
+                        tracing::info!(event = "readiness_withdrawn", "shutting down; no longer ready");
+                    });
+
+                    let mut paths = Vec::new();
+                    if let Some(db) = Db::fetch(rocket) {
+                        let pool = (**db).clone();
+                        #[cfg(not(feature = "postgres"))]
+                        if let Some(file) = database::database_file(&pool).await {
+                            paths.push(("database", file.parent().map(Path::to_path_buf).unwrap_or_default()));
+                        }
+                        health.register(DatabaseCheck {
+                            pool: pool.clone(),
+                            slow: Duration::from_millis(health.config.slow_query_ms),
+                        });
+                        health.register(MigrationsCheck { pool });
+                    }
+                    let logging = rocket
+                        .figment()
+                        .extract_inner::<LoggingConfig>("logging")
+                        .unwrap_or_default();
+                    if logging.sinks.contains(&Sink::File) {
+                        paths.push(("logs", logging.dir));
+                    }
+                    health.register(DiskCheck {
+                        paths,
+                        min_free_mb: health.config.min_free_mb,
+                    });
+                })
+            })))
+    })
+}
@@ -0,0 +1,204 @@
+#[macro_use]
+extern crate rocket;
+
+pub mod admin;
+pub mod auth;
+pub mod backup;
+pub mod config;
+mod cors;
+pub mod database;
+mod error;
+mod events;
+pub mod export;
+mod handlers;
+pub mod health;
+mod mailer;
+mod maintenance;
+mod metrics;
+pub mod models;
+pub mod repository;
+mod quota;
+mod rate_limit;
+mod realtime;
+mod routes;
+mod security_headers;
+pub mod telemetry;
+pub mod validation;
+mod webhooks;
+
+#[cfg(test)]
+mod tests;
+
+use rocket::figment::Figment;
+use rocket::serde::json::Json;
+use rocket::http::Status;
+use rocket::response::status;
+use rocket::{Build, Rocket, State};
+use utoipa::OpenApi;
+use utoipa_swagger_ui::SwaggerUi;
+
+use auth::jwt::AdminAuth;
+use config::{AppConfig, PublicConfig};
+use health::{CheckStatus, Health};
+
+#[get("/")]
+fn index() -> Json<serde_json::Value> {
+    Json(serde_json::json!({
+        "message": "Welcome to Todo List API",
+        "version": env!("CARGO_PKG_VERSION"),
+        "endpoints": {
+            "todos": "/api/todos",
+            "health": "/health",
+            "docs": "/docs"
+        }
+    }))
+}
+
+/// Every check, critical or not; 503 when any of them fails. Only
+/// administrators see the details.
+#[get("/health")]
+async fn health_check(health: &State<Health>, admin: Option<AdminAuth>) -> status::Custom<Json<serde_json::Value>> {
+    let mut checks = health.run(false).await;
+    let overall = health::overall(&checks);
+    if admin.is_none() {
+        health::redact(&mut checks);
+    }
+
+    status::Custom(
+        if overall == CheckStatus::Fail { Status::ServiceUnavailable } else { Status::Ok },
+        Json(serde_json::json!({
+            "status": overall,
+            "checks": checks,
+            "timestamp": chrono::Utc::now()
+        })),
+    )
+}
+
+#[get("/live")]
+fn live() -> Json<serde_json::Value> {
+    Json(serde_json::json!({
+        "status": "live"
+    }))
+}
+
+/// The settings clients may rely on; never secrets
+#[get("/config")]
+fn get_config(config: &State<AppConfig>) -> Json<PublicConfig> {
+    Json(config.public())
+}
+
+/// Whether to send traffic here: 503 while shutting down or when a
+/// critical check fails
+#[get("/ready")]
+async fn ready(health: &State<Health>, admin: Option<AdminAuth>) -> status::Custom<Json<serde_json::Value>> {
+    if health.is_shutting_down() {
+        return status::Custom(
+            Status::ServiceUnavailable,
+            Json(serde_json::json!({ "status": "shutting_down" })),
+        );
+    }
+    let mut checks = health.run(true).await;
+    let ready = health::overall(&checks) != CheckStatus::Fail;
+    if admin.is_none() {
+        health::redact(&mut checks);
+    }
+
+    status::Custom(
+        if ready { Status::Ok } else { Status::ServiceUnavailable },
+        Json(serde_json::json!({
+            "status": if ready { "ready" } else { "not_ready" },
+            "checks": checks
+        })),
+    )
+}
+
+/// Builds the application from a configuration figment, so tests can point
+/// it at their own database and identity providers.
+pub fn app(figment: Figment) -> Rocket<Build> {
+    let openapi = routes::ApiDoc::openapi();
+
+    rocket::custom(figment)
+        .attach(config::stage())
+        .attach(cors::Cors)
+        .attach(security_headers::shield())
+        .attach(security_headers::SecurityHeaders)
+        .attach(telemetry::RequestTracingFairing)
+        .attach(database::stage())
+        .attach(events::stage())
+        .attach(realtime::stage())
+        .attach(webhooks::stage())
+        .attach(auth::oidc::stage())
+        .attach(export::stage())
+        .attach(mailer::stage())
+        .attach(backup::stage())
+        .attach(maintenance::stage())
+        .attach(metrics::stage())
+        .attach(health::stage())
+        .attach(rate_limit::stage())
+        .register("/", error::catchers())
+        .mount("/", telemetry::traced(routes![index, health_check, live, get_config, ready]))
+        .mount(
+            "/",
+            SwaggerUi::new("/docs/<_..>").url("/api-docs/openapi.json", openapi),
+        )
+        .mount(
+            "/api",
+            // 登录、注册及校验密码的路由，按 auth 组限流
+            telemetry::traced(rate_limit::limited_as(rate_limit::Group::Auth, routes![
+                routes::auth_routes::register,
+                routes::auth_routes::login,
+                routes::oidc_routes::login,
+                routes::oidc_routes::callback,
+                routes::user_routes::change_password,
+                routes::user_routes::delete_me,
+            ])),
+        )
+        .mount(
+            "/api",
+            telemetry::traced(rate_limit::limited(routes![
+                // 认证路由
+                routes::auth_routes::logout,
+                routes::auth_routes::me,
+                routes::auth_routes::csrf_token,
+                routes::oidc_routes::providers,
+                routes::api_key_routes::create_api_key,
+                routes::api_key_routes::list_api_keys,
+                routes::api_key_routes::revoke_api_key,
+                routes::user_routes::update_me,
+                routes::user_routes::verify_email,
+                routes::user_routes::usage,
+                routes::export_routes::export_me,
+                routes::export_routes::get_export,
+                routes::export_routes::download_export,
+                // Webhook 订阅及投递记录
+                routes::webhook_routes::create_webhook,
+                routes::webhook_routes::list_webhooks,
+                routes::webhook_routes::delete_webhook,
+                routes::webhook_routes::list_deliveries,
+                routes::webhook_routes::redeliver,
+                // 管理员路由
+                routes::admin_routes::list_backups,
+                routes::admin_routes::create_backup,
+                routes::admin_routes::restore_backup,
+                routes::admin_routes::list_migrations,
+                routes::admin_routes::get_log_level,
+                routes::admin_routes::set_log_level,
+                routes::admin_routes::get_user_quota,
+                routes::admin_routes::set_user_quota,
+                // Todo 路由 (需要认证)
+                routes::todo_routes::get_all_todos,
+                routes::todo_routes::get_todo,
+                routes::todo_routes::get_todos_by_status,
+                routes::todo_routes::get_todos_by_priority,
+                routes::todo_routes::create_todo,
+                routes::todo_routes::update_todo,
+                routes::todo_routes::delete_todo,
+                // 待办变更推送（SSE）
+                routes::event_routes::events,
+                // 待办实时协作（WebSocket）
+                routes::realtime_routes::connect
+            ])),
+        )
+        // CORS 预检请求
+        .mount("/api", telemetry::traced(routes![cors::preflight]))
+}
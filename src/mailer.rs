@@ -0,0 +1,80 @@
+use std::path::PathBuf;
+
+use rocket::fairing::AdHoc;
+use serde::Deserialize;
+
+/// `[default.mailer]` in Rocket.toml
+#[derive(Debug, Clone, Deserialize)]
+pub struct MailerConfig {
+    /// Where outgoing messages are written
+    #[serde(default = "default_outbox_dir")]
+    pub outbox_dir: PathBuf,
+}
+
+fn default_outbox_dir() -> PathBuf {
+    PathBuf::from("mail")
+}
+
+impl Default for MailerConfig {
+    fn default() -> Self {
+        MailerConfig {
+            outbox_dir: default_outbox_dir(),
+        }
+    }
+}
+
+/// Outgoing email. There is no mail transport configured yet, so each message
+/// is written to a file in the outbox directory where operators can pick it
+/// up. Bodies carry secrets such as verification tokens and are never logged.
+pub struct Mailer {
+    config: MailerConfig,
+}
+
+impl Mailer {
+    pub fn new(config: MailerConfig) -> Self {
+        Mailer { config }
+    }
+
+    pub async fn send(&self, to: &str, subject: &str, body: &str) {
+        let file = self.config.outbox_dir.join(format!(
+            "{}-{}.eml",
+            chrono::Utc::now().format("%Y%m%dT%H%M%S"),
+            uuid::Uuid::new_v4().simple()
+        ));
+        let message = format!("To: {}\nSubject: {}\n\n{}\n", to, subject, body);
+
+        let written = async {
+            rocket::tokio::fs::create_dir_all(&self.config.outbox_dir).await?;
+            rocket::tokio::fs::write(&file, message).await
+        }
+        .await;
+
+        match written {
+            Ok(()) => tracing::info!(
+                target: "mailer",
+                to = %to,
+                subject = %subject,
+                file = %file.display(),
+                event = "email_queued"
+            ),
+            Err(e) => tracing::error!(
+                target: "mailer",
+                to = %to,
+                subject = %subject,
+                error = %e,
+                event = "email_failed"
+            ),
+        }
+    }
+}
+
+pub fn stage() -> AdHoc {
+    AdHoc::on_ignite("Mailer", |rocket| async {
+        let config = rocket
+            .figment()
+            .extract_inner::<MailerConfig>("mailer")
+            .unwrap_or_default();
+
+        rocket.manage(Mailer::new(config))
+    })
+}
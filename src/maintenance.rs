@@ -0,0 +1,201 @@
+//! Maintenance mode. While it is on, every request except the health checks
+//! is answered with 503, so nothing reads or writes the database while it is
+//! being replaced. WebSocket connections outlive their request; they take a
+//! [`Watch`] to be counted while they run a command and closed when
+//! maintenance mode comes on.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use rocket::fairing::{AdHoc, Fairing, Info, Kind};
+use rocket::http::Method;
+use rocket::request::{FromRequest, Outcome};
+use rocket::tokio::sync::watch;
+use rocket::{Data, Request, Response};
+
+use crate::error::ApiError;
+
+/// Reachable while in maintenance, so orchestrators can tell "down" from
+/// "busy"
+const EXEMPT: [&str; 3] = ["/health", "/live", "/ready"];
+
+pub struct Maintenance {
+    active: watch::Sender<bool>,
+    in_flight: Arc<AtomicUsize>,
+}
+
+impl Default for Maintenance {
+    fn default() -> Self {
+        Maintenance {
+            active: watch::channel(false).0,
+            in_flight: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+}
+
+/// Maintenance mode ends when this is dropped
+pub struct MaintenanceWindow<'a>(&'a Maintenance);
+
+impl Drop for MaintenanceWindow<'_> {
+    fn drop(&mut self) {
+        self.0.active.send_replace(false);
+        tracing::info!(event = "maintenance_ended", "maintenance mode off");
+    }
+}
+
+impl Maintenance {
+    pub fn is_active(&self) -> bool {
+        *self.active.borrow()
+    }
+
+    /// For a connection that outlives the request that opened it
+    pub fn watch(&self) -> Watch {
+        Watch {
+            active: self.active.subscribe(),
+            in_flight: self.in_flight.clone(),
+        }
+    }
+
+    /// Turns maintenance mode on and waits up to `drain` for the requests
+    /// already running, other than the caller's, to finish. Returns `None`
+    /// if maintenance mode is already on or the requests did not finish.
+    pub async fn begin(&self, drain: Duration) -> Option<MaintenanceWindow<'_>> {
+        if self.active.send_replace(true) {
+            return None;
+        }
+        let window = MaintenanceWindow(self);
+        tracing::info!(event = "maintenance_started", "maintenance mode on");
+
+        let deadline = tokio::time::Instant::now() + drain;
+        while self.in_flight.load(Ordering::SeqCst) > 1 {
+            if tokio::time::Instant::now() >= deadline {
+                tracing::warn!(
+                    event = "maintenance_drain_timeout",
+                    in_flight = self.in_flight.load(Ordering::SeqCst) - 1,
+                    "requests still running; giving up"
+                );
+                return None;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+
+        Some(window)
+    }
+}
+
+/// Maintenance mode as seen from a long-lived connection
+pub struct Watch {
+    active: watch::Receiver<bool>,
+    in_flight: Arc<AtomicUsize>,
+}
+
+impl Watch {
+    /// Counts the caller as a running request, which `begin` waits for,
+    /// until the returned value is dropped. `None` in maintenance mode.
+    pub fn admit(&self) -> Option<Running> {
+        // Counted before the check, as the fairing does
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+        let running = Running(self.in_flight.clone());
+        (!*self.active.borrow()).then_some(running)
+    }
+
+    /// Returns once maintenance mode is on
+    pub async fn started(&mut self) {
+        if self.active.wait_for(|active| *active).await.is_err() {
+            std::future::pending::<()>().await;
+        }
+    }
+}
+
+/// A command counted by [`Watch::admit`]
+pub struct Running(Arc<AtomicUsize>);
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// What the fairing did with a request
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Admission {
+    /// Health checks, or no maintenance state managed
+    Exempt,
+    /// Counted in `in_flight` until its response is sent
+    Counted,
+    /// Sent to the maintenance route
+    Diverted,
+}
+
+/// Counts running requests and diverts new ones while maintenance mode is on
+struct MaintenanceFairing;
+
+#[rocket::async_trait]
+impl Fairing for MaintenanceFairing {
+    fn info(&self) -> Info {
+        Info {
+            name: "Maintenance Mode",
+            kind: Kind::Request | Kind::Response,
+        }
+    }
+
+    async fn on_request(&self, request: &mut Request<'_>, _: &mut Data<'_>) {
+        let Some(maintenance) = request.rocket().state::<Maintenance>() else {
+            return;
+        };
+        if EXEMPT.contains(&request.uri().path().as_str()) {
+            return;
+        }
+
+        // Counted before the check, so `begin` cannot miss a request that
+        // slipped in just as maintenance mode was turned on
+        maintenance.in_flight.fetch_add(1, Ordering::SeqCst);
+        if maintenance.is_active() {
+            maintenance.in_flight.fetch_sub(1, Ordering::SeqCst);
+            request.set_method(Method::Get);
+            request.set_uri(uri!("/maintenance"));
+            request.local_cache(|| Admission::Diverted);
+        } else {
+            request.local_cache(|| Admission::Counted);
+        }
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, _: &mut Response<'r>) {
+        if let Some(maintenance) = request.rocket().state::<Maintenance>()
+            && *request.local_cache(|| Admission::Exempt) == Admission::Counted
+        {
+            maintenance.in_flight.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+}
+
+/// Only matches requests the fairing diverted
+struct Diverted;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Diverted {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        if *request.local_cache(|| Admission::Exempt) == Admission::Diverted {
+            Outcome::Success(Diverted)
+        } else {
+            Outcome::Forward(rocket::http::Status::NotFound)
+        }
+    }
+}
+
+#[get("/maintenance")]
+fn unavailable(_diverted: Diverted) -> ApiError {
+    ApiError::ServiceUnavailable("The service is under maintenance; please retry shortly".to_string())
+}
+
+pub fn stage() -> AdHoc {
+    AdHoc::on_ignite("Maintenance Mode", |rocket| async {
+        rocket
+            .manage(Maintenance::default())
+            .attach(MaintenanceFairing)
+            .mount("/", routes![unavailable])
+    })
+}
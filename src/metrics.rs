@@ -0,0 +1,403 @@
+//! Prometheus metrics, served in the text exposition format at `/metrics`.
+//!
+//! Counters live in one process-wide registry, like the default registry of
+//! the Prometheus client libraries, so code without access to Rocket state
+//! (repositories, request guards) can record into it. Database gauges are
+//! read when the endpoint is scraped.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use once_cell::sync::Lazy;
+use rocket::fairing::AdHoc;
+use rocket::figment::Figment;
+use rocket::http::{ContentType, Status};
+use rocket::request::{FromRequest, Outcome};
+use rocket::serde::Deserialize;
+use rocket::Request;
+use tracing::Instrument;
+
+use crate::config::InvalidConfig;
+use crate::database::{DB_SYSTEM, Db};
+use crate::models::{QuotaOverrides, Role, Status as TodoStatus, Todo, UpdateTodoRequest, User};
+use crate::repository::{
+    NewTodo, NewUser, RepositoryResult, TodoFilter, TodoRepository, Usage, UserRepository,
+};
+
+/// Upper bounds of the latency histogram buckets, in seconds
+const BUCKETS: [f64; 11] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+/// Every status, so a status without todos is reported as 0 rather than
+/// disappearing from the output
+const STATUSES: [TodoStatus; 5] = [
+    TodoStatus::Pending,
+    TodoStatus::InProgress,
+    TodoStatus::Blocked,
+    TodoStatus::Completed,
+    TodoStatus::Cancelled,
+];
+
+#[derive(Debug, Clone, Default)]
+struct Histogram {
+    /// Observations per bucket, not cumulative; the `+Inf` bucket is `count`
+    buckets: [u64; BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, elapsed: Duration) {
+        let seconds = elapsed.as_secs_f64();
+        if let Some(i) = BUCKETS.iter().position(|bound| seconds <= *bound) {
+            self.buckets[i] += 1;
+        }
+        self.sum += seconds;
+        self.count += 1;
+    }
+
+    fn render(&self, out: &mut String, name: &str, labels: &str) {
+        let mut cumulative = 0;
+        for (bound, count) in BUCKETS.iter().zip(self.buckets) {
+            cumulative += count;
+            let _ = writeln!(out, "{}_bucket{{{}le=\"{}\"}} {}", name, labels, bound, cumulative);
+        }
+        let _ = writeln!(out, "{}_bucket{{{}le=\"+Inf\"}} {}", name, labels, self.count);
+        let labels = labels.trim_end_matches(',');
+        let _ = writeln!(out, "{}_sum{{{}}} {}", name, labels, self.sum);
+        let _ = writeln!(out, "{}_count{{{}}} {}", name, labels, self.count);
+    }
+}
+
+/// How a caller proved who they are
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum AuthMethod {
+    Password,
+    Jwt,
+    ApiKey,
+    Oidc,
+}
+
+impl AuthMethod {
+    fn as_str(self) -> &'static str {
+        match self {
+            AuthMethod::Password => "password",
+            AuthMethod::Jwt => "jwt",
+            AuthMethod::ApiKey => "api_key",
+            AuthMethod::Oidc => "oidc",
+        }
+    }
+}
+
+#[derive(Default)]
+struct Registry {
+    /// By (route template, method, status)
+    requests: BTreeMap<(String, String, u16), Histogram>,
+    /// By repository operation
+    queries: BTreeMap<&'static str, Histogram>,
+    /// By (method, succeeded)
+    auth: BTreeMap<(AuthMethod, bool), u64>,
+}
+
+static REGISTRY: Lazy<Mutex<Registry>> = Lazy::new(Default::default);
+
+fn registry() -> std::sync::MutexGuard<'static, Registry> {
+    // A panic while recording leaves the counts usable
+    REGISTRY.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Records a finished request. `route` is the matched route's template
+/// (`/api/todos/<id>`), so ids do not each get their own series.
+pub fn observe_request(route: &str, method: &str, status: u16, elapsed: Duration) {
+    registry()
+        .requests
+        .entry((route.to_string(), method.to_string(), status))
+        .or_default()
+        .observe(elapsed);
+}
+
+pub fn record_auth(method: AuthMethod, succeeded: bool) {
+    *registry().auth.entry((method, succeeded)).or_default() += 1;
+}
+
+/// Runs a database operation in a `db.query` span and records how long it
+/// took
+pub async fn time_query<T>(operation: &'static str, query: impl Future<Output = T>) -> T {
+    let span = tracing::info_span!("db.query", db.system = DB_SYSTEM, db.operation = operation);
+    let start = Instant::now();
+    let result = query.instrument(span).await;
+    registry().queries.entry(operation).or_default().observe(start.elapsed());
+    result
+}
+
+/// Escapes a label value as the exposition format requires
+fn label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn render_registry(out: &mut String) {
+    let registry = registry();
+
+    header(out, "http_requests_total", "counter", "Requests handled, by route template, method and status.");
+    for ((route, method, status), histogram) in &registry.requests {
+        let _ = writeln!(
+            out,
+            "http_requests_total{{route=\"{}\",method=\"{}\",status=\"{}\"}} {}",
+            label(route),
+            method,
+            status,
+            histogram.count
+        );
+    }
+
+    header(out, "http_request_duration_seconds", "histogram", "Time from receiving a request to sending its response.");
+    for ((route, method, status), histogram) in &registry.requests {
+        let labels = format!("route=\"{}\",method=\"{}\",status=\"{}\",", label(route), method, status);
+        histogram.render(out, "http_request_duration_seconds", &labels);
+    }
+
+    header(out, "db_query_duration_seconds", "histogram", "Time spent in database operations, by operation.");
+    for (operation, histogram) in &registry.queries {
+        histogram.render(out, "db_query_duration_seconds", &format!("operation=\"{}\",", operation));
+    }
+
+    header(out, "auth_attempts_total", "counter", "Authentication attempts, by method and outcome.");
+    for ((method, succeeded), count) in &registry.auth {
+        let outcome = if *succeeded { "success" } else { "failure" };
+        let _ = writeln!(
+            out,
+            "auth_attempts_total{{method=\"{}\",outcome=\"{}\"}} {}",
+            method.as_str(),
+            outcome,
+            count
+        );
+    }
+}
+
+async fn render_database(out: &mut String, db: &Db) -> Result<(), sqlx::Error> {
+    header(out, "db_pool_connections", "gauge", "Open database connections, by state.");
+    let (size, idle) = (db.size(), db.num_idle() as u32);
+    let _ = writeln!(out, "db_pool_connections{{state=\"idle\"}} {}", idle);
+    let _ = writeln!(out, "db_pool_connections{{state=\"in_use\"}} {}", size.saturating_sub(idle));
+    header(out, "db_pool_max_connections", "gauge", "Configured upper bound of the database pool.");
+    let _ = writeln!(out, "db_pool_max_connections {}", db.options().get_max_connections());
+
+    let counts: Vec<(TodoStatus, i64)> = sqlx::query_as("SELECT status, COUNT(*) FROM todos GROUP BY status")
+        .fetch_all(&**db)
+        .await?;
+    header(out, "todos", "gauge", "Todos, by status.");
+    for status in STATUSES {
+        let count = counts.iter().find(|(s, _)| *s == status).map_or(0, |(_, n)| *n);
+        let name = serde_json::to_value(status).unwrap_or_default();
+        let _ = writeln!(out, "todos{{status=\"{}\"}} {}", name.as_str().unwrap_or_default(), count);
+    }
+
+    let users: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM users").fetch_one(&**db).await?;
+    header(out, "users", "gauge", "Registered accounts.");
+    let _ = writeln!(out, "users {}", users);
+    Ok(())
+}
+
+/// The `[metrics]` section of Rocket.toml
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct MetricsConfig {
+    /// When set, scrapers must send `Authorization: Bearer <token>`
+    pub token: Option<String>,
+    /// Client addresses allowed to scrape; empty allows any. `X-Real-IP` is
+    /// only believed from `app.trusted_proxies`.
+    pub allow_from: Vec<IpAddr>,
+}
+
+impl MetricsConfig {
+    /// Reads the `[metrics]` section; `address` is where the server listens
+    pub fn load(figment: &Figment, address: IpAddr) -> Result<Self, InvalidConfig> {
+        let config: MetricsConfig = match figment.extract_inner("metrics") {
+            Ok(config) => config,
+            Err(e) if e.missing() => MetricsConfig::default(),
+            Err(e) => return Err(InvalidConfig(e.into_iter().map(|e| e.to_string()).collect())),
+        };
+        let problems = config.problems(address);
+        if problems.is_empty() { Ok(config) } else { Err(InvalidConfig(problems)) }
+    }
+
+    pub fn problems(&self, address: IpAddr) -> Vec<String> {
+        let mut problems = Vec::new();
+        match self.token.as_deref() {
+            Some("") => problems.push("metrics.token must not be empty".to_string()),
+            // Address checks alone are not enough once other hosts can connect
+            None if !address.is_loopback() => problems.push(format!(
+                "metrics.token must be set when the server listens on {}, which is not a loopback address",
+                address
+            )),
+            _ => {}
+        }
+        problems
+    }
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        MetricsConfig {
+            token: None,
+            allow_from: vec![IpAddr::from([127, 0, 0, 1]), IpAddr::from([0u16, 0, 0, 0, 0, 0, 0, 1])],
+        }
+    }
+}
+
+/// Compares digests, so the time taken does not reveal how much of the
+/// token was right
+fn same_secret(sent: &str, expected: &str) -> bool {
+    use sha2::{Digest, Sha256};
+
+    Sha256::digest(sent.as_bytes()) == Sha256::digest(expected.as_bytes())
+}
+
+/// Admits scrapers that pass the configured token and address checks. Others
+/// get 404, so the endpoint is not advertised to them.
+struct Scraper;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Scraper {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let Some(config) = request.rocket().state::<MetricsConfig>() else {
+            return Outcome::Forward(Status::NotFound);
+        };
+
+        let address_allowed = config.allow_from.is_empty()
+            || crate::config::client_ip(request).is_some_and(|ip| config.allow_from.contains(&ip));
+        let token_accepted = config.token.as_deref().is_none_or(|token| {
+            request
+                .headers()
+                .get_one("Authorization")
+                .and_then(|value| value.strip_prefix("Bearer "))
+                .is_some_and(|sent| same_secret(sent, token))
+        });
+
+        if address_allowed && token_accepted {
+            Outcome::Success(Scraper)
+        } else {
+            Outcome::Forward(Status::NotFound)
+        }
+    }
+}
+
+#[get("/metrics")]
+async fn metrics(_scraper: Scraper, db: &Db) -> (ContentType, String) {
+    let mut out = String::new();
+    render_registry(&mut out);
+    if let Err(e) = render_database(&mut out, db).await {
+        tracing::warn!(event = "metrics_database_failed", error = %e, "database gauges left out");
+    }
+
+    (ContentType::new("text", "plain").with_params(("version", "0.0.4")), out)
+}
+
+pub fn stage() -> AdHoc {
+    AdHoc::try_on_ignite("Metrics", |rocket| async {
+        // Rocket has not read its own settings yet; a bad address fails it later
+        let address = rocket.figment().extract_inner("address").unwrap_or(rocket::Config::default().address);
+        match MetricsConfig::load(rocket.figment(), address) {
+            Ok(config) => Ok(rocket.manage(config).mount("/", routes![metrics])),
+            Err(InvalidConfig(problems)) => {
+                for problem in &problems {
+                    tracing::error!(event = "invalid_config", problem = %problem);
+                }
+                Err(rocket)
+            }
+        }
+    })
+}
+
+/// Times every call of the todo repository it wraps
+pub struct MeteredTodoRepository(pub Arc<dyn TodoRepository>);
+
+#[rocket::async_trait]
+impl TodoRepository for MeteredTodoRepository {
+    async fn create(&self, user_id: i64, todo: NewTodo) -> RepositoryResult<Todo> {
+        time_query("todos.create", self.0.create(user_id, todo)).await
+    }
+
+    async fn find(&self, user_id: i64, id: i64) -> RepositoryResult<Option<Todo>> {
+        time_query("todos.find", self.0.find(user_id, id)).await
+    }
+
+    async fn list(&self, user_id: i64, filter: TodoFilter) -> RepositoryResult<Vec<Todo>> {
+        time_query("todos.list", self.0.list(user_id, filter)).await
+    }
+
+    async fn update(&self, user_id: i64, id: i64, changes: UpdateTodoRequest) -> RepositoryResult<Option<Todo>> {
+        time_query("todos.update", self.0.update(user_id, id, changes)).await
+    }
+
+    async fn delete(&self, user_id: i64, id: i64) -> RepositoryResult<bool> {
+        time_query("todos.delete", self.0.delete(user_id, id)).await
+    }
+
+    async fn usage(&self, user_id: i64) -> RepositoryResult<Usage> {
+        time_query("todos.usage", self.0.usage(user_id)).await
+    }
+}
+
+/// Times every call of the user repository it wraps
+pub struct MeteredUserRepository(pub Arc<dyn UserRepository>);
+
+#[rocket::async_trait]
+impl UserRepository for MeteredUserRepository {
+    async fn find_by_email(&self, email: &str) -> RepositoryResult<Option<User>> {
+        time_query("users.find_by_email", self.0.find_by_email(email)).await
+    }
+
+    async fn find_by_username(&self, username: &str) -> RepositoryResult<Option<User>> {
+        time_query("users.find_by_username", self.0.find_by_username(username)).await
+    }
+
+    async fn exists(&self, username: &str, email: &str) -> RepositoryResult<bool> {
+        time_query("users.exists", self.0.exists(username, email)).await
+    }
+
+    async fn create(&self, user: NewUser) -> RepositoryResult<User> {
+        time_query("users.create", self.0.create(user)).await
+    }
+
+    async fn create_session(&self, user_id: i64) -> RepositoryResult<String> {
+        time_query("users.create_session", self.0.create_session(user_id)).await
+    }
+
+    async fn revoke_session(&self, session_id: &str) -> RepositoryResult<()> {
+        time_query("users.revoke_session", self.0.revoke_session(session_id)).await
+    }
+
+    async fn revoke_all_sessions(&self, user_id: i64) -> RepositoryResult<()> {
+        time_query("users.revoke_all_sessions", self.0.revoke_all_sessions(user_id)).await
+    }
+
+    async fn set_password_hash(&self, user_id: i64, password_hash: &str) -> RepositoryResult<bool> {
+        time_query("users.set_password_hash", self.0.set_password_hash(user_id, password_hash)).await
+    }
+
+    async fn set_role(&self, user_id: i64, role: Role) -> RepositoryResult<bool> {
+        time_query("users.set_role", self.0.set_role(user_id, role)).await
+    }
+
+    async fn set_disabled(&self, user_id: i64, disabled: bool) -> RepositoryResult<bool> {
+        time_query("users.set_disabled", self.0.set_disabled(user_id, disabled)).await
+    }
+
+    async fn quota_overrides(&self, user_id: i64) -> RepositoryResult<Option<QuotaOverrides>> {
+        time_query("users.quota_overrides", self.0.quota_overrides(user_id)).await
+    }
+
+    async fn set_quota_overrides(&self, user_id: i64, overrides: &QuotaOverrides) -> RepositoryResult<bool> {
+        time_query("users.set_quota_overrides", self.0.set_quota_overrides(user_id, overrides)).await
+    }
+}
@@ -0,0 +1,60 @@
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use utoipa::ToSchema;
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow, ToSchema)]
+pub struct ApiKey {
+    pub id: Option<i64>,
+    pub user_id: i64,
+    pub name: String,
+    pub prefix: String,
+    pub scopes: String, // Space separated, e.g. "todos:read todos:write"
+    pub expires_at: Option<NaiveDateTime>,
+    pub last_used_at: Option<NaiveDateTime>,
+    pub revoked_at: Option<NaiveDateTime>,
+    pub created_at: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreateApiKeyRequest {
+    pub name: String,
+    /// Defaults to every known scope when omitted
+    pub scopes: Option<Vec<String>>,
+    /// Days until the key expires; the key never expires when omitted
+    pub expires_in_days: Option<i64>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ApiKeyResponse {
+    pub id: i64,
+    pub name: String,
+    pub prefix: String,
+    pub scopes: Vec<String>,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CreatedApiKeyResponse {
+    pub api_key: ApiKeyResponse,
+    /// The plaintext token. It is only returned once and cannot be recovered.
+    pub token: String,
+}
+
+impl From<ApiKey> for ApiKeyResponse {
+    fn from(key: ApiKey) -> Self {
+        let to_utc = |dt: NaiveDateTime| DateTime::from_naive_utc_and_offset(dt, Utc);
+
+        ApiKeyResponse {
+            id: key.id.unwrap_or(0),
+            name: key.name,
+            prefix: key.prefix,
+            scopes: key.scopes.split_whitespace().map(str::to_string).collect(),
+            created_at: key.created_at.map(to_utc).unwrap_or_else(Utc::now),
+            expires_at: key.expires_at.map(to_utc),
+            last_used_at: key.last_used_at.map(to_utc),
+        }
+    }
+}
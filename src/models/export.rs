@@ -0,0 +1,17 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct DataExportResponse {
+    pub id: String,
+    /// One of pending, running, ready, failed or expired
+    pub status: String,
+    pub created_at: DateTime<Utc>,
+    pub completed_at: Option<DateTime<Utc>>,
+    /// When the archive is deleted from the server
+    pub expires_at: Option<DateTime<Utc>>,
+    /// Short-lived link that works without credentials; only set once ready
+    pub download_url: Option<String>,
+    pub error: Option<String>,
+}
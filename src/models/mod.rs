@@ -1,5 +1,13 @@
+pub mod api_key;
+pub mod export;
+pub mod quota;
 pub mod todo;
 pub mod user;
+pub mod webhook;
 
+pub use api_key::*;
+pub use export::*;
+pub use quota::*;
 pub use todo::*;
 pub use user::*;
+pub use webhook::*;
@@ -0,0 +1,78 @@
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use utoipa::ToSchema;
+
+use crate::models::DESCRIPTION_MAX_LEN;
+use crate::validation::{FieldError, Validate, Violations};
+
+/// An administrator's replacements for a user's `[app.quotas]` defaults.
+/// Unset fields keep the default; setting none removes the override.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, FromRow, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct QuotaOverrides {
+    #[schema(minimum = 0)]
+    pub max_todos: Option<i64>,
+    /// In characters, up to the 10000 any description may have
+    #[schema(minimum = 0, maximum = 10000)]
+    pub max_description_len: Option<i64>,
+    #[schema(minimum = 0)]
+    pub max_storage_bytes: Option<i64>,
+}
+
+impl QuotaOverrides {
+    pub fn is_empty(&self) -> bool {
+        self.max_todos.is_none() && self.max_description_len.is_none() && self.max_storage_bytes.is_none()
+    }
+}
+
+impl Validate for QuotaOverrides {
+    fn validate(&mut self) -> Result<(), Vec<FieldError>> {
+        let mut violations = Violations::default();
+
+        for (field, value) in [
+            ("max_todos", self.max_todos),
+            ("max_description_len", self.max_description_len),
+            ("max_storage_bytes", self.max_storage_bytes),
+        ] {
+            if value.is_some_and(|value| value < 0) {
+                violations.push(field, "too_small", "must not be negative");
+            }
+        }
+        if self.max_description_len.is_some_and(|len| len > DESCRIPTION_MAX_LEN as i64) {
+            violations.push(
+                "max_description_len",
+                "too_large",
+                format!("must be at most {}", DESCRIPTION_MAX_LEN),
+            );
+        }
+
+        violations.into_result()
+    }
+}
+
+/// How much of one quota is used
+#[derive(Debug, Serialize, ToSchema)]
+pub struct QuotaUsage {
+    pub used: i64,
+    pub limit: i64,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct UsageResponse {
+    pub todos: QuotaUsage,
+    /// Bytes of todo titles and descriptions, as UTF-8
+    pub storage_bytes: QuotaUsage,
+    /// Longest description allowed, in characters
+    pub max_description_len: i64,
+    /// Whether an administrator changed this user's limits
+    pub overridden: bool,
+}
+
+/// A user's quotas as an administrator sees them
+#[derive(Debug, Serialize, ToSchema)]
+pub struct UserQuotaResponse {
+    pub user_id: i64,
+    pub overrides: QuotaOverrides,
+    /// With the overrides applied
+    pub usage: UsageResponse,
+}
@@ -1,94 +1,146 @@
 use chrono::{DateTime, NaiveDateTime, Utc};
+use rocket::request::FromParam;
+use serde::de::IntoDeserializer;
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
 use utoipa::ToSchema;
 
+use crate::validation::{trim, trim_optional, FieldError, Validate, Violations};
+
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow, ToSchema)]
 pub struct Todo {
     pub id: Option<i64>,
     pub title: String,
     pub description: Option<String>,
-    pub status: String,
-    pub priority: String,
+    pub status: Status,
+    pub priority: Priority,
     pub created_at: Option<NaiveDateTime>,
     pub updated_at: Option<NaiveDateTime>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+// The serde and sqlx names below are the only list of allowed values: the
+// API, the OpenAPI schema and the database column all derive from them.
+// Both backends store them in a plain TEXT column.
+
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, sqlx::Type, ToSchema,
+)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "text", rename_all = "snake_case")]
 pub enum Priority {
     Low,
+    #[default]
     Medium,
     High,
 }
 
-impl Priority {
-    pub fn as_str(&self) -> &'static str {
-        match self {
-            Priority::Low => "low",
-            Priority::Medium => "medium",
-            Priority::High => "high",
-        }
-    }
-
-    pub fn from_str(s: &str) -> Option<Self> {
-        match s {
-            "low" => Some(Priority::Low),
-            "medium" => Some(Priority::Medium),
-            "high" => Some(Priority::High),
-            _ => None,
-        }
-    }
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, sqlx::Type, ToSchema,
+)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "text", rename_all = "snake_case")]
 pub enum Status {
+    #[default]
     Pending,
     InProgress,
+    Blocked,
     Completed,
+    Cancelled,
 }
 
-impl Status {
-    pub fn as_str(&self) -> &'static str {
-        match self {
-            Status::Pending => "pending",
-            Status::InProgress => "in_progress",
-            Status::Completed => "completed",
-        }
+/// Reads a path segment using the enum's serde names, so the error lists
+/// every allowed value
+fn parse_param<'a, T: Deserialize<'a>>(field: &str, value: &'a str) -> Result<T, FieldError> {
+    T::deserialize(value.into_deserializer())
+        .map_err(|e: serde::de::value::Error| FieldError::new(field, "invalid_choice", e.to_string()))
+}
+
+impl<'a> FromParam<'a> for Priority {
+    type Error = FieldError;
+
+    fn from_param(param: &'a str) -> Result<Self, Self::Error> {
+        parse_param("priority", param)
     }
+}
 
-    pub fn from_str(s: &str) -> Option<Self> {
-        match s {
-            "pending" => Some(Status::Pending),
-            "in_progress" => Some(Status::InProgress),
-            "completed" => Some(Status::Completed),
-            _ => None,
-        }
+impl<'a> FromParam<'a> for Status {
+    type Error = FieldError;
+
+    fn from_param(param: &'a str) -> Result<Self, Self::Error> {
+        parse_param("status", param)
     }
 }
 
+pub const TITLE_MAX_LEN: usize = 200;
+/// The most any quota may allow; `[app.quotas]` sets the usual limit
+pub const DESCRIPTION_MAX_LEN: usize = 10_000;
+
 #[derive(Debug, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
 pub struct CreateTodoRequest {
+    /// Leading and trailing whitespace is trimmed
+    #[schema(min_length = 1, max_length = 200)]
     pub title: String,
+    /// Blank descriptions are stored as null. The user's quota may allow less.
+    #[schema(max_length = 10000)]
     pub description: Option<String>,
     pub priority: Option<Priority>,
     pub status: Option<Status>,
 }
 
 #[derive(Debug, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
 pub struct UpdateTodoRequest {
+    #[schema(min_length = 1, max_length = 200)]
     pub title: Option<String>,
+    #[schema(max_length = 10000)]
     pub description: Option<String>,
     pub status: Option<Status>,
     pub priority: Option<Priority>,
 }
 
-#[derive(Debug, Serialize, ToSchema)]
+impl Validate for CreateTodoRequest {
+    fn validate(&mut self) -> Result<(), Vec<FieldError>> {
+        let mut violations = Violations::default();
+
+        trim(&mut self.title);
+        violations.length("title", &self.title, 1, TITLE_MAX_LEN);
+
+        trim_optional(&mut self.description);
+        if let Some(description) = &self.description {
+            violations.length("description", description, 0, DESCRIPTION_MAX_LEN);
+        }
+
+        violations.into_result()
+    }
+}
+
+impl Validate for UpdateTodoRequest {
+    fn validate(&mut self) -> Result<(), Vec<FieldError>> {
+        let mut violations = Violations::default();
+
+        if let Some(title) = &mut self.title {
+            trim(title);
+            violations.length("title", title, 1, TITLE_MAX_LEN);
+        }
+
+        // An empty description clears it
+        if let Some(description) = &mut self.description {
+            trim(description);
+            violations.length("description", description, 0, DESCRIPTION_MAX_LEN);
+        }
+
+        violations.into_result()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct TodoResponse {
     pub id: i64,
     pub title: String,
     pub description: Option<String>,
-    pub status: String,
-    pub priority: String,
+    pub status: Status,
+    pub priority: Priority,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
@@ -104,11 +156,11 @@ fn from(todo: Todo) -> Self {
             created_at: todo
                 .created_at
                 .map(|dt| DateTime::from_naive_utc_and_offset(dt, Utc))
-                .unwrap_or_else(|| Utc::now()),
+                .unwrap_or_else(Utc::now),
             updated_at: todo
                 .updated_at
                 .map(|dt| DateTime::from_naive_utc_and_offset(dt, Utc))
-                .unwrap_or_else(|| Utc::now()),
+                .unwrap_or_else(Utc::now),
         }
     }
 }
@@ -125,8 +177,8 @@ pub fn new(
             id: None, // Will be set by database
             title,
             description,
-            status: status.as_str().to_string(),
-            priority: priority.as_str().to_string(),
+            status,
+            priority,
             created_at: Some(now),
             updated_at: Some(now),
         }
@@ -144,25 +196,17 @@ pub fn update(&mut self, update: UpdateTodoRequest) {
         }
 
         if let Some(status) = update.status {
-            self.status = status.as_str().to_string();
+            self.status = status;
         }
 
         if let Some(priority) = update.priority {
-            self.priority = priority.as_str().to_string();
+            self.priority = priority;
         }
 
         self.updated_at = Some(now);
     }
 
     pub fn is_completed(&self) -> bool {
-        self.status == "completed"
-    }
-
-    pub fn get_priority(&self) -> Option<Priority> {
-        Priority::from_str(&self.priority)
-    }
-
-    pub fn get_status(&self) -> Option<Status> {
-        Status::from_str(&self.status)
+        self.status == Status::Completed
     }
 }
@@ -3,42 +3,241 @@
 use sqlx::FromRow;
 use utoipa::ToSchema;
 
+use crate::validation::{trim, FieldError, Validate, Violations};
+
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow, ToSchema)]
 pub struct User {
     pub id: Option<i64>,
     pub username: String,
     pub email: String,
     pub password_hash: String,
+    pub pending_email: Option<String>,
+    pub role: Role,
+    pub disabled_at: Option<NaiveDateTime>,
     pub created_at: Option<NaiveDateTime>,
     pub updated_at: Option<NaiveDateTime>,
 }
 
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, sqlx::Type, ToSchema,
+)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "text", rename_all = "snake_case")]
+pub enum Role {
+    #[default]
+    User,
+    Admin,
+}
+
+impl User {
+    /// Stored as `password_hash` for accounts created through an external
+    /// identity provider. It is not a valid PHC string, so password login
+    /// always fails for these accounts.
+    pub const NO_PASSWORD: &'static str = "!external";
+}
+
+pub const USERNAME_MIN_LEN: usize = 3;
+pub const USERNAME_MAX_LEN: usize = 32;
+pub const EMAIL_MAX_LEN: usize = 254;
+pub const PASSWORD_MIN_LEN: usize = 8;
+pub const PASSWORD_MAX_LEN: usize = 128;
+
 #[derive(Debug, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
 pub struct CreateUserRequest {
+    #[schema(min_length = 3, max_length = 32, pattern = "^[A-Za-z0-9._-]+$")]
     pub username: String,
+    #[schema(max_length = 254, format = "email")]
     pub email: String,
+    /// Not trimmed
+    #[schema(min_length = 8, max_length = 128, format = "password")]
     pub password: String,
 }
 
 #[derive(Debug, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
 pub struct LoginRequest {
+    #[schema(max_length = 254, format = "email")]
     pub email: String,
+    #[schema(min_length = 1, max_length = 128, format = "password")]
     pub password: String,
 }
 
+/// Shared username rules for registration and profile updates
+pub fn validate_username(violations: &mut Violations, field: &str, username: &str) {
+    violations.length(field, username, USERNAME_MIN_LEN, USERNAME_MAX_LEN);
+    if !username
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'))
+    {
+        violations.push(
+            field,
+            "invalid_format",
+            "may only contain letters, digits, '.', '_' and '-'",
+        );
+    }
+}
+
+/// A deliberately loose check; the verification mail is the real test
+pub fn validate_email(violations: &mut Violations, field: &str, email: &str) {
+    violations.length(field, email, 1, EMAIL_MAX_LEN);
+    let valid = email.split_once('@').is_some_and(|(local, domain)| {
+        !local.is_empty()
+            && domain.contains('.')
+            && !domain.starts_with('.')
+            && !domain.ends_with('.')
+    });
+    if !email.is_empty() && (!valid || email.contains(char::is_whitespace)) {
+        violations.push(field, "invalid_format", "must be an email address");
+    }
+}
+
+impl Validate for CreateUserRequest {
+    fn validate(&mut self) -> Result<(), Vec<FieldError>> {
+        let mut violations = Violations::default();
+
+        trim(&mut self.username);
+        validate_username(&mut violations, "username", &self.username);
+
+        trim(&mut self.email);
+        validate_email(&mut violations, "email", &self.email);
+
+        violations.length("password", &self.password, PASSWORD_MIN_LEN, PASSWORD_MAX_LEN);
+
+        violations.into_result()
+    }
+}
+
+impl Validate for LoginRequest {
+    fn validate(&mut self) -> Result<(), Vec<FieldError>> {
+        let mut violations = Violations::default();
+
+        trim(&mut self.email);
+        violations.length("email", &self.email, 1, EMAIL_MAX_LEN);
+        violations.length("password", &self.password, 1, PASSWORD_MAX_LEN);
+
+        violations.into_result()
+    }
+}
+
 #[derive(Debug, Serialize, ToSchema)]
 pub struct UserResponse {
     pub id: i64,
     pub username: String,
     pub email: String,
+    /// New email address waiting for verification
+    pub pending_email: Option<String>,
     pub created_at: DateTime<Utc>,
 }
 
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct UpdateProfileRequest {
+    #[schema(min_length = 3, max_length = 32, pattern = "^[A-Za-z0-9._-]+$")]
+    pub username: Option<String>,
+    /// Takes effect only after the new address is verified
+    #[schema(max_length = 254, format = "email")]
+    pub email: Option<String>,
+}
+
+impl Validate for UpdateProfileRequest {
+    fn validate(&mut self) -> Result<(), Vec<FieldError>> {
+        let mut violations = Violations::default();
+
+        // Blank fields are rejected rather than read as "leave unchanged"
+        if let Some(username) = &mut self.username {
+            trim(username);
+            validate_username(&mut violations, "username", username);
+        }
+        if let Some(email) = &mut self.email {
+            trim(email);
+            validate_email(&mut violations, "email", email);
+        }
+
+        violations.into_result()
+    }
+}
+
+/// Verification tokens are 32 random bytes, hex encoded
+pub const VERIFICATION_TOKEN_MAX_LEN: usize = 64;
+
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct VerifyEmailRequest {
+    #[schema(min_length = 1, max_length = 64)]
+    pub token: String,
+}
+
+impl Validate for VerifyEmailRequest {
+    fn validate(&mut self) -> Result<(), Vec<FieldError>> {
+        let mut violations = Violations::default();
+
+        trim(&mut self.token);
+        violations.length("token", &self.token, 1, VERIFICATION_TOKEN_MAX_LEN);
+
+        violations.into_result()
+    }
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ChangePasswordRequest {
+    #[schema(min_length = 1, max_length = 128, format = "password")]
+    pub current_password: String,
+    #[schema(min_length = 8, max_length = 128, format = "password")]
+    pub new_password: String,
+}
+
+impl Validate for ChangePasswordRequest {
+    fn validate(&mut self) -> Result<(), Vec<FieldError>> {
+        let mut violations = Violations::default();
+
+        violations.length("current_password", &self.current_password, 1, PASSWORD_MAX_LEN);
+        violations.length("new_password", &self.new_password, PASSWORD_MIN_LEN, PASSWORD_MAX_LEN);
+
+        violations.into_result()
+    }
+}
+
+/// What happens to a deleted user's todos
+#[derive(Debug, Clone, Copy, Default, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TodoDisposition {
+    #[default]
+    Delete,
+    /// Keep the todos but detach them from the user
+    Anonymize,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct DeleteAccountRequest {
+    /// Required for accounts with a password; accounts created through an
+    /// identity provider must have signed in within the last few minutes
+    #[schema(max_length = 128, format = "password")]
+    pub password: Option<String>,
+    #[serde(default)]
+    pub todos: TodoDisposition,
+}
+
+impl Validate for DeleteAccountRequest {
+    fn validate(&mut self) -> Result<(), Vec<FieldError>> {
+        let mut violations = Violations::default();
+
+        if let Some(password) = &self.password {
+            violations.length("password", password, 0, PASSWORD_MAX_LEN);
+        }
+
+        violations.into_result()
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct Claims {
     pub sub: String, // User ID
     pub email: String,
     pub username: String,
+    pub sid: String, // Session ID
     pub exp: usize, // Expiration time
     pub iat: usize, // Issued at
 }
\ No newline at end of file
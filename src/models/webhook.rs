@@ -0,0 +1,161 @@
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use utoipa::ToSchema;
+
+use crate::validation::{trim, FieldError, Validate, Violations};
+use crate::webhooks::EVENT_TYPES;
+
+pub const URL_MAX_LEN: usize = 2000;
+
+#[derive(Debug, Clone, FromRow)]
+pub struct Webhook {
+    pub id: i64,
+    pub user_id: i64,
+    pub url: String,
+    pub event_types: String, // Space separated, e.g. "todo.created todo.completed"
+    pub all_users: bool,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct CreateWebhookRequest {
+    /// An `http` or `https` URL; deliveries are POSTed to it. Its host must
+    /// resolve to public addresses only.
+    #[schema(example = "https://example.com/hooks/todos")]
+    pub url: String,
+    /// Defaults to every event type when omitted
+    pub event_types: Option<Vec<String>>,
+    /// Receive every user's events rather than your own. Administrators only.
+    #[serde(default)]
+    pub all_users: bool,
+}
+
+impl Validate for CreateWebhookRequest {
+    fn validate(&mut self) -> Result<(), Vec<FieldError>> {
+        let mut violations = Violations::default();
+
+        trim(&mut self.url);
+        violations.length("url", &self.url, 1, URL_MAX_LEN);
+        if !self.url.is_empty() {
+            match reqwest::Url::parse(&self.url) {
+                Ok(url) if matches!(url.scheme(), "http" | "https") && url.host().is_some() => {}
+                _ => violations.push("url", "invalid", "must be an absolute http or https URL"),
+            }
+        }
+
+        if let Some(types) = &mut self.event_types {
+            types.sort();
+            types.dedup();
+            if types.is_empty() {
+                violations.push("event_types", "required", "must name at least one event type");
+            }
+            for (i, event_type) in types.iter().enumerate() {
+                if !EVENT_TYPES.contains(&event_type.as_str()) {
+                    violations.push(
+                        &format!("event_types[{}]", i),
+                        "invalid_choice",
+                        format!("expected one of: {}", EVENT_TYPES.join(", ")),
+                    );
+                }
+            }
+        }
+
+        violations.into_result()
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct WebhookResponse {
+    pub id: i64,
+    pub url: String,
+    pub event_types: Vec<String>,
+    pub all_users: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<Webhook> for WebhookResponse {
+    fn from(webhook: Webhook) -> Self {
+        WebhookResponse {
+            id: webhook.id,
+            url: webhook.url,
+            event_types: webhook.event_types.split_whitespace().map(str::to_string).collect(),
+            all_users: webhook.all_users,
+            created_at: DateTime::from_naive_utc_and_offset(webhook.created_at, Utc),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CreatedWebhookResponse {
+    pub webhook: WebhookResponse,
+    /// Signs every delivery. It is only returned once.
+    pub secret: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type, ToSchema)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "text", rename_all = "snake_case")]
+pub enum DeliveryStatus {
+    /// Waiting for its first or next attempt
+    Pending,
+    Succeeded,
+    /// Every attempt failed; only a redelivery sends it again
+    Failed,
+}
+
+#[derive(Debug, Clone, FromRow)]
+pub struct WebhookDelivery {
+    pub id: i64,
+    pub webhook_id: i64,
+    pub event_id: i64,
+    pub event_type: String,
+    pub status: DeliveryStatus,
+    pub attempts: i64,
+    pub next_attempt_at: Option<NaiveDateTime>,
+    pub last_attempt_at: Option<NaiveDateTime>,
+    pub response_status: Option<i64>,
+    pub last_error: Option<String>,
+    pub redelivery_of: Option<i64>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct DeliveryResponse {
+    pub id: i64,
+    pub event_id: i64,
+    #[schema(example = "todo.completed")]
+    pub event_type: String,
+    pub status: DeliveryStatus,
+    pub attempts: i64,
+    /// When the next attempt is due, while pending
+    pub next_attempt_at: Option<DateTime<Utc>>,
+    pub last_attempt_at: Option<DateTime<Utc>>,
+    /// The HTTP status the endpoint last answered with
+    pub response_status: Option<i64>,
+    pub last_error: Option<String>,
+    /// The delivery this one repeats
+    pub redelivery_of: Option<i64>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<WebhookDelivery> for DeliveryResponse {
+    fn from(delivery: WebhookDelivery) -> Self {
+        let to_utc = |dt: NaiveDateTime| DateTime::from_naive_utc_and_offset(dt, Utc);
+
+        DeliveryResponse {
+            id: delivery.id,
+            event_id: delivery.event_id,
+            event_type: delivery.event_type,
+            status: delivery.status,
+            attempts: delivery.attempts,
+            next_attempt_at: delivery.next_attempt_at.map(to_utc),
+            last_attempt_at: delivery.last_attempt_at.map(to_utc),
+            response_status: delivery.response_status,
+            last_error: delivery.last_error,
+            redelivery_of: delivery.redelivery_of,
+            created_at: to_utc(delivery.created_at),
+        }
+    }
+}
@@ -0,0 +1,129 @@
+//! Per-user quotas: the `[app.quotas]` defaults, which an administrator can
+//! replace for a single user. They are checked before a todo is written;
+//! the check and the write are not one transaction, so requests racing each
+//! other may overshoot a limit by the few that were in flight. Usage over a
+//! lowered limit is kept, but only changes that shrink it are accepted.
+//!
+//! There is no project quota: todos are not grouped into projects (or
+//! carry attachments) in this API, so there is nothing to count yet. A
+//! `max_projects` limit belongs here once projects exist.
+
+use rocket::serde::Deserialize;
+
+use crate::error::ApiError;
+use crate::models::{QuotaOverrides, QuotaUsage, UsageResponse, DESCRIPTION_MAX_LEN};
+use crate::repository::{RepositoryResult, Usage, UserRepository};
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct QuotaConfig {
+    pub max_todos: i64,
+    /// In characters, up to `DESCRIPTION_MAX_LEN`
+    pub max_description_len: i64,
+    /// Bytes of todo titles and descriptions, as UTF-8
+    pub max_storage_bytes: i64,
+}
+
+impl Default for QuotaConfig {
+    fn default() -> Self {
+        QuotaConfig {
+            max_todos: 1000,
+            max_description_len: 2000,
+            max_storage_bytes: 5 * 1024 * 1024,
+        }
+    }
+}
+
+impl QuotaConfig {
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        for (name, value) in [
+            ("max_todos", self.max_todos),
+            ("max_description_len", self.max_description_len),
+            ("max_storage_bytes", self.max_storage_bytes),
+        ] {
+            if value < 0 {
+                problems.push(format!("app.quotas.{} must not be negative, got {}", name, value));
+            }
+        }
+        if self.max_description_len > DESCRIPTION_MAX_LEN as i64 {
+            problems.push(format!(
+                "app.quotas.max_description_len must be at most {}, got {}",
+                DESCRIPTION_MAX_LEN, self.max_description_len
+            ));
+        }
+        problems
+    }
+
+    /// The defaults, with `overrides` in their place where set
+    pub fn limits(&self, overrides: &QuotaOverrides) -> Limits {
+        Limits {
+            max_todos: overrides.max_todos.unwrap_or(self.max_todos),
+            max_description_len: overrides.max_description_len.unwrap_or(self.max_description_len),
+            max_storage_bytes: overrides.max_storage_bytes.unwrap_or(self.max_storage_bytes),
+            overridden: !overrides.is_empty(),
+        }
+    }
+}
+
+/// A limit that a request would exceed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Quota {
+    Todos,
+    DescriptionLength,
+    Storage,
+}
+
+/// The quotas in effect for one user
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Limits {
+    pub max_todos: i64,
+    pub max_description_len: i64,
+    pub max_storage_bytes: i64,
+    pub overridden: bool,
+}
+
+impl Limits {
+    pub fn check_description(&self, description: Option<&str>) -> Result<(), ApiError> {
+        match description {
+            Some(description) if description.chars().count() as i64 > self.max_description_len => {
+                Err(ApiError::QuotaExceeded(Quota::DescriptionLength, self.max_description_len))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Whether `usage` may grow by `todos` todos and `bytes` bytes. Growing
+    /// by nothing, or shrinking, is always allowed.
+    pub fn check_growth(&self, usage: Usage, todos: i64, bytes: i64) -> Result<(), ApiError> {
+        if todos > 0 && usage.todos + todos > self.max_todos {
+            return Err(ApiError::QuotaExceeded(Quota::Todos, self.max_todos));
+        }
+        if bytes > 0 && usage.storage_bytes + bytes > self.max_storage_bytes {
+            return Err(ApiError::QuotaExceeded(Quota::Storage, self.max_storage_bytes));
+        }
+        Ok(())
+    }
+
+    pub fn usage_response(&self, usage: Usage) -> UsageResponse {
+        UsageResponse {
+            todos: QuotaUsage { used: usage.todos, limit: self.max_todos },
+            storage_bytes: QuotaUsage { used: usage.storage_bytes, limit: self.max_storage_bytes },
+            max_description_len: self.max_description_len,
+            overridden: self.overridden,
+        }
+    }
+}
+
+/// What a todo counts against the storage quota
+pub fn size(title: &str, description: Option<&str>) -> i64 {
+    (title.len() + description.map_or(0, str::len)) as i64
+}
+
+pub async fn limits_for(
+    users: &dyn UserRepository,
+    config: &QuotaConfig,
+    user_id: i64,
+) -> RepositoryResult<Limits> {
+    Ok(config.limits(&users.quota_overrides(user_id).await?.unwrap_or_default()))
+}
@@ -0,0 +1,350 @@
+//! Token-bucket rate limiting, configured by the `[rate_limit]` section.
+//!
+//! Routes are limited per group: `auth` (sign-in and registration), `reads`
+//! and `writes`. Each caller has a bucket per group, keyed by user id once
+//! authenticated and by client IP before that (the connecting address;
+//! `X-Real-IP` only counts from `app.trusted_proxies`). Every limited
+//! response carries `RateLimit-*` headers; an empty bucket answers 429 with
+//! `Retry-After`. Buckets live in a [`RateLimitStore`]; [`MemoryStore`]
+//! keeps them in this process, another store could share them.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use rocket::fairing::{AdHoc, Fairing, Info, Kind};
+use rocket::http::{Header, Method, Status};
+use rocket::request::Outcome;
+use rocket::route::{self, Handler, Route};
+use rocket::serde::{Deserialize, Serialize};
+use rocket::{Data, Request, Response};
+
+use crate::auth::jwt::JwtAuth;
+use crate::config;
+
+/// A bucket of `burst` requests, refilled at `per_minute`
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct Limit {
+    pub burst: u32,
+    pub per_minute: u32,
+}
+
+impl Limit {
+    fn per_second(&self) -> f64 {
+        f64::from(self.per_minute) / 60.0
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Group {
+    Auth,
+    Reads,
+    Writes,
+}
+
+impl Group {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Group::Auth => "auth",
+            Group::Reads => "reads",
+            Group::Writes => "writes",
+        }
+    }
+}
+
+/// The `[rate_limit]` section of Rocket.toml
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct RateLimitConfig {
+    pub enabled: bool,
+    pub auth: Limit,
+    pub reads: Limit,
+    pub writes: Limit,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        RateLimitConfig {
+            enabled: true,
+            auth: Limit { burst: 10, per_minute: 10 },
+            reads: Limit { burst: 120, per_minute: 600 },
+            writes: Limit { burst: 60, per_minute: 120 },
+        }
+    }
+}
+
+impl RateLimitConfig {
+    pub fn limit(&self, group: Group) -> Limit {
+        match group {
+            Group::Auth => self.auth,
+            Group::Reads => self.reads,
+            Group::Writes => self.writes,
+        }
+    }
+
+    fn problems(&self) -> Vec<String> {
+        [Group::Auth, Group::Reads, Group::Writes]
+            .into_iter()
+            .filter(|group| {
+                let limit = self.limit(*group);
+                limit.burst == 0 || limit.per_minute == 0
+            })
+            .map(|group| format!("rate_limit.{}: burst and per_minute must be at least 1", group.as_str()))
+            .collect()
+    }
+}
+
+/// The outcome of taking a token from a bucket
+#[derive(Debug, Clone, PartialEq)]
+pub struct Decision {
+    pub allowed: bool,
+    pub limit: Limit,
+    /// Requests left right now
+    pub remaining: u32,
+    /// Seconds until the bucket is full again
+    pub reset_secs: u64,
+    /// Seconds until the next request would be allowed; 0 when allowed
+    pub retry_after_secs: u64,
+}
+
+#[derive(Debug)]
+pub struct StoreError(pub String);
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "rate limit store failed: {}", self.0)
+    }
+}
+
+/// Where buckets are kept. `take` removes one token from the bucket at
+/// `key` if it has one, creating a full bucket on first use.
+#[rocket::async_trait]
+pub trait RateLimitStore: Send + Sync {
+    async fn take(&self, key: &str, limit: Limit) -> Result<Decision, StoreError>;
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn refilled(&self, limit: Limit, now: Instant) -> f64 {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        (self.tokens + elapsed * limit.per_second()).min(f64::from(limit.burst))
+    }
+}
+
+/// Buckets are pruned once the map holds this many, dropping those that
+/// have refilled completely and so are no different from a new one
+const PRUNE_AT: usize = 10_000;
+
+/// Buckets in this process only, lost on restart
+#[derive(Default)]
+pub struct MemoryStore {
+    buckets: Mutex<Buckets>,
+}
+
+#[derive(Default)]
+struct Buckets {
+    map: HashMap<String, (Bucket, Limit)>,
+    /// The size that triggers the next prune: twice what survived the last
+    /// one, so a map full of live buckets is not scanned on every request
+    prune_at: usize,
+}
+
+impl MemoryStore {
+    /// Like [`RateLimitStore::take`], at a given time
+    pub fn take_at(&self, key: &str, limit: Limit, now: Instant) -> Decision {
+        let mut buckets = self.buckets.lock().unwrap();
+        let Buckets { map, prune_at } = &mut *buckets;
+        if map.len() >= (*prune_at).max(PRUNE_AT) {
+            map.retain(|_, (bucket, limit)| bucket.refilled(*limit, now) < f64::from(limit.burst));
+            *prune_at = map.len() * 2;
+        }
+
+        let (bucket, stored_limit) = map.entry(key.to_string()).or_insert((
+            Bucket { tokens: f64::from(limit.burst), updated: now },
+            limit,
+        ));
+        *stored_limit = limit;
+        bucket.tokens = bucket.refilled(limit, now);
+        bucket.updated = now;
+
+        let allowed = bucket.tokens >= 1.0;
+        if allowed {
+            bucket.tokens -= 1.0;
+        }
+        let rate = limit.per_second();
+        Decision {
+            allowed,
+            limit,
+            remaining: bucket.tokens.floor() as u32,
+            reset_secs: ((f64::from(limit.burst) - bucket.tokens) / rate).ceil() as u64,
+            retry_after_secs: if allowed { 0 } else { ((1.0 - bucket.tokens) / rate).ceil().max(1.0) as u64 },
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl RateLimitStore for MemoryStore {
+    async fn take(&self, key: &str, limit: Limit) -> Result<Decision, StoreError> {
+        Ok(self.take_at(key, limit, Instant::now()))
+    }
+}
+
+/// The configured limits and the store they are counted in
+#[derive(Clone)]
+pub struct RateLimiter {
+    pub config: RateLimitConfig,
+    pub store: Arc<dyn RateLimitStore>,
+}
+
+impl RateLimiter {
+    /// Takes a token for the caller of `request`. A failing store lets the
+    /// request through rather than taking the API down with it.
+    pub async fn check(&self, group: Group, request: &Request<'_>) -> Option<Decision> {
+        let caller = match request.guard::<JwtAuth>().await {
+            Outcome::Success(auth) => format!("user:{}", auth.user_id),
+            _ => match config::client_ip(request) {
+                Some(ip) => format!("ip:{}", ip),
+                None => "ip:unknown".to_string(),
+            },
+        };
+        self.take(group, &caller).await
+    }
+
+    /// Takes a token for a signed-in user outside of an HTTP request, from
+    /// the same bucket their requests use
+    pub async fn check_user(&self, group: Group, user_id: &str) -> Option<Decision> {
+        self.take(group, &format!("user:{}", user_id)).await
+    }
+
+    async fn take(&self, group: Group, caller: &str) -> Option<Decision> {
+        let key = format!("{}:{}", group.as_str(), caller);
+
+        match self.store.take(&key, self.config.limit(group)).await {
+            Ok(decision) => Some(decision),
+            Err(e) => {
+                tracing::warn!(event = "rate_limit_store_failed", error = %e, "request let through");
+                None
+            }
+        }
+    }
+}
+
+/// Limits each of `routes` in `group`
+pub fn limited_as(group: Group, routes: Vec<Route>) -> Vec<Route> {
+    wrap(routes, |_| Some(group))
+}
+
+/// Limits GET routes as reads and the rest as writes. Preflight requests
+/// are left alone.
+pub fn limited(routes: Vec<Route>) -> Vec<Route> {
+    wrap(routes, |route| match route.method {
+        Method::Options => None,
+        Method::Get | Method::Head => Some(Group::Reads),
+        _ => Some(Group::Writes),
+    })
+}
+
+fn wrap(routes: Vec<Route>, group: impl Fn(&Route) -> Option<Group>) -> Vec<Route> {
+    routes
+        .into_iter()
+        .map(|mut route| {
+            if let Some(group) = group(&route) {
+                route.handler = Box::new(LimitedHandler { group, handler: route.handler });
+            }
+            route
+        })
+        .collect()
+}
+
+#[derive(Clone)]
+struct LimitedHandler {
+    group: Group,
+    handler: Box<dyn Handler>,
+}
+
+#[rocket::async_trait]
+impl Handler for LimitedHandler {
+    async fn handle<'r>(&self, request: &'r Request<'_>, data: Data<'r>) -> route::Outcome<'r> {
+        let Some(limiter) = request.rocket().state::<RateLimiter>().filter(|l| l.config.enabled) else {
+            return self.handler.handle(request, data).await;
+        };
+
+        let decision = limiter.check(self.group, request).await;
+        let allowed = decision.as_ref().is_none_or(|d| d.allowed);
+        request.local_cache(|| decision);
+        if !allowed {
+            tracing::info!(event = "rate_limited", group = self.group.as_str(), path = %request.uri().path());
+            return route::Outcome::Error(Status::TooManyRequests);
+        }
+
+        self.handler.handle(request, data).await
+    }
+}
+
+/// The decision for the current request, if it was limited
+pub fn decision<'r>(request: &'r Request<'_>) -> Option<&'r Decision> {
+    request.local_cache(|| None::<Decision>).as_ref()
+}
+
+/// Adds the `RateLimit-*` headers, and `Retry-After` to refusals
+struct RateLimitHeaders;
+
+#[rocket::async_trait]
+impl Fairing for RateLimitHeaders {
+    fn info(&self) -> Info {
+        Info {
+            name: "Rate Limit Headers",
+            kind: Kind::Response,
+        }
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let Some(decision) = decision(request) else {
+            return;
+        };
+        let window = Duration::from_secs_f64(f64::from(decision.limit.burst) / decision.limit.per_second());
+
+        response.set_header(Header::new("RateLimit-Limit", decision.limit.burst.to_string()));
+        response.set_header(Header::new("RateLimit-Remaining", decision.remaining.to_string()));
+        response.set_header(Header::new("RateLimit-Reset", decision.reset_secs.to_string()));
+        response.set_header(Header::new(
+            "RateLimit-Policy",
+            format!("{};w={}", decision.limit.burst, window.as_secs().max(1)),
+        ));
+        if !decision.allowed {
+            response.set_header(Header::new("Retry-After", decision.retry_after_secs.to_string()));
+        }
+    }
+}
+
+pub fn stage() -> AdHoc {
+    AdHoc::try_on_ignite("Rate Limiting", |rocket| async {
+        let config: RateLimitConfig = match rocket.figment().extract_inner("rate_limit") {
+            Ok(config) => config,
+            Err(e) if e.missing() => RateLimitConfig::default(),
+            Err(e) => {
+                tracing::error!(event = "invalid_config", problem = %e);
+                return Err(rocket);
+            }
+        };
+        let problems = config.problems();
+        if !problems.is_empty() {
+            for problem in &problems {
+                tracing::error!(event = "invalid_config", problem = %problem);
+            }
+            return Err(rocket);
+        }
+
+        let limiter = RateLimiter {
+            config,
+            store: Arc::new(MemoryStore::default()),
+        };
+        Ok(rocket.manage(limiter).attach(RateLimitHeaders))
+    })
+}
@@ -0,0 +1,682 @@
+//! Todos over a WebSocket at `GET /api/ws`. A connection subscribes to
+//! all of the user's todos or to some of them, and is sent their changes
+//! (the events of the SSE feed) and presence: which of the user's other
+//! connections is viewing or editing which todo. Commands create, update
+//! and delete todos through `todo_handler`, with the checks the HTTP routes
+//! make. Messages are JSON text with a `type`; see the README.
+//!
+//! Todos belong to their owner alone, and there are no shared lists or
+//! projects to subscribe to (see `quota`), so presence is only ever seen
+//! by the same user's other connections: other devices and tabs. Showing
+//! other viewers needs sharing first.
+//!
+//! Each connection has a bounded send queue. Replies wait for room, and the
+//! client's next command is not read until they are queued. Events and
+//! presence finding the queue full are dropped instead, and the client is
+//! sent one `resync` once there is room again. A client that stops reading
+//! altogether is disconnected when a write takes `write_timeout_secs`.
+//!
+//! A connection is closed when maintenance mode comes on, and its commands
+//! count as running requests meanwhile, so a restore waits for them. Every
+//! ping it rechecks its token, and it is closed once the token has expired
+//! or its session or API key has been revoked.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use rocket::fairing::AdHoc;
+use rocket::futures::stream::{SplitSink, SplitStream};
+use rocket::futures::{SinkExt, StreamExt};
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome};
+use rocket::serde::{Deserialize, Serialize};
+use rocket::tokio::sync::broadcast::{self, error::RecvError};
+use rocket::tokio::sync::mpsc;
+use rocket::{Request, Shutdown};
+use rocket_db_pools::Database;
+use rocket_ws::frame::{CloseCode, CloseFrame};
+use rocket_ws::result::Error as WsError;
+use rocket_ws::stream::DuplexStream;
+use rocket_ws::{Channel, Message, WebSocket};
+use utoipa::ToSchema;
+
+use crate::auth::jwt::{self, JwtAuth};
+use crate::config::{AppConfig, JwtConfig};
+use crate::database::Db;
+use crate::error::{ApiError, ApiResult};
+use crate::events::{EventKind, EventLog, TodoEvent};
+use crate::handlers::todo_handler;
+use crate::maintenance::{self, Maintenance};
+use crate::models::TodoResponse;
+use crate::quota::{self, QuotaConfig};
+use crate::rate_limit::{Group, RateLimiter};
+use crate::repository::{TodoRepository, UserRepository};
+use crate::validation::{self, FieldError};
+
+/// The `[websocket]` section of Rocket.toml
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct WebSocketConfig {
+    /// Largest message accepted from a client, in bytes
+    pub max_message_bytes: usize,
+    /// Messages queued for each connection before events are dropped
+    pub send_buffer: usize,
+    /// Clients are pinged, and their token rechecked, this often; one
+    /// silent for two pings is dropped
+    pub ping_secs: u64,
+    /// A client is disconnected when one write to it takes this long
+    pub write_timeout_secs: u64,
+    /// Todos one connection may subscribe to by id
+    pub max_subscriptions: usize,
+}
+
+impl Default for WebSocketConfig {
+    fn default() -> Self {
+        WebSocketConfig {
+            max_message_bytes: 64 * 1024,
+            send_buffer: 64,
+            ping_secs: 30,
+            write_timeout_secs: 10,
+            max_subscriptions: 1000,
+        }
+    }
+}
+
+impl WebSocketConfig {
+    fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        for (name, value) in [
+            ("max_message_bytes", self.max_message_bytes as u64),
+            ("send_buffer", self.send_buffer as u64),
+            ("ping_secs", self.ping_secs),
+            ("write_timeout_secs", self.write_timeout_secs),
+            ("max_subscriptions", self.max_subscriptions as u64),
+        ] {
+            if value == 0 {
+                problems.push(format!("websocket.{} must be at least 1", name));
+            }
+        }
+        problems
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PresenceState {
+    Viewing,
+    Editing,
+    Left,
+}
+
+/// A connection arriving on or leaving a todo
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct Presence {
+    pub connection_id: u64,
+    #[serde(skip)]
+    pub user_id: i64,
+    pub username: String,
+    pub todo_id: i64,
+    pub state: PresenceState,
+}
+
+/// Which connection is on which todo, one todo per connection at a time
+pub struct PresenceHub {
+    next_id: AtomicU64,
+    current: Mutex<HashMap<u64, Presence>>,
+    sender: broadcast::Sender<Presence>,
+}
+
+impl Default for PresenceHub {
+    fn default() -> Self {
+        PresenceHub {
+            next_id: AtomicU64::new(1),
+            current: Mutex::new(HashMap::new()),
+            sender: broadcast::channel(1024).0,
+        }
+    }
+}
+
+impl PresenceHub {
+    fn connection_id(&self) -> u64 {
+        self.next_id.fetch_add(1, Ordering::Relaxed)
+    }
+
+    fn subscribe(&self) -> broadcast::Receiver<Presence> {
+        self.sender.subscribe()
+    }
+
+    /// Puts the connection on `presence.todo_id`, leaving the todo it was on
+    fn enter(&self, presence: Presence) {
+        let previous = self.current.lock().unwrap().insert(presence.connection_id, presence.clone());
+        if let Some(previous) = previous
+            && previous.todo_id != presence.todo_id
+        {
+            let _ = self.sender.send(Presence { state: PresenceState::Left, ..previous });
+        }
+        let _ = self.sender.send(presence);
+    }
+
+    /// Takes the connection off the todo it is on, if any
+    fn leave(&self, connection_id: u64) {
+        let previous = self.current.lock().unwrap().remove(&connection_id);
+        if let Some(previous) = previous {
+            let _ = self.sender.send(Presence { state: PresenceState::Left, ..previous });
+        }
+    }
+
+    /// Who is on the user's todos, but the connection asking
+    fn others(&self, user_id: i64, connection_id: u64, todo: impl Fn(i64) -> bool) -> Vec<Presence> {
+        self.current
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|p| p.user_id == user_id && p.connection_id != connection_id && todo(p.todo_id))
+            .cloned()
+            .collect()
+    }
+}
+
+/// A message from the client; `ref` is echoed in the reply
+#[derive(Deserialize)]
+struct Command {
+    #[serde(rename = "ref", default)]
+    reference: Option<String>,
+    #[serde(flatten)]
+    action: Action,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Action {
+    /// Without ids, every todo of the user's, including new ones
+    Subscribe {
+        #[serde(default)]
+        todo_ids: Option<Vec<i64>>,
+    },
+    /// Without ids, everything
+    Unsubscribe {
+        #[serde(default)]
+        todo_ids: Option<Vec<i64>>,
+    },
+    Presence { todo_id: i64, state: PresenceState },
+    /// `todo` is a `CreateTodoRequest`
+    Create { todo: serde_json::Value },
+    /// `changes` is an `UpdateTodoRequest`
+    Update { id: i64, changes: serde_json::Value },
+    Delete { id: i64 },
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Outgoing {
+    Ok {
+        #[serde(rename = "ref")]
+        reference: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        todo: Option<TodoResponse>,
+    },
+    Error {
+        #[serde(rename = "ref")]
+        reference: Option<String>,
+        code: &'static str,
+        detail: String,
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        errors: Vec<FieldError>,
+    },
+    Event(TodoEvent),
+    Presence(Presence),
+    /// Events were dropped; the client should reload its todos
+    Resync,
+}
+
+impl Outgoing {
+    fn error(reference: Option<String>, error: ApiError) -> Self {
+        match &error {
+            ApiError::Database(e) => tracing::error!(error = %e, "database error"),
+            ApiError::Internal(e) => tracing::error!(error = %e, "internal error"),
+            _ => {}
+        }
+        Outgoing::Error {
+            reference,
+            code: error.code(),
+            detail: error.detail(),
+            errors: match error {
+                ApiError::Validation(errors) => errors,
+                _ => Vec::new(),
+            },
+        }
+    }
+
+    fn invalid(reference: Option<String>, detail: impl Into<String>) -> Self {
+        Outgoing::Error {
+            reference,
+            code: "invalid_message",
+            detail: detail.into(),
+            errors: Vec::new(),
+        }
+    }
+
+    fn message(&self) -> Message {
+        Message::Text(serde_json::to_string(self).expect("messages serialize"))
+    }
+}
+
+#[derive(Default)]
+struct Subscriptions {
+    all: bool,
+    todos: HashSet<i64>,
+}
+
+impl Subscriptions {
+    fn includes(&self, todo_id: i64) -> bool {
+        self.all || self.todos.contains(&todo_id)
+    }
+}
+
+/// What a command did, besides succeeding
+#[derive(Default)]
+struct Done {
+    todo: Option<TodoResponse>,
+    /// Presence the client has not been sent yet
+    present: Vec<Presence>,
+}
+
+/// One client's socket, and what its commands need
+pub struct Connection {
+    auth: JwtAuth,
+    /// The token the handshake presented, rechecked every ping
+    token: String,
+    user_id: i64,
+    db: Db,
+    jwt: JwtConfig,
+    maintenance: maintenance::Watch,
+    todos: Arc<dyn TodoRepository>,
+    users: Arc<dyn UserRepository>,
+    quotas: QuotaConfig,
+    limiter: Option<RateLimiter>,
+    log: Arc<EventLog>,
+    presence: Arc<PresenceHub>,
+    config: WebSocketConfig,
+    shutdown: Shutdown,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Connection {
+    type Error = ApiError;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let auth = match request.guard::<JwtAuth>().await {
+            Outcome::Success(auth) => auth,
+            Outcome::Error((status, e)) => return Outcome::Error((status, e.into())),
+            Outcome::Forward(status) => return Outcome::Forward(status),
+        };
+        let user_id = match auth.numeric_user_id() {
+            Ok(user_id) => user_id,
+            Err(e) => return Outcome::Error((e.status(), e)),
+        };
+
+        let Some(token) = jwt::presented_token(request) else {
+            return Outcome::Error((Status::Unauthorized, ApiError::InvalidToken));
+        };
+
+        let rocket = request.rocket();
+        let (Some(db), Some(maintenance), Some(todos), Some(users), Some(app), Some(log), Some(presence), Some(config)) = (
+            Db::fetch(rocket),
+            rocket.state::<Maintenance>(),
+            rocket.state::<Arc<dyn TodoRepository>>(),
+            rocket.state::<Arc<dyn UserRepository>>(),
+            rocket.state::<AppConfig>(),
+            rocket.state::<Arc<EventLog>>(),
+            rocket.state::<Arc<PresenceHub>>(),
+            rocket.state::<WebSocketConfig>(),
+        ) else {
+            let error = ApiError::Internal("WebSocket state is not managed".to_string());
+            return Outcome::Error((Status::InternalServerError, error));
+        };
+
+        Outcome::Success(Connection {
+            auth,
+            token: token.to_string(),
+            user_id,
+            db: db.clone(),
+            jwt: app.jwt.clone(),
+            maintenance: maintenance.watch(),
+            todos: todos.clone(),
+            users: users.clone(),
+            quotas: app.quotas.clone(),
+            limiter: rocket.state::<RateLimiter>().filter(|l| l.config.enabled).cloned(),
+            log: log.clone(),
+            presence: presence.clone(),
+            config: config.clone(),
+            shutdown: rocket.shutdown(),
+        })
+    }
+}
+
+impl Connection {
+    /// Answers the handshake; the connection then runs until either side
+    /// closes it
+    pub fn accept(self, ws: WebSocket) -> Channel<'static> {
+        let ws = ws.config(rocket_ws::Config {
+            max_message_size: Some(self.config.max_message_bytes),
+            max_frame_size: Some(self.config.max_message_bytes),
+            ..Default::default()
+        });
+        ws.channel(move |stream| Box::pin(self.run(stream)))
+    }
+
+    async fn run(mut self, stream: DuplexStream) -> rocket_ws::result::Result<()> {
+        let connection_id = self.presence.connection_id();
+        let (sink, source) = stream.split();
+
+        let (outbox, queue) = mpsc::channel(self.config.send_buffer);
+        let write_timeout = Duration::from_secs(self.config.write_timeout_secs);
+        let writer = tokio::spawn(write_all(sink, queue, write_timeout, connection_id));
+        // Holds one message while a command runs, then reading waits
+        let (inbox_sender, mut inbox) = mpsc::channel(1);
+        let reader = tokio::spawn(read_all(source, inbox_sender));
+
+        let mut events = self.log.subscribe();
+        let mut presence = self.presence.subscribe();
+        let ping_every = Duration::from_secs(self.config.ping_secs);
+        let mut ping = tokio::time::interval_at(tokio::time::Instant::now() + ping_every, ping_every);
+        let mut last_heard = Instant::now();
+        let mut subscriptions = Subscriptions::default();
+        // Set when a broadcast did not fit, until `resync` is queued
+        let mut dropped = false;
+        tracing::info!(event = "websocket_opened", user_id = self.user_id, connection_id);
+
+        let close = loop {
+            if dropped && outbox.try_send(Outgoing::Resync.message()).is_ok() {
+                dropped = false;
+            }
+
+            tokio::select! {
+                biased;
+                _ = &mut self.shutdown => break Some(close_frame(CloseCode::Away, "server shutting down")),
+                _ = self.maintenance.started() => break Some(close_frame(CloseCode::Again, "down for maintenance")),
+                _ = outbox.closed() => break None,
+                received = inbox.recv() => {
+                    let message = match received {
+                        Some(Ok(message)) => message,
+                        Some(Err(e)) => break close_for(&e),
+                        None => break None,
+                    };
+                    last_heard = Instant::now();
+                    let replies = match message {
+                        Message::Text(text) => self.handle(connection_id, &mut subscriptions, &text).await,
+                        Message::Binary(_) => vec![Outgoing::invalid(None, "Messages must be JSON text").message()],
+                        // Pings are answered by the protocol layer
+                        Message::Ping(_) | Message::Pong(_) | Message::Frame(_) => Vec::new(),
+                        // As is the close handshake
+                        Message::Close(_) => break None,
+                    };
+                    for reply in replies {
+                        if outbox.send(reply).await.is_err() {
+                            break;
+                        }
+                    }
+                }
+                received = events.recv() => match received {
+                    Ok(event) => {
+                        if event.user_id == self.user_id && subscriptions.includes(event.todo_id) {
+                            if event.kind == EventKind::Deleted {
+                                subscriptions.todos.remove(&event.todo_id);
+                            }
+                            offer(&outbox, &mut dropped, Outgoing::Event(event).message());
+                        }
+                    }
+                    Err(RecvError::Lagged(_)) => dropped = true,
+                    Err(RecvError::Closed) => break None,
+                },
+                received = presence.recv() => match received {
+                    Ok(presence) => {
+                        if presence.user_id == self.user_id
+                            && presence.connection_id != connection_id
+                            && subscriptions.includes(presence.todo_id)
+                        {
+                            offer(&outbox, &mut dropped, Outgoing::Presence(presence).message());
+                        }
+                    }
+                    Err(RecvError::Lagged(_)) => dropped = true,
+                    Err(RecvError::Closed) => break None,
+                },
+                _ = ping.tick() => {
+                    if last_heard.elapsed() > ping_every * 2 {
+                        break None;
+                    }
+                    if let Ok(false) = jwt::still_valid(&self.db, &self.jwt, &self.token).await {
+                        tracing::info!(event = "websocket_session_ended", user_id = self.user_id, connection_id);
+                        break Some(close_frame(CloseCode::Policy, "session ended"));
+                    }
+                    offer(&outbox, &mut dropped, Message::Ping(Vec::new()));
+                }
+            }
+        };
+
+        self.presence.leave(connection_id);
+        reader.abort();
+        if let Some(close) = close {
+            let _ = outbox.try_send(Message::Close(Some(close)));
+        }
+        drop(outbox);
+        let _ = writer.await;
+        tracing::info!(event = "websocket_closed", user_id = self.user_id, connection_id);
+        Ok(())
+    }
+
+    /// Runs one command and returns the replies
+    async fn handle(&self, connection_id: u64, subscriptions: &mut Subscriptions, text: &str) -> Vec<Message> {
+        let command: Command = match serde_json::from_str(text) {
+            Ok(command) => command,
+            Err(e) => return vec![Outgoing::invalid(None, e.to_string()).message()],
+        };
+
+        // Counted until the reply, so a restore waits for the command
+        let Some(_running) = self.maintenance.admit() else {
+            let error = ApiError::ServiceUnavailable("The service is under maintenance; please retry shortly".to_string());
+            return vec![Outgoing::error(command.reference, error).message()];
+        };
+        match self.execute(connection_id, subscriptions, command.action).await {
+            Ok(done) => {
+                let mut replies = vec![Outgoing::Ok { reference: command.reference, todo: done.todo }.message()];
+                replies.extend(done.present.into_iter().map(|p| Outgoing::Presence(p).message()));
+                replies
+            }
+            Err(e) => vec![Outgoing::error(command.reference, e).message()],
+        }
+    }
+
+    async fn execute(&self, connection_id: u64, subscriptions: &mut Subscriptions, action: Action) -> ApiResult<Done> {
+        let todos = self.todos.as_ref();
+        match action {
+            Action::Subscribe { todo_ids: None } => {
+                subscriptions.all = true;
+                let present = self.presence.others(self.user_id, connection_id, |_| true);
+                Ok(Done { todo: None, present })
+            }
+            Action::Subscribe { todo_ids: Some(ids) } => {
+                let ids: HashSet<i64> = ids.into_iter().collect();
+                if subscriptions.todos.union(&ids).count() > self.config.max_subscriptions {
+                    return Err(ApiError::UnprocessableEntity(format!(
+                        "A connection may subscribe to at most {} todos",
+                        self.config.max_subscriptions
+                    )));
+                }
+                for id in &ids {
+                    todo_handler::get_todo(todos, self.user_id, *id).await?;
+                }
+                let present = self.presence.others(self.user_id, connection_id, |id| ids.contains(&id));
+                subscriptions.todos.extend(ids);
+                Ok(Done { todo: None, present })
+            }
+            Action::Unsubscribe { todo_ids: None } => {
+                *subscriptions = Subscriptions::default();
+                Ok(Done::default())
+            }
+            Action::Unsubscribe { todo_ids: Some(ids) } => {
+                for id in ids {
+                    subscriptions.todos.remove(&id);
+                }
+                Ok(Done::default())
+            }
+            Action::Presence { state: PresenceState::Left, .. } => {
+                self.presence.leave(connection_id);
+                Ok(Done::default())
+            }
+            Action::Presence { todo_id, state } => {
+                todo_handler::get_todo(todos, self.user_id, todo_id).await?;
+                self.presence.enter(Presence {
+                    connection_id,
+                    user_id: self.user_id,
+                    username: self.auth.username.clone(),
+                    todo_id,
+                    state,
+                });
+                Ok(Done::default())
+            }
+            Action::Create { todo } => {
+                self.allow_write().await?;
+                let request = validation::parse(todo).map_err(ApiError::Validation)?;
+                let limits = quota::limits_for(self.users.as_ref(), &self.quotas, self.user_id).await?;
+                let todo = todo_handler::create_todo(todos, &limits, self.user_id, request).await?;
+                Ok(Done { todo: Some(todo), present: Vec::new() })
+            }
+            Action::Update { id, changes } => {
+                self.allow_write().await?;
+                let request = validation::parse(changes).map_err(ApiError::Validation)?;
+                let limits = quota::limits_for(self.users.as_ref(), &self.quotas, self.user_id).await?;
+                let todo = todo_handler::update_todo(todos, &limits, self.user_id, id, request).await?;
+                Ok(Done { todo: Some(todo), present: Vec::new() })
+            }
+            Action::Delete { id } => {
+                self.allow_write().await?;
+                todo_handler::delete_todo(todos, self.user_id, id).await?;
+                Ok(Done::default())
+            }
+        }
+    }
+
+    /// The scope and rate limit an HTTP write would need
+    async fn allow_write(&self) -> ApiResult<()> {
+        self.auth.require_scope("todos:write")?;
+        if let Some(limiter) = &self.limiter
+            && let Some(decision) = limiter.check_user(Group::Writes, &self.auth.user_id).await
+            && !decision.allowed
+        {
+            return Err(ApiError::RateLimited(decision.retry_after_secs));
+        }
+        Ok(())
+    }
+}
+
+/// Queues a broadcast message unless the queue is full, or messages were
+/// already dropped and the client has not been told to resync yet
+fn offer(outbox: &mpsc::Sender<Message>, dropped: &mut bool, message: Message) {
+    if *dropped || outbox.try_send(message).is_err() {
+        *dropped = true;
+    }
+}
+
+fn close_frame(code: CloseCode, reason: &'static str) -> CloseFrame<'static> {
+    CloseFrame { code, reason: reason.into() }
+}
+
+/// How to close after a read failed, unless the connection itself did
+fn close_for(error: &WsError) -> Option<CloseFrame<'static>> {
+    let code = match error {
+        WsError::Capacity(_) => CloseCode::Size,
+        WsError::Utf8 => CloseCode::Invalid,
+        WsError::Protocol(_) => CloseCode::Protocol,
+        _ => return None,
+    };
+    Some(CloseFrame { code, reason: error.to_string().into() })
+}
+
+async fn read_all(mut source: SplitStream<DuplexStream>, inbox: mpsc::Sender<Result<Message, WsError>>) {
+    while let Some(message) = source.next().await {
+        let last = matches!(message, Ok(Message::Close(_)) | Err(_));
+        if inbox.send(message).await.is_err() || last {
+            return;
+        }
+    }
+}
+
+async fn write_all(
+    mut sink: SplitSink<DuplexStream, Message>,
+    mut queue: mpsc::Receiver<Message>,
+    timeout: Duration,
+    connection_id: u64,
+) {
+    while let Some(message) = queue.recv().await {
+        let last = message.is_close();
+        match tokio::time::timeout(timeout, sink.send(message)).await {
+            Ok(Ok(())) => {}
+            Ok(Err(_)) => return,
+            Err(_) => {
+                tracing::warn!(event = "websocket_slow_consumer", connection_id, "disconnected a client that stopped reading");
+                return;
+            }
+        }
+        if last {
+            break;
+        }
+    }
+    // Also sends the reply to a close the client started
+    let _ = tokio::time::timeout(timeout, sink.close()).await;
+}
+
+/// Refuses handshakes authenticated by the `auth_token` cookie from pages
+/// on other origins, unless CORS allows them: browsers send the cookie
+/// with any page's WebSocket, and the handshake is never preflighted
+pub struct SameOrigin;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for SameOrigin {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let headers = request.headers();
+        let Some(origin) = headers.get_one("Origin") else {
+            return Outcome::Success(SameOrigin);
+        };
+        if headers.contains("Authorization") {
+            return Outcome::Success(SameOrigin);
+        }
+
+        let host = origin.split_once("://").map_or(origin, |(_, host)| host);
+        let same = headers.get_one("Host").is_some_and(|h| h.eq_ignore_ascii_case(host));
+        let allowed = request.rocket().state::<AppConfig>().is_some_and(|c| c.cors.allows_origin(origin));
+        if same || allowed {
+            return Outcome::Success(SameOrigin);
+        }
+
+        tracing::warn!(event = "websocket_origin_refused", origin);
+        Outcome::Error((Status::Forbidden, ()))
+    }
+}
+
+/// Reads `[websocket]` and manages the presence of every connection
+pub fn stage() -> AdHoc {
+    AdHoc::try_on_ignite("WebSockets", |rocket| async {
+        let config: WebSocketConfig = match rocket.figment().extract_inner("websocket") {
+            Ok(config) => config,
+            Err(e) if e.missing() => WebSocketConfig::default(),
+            Err(e) => {
+                tracing::error!(event = "invalid_config", problem = %e);
+                return Err(rocket);
+            }
+        };
+        let problems = config.problems();
+        if !problems.is_empty() {
+            for problem in &problems {
+                tracing::error!(event = "invalid_config", problem = %problem);
+            }
+            return Err(rocket);
+        }
+
+        Ok(rocket.manage(config).manage(Arc::new(PresenceHub::default())))
+    })
+}
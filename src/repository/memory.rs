@@ -0,0 +1,253 @@
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use chrono::Utc;
+
+use super::{
+    NewTodo, NewUser, RepositoryError, RepositoryResult, TodoFilter, TodoRepository, Usage,
+    UserRepository,
+};
+use crate::models::{QuotaOverrides, Role, Todo, UpdateTodoRequest, User};
+use crate::quota;
+
+/// Keeps todos in a `Vec` behind a mutex, for tests
+#[derive(Default)]
+pub struct InMemoryTodoRepository {
+    // (owner, todo)
+    todos: Mutex<Vec<(i64, Todo)>>,
+}
+
+impl InMemoryTodoRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[rocket::async_trait]
+impl TodoRepository for InMemoryTodoRepository {
+    async fn create(&self, user_id: i64, new: NewTodo) -> RepositoryResult<Todo> {
+        let mut todos = self.todos.lock().unwrap();
+
+        let mut todo = Todo::new(new.title, new.description, new.priority, new.status);
+        todo.id = Some(todos.iter().filter_map(|(_, t)| t.id).max().unwrap_or(0) + 1);
+        todos.push((user_id, todo.clone()));
+
+        Ok(todo)
+    }
+
+    async fn find(&self, user_id: i64, id: i64) -> RepositoryResult<Option<Todo>> {
+        let todos = self.todos.lock().unwrap();
+
+        Ok(todos
+            .iter()
+            .find(|(owner, todo)| *owner == user_id && todo.id == Some(id))
+            .map(|(_, todo)| todo.clone()))
+    }
+
+    async fn list(&self, user_id: i64, filter: TodoFilter) -> RepositoryResult<Vec<Todo>> {
+        let todos = self.todos.lock().unwrap();
+
+        let mut matching: Vec<Todo> = todos
+            .iter()
+            .filter(|(owner, todo)| {
+                *owner == user_id
+                    && filter.status.is_none_or(|status| todo.status == status)
+                    && filter
+                        .priority
+                        .is_none_or(|priority| todo.priority == priority)
+            })
+            .map(|(_, todo)| todo.clone())
+            .collect();
+        matching.sort_by_key(|todo| Reverse((todo.created_at, todo.id)));
+
+        Ok(matching)
+    }
+
+    async fn update(
+        &self,
+        user_id: i64,
+        id: i64,
+        changes: UpdateTodoRequest,
+    ) -> RepositoryResult<Option<Todo>> {
+        let mut todos = self.todos.lock().unwrap();
+
+        let Some((_, todo)) = todos
+            .iter_mut()
+            .find(|(owner, todo)| *owner == user_id && todo.id == Some(id))
+        else {
+            return Ok(None);
+        };
+
+        let unchanged = changes.title.is_none()
+            && changes.description.is_none()
+            && changes.status.is_none()
+            && changes.priority.is_none();
+        if !unchanged {
+            todo.update(changes);
+        }
+
+        Ok(Some(todo.clone()))
+    }
+
+    async fn delete(&self, user_id: i64, id: i64) -> RepositoryResult<bool> {
+        let mut todos = self.todos.lock().unwrap();
+
+        let before = todos.len();
+        todos.retain(|(owner, todo)| !(*owner == user_id && todo.id == Some(id)));
+
+        Ok(todos.len() < before)
+    }
+
+    async fn usage(&self, user_id: i64) -> RepositoryResult<Usage> {
+        let todos = self.todos.lock().unwrap();
+
+        Ok(todos
+            .iter()
+            .filter(|(owner, _)| *owner == user_id)
+            .fold(Usage::default(), |usage, (_, todo)| Usage {
+                todos: usage.todos + 1,
+                storage_bytes: usage.storage_bytes + quota::size(&todo.title, todo.description.as_deref()),
+            }))
+    }
+}
+
+/// Keeps users and session ids in memory, for tests
+#[derive(Default)]
+pub struct InMemoryUserRepository {
+    users: Mutex<Vec<User>>,
+    // Active session ids and their users; revoking removes them
+    sessions: Mutex<HashMap<String, i64>>,
+    quotas: Mutex<HashMap<i64, QuotaOverrides>>,
+}
+
+impl InMemoryUserRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Applies `change` to the user, returning whether it exists
+    fn update_user(&self, user_id: i64, change: impl FnOnce(&mut User)) -> bool {
+        let mut users = self.users.lock().unwrap();
+
+        match users.iter_mut().find(|user| user.id == Some(user_id)) {
+            Some(user) => {
+                change(user);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl UserRepository for InMemoryUserRepository {
+    async fn find_by_email(&self, email: &str) -> RepositoryResult<Option<User>> {
+        let users = self.users.lock().unwrap();
+
+        Ok(users.iter().find(|user| user.email == email).cloned())
+    }
+
+    async fn find_by_username(&self, username: &str) -> RepositoryResult<Option<User>> {
+        let users = self.users.lock().unwrap();
+
+        Ok(users.iter().find(|user| user.username == username).cloned())
+    }
+
+    async fn exists(&self, username: &str, email: &str) -> RepositoryResult<bool> {
+        let users = self.users.lock().unwrap();
+
+        Ok(users
+            .iter()
+            .any(|user| user.username == username || user.email == email))
+    }
+
+    async fn create(&self, new: NewUser) -> RepositoryResult<User> {
+        let mut users = self.users.lock().unwrap();
+
+        if users
+            .iter()
+            .any(|user| user.username == new.username || user.email == new.email)
+        {
+            return Err(RepositoryError::Conflict);
+        }
+
+        let now = Utc::now().naive_utc();
+        let user = User {
+            id: Some(users.len() as i64 + 1),
+            username: new.username,
+            email: new.email,
+            password_hash: new.password_hash,
+            pending_email: None,
+            role: Role::default(),
+            disabled_at: None,
+            created_at: Some(now),
+            updated_at: Some(now),
+        };
+        users.push(user.clone());
+
+        Ok(user)
+    }
+
+    async fn create_session(&self, user_id: i64) -> RepositoryResult<String> {
+        let session_id = uuid::Uuid::new_v4().to_string();
+        self.sessions.lock().unwrap().insert(session_id.clone(), user_id);
+
+        Ok(session_id)
+    }
+
+    async fn revoke_session(&self, session_id: &str) -> RepositoryResult<()> {
+        self.sessions.lock().unwrap().remove(session_id);
+
+        Ok(())
+    }
+
+    async fn revoke_all_sessions(&self, user_id: i64) -> RepositoryResult<()> {
+        self.sessions
+            .lock()
+            .unwrap()
+            .retain(|_, owner| *owner != user_id);
+
+        Ok(())
+    }
+
+    async fn set_password_hash(&self, user_id: i64, password_hash: &str) -> RepositoryResult<bool> {
+        Ok(self.update_user(user_id, |user| user.password_hash = password_hash.to_string()))
+    }
+
+    async fn set_role(&self, user_id: i64, role: Role) -> RepositoryResult<bool> {
+        Ok(self.update_user(user_id, |user| user.role = role))
+    }
+
+    async fn set_disabled(&self, user_id: i64, disabled: bool) -> RepositoryResult<bool> {
+        Ok(self.update_user(user_id, |user| {
+            user.disabled_at = match (disabled, user.disabled_at) {
+                (true, None) => Some(Utc::now().naive_utc()),
+                (true, since) => since,
+                (false, _) => None,
+            };
+        }))
+    }
+
+    async fn quota_overrides(&self, user_id: i64) -> RepositoryResult<Option<QuotaOverrides>> {
+        if !self.update_user(user_id, |_| ()) {
+            return Ok(None);
+        }
+
+        Ok(Some(self.quotas.lock().unwrap().get(&user_id).cloned().unwrap_or_default()))
+    }
+
+    async fn set_quota_overrides(&self, user_id: i64, overrides: &QuotaOverrides) -> RepositoryResult<bool> {
+        if !self.update_user(user_id, |_| ()) {
+            return Ok(false);
+        }
+        let mut quotas = self.quotas.lock().unwrap();
+        if overrides.is_empty() {
+            quotas.remove(&user_id);
+        } else {
+            quotas.insert(user_id, overrides.clone());
+        }
+
+        Ok(true)
+    }
+}
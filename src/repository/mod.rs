@@ -0,0 +1,190 @@
+//! Storage behind the todo and auth services. Handlers only see the traits,
+//! so they can be exercised against the in-memory implementations without a
+//! database.
+
+#[cfg(test)]
+mod memory;
+mod sql;
+
+use std::sync::Arc;
+
+use rocket::fairing::AdHoc;
+use rocket_db_pools::Database;
+
+use crate::database::Db;
+use crate::error::ApiError;
+use crate::events::{EventLog, PublishingTodoRepository};
+use crate::metrics::{MeteredTodoRepository, MeteredUserRepository};
+use crate::models::{
+    Priority, QuotaOverrides, Role, Status as TodoStatus, Todo, UpdateTodoRequest, User,
+};
+
+#[cfg(test)]
+pub use memory::{InMemoryTodoRepository, InMemoryUserRepository};
+pub use sql::{SqlTodoRepository, SqlUserRepository};
+
+pub type RepositoryResult<T> = Result<T, RepositoryError>;
+
+#[derive(Debug)]
+pub enum RepositoryError {
+    /// A unique constraint was violated
+    Conflict,
+    Database(sqlx::Error),
+}
+
+impl From<sqlx::Error> for RepositoryError {
+    fn from(e: sqlx::Error) -> Self {
+        match e {
+            sqlx::Error::Database(ref db) if db.is_unique_violation() => RepositoryError::Conflict,
+            e => RepositoryError::Database(e),
+        }
+    }
+}
+
+impl std::fmt::Display for RepositoryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RepositoryError::Conflict => write!(f, "a unique constraint was violated"),
+            RepositoryError::Database(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for RepositoryError {}
+
+impl From<RepositoryError> for ApiError {
+    fn from(e: RepositoryError) -> Self {
+        match e {
+            RepositoryError::Conflict => ApiError::Conflict("Resource already exists".to_string()),
+            RepositoryError::Database(e) => e.into(),
+        }
+    }
+}
+
+/// A todo about to be inserted, with defaults already applied
+#[derive(Debug, Clone)]
+pub struct NewTodo {
+    pub title: String,
+    pub description: Option<String>,
+    pub status: TodoStatus,
+    pub priority: Priority,
+}
+
+/// Narrows a listing; unset fields match everything
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TodoFilter {
+    pub status: Option<TodoStatus>,
+    pub priority: Option<Priority>,
+}
+
+/// What a user's todos take up, counted against their quotas
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Usage {
+    pub todos: i64,
+    /// Bytes of titles and descriptions, as UTF-8
+    pub storage_bytes: i64,
+}
+
+#[derive(Debug, Clone)]
+pub struct NewUser {
+    pub username: String,
+    pub email: String,
+    pub password_hash: String,
+}
+
+/// Todos are always scoped to their owner: another user's todo behaves as if
+/// it did not exist.
+#[rocket::async_trait]
+pub trait TodoRepository: Send + Sync {
+    async fn create(&self, user_id: i64, todo: NewTodo) -> RepositoryResult<Todo>;
+
+    async fn find(&self, user_id: i64, id: i64) -> RepositoryResult<Option<Todo>>;
+
+    /// Newest first
+    async fn list(&self, user_id: i64, filter: TodoFilter) -> RepositoryResult<Vec<Todo>>;
+
+    /// Applies the fields that are set. `None` if there is no such todo.
+    async fn update(
+        &self,
+        user_id: i64,
+        id: i64,
+        changes: UpdateTodoRequest,
+    ) -> RepositoryResult<Option<Todo>>;
+
+    /// Whether a todo was deleted
+    async fn delete(&self, user_id: i64, id: i64) -> RepositoryResult<bool>;
+
+    async fn usage(&self, user_id: i64) -> RepositoryResult<Usage>;
+}
+
+#[rocket::async_trait]
+pub trait UserRepository: Send + Sync {
+    async fn find_by_email(&self, email: &str) -> RepositoryResult<Option<User>>;
+
+    async fn find_by_username(&self, username: &str) -> RepositoryResult<Option<User>>;
+
+    /// Whether either the username or the email is taken
+    async fn exists(&self, username: &str, email: &str) -> RepositoryResult<bool>;
+
+    /// Fails with `Conflict` if the username or email is taken
+    async fn create(&self, user: NewUser) -> RepositoryResult<User>;
+
+    /// Starts a session and returns its id, for the token's `sid` claim
+    async fn create_session(&self, user_id: i64) -> RepositoryResult<String>;
+
+    async fn revoke_session(&self, session_id: &str) -> RepositoryResult<()>;
+
+    async fn revoke_all_sessions(&self, user_id: i64) -> RepositoryResult<()>;
+
+    // The setters below return whether the user exists
+
+    async fn set_password_hash(&self, user_id: i64, password_hash: &str) -> RepositoryResult<bool>;
+
+    async fn set_role(&self, user_id: i64, role: Role) -> RepositoryResult<bool>;
+
+    /// Disabling does not end existing sessions; see `revoke_all_sessions`
+    async fn set_disabled(&self, user_id: i64, disabled: bool) -> RepositoryResult<bool>;
+
+    /// Empty unless an administrator set some; `None` if there is no such user
+    async fn quota_overrides(&self, user_id: i64) -> RepositoryResult<Option<QuotaOverrides>>;
+
+    /// Replaces the user's overrides; empty ones remove them
+    async fn set_quota_overrides(&self, user_id: i64, overrides: &QuotaOverrides) -> RepositoryResult<bool>;
+}
+
+/// Manages the SQL repositories over the `Db` pool, timed for `/metrics`, unless repositories
+/// were already put in managed state (e.g. in-memory ones for tests). Changes to the SQL
+/// todos are published to the event log, which is always managed; a repository managed
+/// in their place publishes nothing.
+/// Must be attached after the database pool is opened.
+pub fn stage() -> AdHoc {
+    AdHoc::try_on_ignite("Repositories", |rocket| async {
+        let Some(db) = Db::fetch(&rocket) else {
+            return Err(rocket);
+        };
+        let log = Arc::new(EventLog::new((**db).clone()));
+
+        let rocket = if rocket.state::<Arc<dyn TodoRepository>>().is_none() {
+            let sql = Arc::new(SqlTodoRepository::new((**db).clone()));
+            let metered = Arc::new(MeteredTodoRepository(sql));
+            let todos: Arc<dyn TodoRepository> = Arc::new(PublishingTodoRepository::new(metered, log.clone()));
+            rocket.manage(todos)
+        } else {
+            rocket
+        };
+        let rocket = rocket.manage(log);
+
+        let rocket = if rocket.state::<Arc<dyn UserRepository>>().is_none() {
+            let Some(db) = Db::fetch(&rocket) else {
+                return Err(rocket);
+            };
+            let sql = Arc::new(SqlUserRepository::new((**db).clone()));
+            let users: Arc<dyn UserRepository> = Arc::new(MeteredUserRepository(sql));
+            rocket.manage(users)
+        } else {
+            rocket
+        };
+
+        Ok(rocket)
+    })
+}
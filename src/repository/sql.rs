@@ -0,0 +1,317 @@
+use sqlx::QueryBuilder;
+
+use super::{
+    NewTodo, NewUser, RepositoryResult, TodoFilter, TodoRepository, Usage, UserRepository,
+};
+use crate::auth::session;
+use crate::database::{returned, DbPool};
+use crate::models::{
+    Priority, QuotaOverrides, Role, Status as TodoStatus, Todo, UpdateTodoRequest, User,
+};
+
+pub struct SqlTodoRepository {
+    pool: DbPool,
+}
+
+impl SqlTodoRepository {
+    pub fn new(pool: DbPool) -> Self {
+        SqlTodoRepository { pool }
+    }
+}
+
+#[rocket::async_trait]
+impl TodoRepository for SqlTodoRepository {
+    async fn create(&self, user_id: i64, todo: NewTodo) -> RepositoryResult<Todo> {
+        let id = returned(
+            sqlx::query_scalar!(
+                r#"INSERT INTO todos (title, description, status, priority, user_id) VALUES ($1, $2, $3, $4, $5) RETURNING id AS "id!""#,
+                todo.title,
+                todo.description,
+                todo.status as _,
+                todo.priority as _,
+                user_id
+            )
+            .fetch_all(&self.pool)
+            .await?,
+        )?;
+
+        let todo = sqlx::query_as!(
+            Todo,
+            r#"SELECT id AS "id?", title, description, status AS "status: TodoStatus", priority AS "priority: Priority", created_at, updated_at FROM todos WHERE id = $1"#,
+            id
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(todo)
+    }
+
+    async fn find(&self, user_id: i64, id: i64) -> RepositoryResult<Option<Todo>> {
+        let todo = sqlx::query_as!(
+            Todo,
+            r#"SELECT id AS "id?", title, description, status AS "status: TodoStatus", priority AS "priority: Priority", created_at, updated_at FROM todos WHERE id = $1 AND user_id = $2"#,
+            id,
+            user_id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(todo)
+    }
+
+    async fn list(&self, user_id: i64, filter: TodoFilter) -> RepositoryResult<Vec<Todo>> {
+        let todos = sqlx::query_as!(
+            Todo,
+            r#"SELECT id AS "id?", title, description, status AS "status: TodoStatus", priority AS "priority: Priority", created_at, updated_at FROM todos
+            WHERE user_id = $1 AND (CAST($2 AS TEXT) IS NULL OR status = $2) AND (CAST($3 AS TEXT) IS NULL OR priority = $3)
+            ORDER BY created_at DESC, id DESC"#,
+            user_id,
+            filter.status as _,
+            filter.priority as _
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(todos)
+    }
+
+    async fn update(
+        &self,
+        user_id: i64,
+        id: i64,
+        changes: UpdateTodoRequest,
+    ) -> RepositoryResult<Option<Todo>> {
+        if changes.title.is_none()
+            && changes.description.is_none()
+            && changes.status.is_none()
+            && changes.priority.is_none()
+        {
+            // No fields to update, return the todo as it is
+            return self.find(user_id, id).await;
+        }
+
+        // Build update query dynamically; the builder numbers the placeholders
+        let mut update = QueryBuilder::new("UPDATE todos SET updated_at = CURRENT_TIMESTAMP");
+        if let Some(title) = changes.title {
+            update.push(", title = ").push_bind(title);
+        }
+        if let Some(description) = changes.description {
+            update.push(", description = ").push_bind(description);
+        }
+        if let Some(status) = changes.status {
+            update.push(", status = ").push_bind(status);
+        }
+        if let Some(priority) = changes.priority {
+            update.push(", priority = ").push_bind(priority);
+        }
+        update.push(" WHERE id = ").push_bind(id);
+        update.push(" AND user_id = ").push_bind(user_id);
+
+        let result = update.build().execute(&self.pool).await?;
+
+        if result.rows_affected() == 0 {
+            return Ok(None);
+        }
+
+        self.find(user_id, id).await
+    }
+
+    async fn delete(&self, user_id: i64, id: i64) -> RepositoryResult<bool> {
+        let result = sqlx::query!(
+            "DELETE FROM todos WHERE id = $1 AND user_id = $2",
+            id,
+            user_id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn usage(&self, user_id: i64) -> RepositoryResult<Usage> {
+        let usage = sqlx::query_as!(
+            Usage,
+            r#"SELECT COUNT(*) AS "todos!: i64",
+            COALESCE(SUM(octet_length(title) + COALESCE(octet_length(description), 0)), 0) AS "storage_bytes!: i64"
+            FROM todos WHERE user_id = $1"#,
+            user_id
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(usage)
+    }
+}
+
+pub struct SqlUserRepository {
+    pool: DbPool,
+}
+
+impl SqlUserRepository {
+    pub fn new(pool: DbPool) -> Self {
+        SqlUserRepository { pool }
+    }
+}
+
+#[rocket::async_trait]
+impl UserRepository for SqlUserRepository {
+    async fn find_by_email(&self, email: &str) -> RepositoryResult<Option<User>> {
+        let user = sqlx::query_as!(
+            User,
+            r#"SELECT id AS "id?", username, email, password_hash, pending_email, role AS "role: Role", disabled_at, created_at, updated_at FROM users WHERE email = $1"#,
+            email
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(user)
+    }
+
+    async fn find_by_username(&self, username: &str) -> RepositoryResult<Option<User>> {
+        let user = sqlx::query_as!(
+            User,
+            r#"SELECT id AS "id?", username, email, password_hash, pending_email, role AS "role: Role", disabled_at, created_at, updated_at FROM users WHERE username = $1"#,
+            username
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(user)
+    }
+
+    async fn exists(&self, username: &str, email: &str) -> RepositoryResult<bool> {
+        let existing = sqlx::query!(
+            "SELECT id FROM users WHERE email = $1 OR username = $2",
+            email,
+            username
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(existing.is_some())
+    }
+
+    async fn create(&self, user: NewUser) -> RepositoryResult<User> {
+        let id = returned(
+            sqlx::query_scalar!(
+                r#"INSERT INTO users (username, email, password_hash) VALUES ($1, $2, $3) RETURNING id AS "id!""#,
+                user.username,
+                user.email,
+                user.password_hash
+            )
+            .fetch_all(&self.pool)
+            .await?,
+        )?;
+
+        let user = sqlx::query_as!(
+            User,
+            r#"SELECT id AS "id?", username, email, password_hash, pending_email, role AS "role: Role", disabled_at, created_at, updated_at FROM users WHERE id = $1"#,
+            id
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(user)
+    }
+
+    async fn create_session(&self, user_id: i64) -> RepositoryResult<String> {
+        let mut conn = self.pool.acquire().await?;
+        Ok(session::create_session(&mut conn, user_id).await?)
+    }
+
+    async fn revoke_session(&self, session_id: &str) -> RepositoryResult<()> {
+        let mut conn = self.pool.acquire().await?;
+        Ok(session::revoke(&mut conn, session_id).await?)
+    }
+
+    async fn revoke_all_sessions(&self, user_id: i64) -> RepositoryResult<()> {
+        sqlx::query!(
+            "UPDATE sessions SET revoked_at = CURRENT_TIMESTAMP WHERE user_id = $1 AND revoked_at IS NULL",
+            user_id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn set_password_hash(&self, user_id: i64, password_hash: &str) -> RepositoryResult<bool> {
+        let result = sqlx::query!(
+            "UPDATE users SET password_hash = $1 WHERE id = $2",
+            password_hash,
+            user_id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn set_role(&self, user_id: i64, role: Role) -> RepositoryResult<bool> {
+        let result = sqlx::query!("UPDATE users SET role = $1 WHERE id = $2", role as _, user_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn set_disabled(&self, user_id: i64, disabled: bool) -> RepositoryResult<bool> {
+        let result = if disabled {
+            sqlx::query!(
+                "UPDATE users SET disabled_at = COALESCE(disabled_at, CURRENT_TIMESTAMP) WHERE id = $1",
+                user_id
+            )
+            .execute(&self.pool)
+            .await?
+        } else {
+            sqlx::query!("UPDATE users SET disabled_at = NULL WHERE id = $1", user_id)
+                .execute(&self.pool)
+                .await?
+        };
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn quota_overrides(&self, user_id: i64) -> RepositoryResult<Option<QuotaOverrides>> {
+        let overrides = sqlx::query_as!(
+            QuotaOverrides,
+            r#"SELECT q.max_todos AS "max_todos?", q.max_description_len AS "max_description_len?", q.max_storage_bytes AS "max_storage_bytes?"
+            FROM users u LEFT JOIN user_quotas q ON q.user_id = u.id WHERE u.id = $1"#,
+            user_id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(overrides)
+    }
+
+    async fn set_quota_overrides(&self, user_id: i64, overrides: &QuotaOverrides) -> RepositoryResult<bool> {
+        if overrides.is_empty() {
+            sqlx::query!("DELETE FROM user_quotas WHERE user_id = $1", user_id)
+                .execute(&self.pool)
+                .await?;
+            let user = sqlx::query!("SELECT id FROM users WHERE id = $1", user_id)
+                .fetch_optional(&self.pool)
+                .await?;
+            return Ok(user.is_some());
+        }
+
+        // Selecting from users inserts nothing for an unknown user
+        let result = sqlx::query!(
+            "INSERT INTO user_quotas (user_id, max_todos, max_description_len, max_storage_bytes)
+            SELECT id, CAST($2 AS BIGINT), CAST($3 AS BIGINT), CAST($4 AS BIGINT) FROM users WHERE id = $1
+            ON CONFLICT (user_id) DO UPDATE SET max_todos = excluded.max_todos,
+                max_description_len = excluded.max_description_len,
+                max_storage_bytes = excluded.max_storage_bytes,
+                updated_at = CURRENT_TIMESTAMP",
+            user_id,
+            overrides.max_todos,
+            overrides.max_description_len,
+            overrides.max_storage_bytes
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
@@ -0,0 +1,201 @@
+use rocket::get;
+use rocket::post;
+use rocket::put;
+use rocket::response::status;
+use rocket::serde::json::Json;
+use rocket::State;
+use std::sync::Arc;
+
+use crate::auth::jwt::AdminAuth;
+use crate::backup::{BackupConfig, Snapshot};
+use crate::config::AppConfig;
+use crate::database::{Db, MigrationStatus};
+use crate::error::ApiResult;
+use crate::handlers::admin_handler;
+use crate::maintenance::Maintenance;
+use crate::models::{QuotaOverrides, UserQuotaResponse};
+use crate::repository::{TodoRepository, UserRepository};
+use crate::telemetry::logging::{LogLevelResponse, SetLogLevelRequest};
+use crate::validation::Validated;
+
+#[utoipa::path(
+    get,
+    path = "/api/admin/backups",
+    tag = "admin",
+    responses(
+        (status = 200, description = "Database snapshots, newest first", body = [Snapshot]),
+        (status = 403, description = "Not an administrator", body = ProblemDetails, content_type = "application/problem+json")
+    ),
+    security(
+        ("jwt_auth" = [])
+    )
+)]
+#[get("/admin/backups")]
+pub async fn list_backups(admin: AdminAuth, config: &State<BackupConfig>) -> ApiResult<Json<Vec<Snapshot>>> {
+    admin_handler::list_backups(admin, config).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/admin/backups",
+    tag = "admin",
+    responses(
+        (status = 201, description = "Snapshot written and old ones rotated out", body = Snapshot),
+        (status = 403, description = "Not an administrator", body = ProblemDetails, content_type = "application/problem+json")
+    ),
+    security(
+        ("jwt_auth" = [])
+    )
+)]
+#[post("/admin/backups")]
+pub async fn create_backup(
+    db: &Db,
+    admin: AdminAuth,
+    config: &State<BackupConfig>,
+) -> ApiResult<status::Created<Json<Snapshot>>> {
+    admin_handler::create_backup(db, admin, config).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/admin/backups/{name}/restore",
+    tag = "admin",
+    params(
+        ("name" = String, Path, description = "Snapshot name from the listing")
+    ),
+    responses(
+        (status = 200, description = "Database replaced by the snapshot; other requests got 503 meanwhile"),
+        (status = 403, description = "Not an administrator", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 404, description = "No such snapshot", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 422, description = "Checksum mismatch or corrupt snapshot", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 503, description = "Another restore is running or requests did not drain", body = ProblemDetails, content_type = "application/problem+json")
+    ),
+    security(
+        ("jwt_auth" = [])
+    )
+)]
+#[post("/admin/backups/<name>/restore")]
+pub async fn restore_backup(
+    db: &Db,
+    admin: AdminAuth,
+    config: &State<BackupConfig>,
+    maintenance: &State<Maintenance>,
+    name: &str,
+) -> ApiResult<Json<serde_json::Value>> {
+    admin_handler::restore_backup(db, admin, config, maintenance, name).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/admin/migrations",
+    tag = "admin",
+    responses(
+        (status = 200, description = "Every migration in this build, oldest first, with whether it is applied and unchanged", body = [MigrationStatus]),
+        (status = 403, description = "Not an administrator", body = ProblemDetails, content_type = "application/problem+json")
+    ),
+    security(
+        ("jwt_auth" = [])
+    )
+)]
+#[get("/admin/migrations")]
+pub async fn list_migrations(db: &Db, admin: AdminAuth) -> ApiResult<Json<Vec<MigrationStatus>>> {
+    admin_handler::list_migrations(db, admin).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/admin/log-level",
+    tag = "admin",
+    responses(
+        (status = 200, description = "The log filter in effect and the one configured at startup", body = LogLevelResponse),
+        (status = 403, description = "Not an administrator", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 503, description = "This process did not install its own logging", body = ProblemDetails, content_type = "application/problem+json")
+    ),
+    security(
+        ("jwt_auth" = [])
+    )
+)]
+#[get("/admin/log-level")]
+pub async fn get_log_level(admin: AdminAuth) -> ApiResult<Json<LogLevelResponse>> {
+    admin_handler::get_log_level(admin).await
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/admin/log-level",
+    tag = "admin",
+    request_body = SetLogLevelRequest,
+    responses(
+        (status = 200, description = "New filter in effect until changed again or the server restarts", body = LogLevelResponse),
+        (status = 403, description = "Not an administrator", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 422, description = "Not a valid filter", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 503, description = "This process did not install its own logging", body = ProblemDetails, content_type = "application/problem+json")
+    ),
+    security(
+        ("jwt_auth" = [])
+    )
+)]
+#[put("/admin/log-level", data = "<request>")]
+pub async fn set_log_level(
+    admin: AdminAuth,
+    request: Validated<SetLogLevelRequest>,
+) -> ApiResult<Json<LogLevelResponse>> {
+    admin_handler::set_log_level(admin, request.0).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/admin/users/{id}/quota",
+    tag = "admin",
+    params(
+        ("id" = i64, Path, description = "User id")
+    ),
+    responses(
+        (status = 200, description = "The user's overrides and usage against the quotas in effect", body = UserQuotaResponse),
+        (status = 403, description = "Not an administrator", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 404, description = "No such user", body = ProblemDetails, content_type = "application/problem+json")
+    ),
+    security(
+        ("jwt_auth" = [])
+    )
+)]
+#[get("/admin/users/<id>/quota")]
+pub async fn get_user_quota(
+    admin: AdminAuth,
+    todos: &State<Arc<dyn TodoRepository>>,
+    users: &State<Arc<dyn UserRepository>>,
+    config: &State<AppConfig>,
+    id: i64,
+) -> ApiResult<Json<UserQuotaResponse>> {
+    admin_handler::get_user_quota(admin, todos, users, config, id).await
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/admin/users/{id}/quota",
+    tag = "admin",
+    params(
+        ("id" = i64, Path, description = "User id")
+    ),
+    request_body = QuotaOverrides,
+    responses(
+        (status = 200, description = "Overrides replaced; unset fields follow [app.quotas] again", body = UserQuotaResponse),
+        (status = 403, description = "Not an administrator", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 404, description = "No such user", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 422, description = "Negative limit or description limit above the maximum", body = ProblemDetails, content_type = "application/problem+json")
+    ),
+    security(
+        ("jwt_auth" = [])
+    )
+)]
+#[put("/admin/users/<id>/quota", data = "<request>")]
+pub async fn set_user_quota(
+    admin: AdminAuth,
+    todos: &State<Arc<dyn TodoRepository>>,
+    users: &State<Arc<dyn UserRepository>>,
+    config: &State<AppConfig>,
+    id: i64,
+    request: Validated<QuotaOverrides>,
+) -> ApiResult<Json<UserQuotaResponse>> {
+    admin_handler::set_user_quota(admin, todos, users, config, id, request.0).await
+}
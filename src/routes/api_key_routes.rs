@@ -0,0 +1,83 @@
+use rocket::delete;
+use rocket::get;
+use rocket::post;
+use rocket::response::status;
+use rocket::serde::json::Json;
+use rocket::State;
+use rocket_db_pools::Connection;
+
+use crate::auth::jwt::JwtAuth;
+use crate::config::{AppConfig, Feature};
+use crate::database::Db;
+use crate::error::ApiResult;
+use crate::handlers::api_key_handler;
+use crate::models::{ApiKeyResponse, CreateApiKeyRequest, CreatedApiKeyResponse};
+
+#[utoipa::path(
+    post,
+    path = "/api/auth/api-keys",
+    tag = "api_keys",
+    request_body = CreateApiKeyRequest,
+    responses(
+        (status = 201, description = "API key created; the token is only shown once", body = CreatedApiKeyResponse),
+        (status = 400, description = "Invalid name, scope or expiry"),
+        (status = 403, description = "API keys cannot be used to manage API keys, or API keys are disabled")
+    ),
+    security(
+        ("jwt_auth" = [])
+    )
+)]
+#[post("/auth/api-keys", data = "<request>")]
+pub async fn create_api_key(
+    db: Connection<Db>,
+    config: &State<AppConfig>,
+    auth: JwtAuth,
+    request: Json<CreateApiKeyRequest>,
+) -> ApiResult<status::Created<Json<CreatedApiKeyResponse>>> {
+    config.features.require(Feature::ApiKeys)?;
+    api_key_handler::create_api_key(db, auth, request).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/auth/api-keys",
+    tag = "api_keys",
+    responses(
+        (status = 200, description = "List active API keys", body = [ApiKeyResponse]),
+        (status = 403, description = "API keys cannot be used to manage API keys")
+    ),
+    security(
+        ("jwt_auth" = [])
+    )
+)]
+#[get("/auth/api-keys")]
+pub async fn list_api_keys(
+    db: Connection<Db>,
+    auth: JwtAuth,
+) -> ApiResult<Json<Vec<ApiKeyResponse>>> {
+    api_key_handler::list_api_keys(db, auth).await
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/auth/api-keys/{id}",
+    tag = "api_keys",
+    params(
+        ("id" = i64, Path, description = "API key id")
+    ),
+    responses(
+        (status = 204, description = "Revoked"),
+        (status = 404, description = "Not found")
+    ),
+    security(
+        ("jwt_auth" = [])
+    )
+)]
+#[delete("/auth/api-keys/<id>")]
+pub async fn revoke_api_key(
+    db: Connection<Db>,
+    auth: JwtAuth,
+    id: i64,
+) -> ApiResult<status::NoContent> {
+    api_key_handler::revoke_api_key(db, auth, id).await
+}
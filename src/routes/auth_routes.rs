@@ -1,16 +1,20 @@
-use rocket::delete;
 use rocket::get;
 use rocket::http::CookieJar;
 use rocket::post;
 use rocket::response::status;
 use rocket::serde::json::Json;
 
+use std::sync::Arc;
+
+use rocket::State;
+
 use crate::auth::jwt::JwtAuth;
-use crate::database::Db;
+use crate::config::{AppConfig, Feature};
+use crate::error::ApiResult;
 use crate::handlers::auth_handler;
 use crate::models::user::{CreateUserRequest, LoginRequest};
-use rocket_db_pools::Connection;
-use utoipa::path;
+use crate::repository::UserRepository;
+use crate::validation::Validated;
 
 #[utoipa::path(
     post,
@@ -19,15 +23,20 @@
     request_body = CreateUserRequest,
     responses(
         (status = 201, description = "User registered successfully"),
-        (status = 409, description = "User already exists")
+        (status = 403, description = "Registration is disabled", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 409, description = "User already exists", body = ProblemDetails, content_type = "application/problem+json")
     )
 )]
 #[post("/auth/register", data = "<request>")]
 pub async fn register(
-    db: Connection<Db>,
-    request: Json<CreateUserRequest>,
-) -> Result<status::Created<Json<serde_json::Value>>, status::Custom<Json<serde_json::Value>>> {
-    auth_handler::register(db, request).await
+    users: &State<Arc<dyn UserRepository>>,
+    config: &State<AppConfig>,
+    request: Validated<CreateUserRequest>,
+) -> ApiResult<status::Created<Json<serde_json::Value>>> {
+    config.features.require(Feature::Registration)?;
+    let signed_in = auth_handler::register(users.inner().as_ref(), &config.jwt, request.0).await?;
+
+    Ok(status::Created::new("/users").body(Json(signed_in.body("User created successfully"))))
 }
 
 #[utoipa::path(
@@ -37,16 +46,22 @@ pub async fn register(
     request_body = LoginRequest,
     responses(
         (status = 200, description = "Login successful"),
-        (status = 401, description = "Invalid credentials")
+        (status = 401, description = "Invalid credentials", body = ProblemDetails, content_type = "application/problem+json")
     )
 )]
 #[post("/auth/login", data = "<request>")]
 pub async fn login(
-    db: Connection<Db>,
-    request: Json<LoginRequest>,
+    users: &State<Arc<dyn UserRepository>>,
+    config: &State<AppConfig>,
+    request: Validated<LoginRequest>,
     cookies: &CookieJar<'_>,
-) -> Result<Json<serde_json::Value>, status::Custom<Json<serde_json::Value>>> {
-    auth_handler::login(db, request, cookies).await
+) -> ApiResult<Json<serde_json::Value>> {
+    let signed_in = auth_handler::login(users.inner().as_ref(), &config.jwt, request.0).await?;
+    auth_handler::set_auth_cookie(cookies, config, &signed_in.token, &signed_in.csrf_token);
+
+    let mut body = signed_in.body("Login successful");
+    body["csrf_token"] = signed_in.csrf_token.into();
+    Ok(Json(body))
 }
 
 #[utoipa::path(
@@ -58,8 +73,13 @@ pub async fn login(
     )
 )]
 #[post("/auth/logout")]
-pub async fn logout(cookies: &CookieJar<'_>) -> Json<serde_json::Value> {
-    auth_handler::logout(cookies).await
+pub async fn logout(
+    users: &State<Arc<dyn UserRepository>>,
+    config: &State<AppConfig>,
+    auth: Option<JwtAuth>,
+    cookies: &CookieJar<'_>,
+) -> Json<serde_json::Value> {
+    auth_handler::logout(users.inner().as_ref(), &config.jwt, auth, cookies).await
 }
 
 #[utoipa::path(
@@ -68,13 +88,33 @@ pub async fn logout(cookies: &CookieJar<'_>) -> Json<serde_json::Value> {
     tag = "auth",
     responses(
         (status = 200, description = "Get current user info"),
-        (status = 401, description = "Unauthorized")
+        (status = 401, description = "Unauthorized", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "API key is missing the user:read scope", body = ProblemDetails, content_type = "application/problem+json")
     ),
     security(
         ("jwt_auth" = [])
     )
 )]
 #[get("/auth/me")]
-pub async fn me(auth: JwtAuth) -> Json<serde_json::Value> {
-    auth_handler::me(auth).await
-}
\ No newline at end of file
+pub async fn me(auth: JwtAuth) -> ApiResult<Json<serde_json::Value>> {
+    auth.require_scope("user:read")?;
+
+    Ok(auth_handler::me(auth).await)
+}
+#[utoipa::path(
+    get,
+    path = "/api/auth/csrf",
+    tag = "auth",
+    responses(
+        (status = 200, description = "CSRF token of the current session, to send as X-CSRF-Token"),
+        (status = 401, description = "Unauthorized", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "API keys have no CSRF token", body = ProblemDetails, content_type = "application/problem+json")
+    ),
+    security(
+        ("jwt_auth" = [])
+    )
+)]
+#[get("/auth/csrf")]
+pub fn csrf_token(config: &State<AppConfig>, auth: JwtAuth) -> ApiResult<Json<serde_json::Value>> {
+    auth_handler::csrf_token(&config.jwt, &auth)
+}
@@ -0,0 +1,42 @@
+use std::sync::Arc;
+
+use rocket::get;
+use rocket::response::stream::EventStream;
+use rocket::{Shutdown, State};
+
+use crate::auth::jwt::JwtAuth;
+use crate::error::ApiResult;
+use crate::events::{EventLog, EventsConfig, LastEventId};
+
+#[utoipa::path(
+    get,
+    path = "/api/events",
+    tag = "events",
+    params(
+        ("Last-Event-ID" = Option<i64>, Header, description = "Id of the last event received; the events since are sent first")
+    ),
+    responses(
+        (status = 200, description = "Server-Sent Events named todo.created, todo.updated and todo.deleted, for the caller's todos only. \
+            A resync event means events were missed and the todos should be reloaded. The stream ends when the server shuts down.",
+            body = TodoEvent, content_type = "text/event-stream"),
+        (status = 401, description = "Missing, invalid or expired token", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "API key is missing the todos:read scope", body = ProblemDetails, content_type = "application/problem+json")
+    ),
+    security(
+        ("jwt_auth" = [])
+    )
+)]
+#[get("/events")]
+pub async fn events(
+    log: &State<Arc<EventLog>>,
+    config: &State<EventsConfig>,
+    auth: JwtAuth,
+    last_event_id: LastEventId,
+    shutdown: Shutdown,
+) -> ApiResult<EventStream![]> {
+    auth.require_scope("todos:read")?;
+    let user_id = auth.numeric_user_id()?;
+
+    let heartbeat = std::time::Duration::from_secs(config.heartbeat_secs);
+    Ok(crate::events::stream(log.inner().clone(), user_id, last_event_id.0, heartbeat, shutdown).await?)
+}
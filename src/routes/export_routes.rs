@@ -0,0 +1,91 @@
+use rocket::get;
+use rocket::serde::json::Json;
+use rocket::State;
+
+use crate::auth::jwt::JwtAuth;
+use crate::config::{AppConfig, Feature};
+use crate::database::Db;
+use crate::error::ApiResult;
+use crate::export::{Attachment, ExportConfig};
+use crate::handlers::export_handler::{self, ExportOutcome};
+use crate::models::DataExportResponse;
+
+#[utoipa::path(
+    get,
+    path = "/api/users/me/export",
+    tag = "users",
+    params(
+        ("background" = Option<bool>, Query, description = "Always queue the export instead of returning it inline")
+    ),
+    responses(
+        (status = 200, description = "JSON archive of the profile, todos, sessions, API keys and linked identities"),
+        (status = 202, description = "Export queued; poll the status endpoint for a download link", body = DataExportResponse),
+        (status = 403, description = "API keys cannot export account data, or exports are disabled")
+    ),
+    security(
+        ("jwt_auth" = [])
+    )
+)]
+#[get("/users/me/export?<background>")]
+pub async fn export_me(
+    db: &Db,
+    auth: JwtAuth,
+    config: &State<ExportConfig>,
+    app: &State<AppConfig>,
+    background: Option<bool>,
+) -> ApiResult<ExportOutcome> {
+    app.features.require(Feature::DataExport)?;
+    export_handler::export_me(db, auth, config, background.unwrap_or(false)).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/users/me/exports/{id}",
+    tag = "users",
+    params(
+        ("id" = String, Path, description = "Export ID")
+    ),
+    responses(
+        (status = 200, description = "Export status, with a download link once ready", body = DataExportResponse),
+        (status = 404, description = "Export not found")
+    ),
+    security(
+        ("jwt_auth" = [])
+    )
+)]
+#[get("/users/me/exports/<id>")]
+pub async fn get_export(
+    db: &Db,
+    auth: JwtAuth,
+    config: &State<ExportConfig>,
+    app: &State<AppConfig>,
+    id: &str,
+) -> ApiResult<Json<DataExportResponse>> {
+    export_handler::get_export(db, auth, config, &app.jwt, id).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/exports/{id}/download",
+    tag = "users",
+    params(
+        ("id" = String, Path, description = "Export ID"),
+        ("token" = String, Query, description = "Signed token from the status endpoint")
+    ),
+    responses(
+        (status = 200, description = "The export archive"),
+        (status = 403, description = "Invalid or expired download link"),
+        (status = 409, description = "Export is not ready yet"),
+        (status = 410, description = "Export has been deleted")
+    ),
+    security(())
+)]
+#[get("/exports/<id>/download?<token>")]
+pub async fn download_export(
+    db: &Db,
+    app: &State<AppConfig>,
+    id: &str,
+    token: &str,
+) -> ApiResult<Attachment> {
+    export_handler::download_export(db, &app.jwt, id, token).await
+}
@@ -1,8 +1,13 @@
-pub mod todo_routes;
+pub mod admin_routes;
+pub mod api_key_routes;
 pub mod auth_routes;
-
-pub use todo_routes::*;
-pub use auth_routes::*;
+pub mod event_routes;
+pub mod export_routes;
+pub mod oidc_routes;
+pub mod realtime_routes;
+pub mod todo_routes;
+pub mod user_routes;
+pub mod webhook_routes;
 
 use utoipa::OpenApi;
 
@@ -16,10 +21,40 @@
         crate::routes::todo_routes::create_todo,
         crate::routes::todo_routes::update_todo,
         crate::routes::todo_routes::delete_todo,
+        crate::routes::event_routes::events,
+        crate::routes::realtime_routes::connect,
         crate::routes::auth_routes::register,
         crate::routes::auth_routes::login,
         crate::routes::auth_routes::logout,
-        crate::routes::auth_routes::me
+        crate::routes::auth_routes::me,
+        crate::routes::auth_routes::csrf_token,
+        crate::routes::oidc_routes::providers,
+        crate::routes::oidc_routes::login,
+        crate::routes::oidc_routes::callback,
+        crate::routes::api_key_routes::create_api_key,
+        crate::routes::api_key_routes::list_api_keys,
+        crate::routes::api_key_routes::revoke_api_key,
+        crate::routes::user_routes::update_me,
+        crate::routes::user_routes::verify_email,
+        crate::routes::user_routes::change_password,
+        crate::routes::user_routes::delete_me,
+        crate::routes::user_routes::usage,
+        crate::routes::export_routes::export_me,
+        crate::routes::export_routes::get_export,
+        crate::routes::export_routes::download_export,
+        crate::routes::webhook_routes::create_webhook,
+        crate::routes::webhook_routes::list_webhooks,
+        crate::routes::webhook_routes::delete_webhook,
+        crate::routes::webhook_routes::list_deliveries,
+        crate::routes::webhook_routes::redeliver,
+        crate::routes::admin_routes::list_backups,
+        crate::routes::admin_routes::create_backup,
+        crate::routes::admin_routes::restore_backup,
+        crate::routes::admin_routes::list_migrations,
+        crate::routes::admin_routes::get_log_level,
+        crate::routes::admin_routes::set_log_level,
+        crate::routes::admin_routes::get_user_quota,
+        crate::routes::admin_routes::set_user_quota
     ),
     components(
         schemas(
@@ -29,16 +64,52 @@
             crate::models::UpdateTodoRequest,
             crate::models::Priority,
             crate::models::Status,
+            crate::events::TodoEvent,
+            crate::events::EventKind,
+            crate::realtime::Presence,
+            crate::realtime::PresenceState,
             crate::models::User,
             crate::models::CreateUserRequest,
             crate::models::LoginRequest,
             crate::models::UserResponse,
-            crate::models::Claims
+            crate::models::Claims,
+            crate::models::CreateApiKeyRequest,
+            crate::models::ApiKeyResponse,
+            crate::models::CreatedApiKeyResponse,
+            crate::models::UpdateProfileRequest,
+            crate::models::VerifyEmailRequest,
+            crate::models::ChangePasswordRequest,
+            crate::models::DeleteAccountRequest,
+            crate::models::TodoDisposition,
+            crate::models::DataExportResponse,
+            crate::models::CreateWebhookRequest,
+            crate::models::WebhookResponse,
+            crate::models::CreatedWebhookResponse,
+            crate::models::DeliveryStatus,
+            crate::models::DeliveryResponse,
+            crate::models::QuotaOverrides,
+            crate::models::QuotaUsage,
+            crate::models::UsageResponse,
+            crate::models::UserQuotaResponse,
+            crate::backup::Snapshot,
+            crate::database::MigrationStatus,
+            crate::config::PublicConfig,
+            crate::config::PaginationConfig,
+            crate::config::Features,
+            crate::telemetry::logging::LogLevelResponse,
+            crate::telemetry::logging::SetLogLevelRequest,
+            crate::error::ProblemDetails,
+            crate::validation::FieldError
         )
     ),
     tags(
         (name = "todos", description = "Todo management endpoints"),
-        (name = "auth", description = "Authentication endpoints")
+        (name = "events", description = "Live feed of todo changes, over SSE or a WebSocket"),
+        (name = "auth", description = "Authentication endpoints"),
+        (name = "api_keys", description = "Personal access tokens for scripts and CI"),
+        (name = "users", description = "Self-service account management"),
+        (name = "webhooks", description = "Signed HTTP callbacks for todo events, with a delivery log"),
+        (name = "admin", description = "Operations for administrators")
     ),
     security(
         ("jwt_auth" = [])
@@ -0,0 +1,75 @@
+use rocket::get;
+use rocket::http::CookieJar;
+use rocket::response::Redirect;
+use rocket::serde::json::Json;
+use rocket::State;
+use rocket_db_pools::Connection;
+
+use crate::auth::oidc::Oidc;
+use crate::config::AppConfig;
+use crate::database::Db;
+use crate::error::ApiResult;
+use crate::handlers::oidc_handler::{self, CallbackParams};
+
+#[utoipa::path(
+    get,
+    path = "/api/auth/oidc/providers",
+    tag = "auth",
+    responses(
+        (status = 200, description = "List configured external identity providers")
+    )
+)]
+#[get("/auth/oidc/providers")]
+pub async fn providers(oidc: &State<Oidc>) -> Json<serde_json::Value> {
+    oidc_handler::list_providers(oidc).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/auth/oidc/{provider}/login",
+    tag = "auth",
+    params(
+        ("provider" = String, Path, description = "Provider name from Rocket.toml")
+    ),
+    responses(
+        (status = 303, description = "Redirect to the identity provider"),
+        (status = 404, description = "Unknown provider"),
+        (status = 502, description = "Provider discovery failed")
+    )
+)]
+#[get("/auth/oidc/<provider>/login")]
+pub async fn login(
+    oidc: &State<Oidc>,
+    config: &State<AppConfig>,
+    provider: &str,
+    cookies: &CookieJar<'_>,
+) -> ApiResult<Redirect> {
+    oidc_handler::login(oidc, config, provider, cookies).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/auth/oidc/{provider}/callback",
+    tag = "auth",
+    params(
+        ("provider" = String, Path, description = "Provider name from Rocket.toml"),
+        CallbackParams
+    ),
+    responses(
+        (status = 200, description = "Login successful"),
+        (status = 400, description = "Missing or mismatched login state"),
+        (status = 401, description = "Provider rejected the login or returned an invalid id_token"),
+        (status = 403, description = "No verified email available to link the identity")
+    )
+)]
+#[get("/auth/oidc/<provider>/callback?<params..>")]
+pub async fn callback(
+    db: Connection<Db>,
+    oidc: &State<Oidc>,
+    config: &State<AppConfig>,
+    provider: &str,
+    params: CallbackParams,
+    cookies: &CookieJar<'_>,
+) -> ApiResult<Json<serde_json::Value>> {
+    oidc_handler::callback(db, oidc, config, provider, params, cookies).await
+}
@@ -0,0 +1,34 @@
+use rocket::get;
+
+use crate::auth::jwt::JwtAuth;
+use crate::error::{ApiError, ApiResult};
+use crate::realtime::{Connection, SameOrigin};
+
+#[utoipa::path(
+    get,
+    path = "/api/ws",
+    tag = "events",
+    responses(
+        (status = 101, description = "Switched to WebSocket. Clients send subscribe, unsubscribe, presence, create, update and delete \
+            messages and are sent replies, todo events and presence; see the README."),
+        (status = 400, description = "Not a WebSocket handshake", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Missing, invalid or expired token", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "API key is missing the todos:read scope, or a cookie-authenticated handshake from another origin",
+            body = ProblemDetails, content_type = "application/problem+json")
+    ),
+    security(
+        ("jwt_auth" = [])
+    )
+)]
+#[get("/ws")]
+pub async fn connect(
+    auth: JwtAuth,
+    _origin: SameOrigin,
+    ws: Option<rocket_ws::WebSocket>,
+    connection: Connection,
+) -> ApiResult<rocket_ws::Channel<'static>> {
+    auth.require_scope("todos:read")?;
+    let ws = ws.ok_or_else(|| ApiError::BadRequest("Expected a WebSocket version 13 upgrade request".to_string()))?;
+
+    Ok(connection.accept(ws))
+}
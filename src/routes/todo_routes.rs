@@ -1,184 +1,175 @@
 use rocket::delete;
 use rocket::get;
-use rocket::http::Status;
 use rocket::post;
 use rocket::put;
 use rocket::response::status;
 use rocket::serde::json::Json;
 
-use crate::database::Db;
+use std::sync::Arc;
+
+use rocket::State;
+
+use crate::config::{AppConfig, Page};
+use crate::error::ApiResult;
 use crate::handlers::todo_handler;
-use crate::models::{CreateTodoRequest, TodoResponse, UpdateTodoRequest};
+use crate::models::{
+    CreateTodoRequest, Priority, Status as TodoStatus, TodoResponse, UpdateTodoRequest,
+};
 use crate::auth::jwt::JwtAuth;
-use rocket_db_pools::Connection;
+use crate::quota;
+use crate::repository::{TodoRepository, UserRepository};
+use crate::validation::{FieldError, Validated};
 
-#[utoipa::path(get, path = "/api/todos", tag = "todos", responses(
-    (status = 200, description = "List todos", body = [TodoResponse])
+#[utoipa::path(get, path = "/api/todos", tag = "todos", params(Page), responses(
+    (status = 200, description = "List todos", body = [TodoResponse]),
+    (status = 422, description = "Limit out of range", body = ProblemDetails, content_type = "application/problem+json"),
+    (status = 401, description = "Missing, invalid or expired token", body = ProblemDetails, content_type = "application/problem+json"),
+    (status = 403, description = "API key is missing the todos:read scope", body = ProblemDetails, content_type = "application/problem+json")
 ))]
-#[get("/todos")]
+#[get("/todos?<page..>")]
 pub async fn get_all_todos(
-    mut db: Connection<Db>,
+    todos: &State<Arc<dyn TodoRepository>>,
+    config: &State<AppConfig>,
+    page: Page,
     auth: JwtAuth,
-) -> Result<Json<Vec<TodoResponse>>, status::Custom<Json<serde_json::Value>>> {
-    match todo_handler::get_all_todos(db).await {
-        Ok(todos) => Ok(todos),
-        Err(e) => Err(status::Custom(
-            Status::InternalServerError,
-            Json(serde_json::json!({
-                "error": "Failed to fetch todos",
-                "message": format!("{:?}", e)
-            })),
-        )),
-    }
+) -> ApiResult<Json<Vec<TodoResponse>>> {
+    auth.require_scope("todos:read")?;
+    let user_id = auth.numeric_user_id()?;
+
+    let list = todo_handler::get_all_todos(todos.inner().as_ref(), user_id).await?;
+    Ok(Json(config.pagination.apply(page, list)?))
 }
 
 #[utoipa::path(get, path = "/api/todos/{id}", tag = "todos", params(
     ("id" = i64, Path, description = "Todo id")
 ), responses(
     (status = 200, description = "Get todo", body = TodoResponse),
-    (status = 404, description = "Not found")
+    (status = 401, description = "Missing, invalid or expired token", body = ProblemDetails, content_type = "application/problem+json"),
+    (status = 404, description = "Not found", body = ProblemDetails, content_type = "application/problem+json")
 ))]
 #[get("/todos/<id>")]
 pub async fn get_todo(
-    mut db: Connection<Db>,
+    todos: &State<Arc<dyn TodoRepository>>,
     id: i64,
     auth: JwtAuth,
-) -> Result<Json<TodoResponse>, status::Custom<Json<serde_json::Value>>> {
-    match todo_handler::get_todo(db, id).await {
-        Ok(todo) => Ok(todo),
-        Err(not_found) => Err(status::Custom(
-            Status::NotFound,
-            Json(serde_json::json!({
-                "error": "Todo not found",
-                "message": not_found.0
-            })),
-        )),
-    }
+) -> ApiResult<Json<TodoResponse>> {
+    auth.require_scope("todos:read")?;
+    let user_id = auth.numeric_user_id()?;
+
+    todo_handler::get_todo(todos.inner().as_ref(), user_id, id).await.map(Json)
 }
 
 #[utoipa::path(get, path = "/api/todos/status/{status}", tag = "todos", params(
-    ("status" = String, Path, description = "pending | in_progress | completed")
+    ("status" = inline(TodoStatus), Path, description = "Todo status"),
+    Page
 ), responses(
-    (status = 200, description = "List by status", body = [TodoResponse])
+    (status = 200, description = "List by status", body = [TodoResponse]),
+    (status = 422, description = "Unknown status or limit out of range", body = ProblemDetails, content_type = "application/problem+json"),
+    (status = 401, description = "Missing, invalid or expired token", body = ProblemDetails, content_type = "application/problem+json")
 ))]
-#[get("/todos/status/<status>")]
+#[get("/todos/status/<status>?<page..>")]
 pub async fn get_todos_by_status(
-    mut db: Connection<Db>,
-    status: String,
+    todos: &State<Arc<dyn TodoRepository>>,
+    config: &State<AppConfig>,
+    status: Result<TodoStatus, FieldError>,
+    page: Page,
     auth: JwtAuth,
-) -> Result<Json<Vec<TodoResponse>>, status::Custom<Json<serde_json::Value>>> {
-    match todo_handler::get_todos_by_status(db, status).await {
-        Ok(todos) => Ok(todos),
-        Err(e) => Err(status::Custom(
-            Status::InternalServerError,
-            Json(serde_json::json!({
-                "error": "Failed to fetch todos",
-                "message": format!("{:?}", e)
-            })),
-        )),
-    }
+) -> ApiResult<Json<Vec<TodoResponse>>> {
+    auth.require_scope("todos:read")?;
+    let user_id = auth.numeric_user_id()?;
+
+    let list = todo_handler::get_todos_by_status(todos.inner().as_ref(), user_id, status?).await?;
+    Ok(Json(config.pagination.apply(page, list)?))
 }
 
 #[utoipa::path(post, path = "/api/todos", tag = "todos", request_body = CreateTodoRequest, responses(
-    (status = 201, description = "Created", body = TodoResponse)
+    (status = 201, description = "Created", body = TodoResponse),
+    (status = 401, description = "Missing, invalid or expired token", body = ProblemDetails, content_type = "application/problem+json"),
+    (status = 403, description = "Over the todo, description or storage quota", body = ProblemDetails, content_type = "application/problem+json"),
+    (status = 422, description = "Invalid fields", body = ProblemDetails, content_type = "application/problem+json")
 ))]
 #[post("/todos", data = "<request>")]
 pub async fn create_todo(
-    mut db: Connection<Db>,
-    request: Json<CreateTodoRequest>,
+    todos: &State<Arc<dyn TodoRepository>>,
+    users: &State<Arc<dyn UserRepository>>,
+    config: &State<AppConfig>,
+    request: Validated<CreateTodoRequest>,
     auth: JwtAuth,
-) -> Result<status::Created<Json<TodoResponse>>, status::Custom<Json<serde_json::Value>>> {
-    match todo_handler::create_todo(db, request).await {
-        Ok(todo) => Ok(status::Created::new("/todos").body(todo)),
-        Err(e) => Err(status::Custom(
-            Status::BadRequest,
-            Json(serde_json::json!({
-                "error": "Failed to create todo",
-                "message": format!("{:?}", e)
-            })),
-        )),
-    }
+) -> ApiResult<status::Created<Json<TodoResponse>>> {
+    auth.require_scope("todos:write")?;
+    let user_id = auth.numeric_user_id()?;
+
+    let limits = quota::limits_for(users.inner().as_ref(), &config.quotas, user_id).await?;
+    let todo = todo_handler::create_todo(todos.inner().as_ref(), &limits, user_id, request.0).await?;
+    Ok(status::Created::new("/todos").body(Json(todo)))
 }
 
 #[utoipa::path(put, path = "/api/todos/{id}", tag = "todos", request_body = UpdateTodoRequest, params(
     ("id" = i64, Path,)
 ), responses(
     (status = 200, description = "Updated", body = TodoResponse),
-    (status = 404, description = "Not found")
+    (status = 401, description = "Missing, invalid or expired token", body = ProblemDetails, content_type = "application/problem+json"),
+    (status = 403, description = "Over the description or storage quota", body = ProblemDetails, content_type = "application/problem+json"),
+    (status = 404, description = "Not found", body = ProblemDetails, content_type = "application/problem+json"),
+    (status = 422, description = "Invalid fields", body = ProblemDetails, content_type = "application/problem+json")
 ))]
 #[put("/todos/<id>", data = "<request>")]
 pub async fn update_todo(
-    mut db: Connection<Db>,
+    todos: &State<Arc<dyn TodoRepository>>,
+    users: &State<Arc<dyn UserRepository>>,
+    config: &State<AppConfig>,
     id: i64,
-    request: Json<UpdateTodoRequest>,
+    request: Validated<UpdateTodoRequest>,
     auth: JwtAuth,
-) -> Result<Json<TodoResponse>, status::Custom<Json<serde_json::Value>>> {
-    match todo_handler::update_todo(db, id, request).await {
-        Ok(todo) => Ok(todo),
-        Err(not_found) => Err(status::Custom(
-            Status::NotFound,
-            Json(serde_json::json!({
-                "error": "Todo not found",
-                "message": not_found.0
-            })),
-        )),
-    }
+) -> ApiResult<Json<TodoResponse>> {
+    auth.require_scope("todos:write")?;
+    let user_id = auth.numeric_user_id()?;
+
+    let limits = quota::limits_for(users.inner().as_ref(), &config.quotas, user_id).await?;
+    todo_handler::update_todo(todos.inner().as_ref(), &limits, user_id, id, request.0)
+        .await
+        .map(Json)
 }
 
 #[utoipa::path(delete, path = "/api/todos/{id}", tag = "todos", params(
     ("id" = i64, Path,)
 ), responses(
     (status = 204, description = "Deleted"),
-    (status = 404, description = "Not found")
+    (status = 401, description = "Missing, invalid or expired token", body = ProblemDetails, content_type = "application/problem+json"),
+    (status = 404, description = "Not found", body = ProblemDetails, content_type = "application/problem+json")
 ))]
 #[delete("/todos/<id>")]
 pub async fn delete_todo(
-    mut db: Connection<Db>,
+    todos: &State<Arc<dyn TodoRepository>>,
     id: i64,
     auth: JwtAuth,
-) -> Result<status::NoContent, status::Custom<Json<serde_json::Value>>> {
-    match todo_handler::delete_todo(db, id).await {
-        Ok(_) => Ok(status::NoContent),
-        Err(not_found) => Err(status::Custom(
-            Status::NotFound,
-            Json(serde_json::json!({
-                "error": "Todo not found",
-                "message": not_found.0
-            })),
-        )),
-    }
+) -> ApiResult<status::NoContent> {
+    auth.require_scope("todos:write")?;
+    let user_id = auth.numeric_user_id()?;
+
+    todo_handler::delete_todo(todos.inner().as_ref(), user_id, id).await?;
+    Ok(status::NoContent)
 }
 
 #[utoipa::path(get, path = "/api/todos/priority/{priority}", tag = "todos", params(
-    ("priority" = String, Path, description = "low | medium | high")
+    ("priority" = inline(Priority), Path, description = "Todo priority"),
+    Page
 ), responses(
-    (status = 200, description = "List by priority", body = [TodoResponse])
+    (status = 200, description = "List by priority", body = [TodoResponse]),
+    (status = 422, description = "Unknown priority or limit out of range", body = ProblemDetails, content_type = "application/problem+json"),
+    (status = 401, description = "Missing, invalid or expired token", body = ProblemDetails, content_type = "application/problem+json")
 ))]
-#[get("/todos/priority/<priority>")]
+#[get("/todos/priority/<priority>?<page..>")]
 pub async fn get_todos_by_priority(
-    mut db: Connection<Db>,
-    priority: String,
+    todos: &State<Arc<dyn TodoRepository>>,
+    config: &State<AppConfig>,
+    priority: Result<Priority, FieldError>,
+    page: Page,
     auth: JwtAuth,
-) -> Result<Json<Vec<TodoResponse>>, status::Custom<Json<serde_json::Value>>> {
-    // Validate priority
-    if !["low", "medium", "high"].contains(&priority.to_lowercase().as_str()) {
-        return Err(status::Custom(
-            Status::BadRequest,
-            Json(serde_json::json!({
-                "error": "Invalid priority",
-                "message": "Priority must be 'low', 'medium', or 'high'"
-            })),
-        ));
-    }
-
-    match todo_handler::get_todos_by_priority(db, priority).await {
-        Ok(todos) => Ok(todos),
-        Err(e) => Err(status::Custom(
-            Status::InternalServerError,
-            Json(serde_json::json!({
-                "error": "Failed to fetch todos",
-                "message": format!("{:?}", e)
-            })),
-        )),
-    }
+) -> ApiResult<Json<Vec<TodoResponse>>> {
+    auth.require_scope("todos:read")?;
+    let user_id = auth.numeric_user_id()?;
+
+    let list = todo_handler::get_todos_by_priority(todos.inner().as_ref(), user_id, priority?).await?;
+    Ok(Json(config.pagination.apply(page, list)?))
 }
@@ -0,0 +1,149 @@
+use rocket::delete;
+use rocket::get;
+use rocket::http::CookieJar;
+use rocket::patch;
+use rocket::post;
+use rocket::response::status;
+use rocket::serde::json::Json;
+use rocket::State;
+use std::sync::Arc;
+use rocket_db_pools::Connection;
+
+use crate::auth::jwt::JwtAuth;
+use crate::config::AppConfig;
+use crate::database::Db;
+use crate::error::ApiResult;
+use crate::events::EventLog;
+use crate::handlers::{todo_handler, user_handler};
+use crate::mailer::Mailer;
+use crate::models::{
+    ChangePasswordRequest, DeleteAccountRequest, UpdateProfileRequest, UsageResponse,
+    VerifyEmailRequest,
+};
+use crate::quota;
+use crate::repository::{TodoRepository, UserRepository};
+use crate::validation::Validated;
+
+#[utoipa::path(
+    patch,
+    path = "/api/users/me",
+    tag = "users",
+    request_body = UpdateProfileRequest,
+    responses(
+        (status = 200, description = "Profile updated; email changes stay pending until verified"),
+        (status = 422, description = "Invalid username or email", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 409, description = "Username or email already in use")
+    ),
+    security(
+        ("jwt_auth" = [])
+    )
+)]
+#[patch("/users/me", data = "<request>")]
+pub async fn update_me(
+    db: Connection<Db>,
+    mailer: &State<Mailer>,
+    config: &State<AppConfig>,
+    auth: JwtAuth,
+    request: Validated<UpdateProfileRequest>,
+) -> ApiResult<Json<serde_json::Value>> {
+    user_handler::update_profile(db, mailer, &config.jwt, auth, request.0).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/users/me/email/verify",
+    tag = "users",
+    request_body = VerifyEmailRequest,
+    responses(
+        (status = 200, description = "Pending email confirmed"),
+        (status = 400, description = "Invalid or expired token"),
+        (status = 422, description = "Malformed token", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 409, description = "Email already in use")
+    ),
+    security(
+        ("jwt_auth" = [])
+    )
+)]
+#[post("/users/me/email/verify", data = "<request>")]
+pub async fn verify_email(
+    db: Connection<Db>,
+    config: &State<AppConfig>,
+    auth: JwtAuth,
+    request: Validated<VerifyEmailRequest>,
+) -> ApiResult<Json<serde_json::Value>> {
+    user_handler::verify_email(db, &config.jwt, auth, request.0).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/users/me/password",
+    tag = "users",
+    request_body = ChangePasswordRequest,
+    responses(
+        (status = 200, description = "Password changed; other sessions are signed out"),
+        (status = 401, description = "Current password is incorrect"),
+        (status = 422, description = "New password too short or too long", body = ProblemDetails, content_type = "application/problem+json")
+    ),
+    security(
+        ("jwt_auth" = [])
+    )
+)]
+#[post("/users/me/password", data = "<request>")]
+pub async fn change_password(
+    db: Connection<Db>,
+    auth: JwtAuth,
+    request: Validated<ChangePasswordRequest>,
+) -> ApiResult<Json<serde_json::Value>> {
+    user_handler::change_password(db, auth, request.0).await
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/users/me",
+    tag = "users",
+    request_body = DeleteAccountRequest,
+    responses(
+        (status = 204, description = "Account deleted"),
+        (status = 401, description = "Re-authentication failed")
+    ),
+    security(
+        ("jwt_auth" = [])
+    )
+)]
+#[delete("/users/me", data = "<request>")]
+pub async fn delete_me(
+    db: Connection<Db>,
+    events: &State<Arc<EventLog>>,
+    auth: JwtAuth,
+    request: Validated<DeleteAccountRequest>,
+    cookies: &CookieJar<'_>,
+) -> ApiResult<status::NoContent> {
+    user_handler::delete_account(db, events, auth, request.0, cookies).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/users/me/usage",
+    tag = "users",
+    responses(
+        (status = 200, description = "Consumption against the quotas in effect for this user", body = UsageResponse),
+        (status = 401, description = "Missing, invalid or expired token", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "API key is missing the todos:read scope", body = ProblemDetails, content_type = "application/problem+json")
+    ),
+    security(
+        ("jwt_auth" = [])
+    )
+)]
+#[get("/users/me/usage")]
+pub async fn usage(
+    todos: &State<Arc<dyn TodoRepository>>,
+    users: &State<Arc<dyn UserRepository>>,
+    config: &State<AppConfig>,
+    auth: JwtAuth,
+) -> ApiResult<Json<UsageResponse>> {
+    auth.require_scope("todos:read")?;
+    let user_id = auth.numeric_user_id()?;
+
+    let limits = quota::limits_for(users.inner().as_ref(), &config.quotas, user_id).await?;
+    todo_handler::get_usage(todos.inner().as_ref(), &limits, user_id).await.map(Json)
+}
@@ -0,0 +1,119 @@
+use rocket::response::status;
+use rocket::serde::json::Json;
+use rocket::{delete, get, post, State};
+
+use crate::auth::jwt::{AdminAuth, JwtAuth};
+use crate::database::Db;
+use crate::error::ApiResult;
+use crate::handlers::webhook_handler;
+use crate::models::{CreateWebhookRequest, CreatedWebhookResponse, DeliveryResponse, WebhookResponse};
+use crate::validation::Validated;
+use crate::webhooks::WebhookConfig;
+
+#[utoipa::path(
+    post,
+    path = "/api/webhooks",
+    tag = "webhooks",
+    request_body = CreateWebhookRequest,
+    responses(
+        (status = 201, description = "Webhook registered; the signing secret is only shown once", body = CreatedWebhookResponse),
+        (status = 403, description = "API keys cannot manage webhooks, or all_users without being an administrator"),
+        (status = 422, description = "Invalid URL or event type, or a URL that is not a public endpoint")
+    ),
+    security(
+        ("jwt_auth" = [])
+    )
+)]
+#[post("/webhooks", data = "<request>")]
+pub async fn create_webhook(
+    db: &Db,
+    config: &State<WebhookConfig>,
+    auth: JwtAuth,
+    admin: Option<AdminAuth>,
+    request: Validated<CreateWebhookRequest>,
+) -> ApiResult<status::Created<Json<CreatedWebhookResponse>>> {
+    webhook_handler::create_webhook(db, config, auth, admin, request).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/webhooks",
+    tag = "webhooks",
+    responses(
+        (status = 200, description = "Your webhooks", body = [WebhookResponse]),
+        (status = 403, description = "API keys cannot manage webhooks")
+    ),
+    security(
+        ("jwt_auth" = [])
+    )
+)]
+#[get("/webhooks")]
+pub async fn list_webhooks(db: &Db, auth: JwtAuth) -> ApiResult<Json<Vec<WebhookResponse>>> {
+    webhook_handler::list_webhooks(db, auth).await
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/webhooks/{id}",
+    tag = "webhooks",
+    params(
+        ("id" = i64, Path, description = "Webhook id")
+    ),
+    responses(
+        (status = 204, description = "Deleted, with its delivery log and pending deliveries"),
+        (status = 404, description = "Not found")
+    ),
+    security(
+        ("jwt_auth" = [])
+    )
+)]
+#[delete("/webhooks/<id>")]
+pub async fn delete_webhook(db: &Db, auth: JwtAuth, id: i64) -> ApiResult<status::NoContent> {
+    webhook_handler::delete_webhook(db, auth, id).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/webhooks/{id}/deliveries",
+    tag = "webhooks",
+    params(
+        ("id" = i64, Path, description = "Webhook id")
+    ),
+    responses(
+        (status = 200, description = "The 100 newest deliveries, newest first", body = [DeliveryResponse]),
+        (status = 404, description = "Webhook not found")
+    ),
+    security(
+        ("jwt_auth" = [])
+    )
+)]
+#[get("/webhooks/<id>/deliveries")]
+pub async fn list_deliveries(db: &Db, auth: JwtAuth, id: i64) -> ApiResult<Json<Vec<DeliveryResponse>>> {
+    webhook_handler::list_deliveries(db, auth, id).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/webhooks/{id}/deliveries/{delivery_id}/redeliver",
+    tag = "webhooks",
+    params(
+        ("id" = i64, Path, description = "Webhook id"),
+        ("delivery_id" = i64, Path, description = "The delivery to send again")
+    ),
+    responses(
+        (status = 202, description = "A new delivery of the same payload is queued", body = DeliveryResponse),
+        (status = 404, description = "Webhook or delivery not found")
+    ),
+    security(
+        ("jwt_auth" = [])
+    )
+)]
+#[post("/webhooks/<id>/deliveries/<delivery_id>/redeliver")]
+pub async fn redeliver(
+    db: &Db,
+    auth: JwtAuth,
+    id: i64,
+    delivery_id: i64,
+) -> ApiResult<status::Accepted<Json<DeliveryResponse>>> {
+    webhook_handler::redeliver(db, auth, id, delivery_id).await
+}
@@ -0,0 +1,118 @@
+//! Hardening headers on every response, configured by
+//! `[app.security_headers]`. A header a handler already set is left alone.
+//! Rocket's default Shield would set `X-Frame-Options` and friends first,
+//! so [`shield`] replaces it with one that only keeps `Permissions-Policy`.
+
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::Header;
+use rocket::serde::Deserialize;
+use rocket::shield::{Permission, Shield};
+use rocket::{Request, Response};
+
+use crate::config::AppConfig;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SecurityHeadersConfig {
+    /// Send `Strict-Transport-Security`; only enable behind HTTPS
+    pub hsts: bool,
+    pub hsts_max_age_secs: u64,
+    pub hsts_include_subdomains: bool,
+    /// For API responses, which are never rendered as pages
+    pub content_security_policy: String,
+    /// For the Swagger UI under `/docs`, which needs its own scripts and styles
+    pub docs_content_security_policy: String,
+    pub referrer_policy: String,
+    /// `DENY` or `SAMEORIGIN`
+    pub frame_options: String,
+}
+
+impl Default for SecurityHeadersConfig {
+    fn default() -> Self {
+        SecurityHeadersConfig {
+            hsts: false,
+            hsts_max_age_secs: 31_536_000,
+            hsts_include_subdomains: true,
+            content_security_policy: "default-src 'none'; frame-ancestors 'none'; base-uri 'none'".to_string(),
+            docs_content_security_policy: "default-src 'self'; script-src 'self' 'unsafe-inline'; \
+                                           style-src 'self' 'unsafe-inline'; img-src 'self' data:; \
+                                           frame-ancestors 'none'"
+                .to_string(),
+            referrer_policy: "no-referrer".to_string(),
+            frame_options: "DENY".to_string(),
+        }
+    }
+}
+
+impl SecurityHeadersConfig {
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if !matches!(self.frame_options.to_ascii_uppercase().as_str(), "DENY" | "SAMEORIGIN") {
+            problems.push(format!(
+                "app.security_headers.frame_options must be DENY or SAMEORIGIN, got '{}'",
+                self.frame_options
+            ));
+        }
+        for (name, value) in [
+            ("content_security_policy", &self.content_security_policy),
+            ("docs_content_security_policy", &self.docs_content_security_policy),
+            ("referrer_policy", &self.referrer_policy),
+        ] {
+            if value.contains(['\r', '\n']) {
+                problems.push(format!("app.security_headers.{} must be a single line", name));
+            }
+        }
+        problems
+    }
+
+    fn headers(&self, path: &str) -> Vec<Header<'static>> {
+        let docs = path == "/docs" || path.starts_with("/docs/");
+        let csp = if docs { &self.docs_content_security_policy } else { &self.content_security_policy };
+
+        let mut headers = vec![
+            Header::new("X-Content-Type-Options", "nosniff"),
+            Header::new("X-Frame-Options", self.frame_options.to_ascii_uppercase()),
+            Header::new("Referrer-Policy", self.referrer_policy.clone()),
+            Header::new("Cross-Origin-Opener-Policy", "same-origin"),
+        ];
+        if !csp.is_empty() {
+            headers.push(Header::new("Content-Security-Policy", csp.clone()));
+        }
+        if self.hsts {
+            let subdomains = if self.hsts_include_subdomains { "; includeSubDomains" } else { "" };
+            headers.push(Header::new(
+                "Strict-Transport-Security",
+                format!("max-age={}{}", self.hsts_max_age_secs, subdomains),
+            ));
+        }
+        headers
+    }
+}
+
+/// Takes the place of Rocket's default Shield
+pub fn shield() -> Shield {
+    Shield::new().enable(Permission::default())
+}
+
+pub struct SecurityHeaders;
+
+#[rocket::async_trait]
+impl Fairing for SecurityHeaders {
+    fn info(&self) -> Info {
+        Info {
+            name: "Security Headers",
+            kind: Kind::Response,
+        }
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let Some(config) = request.rocket().state::<AppConfig>() else {
+            return;
+        };
+        for header in config.security_headers.headers(request.uri().path().as_str()) {
+            if !response.headers().contains(header.name()) {
+                response.set_header(header);
+            }
+        }
+    }
+}
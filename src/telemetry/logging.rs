@@ -0,0 +1,292 @@
+//! Log output as configured by the `[logging]` section: the format, where
+//! lines go, how log files rotate and the level of each target.
+//!
+//! Every line is scrubbed before it is written: credentials, session
+//! cookies and email addresses never reach a sink, whatever the format.
+//! The filter can be swapped at runtime through `/api/admin/log-level`.
+
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::PathBuf;
+
+use once_cell::sync::{Lazy, OnceCell};
+use regex::Regex;
+use rocket::serde::{Deserialize, Serialize};
+use tracing::{Event, Subscriber};
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::fmt::format::Writer;
+use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields, MakeWriter};
+use tracing_subscriber::layer::Layer;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::{reload, EnvFilter, Registry};
+use utoipa::ToSchema;
+
+use crate::validation::{trim, FieldError, Validate, Violations};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// One JSON object per line, for log shippers
+    Json,
+    /// Multi-line and indented, for reading in a terminal
+    Pretty,
+    /// One short line per event
+    Compact,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Sink {
+    Stdout,
+    File,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogRotation {
+    Minutely,
+    Hourly,
+    Daily,
+    Weekly,
+    Never,
+}
+
+impl From<LogRotation> for Rotation {
+    fn from(rotation: LogRotation) -> Self {
+        match rotation {
+            LogRotation::Minutely => Rotation::MINUTELY,
+            LogRotation::Hourly => Rotation::HOURLY,
+            LogRotation::Daily => Rotation::DAILY,
+            LogRotation::Weekly => Rotation::WEEKLY,
+            LogRotation::Never => Rotation::NEVER,
+        }
+    }
+}
+
+/// The `[logging]` section of Rocket.toml
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct LoggingConfig {
+    pub format: LogFormat,
+    pub sinks: Vec<Sink>,
+    /// Directory of the `file` sink
+    pub dir: PathBuf,
+    /// Log files are named `<file_name>.<date>`
+    pub file_name: String,
+    pub rotation: LogRotation,
+    /// Rotated files kept on disk; 0 keeps them all
+    pub max_files: usize,
+    /// Level of targets not listed in `targets`
+    pub level: String,
+    /// Level per target, e.g. `sqlx = "warn"`
+    pub targets: BTreeMap<String, String>,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        LoggingConfig {
+            format: LogFormat::Json,
+            sinks: vec![Sink::Stdout, Sink::File],
+            dir: PathBuf::from("logs"),
+            file_name: "app.log".to_string(),
+            rotation: LogRotation::Daily,
+            max_files: 14,
+            level: "info".to_string(),
+            targets: BTreeMap::from([
+                ("rocket".to_string(), "info".to_string()),
+                ("sqlx".to_string(), "warn".to_string()),
+            ]),
+        }
+    }
+}
+
+impl LoggingConfig {
+    /// The levels as `EnvFilter` directives, e.g. `info,sqlx=warn`
+    pub fn directives(&self) -> String {
+        std::iter::once(self.level.clone())
+            .chain(self.targets.iter().map(|(target, level)| format!("{}={}", target, level)))
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+
+    pub fn file_appender(&self) -> Result<RollingFileAppender, tracing_appender::rolling::InitError> {
+        // Old files are pruned as the appender opens, before it would create the directory
+        let _ = std::fs::create_dir_all(&self.dir);
+        let mut builder = RollingFileAppender::builder()
+            .rotation(self.rotation.into())
+            .filename_prefix(&self.file_name);
+        if self.max_files > 0 {
+            builder = builder.max_log_files(self.max_files);
+        }
+        builder.build(&self.dir)
+    }
+}
+
+/// A formatting layer for `format` writing to `writer`, with redaction
+pub fn fmt_layer<S, W>(format: LogFormat, writer: W) -> Box<dyn Layer<S> + Send + Sync>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    // Colour codes would split field names from their values
+    let layer = tracing_subscriber::fmt::layer()
+        .with_target(true)
+        .with_thread_ids(false)
+        .with_ansi(false)
+        .with_writer(writer);
+    match format {
+        LogFormat::Json => layer.json().map_event_format(Redacted).boxed(),
+        LogFormat::Pretty => layer.pretty().map_event_format(Redacted).boxed(),
+        LogFormat::Compact => layer.compact().map_event_format(Redacted).boxed(),
+    }
+}
+
+/// Formats events with the wrapped formatter, then scrubs the line
+pub struct Redacted<F>(pub F);
+
+impl<S, N, F> FormatEvent<S, N> for Redacted<F>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'w> FormatFields<'w> + 'static,
+    F: FormatEvent<S, N>,
+{
+    fn format_event(&self, ctx: &FmtContext<'_, S, N>, mut writer: Writer<'_>, event: &Event<'_>) -> fmt::Result {
+        let mut line = String::new();
+        self.0.format_event(ctx, Writer::new(&mut line), event)?;
+        writer.write_str(&redact(&line))
+    }
+}
+
+/// Fields and `key=value` pairs whose value is always a secret
+const SECRET_KEYS: &str = "password|current_password|new_password|secret|client_secret|token|access_token|\
+                           refresh_token|id_token|api_key|authorization|cookie|set-cookie|auth_token|csrf_token";
+
+static JSON_SECRET: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(&format!(r#"(?i)"({})"\s*:\s*("(?:[^"\\]|\\.)*"|[^,}}\s]+)"#, SECRET_KEYS)).unwrap()
+});
+static TEXT_SECRET: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(&format!(r#"(?i)\b({})(=|: )("(?:[^"\\]|\\.)*"|[^\s",;}}]+)"#, SECRET_KEYS)).unwrap()
+});
+static CREDENTIALS: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?i)\b(bearer|basic)\s+[A-Za-z0-9._~+/=-]+").unwrap());
+static EMAIL: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"[A-Za-z0-9._%+-]+@((?:[A-Za-z0-9-]+\.)+[A-Za-z]{2,})").unwrap());
+
+/// Replaces secrets in a formatted line. Email addresses keep their domain.
+pub fn redact(line: &str) -> Cow<'_, str> {
+    let mut line = Cow::Borrowed(line);
+    for (pattern, replacement) in [
+        // Before the keys, which would only take the scheme of `Authorization: Bearer ...`
+        (&*CREDENTIALS, "$1 [REDACTED]"),
+        (&*JSON_SECRET, r#""$1":"[REDACTED]""#),
+        (&*TEXT_SECRET, "$1$2[REDACTED]"),
+        (&*EMAIL, "***@$1"),
+    ] {
+        if let Cow::Owned(replaced) = pattern.replace_all(&line, replacement) {
+            line = Cow::Owned(replaced);
+        }
+    }
+    line
+}
+
+/// The global filter, swappable while the server runs
+pub struct LogLevels {
+    handle: reload::Handle<EnvFilter, Registry>,
+    configured: String,
+}
+
+static LEVELS: OnceCell<LogLevels> = OnceCell::new();
+
+#[derive(Debug)]
+pub enum LogLevelError {
+    Invalid(String),
+    /// The subscriber the filter belonged to is gone
+    Detached,
+}
+
+impl fmt::Display for LogLevelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LogLevelError::Invalid(e) => write!(f, "invalid filter: {}", e),
+            LogLevelError::Detached => write!(f, "the log filter is no longer installed"),
+        }
+    }
+}
+
+impl LogLevels {
+    /// A reloadable filter for `directives` and the handle that controls it.
+    /// `RUST_LOG`, when set, takes precedence.
+    pub fn filter(directives: &str) -> (reload::Layer<EnvFilter, Registry>, LogLevels) {
+        let configured = std::env::var("RUST_LOG")
+            .ok()
+            .filter(|env| EnvFilter::try_new(env).is_ok())
+            .unwrap_or_else(|| directives.to_string());
+        let filter = EnvFilter::try_new(&configured).unwrap_or_else(|e| {
+            eprintln!("invalid log levels '{}': {}", configured, e);
+            EnvFilter::new("info")
+        });
+        let (layer, handle) = reload::Layer::new(filter);
+        (layer, LogLevels { handle, configured })
+    }
+
+    /// Makes these the levels `/api/admin/log-level` controls. Only the
+    /// first call has an effect.
+    pub fn install(self) -> bool {
+        LEVELS.set(self).is_ok()
+    }
+
+    pub fn installed() -> Option<&'static LogLevels> {
+        LEVELS.get()
+    }
+
+    pub fn current(&self) -> Result<String, LogLevelError> {
+        self.handle
+            .with_current(|filter| filter.to_string())
+            .map_err(|_| LogLevelError::Detached)
+    }
+
+    /// The levels the process started with
+    pub fn configured(&self) -> &str {
+        &self.configured
+    }
+
+    pub fn set(&self, directives: &str) -> Result<(), LogLevelError> {
+        let filter = EnvFilter::try_new(directives).map_err(|e| LogLevelError::Invalid(e.to_string()))?;
+        self.handle.reload(filter).map_err(|_| LogLevelError::Detached)
+    }
+}
+
+/// The active and startup log levels
+#[derive(Debug, Serialize, ToSchema)]
+pub struct LogLevelResponse {
+    #[schema(example = "info,my_rocket_app=debug,sqlx=warn")]
+    pub filter: String,
+    /// What `filter` goes back to on restart
+    #[schema(example = "info,rocket=info,sqlx=warn")]
+    pub configured: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct SetLogLevelRequest {
+    /// `EnvFilter` directives: a default level, then `target=level` pairs
+    #[schema(example = "info,my_rocket_app=debug")]
+    pub filter: String,
+}
+
+impl Validate for SetLogLevelRequest {
+    fn validate(&mut self) -> Result<(), Vec<FieldError>> {
+        let mut violations = Violations::default();
+
+        trim(&mut self.filter);
+        violations.length("filter", &self.filter, 1, 1024);
+        if !self.filter.is_empty()
+            && let Err(e) = EnvFilter::try_new(&self.filter)
+        {
+            violations.push("filter", "invalid", e.to_string());
+        }
+
+        violations.into_result()
+    }
+}
@@ -0,0 +1,206 @@
+use std::time::Instant;
+
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::figment::Figment;
+use rocket::http::Header;
+use rocket::route::{self, Handler, Route};
+use rocket::{Data, Orbit, Build, Request, Response, Rocket};
+use tracing::{info, info_span, Instrument, Span};
+use tracing_subscriber::layer::{Layer, Layered, SubscriberExt};
+use tracing_subscriber::{reload, EnvFilter, Registry};
+use once_cell::sync::OnceCell;
+
+pub mod logging;
+pub mod trace;
+
+use logging::{LoggingConfig, LogLevels, Sink};
+use trace::{OtlpExporter, TraceContext, TraceLayer, TracingConfig};
+
+static APPENDER_GUARD: OnceCell<tracing_appender::non_blocking::WorkerGuard> = OnceCell::new();
+
+type Filtered = Layered<reload::Layer<EnvFilter, Registry>, Registry>;
+
+/// Installs the global subscriber: log lines as configured by `[logging]`,
+/// and span export over OTLP when `[telemetry] otlp_endpoint` is set
+pub fn init_tracing(figment: &Figment) {
+    let logging: LoggingConfig = figment.extract_inner("logging").unwrap_or_else(|e| {
+        eprintln!("invalid [logging] configuration, using the defaults: {}", e);
+        LoggingConfig::default()
+    });
+    let (filter, levels) = LogLevels::filter(&logging.directives());
+    levels.install();
+
+    let mut layers: Vec<Box<dyn Layer<Filtered> + Send + Sync>> = Vec::new();
+    if logging.sinks.contains(&Sink::Stdout) {
+        layers.push(logging::fmt_layer(logging.format, std::io::stdout));
+    }
+    if logging.sinks.contains(&Sink::File) {
+        match logging.file_appender() {
+            Ok(appender) => {
+                let (non_blocking, guard) = tracing_appender::non_blocking(appender);
+                let _ = APPENDER_GUARD.set(guard);
+                layers.push(logging::fmt_layer(logging.format, non_blocking));
+            }
+            Err(e) => eprintln!("log directory {} unusable: {}", logging.dir.display(), e),
+        }
+    }
+
+    let config: TracingConfig = figment.extract_inner("telemetry").unwrap_or_default();
+    if let Some(endpoint) = &config.otlp_endpoint {
+        match OtlpExporter::spawn(endpoint, &config) {
+            Ok(exporter) => layers.push(TraceLayer::new(exporter).boxed()),
+            Err(e) => eprintln!("OTLP exporter not started: {}", e),
+        }
+    }
+
+    let subscriber = Registry::default().with(filter).with(layers);
+    tracing::subscriber::set_global_default(subscriber).ok();
+}
+
+/// The request's span and the ids it goes by, set by the fairing
+struct RequestTrace {
+    span: Span,
+    context: TraceContext,
+    request_id: String,
+}
+
+impl RequestTrace {
+    /// For requests the fairing did not see
+    fn detached() -> Self {
+        RequestTrace {
+            span: Span::none(),
+            context: TraceContext::root(),
+            request_id: uuid::Uuid::new_v4().to_string(),
+        }
+    }
+}
+
+/// Request ids are echoed into logs and headers, so only plain ones are
+/// taken from the caller
+fn valid_request_id(id: &str) -> bool {
+    (1..=128).contains(&id.len())
+        && id
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.' | b':'))
+}
+
+/// Opens a span per request, continuing the caller's trace when it sends a
+/// `traceparent`, and keeps the caller's `x-request-id` when it sends one
+pub struct RequestTracingFairing;
+
+#[rocket::async_trait]
+impl Fairing for RequestTracingFairing {
+    fn info(&self) -> Info {
+        Info {
+            name: "request_tracing",
+            kind: Kind::Request | Kind::Response | Kind::Ignite | Kind::Liftoff,
+        }
+    }
+
+    async fn on_ignite(&self, rocket: Rocket<Build>) -> rocket::fairing::Result {
+        info!(event = "ignite", "Rocket is igniting");
+        Ok(rocket)
+    }
+
+    async fn on_liftoff(&self, _rocket: &Rocket<Orbit>) {
+        info!(event = "liftoff", "Rocket has launched");
+    }
+
+    async fn on_request(&self, request: &mut Request<'_>, _data: &mut Data<'_>) {
+        let start = Instant::now();
+        request.local_cache(|| start);
+
+        let caller = request
+            .headers()
+            .get_one("traceparent")
+            .and_then(TraceContext::from_traceparent);
+        let context = caller.map_or_else(TraceContext::root, |caller| caller.child());
+
+        let request_id = match request.headers().get_one("x-request-id") {
+            Some(id) if valid_request_id(id) => id.to_string(),
+            _ => uuid::Uuid::new_v4().to_string(),
+        };
+        // Read back by the error responses
+        request.replace_header(Header::new("x-request-id", request_id.clone()));
+
+        let span = info_span!(
+            "http_request",
+            otel.kind = "server",
+            trace_id = %context.trace_id_hex(),
+            span_id = %context.span_id_hex(),
+            parent_span_id = caller.map(|c| c.span_id_hex()),
+            sampled = context.sampled,
+            request_id = %request_id,
+            http.method = %request.method(),
+            http.target = %request.uri(),
+            http.route = tracing::field::Empty,
+            http.status_code = tracing::field::Empty,
+        );
+        info!(
+            parent: &span,
+            request_id = %request_id,
+            method = %request.method(),
+            uri = %request.uri(),
+            event = "request_start"
+        );
+
+        request.local_cache(|| RequestTrace {
+            span,
+            context,
+            request_id,
+        });
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let elapsed = request.local_cache::<Instant, _>(Instant::now).elapsed();
+        let latency_ms = elapsed.as_millis();
+
+        // By path template, so `/api/todos/1` and `/api/todos/2` share a series
+        // and query parameters never split one
+        let route = request.route().map_or_else(|| "unmatched".to_string(), |r| r.uri.path().to_string());
+        crate::metrics::observe_request(&route, request.method().as_str(), response.status().code, elapsed);
+
+        let trace = request.local_cache(RequestTrace::detached);
+        trace.span.record("http.route", route.as_str());
+        trace.span.record("http.status_code", response.status().code);
+
+        response.set_raw_header("x-request-id", trace.request_id.clone());
+        response.set_raw_header("traceresponse", trace.context.traceparent());
+
+        info!(
+            parent: &trace.span,
+            request_id = %trace.request_id,
+            method = %request.method(),
+            uri = %request.uri(),
+            status = %response.status(),
+            latency_ms = latency_ms as u64,
+            event = "request_end"
+        );
+    }
+}
+
+/// Runs each route's handler in a `handler` span under the request's span,
+/// so spans opened while handling (database queries, ...) join its trace
+pub fn traced(routes: Vec<Route>) -> Vec<Route> {
+    routes
+        .into_iter()
+        .map(|mut route| {
+            route.handler = Box::new(TracedHandler(route.handler));
+            route
+        })
+        .collect()
+}
+
+#[derive(Clone)]
+struct TracedHandler(Box<dyn Handler>);
+
+#[rocket::async_trait]
+impl Handler for TracedHandler {
+    async fn handle<'r>(&self, request: &'r Request<'_>, data: Data<'r>) -> route::Outcome<'r> {
+        let trace = request.local_cache(RequestTrace::detached);
+        let name = request.route().and_then(|r| r.name.as_deref()).unwrap_or("handler");
+        let span = info_span!(parent: &trace.span, "handler", code.function = name);
+
+        self.0.handle(request, data).instrument(span).await
+    }
+}
@@ -0,0 +1,487 @@
+//! Distributed tracing in the W3C Trace Context model. A `tracing` layer
+//! gives every span a trace and span id, continuing the trace of its parent
+//! span, and hands each span to a [`SpanExporter`] when it ends.
+//!
+//! The request span is opened by `RequestTracingFairing` with the ids of the
+//! caller's `traceparent`, when there is one; handler and database spans are
+//! opened inside it and inherit its trace.
+
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use rocket::serde::Deserialize;
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::Subscriber;
+use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::registry::LookupSpan;
+
+/// Field names the fairing uses to hand its ids to the layer
+const TRACE_ID: &str = "trace_id";
+const SPAN_ID: &str = "span_id";
+const PARENT_SPAN_ID: &str = "parent_span_id";
+const SAMPLED: &str = "sampled";
+const KIND: &str = "otel.kind";
+
+/// The part of a span that is propagated to other services
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TraceContext {
+    pub trace_id: [u8; 16],
+    pub span_id: [u8; 8],
+    pub sampled: bool,
+}
+
+fn random_bytes<const N: usize>() -> [u8; N] {
+    let mut bytes = [0; N];
+    bytes.copy_from_slice(&uuid::Uuid::new_v4().as_bytes()[..N]);
+    bytes
+}
+
+fn parse_hex<const N: usize>(hex: &str) -> Option<[u8; N]> {
+    if hex.len() != N * 2 || !hex.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f')) {
+        return None;
+    }
+    let mut bytes = [0; N];
+    hex::decode_to_slice(hex, &mut bytes).ok()?;
+    // All-zero ids are invalid
+    bytes.iter().any(|b| *b != 0).then_some(bytes)
+}
+
+impl TraceContext {
+    /// The start of a new trace
+    pub fn root() -> Self {
+        TraceContext {
+            trace_id: random_bytes(),
+            span_id: random_bytes(),
+            sampled: true,
+        }
+    }
+
+    /// A new span in the same trace
+    pub fn child(&self) -> Self {
+        TraceContext {
+            span_id: random_bytes(),
+            ..*self
+        }
+    }
+
+    /// Reads a version 00 `traceparent` header; anything malformed is
+    /// ignored, as the specification asks
+    pub fn from_traceparent(header: &str) -> Option<Self> {
+        let mut parts = header.trim().split('-');
+        let (version, trace_id, span_id, flags) = (parts.next()?, parts.next()?, parts.next()?, parts.next()?);
+        if version != "00" || parts.next().is_some() {
+            return None;
+        }
+
+        if flags.len() != 2 || !flags.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f')) {
+            return None;
+        }
+        let flags = u8::from_str_radix(flags, 16).ok()?;
+        Some(TraceContext {
+            trace_id: parse_hex(trace_id)?,
+            span_id: parse_hex(span_id)?,
+            sampled: flags & 1 == 1,
+        })
+    }
+
+    pub fn traceparent(&self) -> String {
+        format!(
+            "00-{}-{}-{:02x}",
+            self.trace_id_hex(),
+            self.span_id_hex(),
+            self.sampled as u8
+        )
+    }
+
+    pub fn trace_id_hex(&self) -> String {
+        hex::encode(self.trace_id)
+    }
+
+    pub fn span_id_hex(&self) -> String {
+        hex::encode(self.span_id)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpanKind {
+    Internal,
+    /// Handles a request from another service
+    Server,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AttributeValue {
+    String(String),
+    Int(i64),
+    Bool(bool),
+}
+
+/// A span that has ended
+#[derive(Debug, Clone)]
+pub struct FinishedSpan {
+    pub context: TraceContext,
+    pub parent_span_id: Option<[u8; 8]>,
+    pub name: String,
+    pub kind: SpanKind,
+    pub start: SystemTime,
+    pub end: SystemTime,
+    pub attributes: Vec<(String, AttributeValue)>,
+}
+
+impl FinishedSpan {
+    pub fn attribute(&self, key: &str) -> Option<&AttributeValue> {
+        self.attributes.iter().find(|(k, _)| k == key).map(|(_, v)| v)
+    }
+}
+
+/// Receives spans as they end. Must not block.
+pub trait SpanExporter: Send + Sync + 'static {
+    fn export(&self, span: FinishedSpan);
+}
+
+/// Keeps finished spans in memory, for tests
+#[derive(Clone, Default)]
+pub struct InMemoryExporter {
+    spans: Arc<Mutex<Vec<FinishedSpan>>>,
+}
+
+impl InMemoryExporter {
+    pub fn finished(&self) -> Vec<FinishedSpan> {
+        self.spans.lock().unwrap().clone()
+    }
+}
+
+impl SpanExporter for InMemoryExporter {
+    fn export(&self, span: FinishedSpan) {
+        self.spans.lock().unwrap().push(span);
+    }
+}
+
+/// A span still open, kept in the span's extensions
+struct OpenSpan {
+    context: TraceContext,
+    parent_span_id: Option<[u8; 8]>,
+    kind: SpanKind,
+    start: SystemTime,
+    attributes: Vec<(String, AttributeValue)>,
+}
+
+#[derive(Default)]
+struct FieldVisitor {
+    attributes: Vec<(String, AttributeValue)>,
+}
+
+impl FieldVisitor {
+    fn set(&mut self, field: &Field, value: AttributeValue) {
+        let key = field.name().to_string();
+        match self.attributes.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, existing)) => *existing = value,
+            None => self.attributes.push((key, value)),
+        }
+    }
+
+    fn take_str(&mut self, key: &str) -> Option<String> {
+        let i = self.attributes.iter().position(|(k, _)| k == key)?;
+        match self.attributes.remove(i).1 {
+            AttributeValue::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    fn take_bool(&mut self, key: &str) -> Option<bool> {
+        let i = self.attributes.iter().position(|(k, _)| k == key)?;
+        match self.attributes.remove(i).1 {
+            AttributeValue::Bool(b) => Some(b),
+            _ => None,
+        }
+    }
+}
+
+impl Visit for FieldVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.set(field, AttributeValue::String(value.to_string()));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.set(field, AttributeValue::Int(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.set(field, AttributeValue::Int(value as i64));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.set(field, AttributeValue::Bool(value));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.set(field, AttributeValue::String(format!("{:?}", value)));
+    }
+}
+
+/// Turns `tracing` spans into [`FinishedSpan`]s for an exporter
+pub struct TraceLayer<E> {
+    exporter: E,
+}
+
+impl<E: SpanExporter> TraceLayer<E> {
+    pub fn new(exporter: E) -> Self {
+        TraceLayer { exporter }
+    }
+}
+
+impl<S, E> Layer<S> for TraceLayer<E>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    E: SpanExporter,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut fields = FieldVisitor::default();
+        attrs.record(&mut fields);
+
+        // Ids handed over by the fairing win; otherwise continue the parent
+        // span's trace, or start a new one
+        let given = fields
+            .take_str(TRACE_ID)
+            .and_then(|t| parse_hex(&t))
+            .zip(fields.take_str(SPAN_ID).and_then(|s| parse_hex(&s)));
+        let remote_parent = fields.take_str(PARENT_SPAN_ID).and_then(|p| parse_hex(&p));
+        let sampled = fields.take_bool(SAMPLED);
+        let kind = match fields.take_str(KIND).as_deref() {
+            Some("server") => SpanKind::Server,
+            _ => SpanKind::Internal,
+        };
+        let parent = span
+            .parent()
+            .and_then(|parent| parent.extensions().get::<OpenSpan>().map(|open| open.context));
+
+        let (context, parent_span_id) = match (given, parent) {
+            (Some((trace_id, span_id)), _) => (
+                TraceContext {
+                    trace_id,
+                    span_id,
+                    sampled: sampled.unwrap_or(true),
+                },
+                remote_parent,
+            ),
+            (None, Some(parent)) => (parent.child(), Some(parent.span_id)),
+            (None, None) => (TraceContext::root(), None),
+        };
+
+        span.extensions_mut().insert(OpenSpan {
+            context,
+            parent_span_id,
+            kind,
+            start: SystemTime::now(),
+            attributes: fields.attributes,
+        });
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut extensions = span.extensions_mut();
+        if let Some(open) = extensions.get_mut::<OpenSpan>() {
+            let mut fields = FieldVisitor {
+                attributes: std::mem::take(&mut open.attributes),
+            };
+            values.record(&mut fields);
+            open.attributes = fields.attributes;
+        }
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(&id) else {
+            return;
+        };
+        let Some(open) = span.extensions_mut().remove::<OpenSpan>() else {
+            return;
+        };
+        if !open.context.sampled {
+            return;
+        }
+
+        self.exporter.export(FinishedSpan {
+            context: open.context,
+            parent_span_id: open.parent_span_id,
+            name: span.name().to_string(),
+            kind: open.kind,
+            start: open.start,
+            end: SystemTime::now(),
+            attributes: open.attributes,
+        });
+    }
+}
+
+/// The `[telemetry]` section of Rocket.toml
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct TracingConfig {
+    /// Base URL of an OTLP/HTTP collector, e.g. `http://localhost:4318`.
+    /// Spans are only exported when this is set.
+    pub otlp_endpoint: Option<String>,
+    pub service_name: String,
+    /// Spans sent per request to the collector
+    pub batch_size: usize,
+    /// Longest a span waits before its batch is sent
+    pub flush_interval_ms: u64,
+    /// Limits on connecting to the collector and on a whole export request
+    pub connect_timeout_ms: u64,
+    pub timeout_ms: u64,
+}
+
+impl Default for TracingConfig {
+    fn default() -> Self {
+        TracingConfig {
+            otlp_endpoint: None,
+            service_name: "todo-api".to_string(),
+            batch_size: 256,
+            flush_interval_ms: 2000,
+            connect_timeout_ms: 5000,
+            timeout_ms: 10_000,
+        }
+    }
+}
+
+/// Sends spans to an OpenTelemetry collector as OTLP/HTTP JSON, in batches,
+/// from a thread of its own. Spans are dropped when the collector cannot
+/// keep up, rather than slowing down requests.
+pub struct OtlpExporter {
+    sender: SyncSender<FinishedSpan>,
+}
+
+impl OtlpExporter {
+    pub fn spawn(endpoint: &str, config: &TracingConfig) -> std::io::Result<Self> {
+        let (sender, receiver) = mpsc::sync_channel(config.batch_size * 8);
+        let url = format!("{}/v1/traces", endpoint.trim_end_matches('/'));
+        let (service, batch_size) = (config.service_name.clone(), config.batch_size.max(1));
+        let interval = Duration::from_millis(config.flush_interval_ms);
+        // A collector that stops answering must not stall the exporter for good
+        let client = reqwest::Client::builder()
+            .connect_timeout(Duration::from_millis(config.connect_timeout_ms.max(1)))
+            .timeout(Duration::from_millis(config.timeout_ms.max(1)))
+            .build()
+            .map_err(std::io::Error::other)?;
+
+        std::thread::Builder::new()
+            .name("otlp-exporter".to_string())
+            .spawn(move || send_batches(receiver, client, url, service, batch_size, interval))?;
+        Ok(OtlpExporter { sender })
+    }
+}
+
+impl SpanExporter for OtlpExporter {
+    fn export(&self, span: FinishedSpan) {
+        let _ = self.sender.try_send(span);
+    }
+}
+
+fn send_batches(
+    receiver: Receiver<FinishedSpan>,
+    client: reqwest::Client,
+    url: String,
+    service: String,
+    batch_size: usize,
+    interval: Duration,
+) {
+    let Ok(runtime) = tokio::runtime::Builder::new_current_thread().enable_all().build() else {
+        return;
+    };
+    let mut batch = Vec::with_capacity(batch_size);
+    // When the batch is due: `interval` after its first span, however many
+    // follow it
+    let mut deadline: Option<Instant> = None;
+
+    loop {
+        let wait = deadline.map_or(interval, |deadline| deadline.saturating_duration_since(Instant::now()));
+        let closed = match receiver.recv_timeout(wait) {
+            Ok(span) => {
+                deadline.get_or_insert_with(|| Instant::now() + interval);
+                batch.push(span);
+                if batch.len() < batch_size {
+                    continue;
+                }
+                false
+            }
+            Err(RecvTimeoutError::Timeout) => false,
+            Err(RecvTimeoutError::Disconnected) => true,
+        };
+
+        deadline = None;
+        if !batch.is_empty() {
+            let body = otlp_json(&service, &batch);
+            batch.clear();
+            // Inside the runtime, which the request's timeout needs
+            let sent = runtime.block_on(async { client.post(&url).json(&body).send().await });
+            if let Err(e) = sent.and_then(|response| response.error_for_status()) {
+                // Events are not spans, so this does not feed back into the exporter
+                tracing::warn!(event = "otlp_export_failed", error = %e, "spans dropped");
+            }
+        }
+        if closed {
+            return;
+        }
+    }
+}
+
+fn nanos(time: SystemTime) -> String {
+    time.duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos().to_string()
+}
+
+fn otlp_attribute(key: &str, value: &AttributeValue) -> serde_json::Value {
+    let value = match value {
+        AttributeValue::String(s) => serde_json::json!({ "stringValue": s }),
+        // 64-bit integers are strings in OTLP JSON
+        AttributeValue::Int(i) => serde_json::json!({ "intValue": i.to_string() }),
+        AttributeValue::Bool(b) => serde_json::json!({ "boolValue": b }),
+    };
+    serde_json::json!({ "key": key, "value": value })
+}
+
+/// An `ExportTraceServiceRequest` in the OTLP/HTTP JSON encoding
+pub fn otlp_json(service: &str, spans: &[FinishedSpan]) -> serde_json::Value {
+    let spans: Vec<_> = spans
+        .iter()
+        .map(|span| {
+            let failed = span.attribute("error").is_some()
+                || matches!(span.attribute("http.status_code"), Some(AttributeValue::Int(code)) if *code >= 500);
+            serde_json::json!({
+                "traceId": span.context.trace_id_hex(),
+                "spanId": span.context.span_id_hex(),
+                "parentSpanId": span.parent_span_id.map(hex::encode).unwrap_or_default(),
+                "name": span.name,
+                "kind": match span.kind {
+                    SpanKind::Internal => 1,
+                    SpanKind::Server => 2,
+                },
+                "startTimeUnixNano": nanos(span.start),
+                "endTimeUnixNano": nanos(span.end),
+                "attributes": span
+                    .attributes
+                    .iter()
+                    .map(|(k, v)| otlp_attribute(k, v))
+                    .collect::<Vec<_>>(),
+                // 2 is STATUS_CODE_ERROR, 0 is unset
+                "status": { "code": if failed { 2 } else { 0 } }
+            })
+        })
+        .collect();
+
+    serde_json::json!({
+        "resourceSpans": [{
+            "resource": {
+                "attributes": [otlp_attribute("service.name", &AttributeValue::String(service.to_string()))]
+            },
+            "scopeSpans": [{
+                "scope": { "name": env!("CARGO_PKG_NAME"), "version": env!("CARGO_PKG_VERSION") },
+                "spans": spans
+            }]
+        }]
+    })
+}
@@ -0,0 +1,207 @@
+use rocket::http::Status;
+use rocket_db_pools::Database;
+
+use super::{admin_token, bearer, client, register, test_figment};
+use crate::admin::{self, AdminError};
+use crate::config::AppConfig;
+use crate::database::{self, Db, DbPool};
+use crate::models::{Priority, Status as TodoStatus};
+use crate::repository::{NewTodo, SqlTodoRepository, SqlUserRepository, TodoRepository};
+
+async fn migrated_pool() -> DbPool {
+    let pool = database::connect(&test_figment()).await.unwrap();
+    admin::migrate_up(&pool).await.unwrap();
+    pool
+}
+
+#[rocket::async_test]
+async fn migrate_down_reverts_one_migration_at_a_time() {
+    let pool = database::connect(&test_figment()).await.unwrap();
+    let total = database::migration_status(&pool).await.unwrap().len();
+    assert_eq!(admin::migrate_up(&pool).await.unwrap().len(), total);
+    assert!(admin::migrate_up(&pool).await.unwrap().is_empty());
+
+    let latest = admin::migrate_down(&pool).await.unwrap().unwrap();
+    assert_eq!(latest.description, "webhook delivery leases");
+    assert_eq!(admin::pending_migrations(&pool).await.unwrap(), 1);
+    let previous = admin::migrate_down(&pool).await.unwrap().unwrap();
+    assert_eq!(previous.description, "todo events outlive users");
+    assert_eq!(admin::pending_migrations(&pool).await.unwrap(), 2);
+
+    let reapplied = admin::migrate_up(&pool).await.unwrap();
+    assert_eq!(reapplied.iter().map(|m| m.version).collect::<Vec<_>>(), [13, 14]);
+    assert_eq!(admin::pending_migrations(&pool).await.unwrap(), 0);
+}
+
+#[rocket::async_test]
+async fn every_migration_can_be_reverted() {
+    let pool = migrated_pool().await;
+    let status = database::migration_status(&pool).await.unwrap();
+    assert!(status.iter().all(|m| m.reversible));
+
+    let users = SqlUserRepository::new(pool.clone());
+    let todos = SqlTodoRepository::new(pool.clone());
+    let (alice, _) = admin::create_user(&users, "alice".into(), "alice@example.com".into(), None, false)
+        .await
+        .unwrap();
+    let new = NewTodo {
+        title: "stuck".to_string(),
+        description: None,
+        status: TodoStatus::Blocked,
+        priority: Priority::High,
+    };
+    todos.create(alice.id.unwrap(), new).await.unwrap();
+
+    // Before 007 only three statuses were allowed
+    while database::migration_status(&pool).await.unwrap().iter().any(|m| m.applied && m.version >= 7) {
+        admin::migrate_down(&pool).await.unwrap().unwrap();
+    }
+    let statuses: Vec<String> = sqlx::query_scalar("SELECT status FROM todos")
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+    assert_eq!(statuses, ["pending"]);
+
+    let mut reverted = Vec::new();
+    while let Some(m) = admin::migrate_down(&pool).await.unwrap() {
+        reverted.push(m.version);
+    }
+    assert_eq!(reverted, [6, 5, 4, 3, 2, 1]);
+    assert_eq!(admin::pending_migrations(&pool).await.unwrap(), status.len());
+    let tables = [
+        "todos",
+        "users",
+        "api_keys",
+        "user_identities",
+        "sessions",
+        "data_exports",
+        "user_quotas",
+        "todo_events",
+        "webhooks",
+        "webhook_deliveries",
+        "webhook_cursor",
+    ];
+    for table in tables {
+        let query = format!("SELECT COUNT(*) FROM {}", table);
+        assert!(sqlx::query(&query).execute(&pool).await.is_err(), "{} still exists", table);
+    }
+
+    assert_eq!(admin::migrate_up(&pool).await.unwrap().len(), status.len());
+    assert!(database::migration_status(&pool).await.unwrap().iter().all(|m| m.applied && !m.checksum_mismatch));
+}
+
+#[rocket::async_test]
+async fn migration_status_is_admin_only() {
+    let client = client(test_figment()).await;
+    let user = register(&client, "mallory", "mallory@example.com").await;
+    let admin = admin_token(&client, "root").await;
+
+    let response = client
+        .get("/api/admin/migrations")
+        .header(bearer(user["token"].as_str().unwrap()))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Forbidden);
+
+    let response = client.get("/api/admin/migrations").header(bearer(&admin)).dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    let list: Vec<serde_json::Value> = response.into_json().await.unwrap();
+    assert_eq!(list.len(), database::migrator().iter().filter(|m| !m.migration_type.is_down_migration()).count());
+    assert!(list.iter().all(|m| m["applied"] == true && m["checksum_mismatch"] == false && m["reversible"] == true));
+}
+
+#[rocket::async_test]
+async fn exported_todos_import_unchanged() {
+    let pool = migrated_pool().await;
+    let users = SqlUserRepository::new(pool.clone());
+    let todos = SqlTodoRepository::new(pool.clone());
+
+    let (alice, _) = admin::create_user(&users, "alice".into(), "alice@example.com".into(), None, false)
+        .await
+        .unwrap();
+    admin::create_user(&users, "bob".into(), "bob@example.com".into(), None, false)
+        .await
+        .unwrap();
+    for (title, description, status, priority) in [
+        ("first", None, TodoStatus::Completed, Priority::Low),
+        ("second", Some("with notes"), TodoStatus::Blocked, Priority::High),
+    ] {
+        let new = NewTodo {
+            title: title.to_string(),
+            description: description.map(str::to_string),
+            status,
+            priority,
+        };
+        todos.create(alice.id.unwrap(), new).await.unwrap();
+    }
+
+    let exported = admin::export_todos(&users, &todos, "alice").await.unwrap();
+    assert_eq!(exported.iter().map(|t| t.title.as_str()).collect::<Vec<_>>(), ["first", "second"]);
+
+    let json = serde_json::to_string(&exported).unwrap();
+    assert_eq!(admin::import_todos(&users, &todos, "bob", &json).await.unwrap(), 2);
+
+    let imported = admin::export_todos(&users, &todos, "bob").await.unwrap();
+    let fields = |list: &[crate::models::TodoResponse]| {
+        list.iter()
+            .map(|t| (t.title.clone(), t.description.clone(), t.status, t.priority))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(fields(&imported), fields(&exported));
+
+    // One bad entry and nothing is imported
+    let json = r#"[{"title":"fine"},{"title":"   "}]"#;
+    assert!(matches!(
+        admin::import_todos(&users, &todos, "bob", json).await,
+        Err(AdminError::Invalid(_))
+    ));
+    assert_eq!(admin::export_todos(&users, &todos, "bob").await.unwrap().len(), 2);
+}
+
+#[cfg(not(feature = "postgres"))]
+#[rocket::async_test]
+async fn backup_is_a_working_copy() {
+    let pool = migrated_pool().await;
+    let users = SqlUserRepository::new(pool.clone());
+    let todos = SqlTodoRepository::new(pool.clone());
+    admin::seed(&users, &todos, 1, 4).await.unwrap();
+
+    let output = std::env::temp_dir()
+        .join(format!("todo-test-backup-{}", uuid::Uuid::new_v4()))
+        .join("todos.db");
+    admin::backup(&pool, &output).await.unwrap();
+    assert!(matches!(admin::backup(&pool, &output).await, Err(AdminError::Invalid(_))));
+
+    let copy = sqlx::SqlitePool::connect(&format!("sqlite:{}", output.display()))
+        .await
+        .unwrap();
+    assert_eq!(admin::pending_migrations(&copy).await.unwrap(), 0);
+    let copied = admin::export_todos(&SqlUserRepository::new(copy.clone()), &SqlTodoRepository::new(copy), "seed1")
+        .await
+        .unwrap();
+    assert_eq!(copied.len(), 4);
+}
+
+#[rocket::async_test]
+async fn seeded_users_can_use_issued_tokens() {
+    let client = client(test_figment()).await;
+    let pool: DbPool = (**Db::fetch(client.rocket()).unwrap()).clone();
+    let users = SqlUserRepository::new(pool.clone());
+    let todos = SqlTodoRepository::new(pool);
+    let jwt = client.rocket().state::<AppConfig>().unwrap().jwt.clone();
+
+    assert_eq!(admin::seed(&users, &todos, 2, 3).await.unwrap(), ["seed1", "seed2"]);
+    assert_eq!(admin::seed(&users, &todos, 3, 1).await.unwrap(), ["seed3"]);
+
+    let token = admin::issue_token(&users, &jwt, "seed1").await.unwrap();
+    let response = client.get("/api/todos").header(bearer(&token)).dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    let list: Vec<serde_json::Value> = response.into_json().await.unwrap();
+    assert_eq!(list.len(), 3);
+
+    admin::set_disabled(&users, "seed1", true).await.unwrap();
+    let response = client.get("/api/todos").header(bearer(&token)).dispatch().await;
+    assert_eq!(response.status(), Status::Unauthorized);
+    assert!(matches!(admin::issue_token(&users, &jwt, "seed1").await, Err(AdminError::Invalid(_))));
+    assert!(matches!(admin::issue_token(&users, &jwt, "nobody").await, Err(AdminError::Invalid(_))));
+}
@@ -0,0 +1,137 @@
+use rocket::http::{ContentType, Status};
+use rocket::local::asynchronous::Client;
+use rocket_db_pools::Database;
+
+use super::{bearer, register, test_figment, untracked_client as client};
+use crate::database::Db;
+
+/// Creates a key with the given scopes and returns `(id, token)`
+async fn create_key(client: &Client, session: &str, scopes: &[&str]) -> (i64, String) {
+    let response = client
+        .post("/api/auth/api-keys")
+        .header(bearer(session))
+        .header(ContentType::JSON)
+        .body(serde_json::json!({ "name": "ci", "scopes": scopes }).to_string())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Created);
+
+    let body: serde_json::Value = response.into_json().await.unwrap();
+    (
+        body["api_key"]["id"].as_i64().unwrap(),
+        body["token"].as_str().unwrap().to_string(),
+    )
+}
+
+async fn code(response: rocket::local::asynchronous::LocalResponse<'_>) -> String {
+    let body: serde_json::Value = response.into_json().await.unwrap();
+    body["code"].as_str().unwrap().to_string()
+}
+
+#[rocket::async_test]
+async fn read_only_key_cannot_write() {
+    let client = client(test_figment()).await;
+    let registered = register(&client, "alice", "alice@example.com").await;
+    let (_, key) = create_key(&client, registered["token"].as_str().unwrap(), &["todos:read"]).await;
+
+    let response = client.get("/api/todos").header(bearer(&key)).dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+
+    let response = client
+        .post("/api/todos")
+        .header(bearer(&key))
+        .header(ContentType::JSON)
+        .body(r#"{"title":"from ci"}"#)
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Forbidden);
+    assert_eq!(code(response).await, "insufficient_scope");
+
+    let (_, writer) = create_key(&client, registered["token"].as_str().unwrap(), &["todos:write"]).await;
+    let response = client.get("/api/todos").header(bearer(&writer)).dispatch().await;
+    assert_eq!(response.status(), Status::Forbidden);
+    assert_eq!(code(response).await, "insufficient_scope");
+}
+
+#[rocket::async_test]
+async fn keys_cannot_manage_keys() {
+    let client = client(test_figment()).await;
+    let registered = register(&client, "bob", "bob@example.com").await;
+    let (_, key) = create_key(&client, registered["token"].as_str().unwrap(), &["todos:read"]).await;
+
+    let response = client
+        .get("/api/auth/api-keys")
+        .header(bearer(&key))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Forbidden);
+    assert_eq!(code(response).await, "session_required");
+
+    let response = client
+        .post("/api/auth/api-keys")
+        .header(bearer(&key))
+        .header(ContentType::JSON)
+        .body(r#"{"name":"escalated"}"#)
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Forbidden);
+    assert_eq!(code(response).await, "session_required");
+}
+
+#[rocket::async_test]
+async fn revoked_and_expired_keys_are_rejected() {
+    let client = client(test_figment()).await;
+    let registered = register(&client, "carol", "carol@example.com").await;
+    let session = registered["token"].as_str().unwrap();
+    let (revoked_id, revoked) = create_key(&client, session, &["todos:read"]).await;
+    let (expired_id, expired) = create_key(&client, session, &["todos:read"]).await;
+
+    let response = client
+        .delete(format!("/api/auth/api-keys/{}", revoked_id))
+        .header(bearer(session))
+        .dispatch()
+        .await;
+    assert!(response.status().class().is_success());
+
+    let response = client.get("/api/todos").header(bearer(&revoked)).dispatch().await;
+    assert_eq!(response.status(), Status::Unauthorized);
+    assert_eq!(code(response).await, "invalid_token");
+
+    let db = Db::fetch(client.rocket()).unwrap();
+    let past = chrono::Utc::now().naive_utc() - chrono::Duration::minutes(1);
+    sqlx::query!("UPDATE api_keys SET expires_at = $1 WHERE id = $2", past, expired_id)
+        .execute(&**db)
+        .await
+        .unwrap();
+
+    let response = client.get("/api/todos").header(bearer(&expired)).dispatch().await;
+    assert_eq!(response.status(), Status::Unauthorized);
+    assert_eq!(code(response).await, "token_expired");
+}
+
+#[rocket::async_test]
+async fn use_is_recorded() {
+    let client = client(test_figment()).await;
+    let registered = register(&client, "dave", "dave@example.com").await;
+    let session = registered["token"].as_str().unwrap();
+    let (id, key) = create_key(&client, session, &["todos:read"]).await;
+
+    let last_used = || async {
+        let response = client
+            .get("/api/auth/api-keys")
+            .header(bearer(session))
+            .dispatch()
+            .await;
+        let keys: serde_json::Value = response.into_json().await.unwrap();
+        keys.as_array()
+            .unwrap()
+            .iter()
+            .find(|k| k["id"] == id)
+            .unwrap()["last_used_at"]
+            .clone()
+    };
+
+    assert!(last_used().await.is_null());
+    client.get("/api/todos").header(bearer(&key)).dispatch().await;
+    assert!(last_used().await.is_string());
+}
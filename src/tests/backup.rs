@@ -0,0 +1,230 @@
+use std::time::Duration;
+
+use rocket::http::{ContentType, Status};
+use rocket::local::asynchronous::LocalResponse;
+
+use super::{admin_token, bearer, client, register, test_figment};
+use crate::maintenance::Maintenance;
+
+async fn code(response: LocalResponse<'_>) -> String {
+    let body: serde_json::Value = response.into_json().await.unwrap();
+    body["code"].as_str().unwrap().to_string()
+}
+
+#[rocket::async_test]
+async fn backups_are_admin_only() {
+    let client = client(test_figment()).await;
+    let user = register(&client, "mallory", "mallory@example.com").await;
+    let admin = admin_token(&client, "root").await;
+
+    let response = client.get("/api/admin/backups").dispatch().await;
+    assert_eq!(response.status(), Status::Unauthorized);
+
+    let response = client
+        .post("/api/admin/backups")
+        .header(bearer(user["token"].as_str().unwrap()))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Forbidden);
+
+    // An administrator's API key is not enough either
+    let response = client
+        .post("/api/auth/api-keys")
+        .header(bearer(&admin))
+        .header(ContentType::JSON)
+        .body(r#"{"name":"ops","scopes":["todos:read","todos:write"]}"#)
+        .dispatch()
+        .await;
+    let key: serde_json::Value = response.into_json().await.unwrap();
+    let response = client
+        .get("/api/admin/backups")
+        .header(bearer(key["token"].as_str().unwrap()))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Forbidden);
+
+    let response = client.get("/api/admin/backups").header(bearer(&admin)).dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+}
+
+#[rocket::async_test]
+async fn maintenance_mode_turns_requests_away() {
+    let client = client(test_figment()).await;
+    let registered = register(&client, "alice", "alice@example.com").await;
+    let token = registered["token"].as_str().unwrap();
+
+    let response = client.get("/maintenance").dispatch().await;
+    assert_eq!(response.status(), Status::NotFound);
+
+    let maintenance = client.rocket().state::<Maintenance>().unwrap();
+    let mut watch = maintenance.watch();
+    assert!(watch.admit().is_some());
+    let window = maintenance.begin(Duration::ZERO).await.unwrap();
+    assert!(maintenance.begin(Duration::ZERO).await.is_none());
+    assert!(watch.admit().is_none());
+    rocket::tokio::time::timeout(Duration::from_secs(1), watch.started()).await.unwrap();
+
+    let response = client.post("/api/todos").header(bearer(token)).dispatch().await;
+    assert_eq!(response.status(), Status::ServiceUnavailable);
+    assert_eq!(code(response).await, "service_unavailable");
+    let response = client.get("/health").dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+
+    drop(window);
+    let response = client.get("/api/todos").header(bearer(token)).dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+
+    // Commands on sockets are waited for like requests
+    let running = watch.admit().unwrap();
+    let _other = watch.admit().unwrap();
+    assert!(maintenance.begin(Duration::from_millis(50)).await.is_none());
+    drop(running);
+    assert!(maintenance.begin(Duration::from_millis(50)).await.is_some());
+}
+
+#[cfg(not(feature = "postgres"))]
+#[rocket::async_test]
+async fn snapshots_are_checksummed_and_rotated() {
+    use crate::admin;
+    use crate::backup::{self, BackupConfig};
+
+    let pool = crate::database::connect(&test_figment()).await.unwrap();
+    admin::migrate_up(&pool).await.unwrap();
+    let config = BackupConfig {
+        dir: super::scratch_dir("backup"),
+        keep: 2,
+        gzip: true,
+        ..BackupConfig::default()
+    };
+
+    let mut created = Vec::new();
+    for _ in 0..3 {
+        created.push(backup::create(&pool, &config).await.unwrap().name);
+        // Names have millisecond resolution
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    }
+
+    let snapshots = backup::list(&config).unwrap();
+    assert_eq!(
+        snapshots.iter().map(|s| s.name.as_str()).collect::<Vec<_>>(),
+        [created[2].as_str(), created[1].as_str()]
+    );
+    for snapshot in &snapshots {
+        assert!(snapshot.compressed && snapshot.name.ends_with(".db.gz"));
+        backup::verify(&config, &snapshot.name).await.unwrap();
+    }
+    // The rotated snapshot's checksum file went with it
+    assert_eq!(std::fs::read_dir(&config.dir).unwrap().count(), 4);
+
+    assert!(matches!(
+        backup::verify(&config, "../todos.db").await,
+        Err(backup::BackupError::NotFound(_))
+    ));
+}
+
+#[cfg(not(feature = "postgres"))]
+#[rocket::async_test]
+async fn restore_brings_back_deleted_todos() {
+    let client = client(test_figment()).await;
+    let admin = admin_token(&client, "root").await;
+
+    let response = client
+        .post("/api/todos")
+        .header(bearer(&admin))
+        .header(ContentType::JSON)
+        .body(r#"{"title":"keep me"}"#)
+        .dispatch()
+        .await;
+    let todo: serde_json::Value = response.into_json().await.unwrap();
+    let id = todo["id"].as_i64().unwrap();
+
+    let response = client.post("/api/admin/backups").header(bearer(&admin)).dispatch().await;
+    assert_eq!(response.status(), Status::Created);
+    let snapshot: serde_json::Value = response.into_json().await.unwrap();
+    let name = snapshot["name"].as_str().unwrap().to_string();
+
+    client
+        .delete(format!("/api/todos/{}", id))
+        .header(bearer(&admin))
+        .dispatch()
+        .await;
+
+    let response = client
+        .post(format!("/api/admin/backups/{}/restore", name))
+        .header(bearer(&admin))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+
+    let response = client
+        .get(format!("/api/todos/{}", id))
+        .header(bearer(&admin))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+
+    // Ids handed out before the restore are not reused
+    let response = client
+        .post("/api/todos")
+        .header(bearer(&admin))
+        .header(ContentType::JSON)
+        .body(r#"{"title":"after"}"#)
+        .dispatch()
+        .await;
+    let todo: serde_json::Value = response.into_json().await.unwrap();
+    assert!(todo["id"].as_i64().unwrap() > id);
+}
+
+#[cfg(not(feature = "postgres"))]
+#[rocket::async_test]
+async fn tampered_snapshot_is_not_restored() {
+    let client = client(test_figment()).await;
+    let admin = admin_token(&client, "root").await;
+
+    let response = client.post("/api/admin/backups").header(bearer(&admin)).dispatch().await;
+    let snapshot: serde_json::Value = response.into_json().await.unwrap();
+    let name = snapshot["name"].as_str().unwrap();
+
+    let config = client.rocket().state::<crate::backup::BackupConfig>().unwrap();
+    let path = config.dir.join(name);
+    let mut bytes = std::fs::read(&path).unwrap();
+    let last = bytes.len() - 1;
+    bytes[last] ^= 0xff;
+    std::fs::write(&path, bytes).unwrap();
+
+    let response = client
+        .post(format!("/api/admin/backups/{}/restore", name))
+        .header(bearer(&admin))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+
+    let response = client
+        .post("/api/admin/backups/todos-missing.db/restore")
+        .header(bearer(&admin))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::NotFound);
+
+    // Still serving, with the data intact
+    let response = client.get("/api/auth/me").header(bearer(&admin)).dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+}
+
+#[rocket::async_test]
+async fn invalid_backup_settings_refuse_to_ignite() {
+    let file = std::env::temp_dir().join(format!("todo-test-backup-file-{}", uuid::Uuid::new_v4()));
+    std::fs::write(&file, b"not a directory").unwrap();
+
+    for figment in [
+        test_figment().merge(("backup.keep", 0)),
+        test_figment().merge(("backup.dir", &file)),
+        test_figment().merge(("backup.keep", "seven")),
+    ] {
+        let Err(error) = rocket::local::asynchronous::Client::tracked(crate::app(figment)).await else {
+            panic!("started with unusable backup settings");
+        };
+        assert!(matches!(error.kind(), rocket::error::ErrorKind::FailedFairings(_)));
+    }
+    let _ = std::fs::remove_file(file);
+}
@@ -0,0 +1,145 @@
+use rocket::http::{ContentType, Status};
+
+use super::{bearer, client, register, test_figment};
+use crate::config::AppConfig;
+
+#[rocket::async_test]
+async fn invalid_settings_refuse_to_ignite() {
+    let figment = test_figment()
+        .merge(("app.jwt.ttl_hours", 0))
+        .merge(("app.cookies.same_site", "sideways"));
+    let Err(error) = rocket::local::asynchronous::Client::tracked(crate::app(figment)).await else {
+        panic!("started with an invalid configuration");
+    };
+    // Inspecting the error marks it handled; unhandled ones panic on drop
+    assert!(matches!(error.kind(), rocket::error::ErrorKind::FailedFairings(_)));
+}
+
+#[test]
+fn release_needs_a_strong_secret() {
+    let mut config = AppConfig::default();
+    assert!(config.validate(false).is_ok());
+    assert_eq!(config.validate(true).unwrap_err().0.len(), 1);
+
+    config.jwt.secret = Some("short".to_string());
+    assert!(config.validate(false).is_ok());
+    assert!(config.validate(true).is_err());
+
+    config.jwt.secret = Some("x".repeat(32));
+    assert!(config.validate(true).is_ok());
+
+    config.cookies.same_site = "none".to_string();
+    config.pagination.max_limit = 10;
+    config.pagination.default_limit = 20;
+    config.cors.allowed_origins = vec!["https://app.example.com".to_string(), "app.example.com/".to_string()];
+    let problems = config.validate(true).unwrap_err().0;
+    assert_eq!(problems.len(), 3, "{:?}", problems);
+}
+
+#[rocket::async_test]
+async fn config_shows_only_the_public_subset() {
+    let secret = "a-secret-that-must-never-be-served";
+    let client = client(
+        test_figment()
+            .merge(("app.name", "Team Todos"))
+            .merge(("app.jwt.secret", secret))
+            .merge(("app.features.api_keys", false)),
+    )
+    .await;
+
+    let response = client.get("/config").dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    let text = response.into_string().await.unwrap();
+    assert!(!text.contains(secret));
+
+    let body: serde_json::Value = serde_json::from_str(&text).unwrap();
+    assert_eq!(body["app_name"], "Team Todos");
+    assert_eq!(body["version"], env!("CARGO_PKG_VERSION"));
+    assert_eq!(body["token_ttl_hours"], 24);
+    assert_eq!(body["features"]["api_keys"], false);
+    assert_eq!(body["features"]["registration"], true);
+}
+
+#[rocket::async_test]
+async fn disabled_features_are_refused() {
+    let client = client(test_figment().merge(("app.features.registration", false))).await;
+
+    let response = client
+        .post("/api/auth/register")
+        .header(ContentType::JSON)
+        .body(r#"{"username":"alice","email":"alice@example.com","password":"correct horse battery staple"}"#)
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Forbidden);
+    let body: serde_json::Value = response.into_json().await.unwrap();
+    assert_eq!(body["code"], "feature_disabled");
+}
+
+#[rocket::async_test]
+async fn lists_are_paginated() {
+    let client = client(test_figment().merge(("app.pagination.max_limit", 3)).merge(("app.pagination.default_limit", 2))).await;
+    let registered = register(&client, "alice", "alice@example.com").await;
+    let token = registered["token"].as_str().unwrap();
+
+    for n in 1..=5 {
+        let response = client
+            .post("/api/todos")
+            .header(bearer(token))
+            .header(ContentType::JSON)
+            .body(format!(r#"{{"title":"todo {}"}}"#, n))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Created);
+    }
+
+    let list = |uri: &'static str| {
+        let client = &client;
+        async move {
+            let response = client.get(uri).header(bearer(token)).dispatch().await;
+            (response.status(), response.into_json::<serde_json::Value>().await.unwrap())
+        }
+    };
+
+    let (status, body) = list("/api/todos").await;
+    assert_eq!(status, Status::Ok);
+    assert_eq!(body.as_array().unwrap().len(), 2);
+
+    let (_, body) = list("/api/todos?limit=3&offset=3").await;
+    assert_eq!(body.as_array().unwrap().len(), 2);
+
+    let (_, body) = list("/api/todos/status/pending?offset=4").await;
+    assert_eq!(body.as_array().unwrap().len(), 1);
+
+    let (status, body) = list("/api/todos?limit=4").await;
+    assert_eq!(status, Status::UnprocessableEntity);
+    assert_eq!(body["errors"][0]["field"], "limit");
+    assert_eq!(body["errors"][0]["code"], "too_large");
+}
+
+#[rocket::async_test]
+async fn auth_cookie_follows_the_cookie_settings() {
+    let client = client(
+        test_figment()
+            .merge(("app.cookies.secure", true))
+            .merge(("app.cookies.same_site", "strict"))
+            .merge(("app.jwt.ttl_hours", 2)),
+    )
+    .await;
+    register(&client, "alice", "alice@example.com").await;
+
+    let response = client
+        .post("/api/auth/login")
+        .header(ContentType::JSON)
+        .body(r#"{"email":"alice@example.com","password":"correct horse battery staple"}"#)
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    let cookie = response
+        .headers()
+        .get("Set-Cookie")
+        .find(|c| c.starts_with("auth_token="))
+        .unwrap();
+    assert!(cookie.contains("Secure"), "{}", cookie);
+    assert!(cookie.contains("SameSite=Strict"), "{}", cookie);
+    assert!(cookie.contains("Max-Age=7200"), "{}", cookie);
+}
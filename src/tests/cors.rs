@@ -0,0 +1,140 @@
+use rocket::figment::Figment;
+use rocket::http::{Header, Status};
+use rocket::local::asynchronous::{Client, LocalResponse};
+
+use super::{bearer, client, register, test_figment};
+
+const APP: &str = "https://app.example.com";
+
+fn cors_figment() -> Figment {
+    test_figment()
+        .merge(("app.cors.allowed_origins", [APP, "https://*.preview.example.com"]))
+        .merge(("app.cors.allow_credentials", true))
+        .merge(("app.cors.max_age_secs", 600))
+}
+
+async fn preflight<'c>(client: &'c Client, uri: &'static str, origin: &'static str, method: &'static str) -> LocalResponse<'c> {
+    client
+        .options(uri)
+        .header(Header::new("Origin", origin))
+        .header(Header::new("Access-Control-Request-Method", method))
+        .header(Header::new("Access-Control-Request-Headers", "authorization, content-type"))
+        .dispatch()
+        .await
+}
+
+#[rocket::async_test]
+async fn preflight_is_answered_for_api_routes() {
+    let client = client(cors_figment()).await;
+
+    for (uri, method) in [("/api/todos", "POST"), ("/api/todos/7", "DELETE"), ("/api/users/me", "PATCH")] {
+        let response = preflight(&client, uri, APP, method).await;
+        assert_eq!(response.status(), Status::NoContent, "{} {}", method, uri);
+        let headers = response.headers();
+        assert_eq!(headers.get_one("Access-Control-Allow-Origin"), Some(APP));
+        assert_eq!(headers.get_one("Access-Control-Allow-Credentials"), Some("true"));
+        assert!(headers.get_one("Access-Control-Allow-Methods").unwrap().contains(method));
+        let allowed_headers = headers.get_one("Access-Control-Allow-Headers").unwrap().to_ascii_lowercase();
+        assert!(allowed_headers.contains("authorization") && allowed_headers.contains("content-type"));
+        assert_eq!(headers.get_one("Access-Control-Max-Age"), Some("600"));
+        assert!(headers.get("Vary").any(|v| v == "Origin"));
+    }
+}
+
+#[rocket::async_test]
+async fn origins_match_exactly_or_by_subdomain() {
+    let client = client(cors_figment()).await;
+
+    let response = preflight(&client, "/api/todos", "https://pr-12.preview.example.com", "GET").await;
+    assert_eq!(response.status(), Status::NoContent);
+    assert_eq!(
+        response.headers().get_one("Access-Control-Allow-Origin"),
+        Some("https://pr-12.preview.example.com")
+    );
+
+    for origin in [
+        "https://evil.example.com",
+        "http://app.example.com",
+        "https://preview.example.com",
+        "https://evilpreview.example.com",
+        "https://app.example.com.evil.net",
+    ] {
+        let response = preflight(&client, "/api/todos", origin, "GET").await;
+        assert_eq!(response.status(), Status::Forbidden, "{}", origin);
+        assert_eq!(response.headers().get_one("Access-Control-Allow-Origin"), None, "{}", origin);
+    }
+}
+
+#[rocket::async_test]
+async fn unlisted_methods_and_headers_are_refused() {
+    let client = client(cors_figment().merge(("app.cors.allowed_methods", ["GET", "POST"]))).await;
+
+    let response = preflight(&client, "/api/todos/1", APP, "DELETE").await;
+    assert_eq!(response.status(), Status::Forbidden);
+    assert_eq!(response.headers().get_one("Access-Control-Allow-Origin"), None);
+
+    let response = client
+        .options("/api/todos")
+        .header(Header::new("Origin", APP))
+        .header(Header::new("Access-Control-Request-Method", "POST"))
+        .header(Header::new("Access-Control-Request-Headers", "x-debug"))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Forbidden);
+}
+
+#[rocket::async_test]
+async fn actual_responses_carry_cors_headers() {
+    let client = client(cors_figment()).await;
+    let registered = register(&client, "alice", "alice@example.com").await;
+
+    let response = client
+        .get("/api/todos")
+        .header(Header::new("Origin", APP))
+        .header(bearer(registered["token"].as_str().unwrap()))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    let headers = response.headers();
+    assert_eq!(headers.get_one("Access-Control-Allow-Origin"), Some(APP));
+    assert_eq!(headers.get_one("Access-Control-Allow-Credentials"), Some("true"));
+    assert!(headers.get_one("Access-Control-Expose-Headers").unwrap().contains("X-Request-Id"));
+    assert_eq!(headers.get_one("Access-Control-Max-Age"), None);
+
+    // Errors too, so the browser can read the problem details
+    let response = client.get("/api/todos").header(Header::new("Origin", APP)).dispatch().await;
+    assert_eq!(response.status(), Status::Unauthorized);
+    assert_eq!(response.headers().get_one("Access-Control-Allow-Origin"), Some(APP));
+
+    let response = client
+        .get("/api/todos")
+        .header(Header::new("Origin", "https://evil.example.com"))
+        .dispatch()
+        .await;
+    assert_eq!(response.headers().get_one("Access-Control-Allow-Origin"), None);
+}
+
+#[rocket::async_test]
+async fn wildcard_origin_without_credentials() {
+    let client = client(test_figment().merge(("app.cors.allowed_origins", ["*"]))).await;
+
+    let response = preflight(&client, "/api/auth/login", "https://anywhere.test", "POST").await;
+    assert_eq!(response.status(), Status::NoContent);
+    assert_eq!(response.headers().get_one("Access-Control-Allow-Origin"), Some("*"));
+    assert_eq!(response.headers().get_one("Access-Control-Allow-Credentials"), None);
+
+    // Without an Origin header nothing changes
+    let response = client.get("/live").dispatch().await;
+    assert_eq!(response.headers().get_one("Access-Control-Allow-Origin"), None);
+}
+
+#[rocket::async_test]
+async fn credentials_with_any_origin_refuse_to_ignite() {
+    let figment = test_figment()
+        .merge(("app.cors.allowed_origins", ["*"]))
+        .merge(("app.cors.allow_credentials", true));
+    let Err(error) = Client::tracked(crate::app(figment)).await else {
+        panic!("started with credentials for any origin");
+    };
+    assert!(matches!(error.kind(), rocket::error::ErrorKind::FailedFairings(_)));
+}
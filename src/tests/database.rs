@@ -0,0 +1,212 @@
+use std::borrow::Cow;
+
+use rocket_db_pools::Database;
+use sqlx::migrate::Migrator;
+
+use rocket::http::Status;
+
+use super::{admin_token, bearer, client, test_figment};
+use crate::database::{self, Db};
+
+#[rocket::async_test]
+async fn migrations_are_applied_at_ignite() {
+    let client = client(test_figment()).await;
+    let db = Db::fetch(client.rocket()).unwrap();
+
+    let status = database::migration_status(db).await.unwrap();
+    let up = database::migrator()
+        .iter()
+        .filter(|m| !m.migration_type.is_down_migration())
+        .count();
+    assert_eq!(status.len(), up);
+    assert!(status.iter().all(|m| m.applied && !m.checksum_mismatch));
+    assert!(status.windows(2).all(|pair| pair[0].version < pair[1].version));
+}
+
+#[rocket::async_test]
+async fn ready_reports_the_migrations() {
+    let client = client(test_figment()).await;
+    let root = admin_token(&client, "root").await;
+
+    let response = client.get("/ready").header(bearer(&root)).dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    let body: serde_json::Value = response.into_json().await.unwrap();
+    assert_eq!(body["status"], "ready");
+    let up = database::migrator()
+        .iter()
+        .filter(|m| !m.migration_type.is_down_migration())
+        .count();
+    let migrations = &body["checks"]["migrations"];
+    assert_eq!(migrations["status"], "pass");
+    assert_eq!(migrations["data"]["applied"], up);
+    assert_eq!(migrations["data"]["pending"], serde_json::json!([]));
+    assert_eq!(migrations["data"]["checksum_mismatches"], serde_json::json!([]));
+}
+
+/// With `migrations.auto` off, pending migrations stop the server from
+/// starting instead of being applied
+#[rocket::async_test]
+async fn pending_migrations_can_refuse_startup() {
+    let figment = test_figment().merge(("migrations.auto", false));
+    let Err(error) = rocket::local::asynchronous::Client::tracked(crate::app(figment.clone())).await else {
+        panic!("started with pending migrations");
+    };
+    // Inspecting the error marks it handled; unhandled ones panic on drop
+    assert!(matches!(error.kind(), rocket::error::ErrorKind::FailedFairings(_)));
+
+    // A setting that does not parse is not taken for the default `auto = true`
+    let garbled = test_figment().merge(("migrations.auto", "sometimes"));
+    let Err(error) = rocket::local::asynchronous::Client::tracked(crate::app(garbled.clone())).await else {
+        panic!("started with an unreadable [migrations] section");
+    };
+    assert!(matches!(error.kind(), rocket::error::ErrorKind::FailedFairings(_)));
+    let pool = database::connect(&garbled).await.unwrap();
+    assert!(database::migration_status(&pool).await.unwrap().iter().all(|m| !m.applied));
+
+    let pool = database::connect(&figment).await.unwrap();
+    let status = database::migration_status(&pool).await.unwrap();
+    assert!(status.iter().all(|m| !m.applied));
+
+    database::migrator().run(&pool).await.unwrap();
+    let client = client(figment).await;
+    let response = client.get("/ready").dispatch().await;
+    let body: serde_json::Value = response.into_json().await.unwrap();
+    assert_eq!(body["status"], "ready");
+}
+
+/// `todoctl` opens its pool from the same settings as the server
+#[rocket::async_test]
+async fn connect_reads_the_rocket_config() {
+    let figment = test_figment();
+    let pool = database::connect(&figment).await.unwrap();
+
+    let status = database::migration_status(&pool).await.unwrap();
+    assert!(status.iter().all(|m| !m.applied));
+
+    database::migrator().run(&pool).await.unwrap();
+    let status = database::migration_status(&pool).await.unwrap();
+    assert!(status.iter().all(|m| m.applied));
+}
+
+/// Before per-user ownership todos were shared; the migration that adds
+/// owners gives them to the oldest account
+#[rocket::async_test]
+async fn shared_todos_go_to_the_oldest_account() {
+    let pool = database::connect(&test_figment()).await.unwrap();
+    let all = database::migrator();
+    let shared_era = Migrator {
+        migrations: Cow::Owned(all.iter().filter(|m| m.version < 5).cloned().collect()),
+        ..Migrator::DEFAULT
+    };
+    shared_era.run(&pool).await.unwrap();
+
+    sqlx::query(
+        "INSERT INTO users (username, email, password_hash) VALUES ('first', 'first@example.com', 'x'), ('second', 'second@example.com', 'x')",
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+    sqlx::query("INSERT INTO todos (title) VALUES ('shared')")
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    all.run(&pool).await.unwrap();
+
+    let owner: Option<String> = sqlx::query_scalar(
+        "SELECT u.username FROM todos t JOIN users u ON u.id = t.user_id WHERE t.title = 'shared'",
+    )
+    .fetch_optional(&pool)
+    .await
+    .unwrap();
+    assert_eq!(owner.as_deref(), Some("first"));
+}
+
+#[cfg(not(feature = "postgres"))]
+#[rocket::async_test]
+async fn sqlite_pragmas_and_pool_limits_are_configurable() {
+    let figment = test_figment()
+        .merge(("databases.sqlite_db.max_connections", 3))
+        .merge(("databases.sqlite_db.min_connections", 1))
+        .merge(("databases.sqlite_db.busy_timeout_ms", 2500))
+        .merge(("databases.sqlite_db.cache_size", -8000));
+    let pool = database::connect(&figment).await.unwrap();
+    assert_eq!(pool.options().get_max_connections(), 3);
+    assert_eq!(pool.options().get_min_connections(), 1);
+
+    let mut conn = pool.acquire().await.unwrap();
+    let journal_mode: String = sqlx::query_scalar("PRAGMA journal_mode").fetch_one(&mut *conn).await.unwrap();
+    assert_eq!(journal_mode, "wal");
+    for (pragma, expected) in [
+        ("synchronous", 1),
+        ("foreign_keys", 1),
+        ("busy_timeout", 2500),
+        ("cache_size", -8000),
+    ] {
+        let value: i64 = sqlx::query_scalar(&format!("PRAGMA {}", pragma))
+            .fetch_one(&mut *conn)
+            .await
+            .unwrap();
+        assert_eq!(value, expected, "PRAGMA {}", pragma);
+    }
+
+    let bad = test_figment().merge(("databases.sqlite_db.journal_mode", "sideways"));
+    assert!(database::connect(&bad).await.is_err());
+}
+
+/// Many connections writing at once used to end in `database is locked`
+#[rocket::async_test]
+async fn concurrent_writers_do_not_fail() {
+    use crate::models::{Priority, Status};
+    use crate::repository::{NewTodo, SqlTodoRepository, TodoRepository};
+
+    const WRITERS: usize = 16;
+    const TODOS: usize = 25;
+
+    let figment = test_figment().merge((format!("databases.{}.max_connections", database::DB_NAME), WRITERS));
+    let pool = database::connect(&figment).await.unwrap();
+    database::migrator().run(&pool).await.unwrap();
+    let user_id: i64 = sqlx::query_scalar(
+        "INSERT INTO users (username, email, password_hash) VALUES ('busy', 'busy@example.com', 'x') RETURNING id",
+    )
+    .fetch_all(&pool)
+    .await
+    .and_then(database::returned)
+    .unwrap();
+
+    let writers: Vec<_> = (0..WRITERS)
+        .map(|writer| {
+            let todos = SqlTodoRepository::new(pool.clone());
+            tokio::spawn(async move {
+                for i in 0..TODOS {
+                    let new = NewTodo {
+                        title: format!("writer {} todo {}", writer, i),
+                        description: None,
+                        status: Status::Pending,
+                        priority: Priority::Medium,
+                    };
+                    let todo = todos.create(user_id, new).await?;
+                    todos.delete(user_id, todo.id.unwrap()).await?;
+                    let new = NewTodo {
+                        title: format!("writer {} todo {}", writer, i),
+                        description: None,
+                        status: Status::InProgress,
+                        priority: Priority::High,
+                    };
+                    todos.create(user_id, new).await?;
+                    todos.list(user_id, Default::default()).await?;
+                }
+                Ok::<_, crate::repository::RepositoryError>(())
+            })
+        })
+        .collect();
+    for writer in writers {
+        writer.await.unwrap().unwrap();
+    }
+
+    let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM todos")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(count as usize, WRITERS * TODOS);
+}
@@ -0,0 +1,90 @@
+use rocket::http::{ContentType, Status};
+use rocket::local::asynchronous::LocalResponse;
+use utoipa::OpenApi;
+
+use super::{bearer, client, register, test_figment};
+
+async fn problem(response: LocalResponse<'_>) -> serde_json::Value {
+    let content_type = response.content_type().expect("content type");
+    assert_eq!(content_type.to_string(), "application/problem+json");
+
+    let request_id = response
+        .headers()
+        .get_one("x-request-id")
+        .map(str::to_string);
+    let body: serde_json::Value = response.into_json().await.expect("problem body");
+    assert_eq!(body["request_id"].as_str().map(str::to_string), request_id);
+
+    body
+}
+
+#[rocket::async_test]
+async fn missing_todo_is_a_problem_document() {
+    let client = client(test_figment()).await;
+    let registered = register(&client, "alice", "alice@example.com").await;
+    let token = registered["token"].as_str().unwrap();
+
+    let response = client.get("/api/todos/999").header(bearer(token)).dispatch().await;
+    assert_eq!(response.status(), Status::NotFound);
+
+    let body = problem(response).await;
+    assert_eq!(body["status"], 404);
+    assert_eq!(body["code"], "not_found");
+    assert_eq!(body["type"], "/problems/not_found");
+    assert_eq!(body["instance"], "/api/todos/999");
+}
+
+#[rocket::async_test]
+async fn auth_failures_have_distinct_codes() {
+    let client = client(test_figment()).await;
+
+    let response = client.get("/api/todos").dispatch().await;
+    assert_eq!(response.status(), Status::Unauthorized);
+    assert_eq!(problem(response).await["code"], "missing_token");
+
+    let response = client
+        .get("/api/todos")
+        .header(bearer("not-a-jwt"))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Unauthorized);
+    assert_eq!(problem(response).await["code"], "invalid_token");
+
+    let response = client
+        .post("/api/auth/login")
+        .header(ContentType::JSON)
+        .body(r#"{"email":"nobody@example.com","password":"x"}"#)
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Unauthorized);
+    assert_eq!(problem(response).await["code"], "invalid_credentials");
+}
+
+#[rocket::async_test]
+async fn catchers_render_problem_documents() {
+    let client = client(test_figment()).await;
+
+    let response = client.get("/no/such/route").dispatch().await;
+    assert_eq!(response.status(), Status::NotFound);
+    assert_eq!(problem(response).await["code"], "not_found");
+
+    let registered = register(&client, "bob", "bob@example.com").await;
+    let response = client
+        .post("/api/auth/api-keys")
+        .header(bearer(registered["token"].as_str().unwrap()))
+        .header(ContentType::JSON)
+        .body(r#"{"name": 42}"#)
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+    assert_eq!(problem(response).await["code"], "unprocessable_entity");
+}
+
+#[test]
+fn problem_schema_is_documented() {
+    let doc = serde_json::to_value(crate::routes::ApiDoc::openapi()).unwrap();
+    assert!(doc["components"]["schemas"]["ProblemDetails"].is_object());
+
+    let not_found = &doc["paths"]["/api/todos/{id}"]["get"]["responses"]["404"];
+    assert!(not_found["content"]["application/problem+json"].is_object());
+}
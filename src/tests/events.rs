@@ -0,0 +1,136 @@
+use std::sync::Arc;
+
+use rocket::http::{ContentType, Header, Status};
+use rocket::local::asynchronous::{Client, LocalResponse};
+use rocket_db_pools::Database;
+
+use super::{bearer, client, register, test_figment};
+use crate::database::{Db, DbPool};
+
+/// The `(event, id, data)` of each event in a finished stream
+async fn events(response: LocalResponse<'_>) -> Vec<(String, String, String)> {
+    let body = response.into_string().await.unwrap();
+    body.split("\n\n")
+        .filter_map(|block| {
+            let field = |name: &str| {
+                block
+                    .lines()
+                    .find_map(|line| line.strip_prefix(name).map(|value| value.trim_start().to_string()))
+            };
+            Some((field("event:")?, field("id:").unwrap_or_default(), field("data:")?))
+        })
+        .collect()
+}
+
+async fn create(client: &Client, token: &str, title: &str) -> serde_json::Value {
+    let response = client
+        .post("/api/todos")
+        .header(ContentType::JSON)
+        .header(bearer(token))
+        .body(serde_json::json!({ "title": title }).to_string())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Created);
+    response.into_json().await.unwrap()
+}
+
+#[rocket::async_test]
+async fn changes_stream_to_their_owner_only() {
+    let client = client(test_figment()).await;
+    let alice = register(&client, "alice", "alice@example.com").await;
+    let bob = register(&client, "bob", "bob@example.com").await;
+    let alice_token = alice["token"].as_str().unwrap();
+    let before = create(&client, alice_token, "before the stream").await;
+
+    let response = client.get("/api/events").header(bearer(alice_token)).dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.content_type(), Some(ContentType::EventStream));
+
+    let todo = create(&client, alice_token, "during the stream").await;
+    create(&client, bob["token"].as_str().unwrap(), "not for alice").await;
+    client
+        .put(format!("/api/todos/{}", todo["id"]))
+        .header(ContentType::JSON)
+        .header(bearer(alice_token))
+        .body(r#"{"status":"completed"}"#)
+        .dispatch()
+        .await;
+    client.delete(format!("/api/todos/{}", todo["id"])).header(bearer(alice_token)).dispatch().await;
+    // Changing nothing, or a missing todo, is no event
+    client.delete("/api/todos/9999").header(bearer(alice_token)).dispatch().await;
+    client
+        .put(format!("/api/todos/{}", before["id"]))
+        .header(ContentType::JSON)
+        .header(bearer(alice_token))
+        .body("{}")
+        .dispatch()
+        .await;
+
+    client.rocket().shutdown().notify();
+    let events = events(response).await;
+    let names: Vec<_> = events.iter().map(|(event, _, _)| event.as_str()).collect();
+    assert_eq!(names, ["todo.created", "todo.updated", "todo.deleted"]);
+
+    let created: serde_json::Value = serde_json::from_str(&events[0].2).unwrap();
+    assert_eq!(created["kind"], "created");
+    assert_eq!(created["todo_id"], todo["id"]);
+    assert_eq!(created["todo"]["title"], "during the stream");
+    assert_eq!(created["id"].to_string(), events[0].1);
+    assert!(created.get("user_id").is_none());
+    let updated: serde_json::Value = serde_json::from_str(&events[1].2).unwrap();
+    assert_eq!(updated["todo"]["status"], "completed");
+    let deleted: serde_json::Value = serde_json::from_str(&events[2].2).unwrap();
+    assert_eq!(deleted["todo"], serde_json::Value::Null);
+}
+
+#[rocket::async_test]
+async fn reconnecting_resumes_after_the_last_event() {
+    let client = client(test_figment()).await;
+    let alice = register(&client, "alice", "alice@example.com").await;
+    let token = alice["token"].as_str().unwrap();
+
+    let response = client.get("/api/events").header(bearer(token)).dispatch().await;
+    for title in ["one", "two", "three"] {
+        create(&client, token, title).await;
+    }
+    client.rocket().shutdown().notify();
+    let first = events(response).await;
+    assert_eq!(first.len(), 3);
+
+    let response = client
+        .get("/api/events")
+        .header(bearer(token))
+        .header(Header::new("Last-Event-ID", first[0].1.clone()))
+        .dispatch()
+        .await;
+    let resumed = events(response).await;
+    assert_eq!(resumed, first[1..]);
+
+    // Once the log no longer reaches back that far, the client must reload
+    let pool: DbPool = (**Db::fetch(client.rocket()).unwrap()).clone();
+    sqlx::query("DELETE FROM todo_events").execute(&pool).await.unwrap();
+    create(&client, token, "four").await;
+    let response = client
+        .get("/api/events")
+        .header(bearer(token))
+        .header(Header::new("Last-Event-ID", first[0].1.clone()))
+        .dispatch()
+        .await;
+    let resumed = events(response).await;
+    assert_eq!(resumed.len(), 1);
+    assert_eq!(resumed[0].0, "resync");
+    let latest = client.rocket().state::<Arc<crate::events::EventLog>>().unwrap().latest_id().await.unwrap();
+    assert_eq!(resumed[0].1, latest.to_string());
+
+    let response = client.get("/api/events").dispatch().await;
+    assert_eq!(response.status(), Status::Unauthorized);
+}
+
+#[rocket::async_test]
+async fn invalid_event_settings_refuse_to_ignite() {
+    let figment = test_figment().merge(("events.heartbeat_secs", 0));
+    let Err(error) = Client::tracked(crate::app(figment)).await else {
+        panic!("started without heartbeats");
+    };
+    assert!(matches!(error.kind(), rocket::error::ErrorKind::FailedFairings(_)));
+}
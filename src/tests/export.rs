@@ -0,0 +1,159 @@
+use std::time::Duration;
+
+use rocket::figment::Figment;
+use rocket::http::{ContentType, Status};
+use rocket::local::asynchronous::Client;
+
+use super::{bearer, register, test_figment, untracked_client as client};
+use crate::database;
+
+fn export_figment() -> Figment {
+    let dir = std::env::temp_dir().join(format!("todo-exports-{}", uuid::Uuid::new_v4()));
+    test_figment().merge(("export.dir", dir.display().to_string()))
+}
+
+async fn create_todo(client: &Client, token: &str, title: &str) {
+    let response = client
+        .post("/api/todos")
+        .header(bearer(token))
+        .header(ContentType::JSON)
+        .body(serde_json::json!({ "title": title }).to_string())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Created);
+}
+
+async fn poll_status(client: &Client, token: &str, status_url: &str, until: &str) -> serde_json::Value {
+    let mut export = serde_json::Value::Null;
+    for _ in 0..50 {
+        export = client
+            .get(status_url)
+            .header(bearer(token))
+            .dispatch()
+            .await
+            .into_json()
+            .await
+            .unwrap();
+        if export["status"] == until {
+            break;
+        }
+        rocket::tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    export
+}
+
+#[rocket::async_test]
+async fn small_accounts_export_inline() {
+    let client = client(export_figment()).await;
+    let registered = register(&client, "alice", "alice@example.com").await;
+    let token = registered["token"].as_str().unwrap();
+    create_todo(&client, token, "water the plants").await;
+
+    let response = client
+        .get("/api/users/me/export")
+        .header(bearer(token))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    let disposition = response.headers().get_one("Content-Disposition").unwrap();
+    assert!(disposition.starts_with("attachment;"));
+
+    let archive: serde_json::Value = response.into_json().await.unwrap();
+    assert_eq!(archive["profile"]["username"], "alice");
+    assert_eq!(archive["todos"][0]["title"], "water the plants");
+    assert_eq!(archive["sessions"].as_array().unwrap().len(), 1);
+}
+
+#[rocket::async_test]
+async fn background_export_has_expiring_download_link() {
+    let client = client(export_figment()).await;
+    let registered = register(&client, "bob", "bob@example.com").await;
+    let token = registered["token"].as_str().unwrap();
+    create_todo(&client, token, "file taxes").await;
+
+    let response = client
+        .get("/api/users/me/export?background=true")
+        .header(bearer(token))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Accepted);
+    let job: serde_json::Value = response.into_json().await.unwrap();
+    let status_url = format!("/api/users/me/exports/{}", job["id"].as_str().unwrap());
+
+    let export = poll_status(&client, token, &status_url, "ready").await;
+    assert_eq!(export["status"], "ready");
+
+    // Other accounts can't see the job
+    let other = register(&client, "mallory", "mallory@example.com").await;
+    let response = client
+        .get(status_url.as_str())
+        .header(bearer(other["token"].as_str().unwrap()))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::NotFound);
+
+    // The link works without credentials, but only with its signature intact
+    let download_url = export["download_url"].as_str().unwrap().to_string();
+    let response = client.get(format!("{}x", download_url)).dispatch().await;
+    assert_eq!(response.status(), Status::Forbidden);
+
+    let response = client.get(download_url).dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    let archive: serde_json::Value = response.into_json().await.unwrap();
+    assert_eq!(archive["todos"][0]["title"], "file taxes");
+}
+
+#[rocket::async_test]
+async fn failed_export_does_not_reveal_the_cause() {
+    // A file where the export directory should be makes the job fail
+    let blocker = std::env::temp_dir().join(format!("todo-exports-{}", uuid::Uuid::new_v4()));
+    std::fs::write(&blocker, b"").unwrap();
+    let client = client(test_figment().merge(("export.dir", blocker.display().to_string()))).await;
+    let registered = register(&client, "carol", "carol@example.com").await;
+    let token = registered["token"].as_str().unwrap();
+
+    let response = client
+        .get("/api/users/me/export?background=true")
+        .header(bearer(token))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Accepted);
+    let job: serde_json::Value = response.into_json().await.unwrap();
+    let status_url = format!("/api/users/me/exports/{}", job["id"].as_str().unwrap());
+
+    let export = poll_status(&client, token, &status_url, "failed").await;
+    assert_eq!(export["status"], "failed");
+    assert_eq!(export["error"], crate::export::FAILED_MESSAGE);
+}
+
+#[rocket::async_test]
+async fn unfinished_exports_resume_at_startup() {
+    let figment = export_figment();
+    let pool = database::connect(&figment).await.unwrap();
+    database::migrator().run(&pool).await.unwrap();
+    sqlx::query("INSERT INTO users (username, email, password_hash) VALUES ('dave', 'dave@example.com', 'x')")
+        .execute(&pool)
+        .await
+        .unwrap();
+    sqlx::query(
+        "INSERT INTO data_exports (id, user_id, status) SELECT 'interrupted', id, 'running' FROM users WHERE username = 'dave'",
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+
+    let _client = client(figment).await;
+
+    let mut status = String::new();
+    for _ in 0..50 {
+        status = sqlx::query_scalar("SELECT status FROM data_exports WHERE id = 'interrupted'")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        if status == "ready" {
+            break;
+        }
+        rocket::tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(status, "ready");
+}
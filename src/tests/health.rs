@@ -0,0 +1,168 @@
+use std::time::Duration;
+
+use rocket::http::Status;
+use rocket::local::asynchronous::Client;
+use rocket_db_pools::Database;
+
+use super::{admin_token, bearer, client, register, test_figment};
+use crate::database::Db;
+use crate::health::{Check, Health, HealthCheck};
+
+async fn get(client: &Client, uri: &'static str) -> (Status, serde_json::Value) {
+    let response = client.get(uri).dispatch().await;
+    (response.status(), response.into_json().await.unwrap())
+}
+
+async fn get_as(client: &Client, uri: &'static str, token: &str) -> (Status, serde_json::Value) {
+    let response = client.get(uri).header(bearer(token)).dispatch().await;
+    (response.status(), response.into_json().await.unwrap())
+}
+
+struct Fixed {
+    name: &'static str,
+    critical: bool,
+    check: Check,
+    delay: Duration,
+}
+
+#[rocket::async_trait]
+impl HealthCheck for Fixed {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn critical(&self) -> bool {
+        self.critical
+    }
+
+    async fn check(&self) -> Check {
+        tokio::time::sleep(self.delay).await;
+        self.check.clone()
+    }
+}
+
+#[rocket::async_test]
+async fn health_reports_each_check() {
+    let client = client(test_figment()).await;
+
+    let (status, body) = get(&client, "/health").await;
+    assert_eq!(status, Status::Ok);
+    assert_eq!(body["status"], "pass");
+    for check in ["database", "migrations", "disk", "background_jobs"] {
+        assert_eq!(body["checks"][check]["status"], "pass", "{}", check);
+        assert!(body["checks"][check]["duration_ms"].is_number());
+        assert!(body["checks"][check].get("data").is_none(), "{}", check);
+    }
+    assert_eq!(body["checks"]["database"]["critical"], true);
+
+    // What the checks found is for administrators
+    let root = admin_token(&client, "root").await;
+    let (_, body) = get_as(&client, "/health", &root).await;
+    assert_eq!(body["checks"]["background_jobs"]["data"]["export_cleanup"], "running");
+    #[cfg(not(feature = "postgres"))]
+    assert!(body["checks"]["disk"]["data"]["database"]["free_mb"].is_u64());
+    let alice = register(&client, "alice", "alice@example.com").await;
+    let (_, body) = get_as(&client, "/health", alice["token"].as_str().unwrap()).await;
+    assert!(body["checks"]["disk"].get("data").is_none());
+
+    // Only the critical checks decide readiness
+    let (status, body) = get(&client, "/ready").await;
+    assert_eq!(status, Status::Ok);
+    let checks = body["checks"].as_object().unwrap();
+    assert_eq!(checks.keys().collect::<Vec<_>>(), ["database", "migrations"]);
+}
+
+#[rocket::async_test]
+async fn low_disk_fails_health_but_not_readiness() {
+    let client = client(test_figment().merge(("health.min_free_mb", u64::MAX / (1024 * 1024)))).await;
+
+    let (status, body) = get(&client, "/health").await;
+    assert_eq!(status, Status::ServiceUnavailable);
+    assert_eq!(body["status"], "fail");
+    assert_eq!(body["checks"]["disk"]["status"], "fail");
+    assert_eq!(body["checks"]["database"]["status"], "pass");
+
+    let (status, _) = get(&client, "/ready").await;
+    assert_eq!(status, Status::Ok);
+}
+
+#[rocket::async_test]
+async fn not_ready_when_the_database_is_unreachable() {
+    let client = client(test_figment()).await;
+    Db::fetch(client.rocket()).unwrap().close().await;
+
+    let (status, body) = get(&client, "/ready").await;
+    assert_eq!(status, Status::ServiceUnavailable);
+    assert_eq!(body["status"], "not_ready");
+    assert_eq!(body["checks"]["database"]["status"], "fail");
+    assert!(body["checks"]["database"]["detail"].is_string());
+
+    let (status, _) = get(&client, "/health").await;
+    assert_eq!(status, Status::ServiceUnavailable);
+    let response = client.get("/live").dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+}
+
+#[rocket::async_test]
+async fn registered_checks_are_reported_and_timed_out() {
+    let client = client(test_figment().merge(("health.timeout_ms", 50))).await;
+    let health = client.rocket().state::<Health>().unwrap();
+    let root = admin_token(&client, "root").await;
+
+    health.register(Fixed {
+        name: "queue",
+        critical: false,
+        check: Check::warn("backlog of 120"),
+        delay: Duration::ZERO,
+    });
+    let (status, body) = get(&client, "/ready").await;
+    assert_eq!(status, Status::Ok);
+    assert!(body["checks"].get("queue").is_none());
+    let (status, body) = get(&client, "/health").await;
+    assert_eq!(status, Status::Ok);
+    assert_eq!(body["status"], "warn");
+    assert_eq!(body["checks"]["queue"]["detail"], "details are in the server log");
+    let (_, body) = get_as(&client, "/health", &root).await;
+    assert_eq!(body["checks"]["queue"]["detail"], "backlog of 120");
+
+    health.register(Fixed {
+        name: "upstream",
+        critical: true,
+        check: Check::pass(),
+        delay: Duration::from_secs(5),
+    });
+    let (status, body) = get_as(&client, "/ready", &root).await;
+    assert_eq!(status, Status::ServiceUnavailable);
+    assert_eq!(body["checks"]["upstream"]["status"], "fail");
+    assert_eq!(body["checks"]["upstream"]["detail"], "timed out after 50ms");
+}
+
+#[rocket::async_test]
+async fn readiness_is_withdrawn_at_shutdown() {
+    let client = client(test_figment()).await;
+    let (status, _) = get(&client, "/ready").await;
+    assert_eq!(status, Status::Ok);
+
+    client.rocket().shutdown().notify();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    let (status, body) = get(&client, "/ready").await;
+    assert_eq!(status, Status::ServiceUnavailable);
+    assert_eq!(body["status"], "shutting_down");
+    // Background jobs stop on purpose
+    let (_, body) = get(&client, "/health").await;
+    assert_eq!(body["checks"]["background_jobs"]["status"], "pass");
+}
+
+#[rocket::async_test]
+async fn invalid_health_settings_refuse_to_ignite() {
+    for figment in [
+        test_figment().merge(("health.timeout_ms", 0)),
+        test_figment().merge(("health.min_free_mb", "plenty")),
+    ] {
+        let Err(error) = Client::tracked(crate::app(figment)).await else {
+            panic!("started with unusable health settings");
+        };
+        assert!(matches!(error.kind(), rocket::error::ErrorKind::FailedFairings(_)));
+    }
+}
@@ -0,0 +1,145 @@
+use std::io;
+use std::sync::{Arc, Mutex};
+
+use rocket::http::{ContentType, Status};
+use rocket::local::asynchronous::Client;
+use tracing::Level;
+use tracing_subscriber::layer::SubscriberExt;
+
+use super::{admin_token, bearer, register, test_figment};
+use crate::telemetry::logging::{self, LogFormat, LogLevels, LoggingConfig};
+
+/// Collects everything a layer writes
+#[derive(Clone, Default)]
+struct Capture(Arc<Mutex<Vec<u8>>>);
+
+impl io::Write for Capture {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Capture {
+    fn output(&self) -> String {
+        String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+    }
+}
+
+#[test]
+fn secrets_are_scrubbed_in_every_format() {
+    for format in [LogFormat::Json, LogFormat::Pretty, LogFormat::Compact] {
+        let capture = Capture::default();
+        let writer = capture.clone();
+        let subscriber = tracing_subscriber::registry().with(logging::fmt_layer(format, move || writer.clone()));
+
+        tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!("http_request", request_id = "req-1", authorization = "Bearer abc.def.ghi");
+            let _entered = span.enter();
+            tracing::info!(
+                event = "login",
+                password = "hunter2",
+                email = %"alice@example.com",
+                cookie = "auth_token=s3ss10n; theme=dark",
+                "welcome back alice@example.com"
+            );
+            tracing::warn!(header = %"Authorization: Bearer xyz.123", "token=leaked in a message");
+        });
+
+        let output = capture.output();
+        for secret in ["hunter2", "alice@", "abc.def.ghi", "s3ss10n", "xyz.123", "leaked"] {
+            assert!(!output.contains(secret), "{:?} leaked {}: {}", format, secret, output);
+        }
+        assert!(output.contains("[REDACTED]"), "{}", output);
+        assert!(output.contains("***@example.com"), "{}", output);
+        assert!(output.contains("req-1") && output.contains("login"), "{}", output);
+    }
+}
+
+#[test]
+fn levels_come_from_the_logging_section() {
+    let figment = test_figment()
+        .merge(("logging.level", "warn"))
+        .merge(("logging.targets.my_rocket_app", "debug"))
+        .merge(("logging.targets.sqlx", "error"))
+        .merge(("logging.format", "compact"))
+        .merge(("logging.max_files", 3));
+    let config: LoggingConfig = figment.extract_inner("logging").unwrap();
+    assert_eq!(config.format, LogFormat::Compact);
+    // Merged over the targets in Rocket.toml
+    assert_eq!(config.directives(), "warn,my_rocket_app=debug,rocket=info,sqlx=error");
+    assert_eq!(LoggingConfig::default().directives(), "info,rocket=info,sqlx=warn");
+
+    let config = LoggingConfig {
+        dir: super::scratch_dir("logs"),
+        ..config
+    };
+    let mut appender = config.file_appender().unwrap();
+    io::Write::write_all(&mut appender, b"line\n").unwrap();
+    let files: Vec<_> = std::fs::read_dir(&config.dir).unwrap().map(|f| f.unwrap().file_name()).collect();
+    assert_eq!(files.len(), 1);
+    assert!(files[0].to_string_lossy().starts_with("app.log."));
+}
+
+#[test]
+fn log_level_changes_at_runtime() {
+    let (filter, levels) = LogLevels::filter("info");
+    assert!(levels.install(), "only this test installs log levels");
+    let subscriber = tracing_subscriber::registry().with(filter);
+
+    tracing::subscriber::with_default(subscriber, || {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let client = Client::untracked(crate::app(test_figment())).await.unwrap();
+            let user = register(&client, "mallory", "mallory@example.com").await;
+            let admin = admin_token(&client, "root").await;
+
+            let response = client
+                .put("/api/admin/log-level")
+                .header(bearer(user["token"].as_str().unwrap()))
+                .header(ContentType::JSON)
+                .body(r#"{"filter":"debug"}"#)
+                .dispatch()
+                .await;
+            assert_eq!(response.status(), Status::Forbidden);
+
+            let response = client.get("/api/admin/log-level").header(bearer(&admin)).dispatch().await;
+            assert_eq!(response.status(), Status::Ok);
+            let body: serde_json::Value = response.into_json().await.unwrap();
+            assert_eq!(body["filter"], body["configured"]);
+            assert!(!tracing::enabled!(target: "my_rocket_app::handlers", Level::DEBUG));
+
+            let response = client
+                .put("/api/admin/log-level")
+                .header(bearer(&admin))
+                .header(ContentType::JSON)
+                .body(r#"{"filter":" info,my_rocket_app=debug "}"#)
+                .dispatch()
+                .await;
+            assert_eq!(response.status(), Status::Ok);
+            let body: serde_json::Value = response.into_json().await.unwrap();
+            assert!(body["filter"].as_str().unwrap().contains("my_rocket_app=debug"));
+            assert!(tracing::enabled!(target: "my_rocket_app::handlers", Level::DEBUG));
+            assert!(!tracing::enabled!(target: "sqlx::query", Level::DEBUG));
+
+            let response = client
+                .put("/api/admin/log-level")
+                .header(bearer(&admin))
+                .header(ContentType::JSON)
+                .body(r#"{"filter":"info,my_rocket_app=loud"}"#)
+                .dispatch()
+                .await;
+            assert_eq!(response.status(), Status::UnprocessableEntity);
+            let body: serde_json::Value = response.into_json().await.unwrap();
+            assert_eq!(body["errors"][0]["field"], "filter");
+            assert!(tracing::enabled!(target: "my_rocket_app::handlers", Level::DEBUG));
+        });
+    });
+}
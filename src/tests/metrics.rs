@@ -0,0 +1,117 @@
+use rocket::http::{ContentType, Header, Status};
+use rocket::local::asynchronous::Client;
+
+use super::{bearer, client, register, test_figment};
+
+async fn scrape(client: &Client, from: &str, token: Option<&str>) -> (Status, String) {
+    let mut request = client.get("/metrics").remote(from.parse().unwrap());
+    if let Some(token) = token {
+        request = request.header(bearer(token));
+    }
+    let response = request.dispatch().await;
+    (response.status(), response.into_string().await.unwrap_or_default())
+}
+
+#[rocket::async_test]
+async fn metrics_are_labelled_by_route_template() {
+    let client = client(test_figment()).await;
+    let registered = register(&client, "alice", "alice@example.com").await;
+    let token = registered["token"].as_str().unwrap();
+
+    let response = client
+        .post("/api/todos")
+        .header(bearer(token))
+        .header(ContentType::JSON)
+        .body(r#"{"title":"measured"}"#)
+        .dispatch()
+        .await;
+    let todo: serde_json::Value = response.into_json().await.unwrap();
+    let id = todo["id"].as_i64().unwrap();
+    client
+        .get(format!("/api/todos/{}", id))
+        .header(bearer(token))
+        .dispatch()
+        .await;
+    client
+        .post("/api/auth/login")
+        .header(ContentType::JSON)
+        .body(r#"{"email":"alice@example.com","password":"not the password"}"#)
+        .dispatch()
+        .await;
+    client
+        .get("/api/todos")
+        .header(Header::new("Authorization", "Bearer not-a-jwt"))
+        .dispatch()
+        .await;
+
+    let (status, body) = scrape(&client, "127.0.0.1:9000", None).await;
+    assert_eq!(status, Status::Ok);
+    for expected in [
+        r#"http_requests_total{route="/api/todos/<id>",method="GET",status="200"} "#,
+        r#"http_request_duration_seconds_bucket{route="/api/todos",method="POST",status="201",le="+Inf"} "#,
+        r#"db_query_duration_seconds_count{operation="todos.create"} "#,
+        r#"auth_attempts_total{method="password",outcome="failure"} "#,
+        r#"auth_attempts_total{method="jwt",outcome="success"} "#,
+        r#"auth_attempts_total{method="jwt",outcome="failure"} "#,
+        "db_pool_max_connections ",
+        // Gauges come from this instance's database
+        "todos{status=\"pending\"} 1\n",
+        "todos{status=\"blocked\"} 0\n",
+        "users 1\n",
+    ] {
+        assert!(body.contains(expected), "missing {:?} in\n{}", expected, body);
+    }
+    assert!(!body.contains(&format!("/api/todos/{}\"", id)));
+}
+
+#[rocket::async_test]
+async fn metrics_need_an_allowed_address_and_the_token() {
+    let client = client(test_figment()).await;
+    assert_eq!(scrape(&client, "203.0.113.7:9000", None).await.0, Status::NotFound);
+    assert_eq!(client.get("/metrics").dispatch().await.status(), Status::NotFound);
+
+    let figment = test_figment()
+        .merge(("metrics.token", "scrape-secret"))
+        .merge(("metrics.allow_from", Vec::<String>::new()));
+    let client = super::client(figment).await;
+    assert_eq!(scrape(&client, "203.0.113.7:9000", None).await.0, Status::NotFound);
+    assert_eq!(scrape(&client, "203.0.113.7:9000", Some("wrong")).await.0, Status::NotFound);
+    assert_eq!(scrape(&client, "203.0.113.7:9000", Some("scrape-secret")).await.0, Status::Ok);
+}
+
+/// Scrapes from `from`, claiming to be forwarded for `real_ip`
+async fn scrape_forwarded(client: &Client, from: &str, real_ip: &str) -> Status {
+    let request = client
+        .get("/metrics")
+        .remote(from.parse().unwrap())
+        .header(Header::new("X-Real-IP", real_ip.to_string()));
+    request.dispatch().await.status()
+}
+
+#[rocket::async_test]
+async fn forwarded_addresses_count_only_from_trusted_proxies() {
+    let client = client(test_figment()).await;
+    assert_eq!(scrape_forwarded(&client, "203.0.113.7:9000", "127.0.0.1").await, Status::NotFound);
+
+    let figment = test_figment().merge(("app.trusted_proxies", ["10.0.0.2"]));
+    let client = super::client(figment).await;
+    assert_eq!(scrape_forwarded(&client, "10.0.0.2:9000", "127.0.0.1").await, Status::Ok);
+    assert_eq!(scrape_forwarded(&client, "10.0.0.2:9000", "203.0.113.7").await, Status::NotFound);
+    assert_eq!(scrape_forwarded(&client, "203.0.113.7:9000", "127.0.0.1").await, Status::NotFound);
+}
+
+#[rocket::async_test]
+async fn listening_beyond_loopback_requires_a_token() {
+    let figment = test_figment().merge(("address", "0.0.0.0"));
+    let Err(error) = Client::tracked(crate::app(figment)).await else {
+        panic!("served metrics to every host without a token");
+    };
+    assert!(matches!(error.kind(), rocket::error::ErrorKind::FailedFairings(_)));
+
+    let figment = test_figment()
+        .merge(("address", "0.0.0.0"))
+        .merge(("metrics.token", "scrape-secret"));
+    let client = super::client(figment).await;
+    assert_eq!(scrape(&client, "127.0.0.1:9000", None).await.0, Status::NotFound);
+    assert_eq!(scrape(&client, "127.0.0.1:9000", Some("scrape-secret")).await.0, Status::Ok);
+}
@@ -0,0 +1,166 @@
+mod admin;
+mod api_keys;
+mod backup;
+mod config;
+mod cors;
+mod database;
+mod errors;
+mod events;
+mod logging;
+mod export;
+mod health;
+mod metrics;
+mod oidc;
+mod quotas;
+mod rate_limit;
+mod realtime;
+mod security;
+mod services;
+mod telemetry;
+mod todos;
+mod users;
+mod validation;
+mod webhooks;
+
+use rocket::figment::Figment;
+use rocket::http::{ContentType, Header, Status};
+use rocket::local::asynchronous::Client;
+
+/// Configuration for a test instance backed by its own throwaway database
+#[cfg(not(feature = "postgres"))]
+pub fn test_figment() -> Figment {
+    let db = std::env::temp_dir().join(format!("todo-test-{}.db", uuid::Uuid::new_v4()));
+
+    rocket::Config::figment()
+        .merge(("databases.sqlite_db.url", format!("sqlite:{}", db.display())))
+        .merge(("log_level", "off"))
+        .merge(("mailer.outbox_dir", outbox_dir()))
+        .merge(("backup.dir", scratch_dir("backup")))
+        // Free space on the test machine is not under test
+        .merge(("health.min_free_mb", 0))
+        // Tests that need limits turn them on
+        .merge(("rate_limit.enabled", false))
+}
+
+/// Configuration for a test instance backed by its own throwaway database,
+/// created on the server at `TEST_POSTGRES_URL` and dropped when the test
+/// binary exits
+#[cfg(feature = "postgres")]
+pub fn test_figment() -> Figment {
+    static CLEANUP: std::sync::Once = std::sync::Once::new();
+    CLEANUP.call_once(|| {
+        // SAFETY: registers a plain function with no captured state
+        unsafe { libc::atexit(drop_test_databases) };
+    });
+
+    let name = format!("todo_test_{}", uuid::Uuid::new_v4().simple());
+    on_test_server(format!("CREATE DATABASE {}", name))
+        .expect("test database created; is TEST_POSTGRES_URL reachable?");
+    TEST_DATABASES.lock().unwrap().push(name.clone());
+
+    rocket::Config::figment()
+        .merge(("databases.postgres_db.url", format!("{}/{}", test_server(), name)))
+        .merge(("log_level", "off"))
+        .merge(("mailer.outbox_dir", outbox_dir()))
+        .merge(("backup.dir", scratch_dir("backup")))
+        // Free space on the test machine is not under test
+        .merge(("health.min_free_mb", 0))
+        // Tests that need limits turn them on
+        .merge(("rate_limit.enabled", false))
+}
+
+#[cfg(feature = "postgres")]
+static TEST_DATABASES: std::sync::Mutex<Vec<String>> = std::sync::Mutex::new(Vec::new());
+
+#[cfg(feature = "postgres")]
+fn test_server() -> String {
+    std::env::var("TEST_POSTGRES_URL").unwrap_or_else(|_| "postgres://postgres@127.0.0.1:5432".to_string())
+}
+
+/// Runs a statement against the server's maintenance database
+#[cfg(feature = "postgres")]
+fn on_test_server(statement: String) -> Result<(), sqlx::Error> {
+    use sqlx::{Connection, Executor, PgConnection};
+
+    // Tests call this from inside their runtime, which cannot be blocked on
+    let maintenance_url = format!("{}/postgres", test_server());
+    std::thread::spawn(move || {
+        tokio::runtime::Runtime::new().unwrap().block_on(async {
+            let mut conn = PgConnection::connect(&maintenance_url).await?;
+            conn.execute(statement.as_str()).await.map(|_| ())
+        })
+    })
+    .join()
+    .unwrap()
+}
+
+#[cfg(feature = "postgres")]
+extern "C" fn drop_test_databases() {
+    let names = std::mem::take(&mut *TEST_DATABASES.lock().unwrap());
+    for name in names {
+        // Pools of finished tests may still hold connections
+        if let Err(e) = on_test_server(format!("DROP DATABASE IF EXISTS {} WITH (FORCE)", name)) {
+            eprintln!("could not drop test database {}: {}", name, e);
+        }
+    }
+}
+
+fn outbox_dir() -> std::path::PathBuf {
+    scratch_dir("mail")
+}
+
+fn scratch_dir(kind: &str) -> std::path::PathBuf {
+    std::env::temp_dir().join(format!("todo-test-{}-{}", kind, uuid::Uuid::new_v4()))
+}
+
+pub async fn client(figment: Figment) -> Client {
+    Client::tracked(crate::app(figment))
+        .await
+        .expect("valid rocket instance")
+}
+
+/// A client that ignores `Set-Cookie`, for tests that juggle several tokens
+/// (requests without an `Authorization` header would otherwise fall back to
+/// the last `auth_token` cookie)
+pub async fn untracked_client(figment: Figment) -> Client {
+    Client::untracked(crate::app(figment))
+        .await
+        .expect("valid rocket instance")
+}
+
+/// Registers a user and returns the response body
+pub async fn register(client: &Client, username: &str, email: &str) -> serde_json::Value {
+    let response = client
+        .post("/api/auth/register")
+        .header(ContentType::JSON)
+        .body(
+            serde_json::json!({
+                "username": username,
+                "email": email,
+                "password": "correct horse battery staple"
+            })
+            .to_string(),
+        )
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Created);
+
+    response.into_json().await.expect("json body")
+}
+
+/// Registers a user, promotes them and returns their session token
+pub async fn admin_token(client: &Client, username: &str) -> String {
+    use rocket_db_pools::Database;
+
+    let registered = register(client, username, &format!("{}@example.com", username)).await;
+    let db = crate::database::Db::fetch(client.rocket()).unwrap();
+    let users = crate::repository::SqlUserRepository::new((**db).clone());
+    crate::admin::set_role(&users, username, crate::models::Role::Admin)
+        .await
+        .unwrap();
+    registered["token"].as_str().unwrap().to_string()
+}
+
+pub fn bearer(token: &str) -> Header<'static> {
+    Header::new("Authorization", format!("Bearer {}", token))
+}
@@ -0,0 +1,380 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
+use rocket::figment::Figment;
+use rocket::form::Form;
+use rocket::http::{ContentType, Status};
+use rocket::response::Redirect;
+use rocket::serde::json::Json;
+use rocket::State;
+use rocket_db_pools::Database;
+use sha2::{Digest, Sha256};
+
+use super::{bearer, client, register, test_figment};
+use crate::database::Db;
+
+const CLIENT_ID: &str = "todo-api";
+const CLIENT_SECRET: &str = "mock-secret";
+const SIGNING_KEY: &[u8] = b"mock-idp-signing-key-for-tests-only";
+
+/// The user the mock provider authenticates
+#[derive(Clone)]
+struct MockIdentity {
+    sub: String,
+    email: String,
+    email_verified: bool,
+}
+
+struct MockIdp {
+    issuer: String,
+    identity: MockIdentity,
+    /// authorization code -> (nonce, code_challenge)
+    codes: Mutex<HashMap<String, (String, String)>>,
+}
+
+#[get("/.well-known/openid-configuration")]
+fn discovery(idp: &State<MockIdp>) -> Json<serde_json::Value> {
+    Json(serde_json::json!({
+        "issuer": idp.issuer,
+        "authorization_endpoint": format!("{}/authorize", idp.issuer),
+        "token_endpoint": format!("{}/token", idp.issuer),
+        "jwks_uri": format!("{}/jwks", idp.issuer),
+    }))
+}
+
+#[get("/jwks")]
+fn jwks() -> Json<serde_json::Value> {
+    Json(serde_json::json!({
+        "keys": [{ "kty": "oct", "kid": "mock", "alg": "HS256", "k": URL_SAFE_NO_PAD.encode(SIGNING_KEY) }]
+    }))
+}
+
+#[get("/authorize?<redirect_uri>&<state>&<nonce>&<code_challenge>&<code_challenge_method>")]
+fn authorize(
+    idp: &State<MockIdp>,
+    redirect_uri: &str,
+    state: &str,
+    nonce: &str,
+    code_challenge: &str,
+    code_challenge_method: &str,
+) -> Redirect {
+    assert_eq!(code_challenge_method, "S256");
+
+    let code = uuid::Uuid::new_v4().to_string();
+    idp.codes
+        .lock()
+        .unwrap()
+        .insert(code.clone(), (nonce.to_string(), code_challenge.to_string()));
+
+    Redirect::to(format!("{}?code={}&state={}", redirect_uri, code, state))
+}
+
+#[derive(FromForm)]
+struct TokenForm<'r> {
+    grant_type: &'r str,
+    code: &'r str,
+    client_id: &'r str,
+    client_secret: Option<&'r str>,
+    code_verifier: &'r str,
+    #[allow(dead_code)]
+    redirect_uri: &'r str,
+}
+
+#[post("/token", data = "<form>")]
+fn token(idp: &State<MockIdp>, form: Form<TokenForm<'_>>) -> Result<Json<serde_json::Value>, Status> {
+    if form.grant_type != "authorization_code"
+        || form.client_id != CLIENT_ID
+        || form.client_secret != Some(CLIENT_SECRET)
+    {
+        return Err(Status::Unauthorized);
+    }
+
+    let (nonce, challenge) = idp
+        .codes
+        .lock()
+        .unwrap()
+        .remove(form.code)
+        .ok_or(Status::BadRequest)?;
+
+    // PKCE: the verifier must hash to the challenge sent to /authorize
+    if URL_SAFE_NO_PAD.encode(Sha256::digest(form.code_verifier.as_bytes())) != challenge {
+        return Err(Status::BadRequest);
+    }
+
+    let now = chrono::Utc::now().timestamp();
+    let claims = serde_json::json!({
+        "iss": idp.issuer,
+        "aud": CLIENT_ID,
+        "sub": idp.identity.sub,
+        "email": idp.identity.email,
+        "email_verified": idp.identity.email_verified,
+        "preferred_username": idp.identity.email.split('@').next(),
+        "nonce": nonce,
+        "iat": now,
+        "exp": now + 300,
+    });
+
+    let mut header = Header::new(Algorithm::HS256);
+    header.kid = Some("mock".into());
+    let id_token = encode(&header, &claims, &EncodingKey::from_secret(SIGNING_KEY)).unwrap();
+
+    Ok(Json(serde_json::json!({
+        "access_token": "mock-access-token",
+        "token_type": "Bearer",
+        "id_token": id_token,
+    })))
+}
+
+/// Launches a mock provider on a free local port and returns its issuer URL
+async fn start_mock_idp(identity: MockIdentity) -> String {
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let issuer = format!("http://127.0.0.1:{}", port);
+
+    let config = rocket::Config {
+        port,
+        log_level: rocket::config::LogLevel::Off,
+        ..rocket::Config::debug_default()
+    };
+    let idp = MockIdp {
+        issuer: issuer.clone(),
+        identity,
+        codes: Mutex::new(HashMap::new()),
+    };
+    let mock = rocket::custom(config)
+        .manage(idp)
+        .mount("/", routes![discovery, jwks, authorize, token]);
+    tokio::spawn(mock.launch());
+
+    for _ in 0..50 {
+        if tokio::net::TcpStream::connect(("127.0.0.1", port)).await.is_ok() {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+
+    issuer
+}
+
+fn with_provider(figment: Figment, issuer: &str) -> Figment {
+    figment
+        .merge(("oidc.providers.mock.issuer", issuer))
+        .merge(("oidc.providers.mock.client_id", CLIENT_ID))
+        .merge(("oidc.providers.mock.client_secret", CLIENT_SECRET))
+        .merge((
+            "oidc.providers.mock.redirect_uri",
+            "http://127.0.0.1/api/auth/oidc/mock/callback",
+        ))
+}
+
+/// Runs the browser side of the flow: app login -> provider -> app callback
+async fn sign_in(client: &rocket::local::asynchronous::Client) -> (Status, serde_json::Value) {
+    let response = client.get("/api/auth/oidc/mock/login").dispatch().await;
+    assert_eq!(response.status(), Status::SeeOther);
+    let authorize_url = response.headers().get_one("Location").unwrap().to_string();
+
+    let http = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap();
+    let provider_response = http.get(&authorize_url).send().await.unwrap();
+    let callback = reqwest::Url::parse(
+        provider_response
+            .headers()
+            .get("location")
+            .unwrap()
+            .to_str()
+            .unwrap(),
+    )
+    .unwrap();
+
+    let response = client
+        .get(format!("{}?{}", callback.path(), callback.query().unwrap()))
+        .dispatch()
+        .await;
+    let status = response.status();
+    (status, response.into_json().await.unwrap_or_default())
+}
+
+#[rocket::async_test]
+async fn oidc_login_creates_user_and_reuses_identity() {
+    let issuer = start_mock_idp(MockIdentity {
+        sub: "idp-user-1".into(),
+        email: "ada@company.test".into(),
+        email_verified: true,
+    })
+    .await;
+    let client = client(with_provider(test_figment(), &issuer)).await;
+
+    let (status, body) = sign_in(&client).await;
+    assert_eq!(status, Status::Ok);
+    assert_eq!(body["user"]["email"], "ada@company.test");
+    assert_eq!(body["user"]["username"], "ada");
+    let user_id = body["user"]["id"].clone();
+
+    // The issued token is a regular app JWT
+    let token = body["token"].as_str().unwrap();
+    let me = client.get("/api/auth/me").header(bearer(token)).dispatch().await;
+    assert_eq!(me.status(), Status::Ok);
+
+    // Logging in again resolves the linked identity to the same user
+    let (status, body) = sign_in(&client).await;
+    assert_eq!(status, Status::Ok);
+    assert_eq!(body["user"]["id"], user_id);
+}
+
+async fn password_login(client: &rocket::local::asynchronous::Client, email: &str) -> Status {
+    client
+        .post("/api/auth/login")
+        .header(ContentType::JSON)
+        .body(serde_json::json!({ "email": email, "password": "correct horse battery staple" }).to_string())
+        .dispatch()
+        .await
+        .status()
+}
+
+/// Registration does not prove the email, so the provider's verified claim
+/// wins: the password and every existing session and key stop working
+#[rocket::async_test]
+async fn oidc_login_takes_over_unverified_account() {
+    let issuer = start_mock_idp(MockIdentity {
+        sub: "idp-user-2".into(),
+        email: "grace@company.test".into(),
+        email_verified: true,
+    })
+    .await;
+    let client = client(with_provider(test_figment(), &issuer)).await;
+    let registered = register(&client, "grace", "grace@company.test").await;
+    let user_id = registered["user"]["id"].as_i64().unwrap();
+    let response = client
+        .post("/api/auth/api-keys")
+        .header(bearer(registered["token"].as_str().unwrap()))
+        .header(ContentType::JSON)
+        .body(r#"{"name":"planted"}"#)
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Created);
+
+    let (status, body) = sign_in(&client).await;
+    assert_eq!(status, Status::Ok);
+    assert_eq!(body["user"]["id"], registered["user"]["id"]);
+    assert_eq!(body["user"]["username"], "grace");
+
+    assert_eq!(password_login(&client, "grace@company.test").await, Status::Unauthorized);
+
+    let db = Db::fetch(client.rocket()).unwrap();
+    let active = sqlx::query_scalar!(
+        r#"SELECT COUNT(*) AS "count!: i64" FROM sessions WHERE user_id = $1 AND revoked_at IS NULL"#,
+        user_id
+    )
+    .fetch_one(&**db)
+    .await
+    .unwrap();
+    assert_eq!(active, 1, "only the session just started by the provider");
+    let keys = sqlx::query_scalar!(
+        r#"SELECT COUNT(*) AS "count!: i64" FROM api_keys WHERE user_id = $1 AND revoked_at IS NULL"#,
+        user_id
+    )
+    .fetch_one(&**db)
+    .await
+    .unwrap();
+    assert_eq!(keys, 0);
+}
+
+#[rocket::async_test]
+async fn oidc_login_links_verified_account_as_is() {
+    let issuer = start_mock_idp(MockIdentity {
+        sub: "idp-user-5".into(),
+        email: "hopper@company.test".into(),
+        email_verified: true,
+    })
+    .await;
+    let client = client(with_provider(test_figment(), &issuer)).await;
+    let registered = register(&client, "hopper", "hopper@company.test").await;
+
+    let db = Db::fetch(client.rocket()).unwrap();
+    sqlx::query!(
+        "UPDATE users SET email_verified_at = CURRENT_TIMESTAMP WHERE email = $1",
+        "hopper@company.test"
+    )
+    .execute(&**db)
+    .await
+    .unwrap();
+
+    let (status, body) = sign_in(&client).await;
+    assert_eq!(status, Status::Ok);
+    assert_eq!(body["user"]["id"], registered["user"]["id"]);
+    assert_eq!(password_login(&client, "hopper@company.test").await, Status::Ok);
+}
+
+#[rocket::async_test]
+async fn oidc_login_rejects_unverified_email() {
+    let issuer = start_mock_idp(MockIdentity {
+        sub: "idp-user-3".into(),
+        email: "mallory@company.test".into(),
+        email_verified: false,
+    })
+    .await;
+    let client = client(with_provider(test_figment(), &issuer)).await;
+    register(&client, "victim", "mallory@company.test").await;
+
+    let (status, _) = sign_in(&client).await;
+    assert_eq!(status, Status::Forbidden);
+}
+
+#[rocket::async_test]
+async fn oidc_callback_rejects_state_mismatch() {
+    let issuer = start_mock_idp(MockIdentity {
+        sub: "idp-user-4".into(),
+        email: "eve@company.test".into(),
+        email_verified: true,
+    })
+    .await;
+    let client = client(with_provider(test_figment(), &issuer)).await;
+
+    let response = client.get("/api/auth/oidc/mock/login").dispatch().await;
+    assert_eq!(response.status(), Status::SeeOther);
+
+    let response = client
+        .get("/api/auth/oidc/mock/callback?code=stolen&state=forged")
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::BadRequest);
+}
+
+#[rocket::async_test]
+async fn oidc_unknown_provider_is_not_found() {
+    let client = client(test_figment()).await;
+
+    let response = client.get("/api/auth/oidc/nope/login").dispatch().await;
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+#[rocket::async_test]
+async fn oidc_provider_that_hangs_fails_without_leaking_details() {
+    // Accepts connections and never answers
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let issuer = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+        let mut held = Vec::new();
+        while let Ok((stream, _)) = listener.accept().await {
+            held.push(stream);
+        }
+    });
+    let figment = with_provider(test_figment(), &issuer).merge(("oidc.timeout_secs", 1));
+    let client = client(figment).await;
+
+    let started = std::time::Instant::now();
+    let response = client.get("/api/auth/oidc/mock/login").dispatch().await;
+    assert_eq!(response.status(), Status::BadGateway);
+    assert!(started.elapsed() < std::time::Duration::from_secs(5));
+    let problem: serde_json::Value = response.into_json().await.unwrap();
+    let detail = problem["detail"].as_str().unwrap();
+    assert!(!detail.contains("127.0.0.1") && !detail.contains("timed out"), "{}", detail);
+}
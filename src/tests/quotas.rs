@@ -0,0 +1,145 @@
+use rocket::http::{ContentType, Status};
+use rocket::local::asynchronous::Client;
+
+use super::{admin_token, bearer, client, register, test_figment};
+
+async fn post_todo(client: &Client, token: &str, body: serde_json::Value) -> (Status, serde_json::Value) {
+    let response = client
+        .post("/api/todos")
+        .header(ContentType::JSON)
+        .header(bearer(token))
+        .body(body.to_string())
+        .dispatch()
+        .await;
+    (response.status(), response.into_json().await.unwrap())
+}
+
+async fn usage(client: &Client, token: &str) -> serde_json::Value {
+    let response = client.get("/api/users/me/usage").header(bearer(token)).dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    response.into_json().await.unwrap()
+}
+
+async fn set_quota(client: &Client, admin: &str, user_id: &serde_json::Value, body: &str) -> (Status, serde_json::Value) {
+    let response = client
+        .put(format!("/api/admin/users/{}/quota", user_id))
+        .header(ContentType::JSON)
+        .header(bearer(admin))
+        .body(body)
+        .dispatch()
+        .await;
+    (response.status(), response.into_json().await.unwrap())
+}
+
+#[rocket::async_test]
+async fn writes_past_a_quota_are_refused() {
+    let client = client(
+        test_figment()
+            .merge(("app.quotas.max_todos", 2))
+            .merge(("app.quotas.max_description_len", 5))
+            .merge(("app.quotas.max_storage_bytes", 20)),
+    )
+    .await;
+    let registered = register(&client, "alice", "alice@example.com").await;
+    let token = registered["token"].as_str().unwrap();
+
+    let (status, problem) = post_todo(&client, token, serde_json::json!({ "title": "a", "description": "123456" })).await;
+    assert_eq!(status, Status::Forbidden);
+    assert_eq!(problem["code"], "description_quota_exceeded");
+
+    // "é" is one character but two bytes
+    let (status, todo) = post_todo(&client, token, serde_json::json!({ "title": "todo", "description": "ééééé" })).await;
+    assert_eq!(status, Status::Created);
+    let (status, problem) = post_todo(&client, token, serde_json::json!({ "title": "x".repeat(7) })).await;
+    assert_eq!(status, Status::Forbidden);
+    assert_eq!(problem["code"], "storage_quota_exceeded");
+
+    let response = client
+        .put(format!("/api/todos/{}", todo["id"]))
+        .header(ContentType::JSON)
+        .header(bearer(token))
+        .body(r#"{"title":"a much longer title"}"#)
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Forbidden);
+
+    let (status, _) = post_todo(&client, token, serde_json::json!({ "title": "second" })).await;
+    assert_eq!(status, Status::Created);
+    let (status, problem) = post_todo(&client, token, serde_json::json!({ "title": "third" })).await;
+    assert_eq!(status, Status::Forbidden);
+    assert_eq!(problem["code"], "todo_quota_exceeded");
+    assert_eq!(problem["detail"], "You have reached your limit of 2 todos");
+
+    let usage = usage(&client, token).await;
+    assert_eq!(usage["todos"], serde_json::json!({ "used": 2, "limit": 2 }));
+    assert_eq!(usage["storage_bytes"], serde_json::json!({ "used": 20, "limit": 20 }));
+    assert_eq!(usage["max_description_len"], 5);
+    assert_eq!(usage["overridden"], false);
+
+    // Deleting makes room again
+    let response = client.delete(format!("/api/todos/{}", todo["id"])).header(bearer(token)).dispatch().await;
+    assert_eq!(response.status(), Status::NoContent);
+    let (status, _) = post_todo(&client, token, serde_json::json!({ "title": "third" })).await;
+    assert_eq!(status, Status::Created);
+}
+
+#[rocket::async_test]
+async fn administrators_override_quotas_per_user() {
+    let client = client(test_figment().merge(("app.quotas.max_todos", 1))).await;
+    let admin = admin_token(&client, "root").await;
+    let alice = register(&client, "alice", "alice@example.com").await;
+    let bob = register(&client, "bob", "bob@example.com").await;
+    let alice_token = alice["token"].as_str().unwrap();
+
+    let (status, body) = set_quota(&client, &admin, &alice["user"]["id"], r#"{"max_todos": 2}"#).await;
+    assert_eq!(status, Status::Ok);
+    assert_eq!(body["overrides"]["max_todos"], 2);
+    assert_eq!(body["usage"]["todos"]["limit"], 2);
+    assert_eq!(body["usage"]["max_description_len"], 2000);
+
+    for title in ["one", "two"] {
+        let (status, _) = post_todo(&client, alice_token, serde_json::json!({ "title": title })).await;
+        assert_eq!(status, Status::Created);
+    }
+    let usage = usage(&client, alice_token).await;
+    assert_eq!(usage["todos"], serde_json::json!({ "used": 2, "limit": 2 }));
+    assert_eq!(usage["overridden"], true);
+
+    // Bob keeps the default
+    let bob_token = bob["token"].as_str().unwrap();
+    post_todo(&client, bob_token, serde_json::json!({ "title": "one" })).await;
+    let (status, _) = post_todo(&client, bob_token, serde_json::json!({ "title": "two" })).await;
+    assert_eq!(status, Status::Forbidden);
+
+    // Removing the override leaves Alice over the default, which only stops new todos
+    let (status, body) = set_quota(&client, &admin, &alice["user"]["id"], "{}").await;
+    assert_eq!(status, Status::Ok);
+    assert_eq!(body["usage"]["todos"], serde_json::json!({ "used": 2, "limit": 1 }));
+    assert_eq!(body["usage"]["overridden"], false);
+    let (status, _) = post_todo(&client, alice_token, serde_json::json!({ "title": "three" })).await;
+    assert_eq!(status, Status::Forbidden);
+
+    let response = client
+        .get(format!("/api/admin/users/{}/quota", alice["user"]["id"]))
+        .header(bearer(&admin))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+
+    let (status, problem) = set_quota(&client, &admin, &alice["user"]["id"], r#"{"max_todos": -1, "max_description_len": 10001}"#).await;
+    assert_eq!(status, Status::UnprocessableEntity);
+    assert_eq!(problem["errors"].as_array().unwrap().len(), 2);
+    let (status, _) = set_quota(&client, &admin, &serde_json::json!(9999), "{}").await;
+    assert_eq!(status, Status::NotFound);
+    let (status, _) = set_quota(&client, alice_token, &alice["user"]["id"], r#"{"max_todos": 100}"#).await;
+    assert_eq!(status, Status::Forbidden);
+}
+
+#[rocket::async_test]
+async fn invalid_quotas_refuse_to_ignite() {
+    let figment = test_figment().merge(("app.quotas.max_description_len", 20_000));
+    let Err(error) = Client::tracked(crate::app(figment)).await else {
+        panic!("started with a description quota above the maximum");
+    };
+    assert!(matches!(error.kind(), rocket::error::ErrorKind::FailedFairings(_)));
+}
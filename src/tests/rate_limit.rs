@@ -0,0 +1,162 @@
+use std::time::{Duration, Instant};
+
+use rocket::figment::Figment;
+use rocket::http::{ContentType, Header, Status};
+use rocket::local::asynchronous::Client;
+
+use super::{bearer, client, register, test_figment};
+use crate::rate_limit::{Limit, MemoryStore};
+
+fn limited_figment() -> Figment {
+    test_figment()
+        .merge(("rate_limit.enabled", true))
+        .merge(("rate_limit.reads", Limit { burst: 3, per_minute: 60 }))
+        .merge(("rate_limit.writes", Limit { burst: 2, per_minute: 60 }))
+        .merge(("rate_limit.auth", Limit { burst: 2, per_minute: 1 }))
+}
+
+fn header<'a>(response: &'a rocket::local::asynchronous::LocalResponse<'_>, name: &str) -> Option<&'a str> {
+    response.headers().get_one(name)
+}
+
+/// Signs in from `from`, with an `X-Real-IP` claim when given
+async fn login_from(client: &Client, from: &str, real_ip: Option<&str>) -> Status {
+    let mut request = client
+        .post("/api/auth/login")
+        .remote(format!("{}:4000", from).parse().unwrap())
+        .header(ContentType::JSON)
+        .body(r#"{"email":"nobody@example.com","password":"guess"}"#);
+    if let Some(real_ip) = real_ip {
+        request = request.header(Header::new("X-Real-IP", real_ip.to_string()));
+    }
+    request.dispatch().await.status()
+}
+
+#[test]
+fn buckets_refill_over_time() {
+    let store = MemoryStore::default();
+    let limit = Limit { burst: 2, per_minute: 60 };
+    let start = Instant::now();
+
+    assert_eq!(store.take_at("k", limit, start).remaining, 1);
+    let last = store.take_at("k", limit, start);
+    assert!(last.allowed);
+    assert_eq!((last.remaining, last.reset_secs), (0, 2));
+
+    let refused = store.take_at("k", limit, start);
+    assert!(!refused.allowed);
+    assert_eq!(refused.retry_after_secs, 1);
+    assert!(store.take_at("other", limit, start).allowed);
+
+    assert!(store.take_at("k", limit, start + Duration::from_secs(1)).allowed);
+    assert!(!store.take_at("k", limit, start + Duration::from_secs(1)).allowed);
+    // Never beyond the burst, however long the bucket was idle
+    let later = start + Duration::from_secs(3600);
+    assert_eq!(store.take_at("k", limit, later).remaining, 1);
+}
+
+#[rocket::async_test]
+async fn exhausted_bucket_answers_429() {
+    let client = client(limited_figment()).await;
+    let registered = register(&client, "alice", "alice@example.com").await;
+    let token = registered["token"].as_str().unwrap();
+
+    for remaining in ["2", "1", "0"] {
+        let response = client.get("/api/todos").header(bearer(token)).dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(header(&response, "RateLimit-Limit"), Some("3"));
+        assert_eq!(header(&response, "RateLimit-Remaining"), Some(remaining));
+        assert!(header(&response, "RateLimit-Reset").is_some());
+        assert_eq!(header(&response, "RateLimit-Policy"), Some("3;w=3"));
+        assert_eq!(header(&response, "Retry-After"), None);
+    }
+
+    let response = client.get("/api/todos").header(bearer(token)).dispatch().await;
+    assert_eq!(response.status(), Status::TooManyRequests);
+    assert_eq!(header(&response, "Retry-After"), Some("1"));
+    assert_eq!(header(&response, "RateLimit-Remaining"), Some("0"));
+    let problem: serde_json::Value = response.into_json().await.unwrap();
+    assert_eq!(problem["code"], "rate_limited");
+
+    // Writes have a bucket of their own
+    let response = client
+        .post("/api/todos")
+        .header(bearer(token))
+        .header(ContentType::JSON)
+        .body(r#"{"title":"still allowed"}"#)
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Created);
+    assert_eq!(header(&response, "RateLimit-Limit"), Some("2"));
+}
+
+#[rocket::async_test]
+async fn users_are_limited_separately() {
+    let client = client(limited_figment()).await;
+    let alice = register(&client, "alice", "alice@example.com").await;
+    let bob = register(&client, "bob", "bob@example.com").await;
+
+    for _ in 0..3 {
+        let response = client.get("/api/todos").header(bearer(alice["token"].as_str().unwrap())).dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+    }
+    let response = client.get("/api/todos").header(bearer(alice["token"].as_str().unwrap())).dispatch().await;
+    assert_eq!(response.status(), Status::TooManyRequests);
+
+    let response = client.get("/api/todos").header(bearer(bob["token"].as_str().unwrap())).dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(header(&response, "RateLimit-Remaining"), Some("2"));
+}
+
+#[rocket::async_test]
+async fn sign_in_attempts_are_limited_per_address() {
+    let client = client(limited_figment()).await;
+
+    assert_eq!(login_from(&client, "203.0.113.7", None).await, Status::Unauthorized);
+    assert_eq!(login_from(&client, "203.0.113.7", None).await, Status::Unauthorized);
+    assert_eq!(login_from(&client, "203.0.113.7", None).await, Status::TooManyRequests);
+
+    assert_eq!(login_from(&client, "198.51.100.1", None).await, Status::Unauthorized);
+
+    // Unlimited routes are untouched
+    let response = client.get("/health").remote("203.0.113.7:4000".parse().unwrap()).dispatch().await;
+    assert_eq!(header(&response, "RateLimit-Limit"), None);
+}
+
+#[rocket::async_test]
+async fn forwarded_addresses_count_only_from_trusted_proxies() {
+    let client = client(limited_figment().merge(("app.trusted_proxies", ["10.0.0.2"]))).await;
+
+    // A direct caller cannot claim a fresh address for each attempt
+    assert_eq!(login_from(&client, "198.51.100.9", Some("192.0.2.1")).await, Status::Unauthorized);
+    assert_eq!(login_from(&client, "198.51.100.9", Some("192.0.2.2")).await, Status::Unauthorized);
+    assert_eq!(login_from(&client, "198.51.100.9", Some("192.0.2.3")).await, Status::TooManyRequests);
+
+    // Behind the proxy each client has its own bucket
+    assert_eq!(login_from(&client, "10.0.0.2", Some("203.0.113.7")).await, Status::Unauthorized);
+    assert_eq!(login_from(&client, "10.0.0.2", Some("203.0.113.7")).await, Status::Unauthorized);
+    assert_eq!(login_from(&client, "10.0.0.2", Some("203.0.113.7")).await, Status::TooManyRequests);
+    assert_eq!(login_from(&client, "10.0.0.2", Some("198.51.100.1")).await, Status::Unauthorized);
+}
+
+#[test]
+fn pruning_keeps_buckets_in_use() {
+    let store = MemoryStore::default();
+    let limit = Limit { burst: 1, per_minute: 1 };
+    let start = Instant::now();
+
+    assert!(store.take_at("drained", limit, start).allowed);
+    for i in 0..25_000 {
+        store.take_at(&format!("caller {}", i), limit, start);
+    }
+    assert!(!store.take_at("drained", limit, start).allowed);
+}
+
+#[rocket::async_test]
+async fn zero_limits_refuse_to_ignite() {
+    let figment = test_figment().merge(("rate_limit.writes", Limit { burst: 0, per_minute: 60 }));
+    let Err(error) = Client::tracked(crate::app(figment)).await else {
+        panic!("started with an unusable limit");
+    };
+    assert!(matches!(error.kind(), rocket::error::ErrorKind::FailedFairings(_)));
+}
@@ -0,0 +1,303 @@
+use std::time::Duration;
+
+use rocket::fairing::AdHoc;
+use rocket::http::{Header, Status};
+use rocket::tokio::io::{AsyncReadExt, AsyncWriteExt};
+use rocket::tokio::net::TcpStream;
+use rocket::tokio::sync::oneshot;
+use rocket::{Build, Rocket, State};
+
+use super::{bearer, register, test_figment, untracked_client};
+use crate::maintenance::Maintenance;
+
+/// A client frame, masked
+fn frame(opcode: u8, fin: bool, payload: &[u8]) -> Vec<u8> {
+    let mask = [0x12, 0x34, 0x56, 0x78];
+    let mut frame = vec![if fin { 0x80 } else { 0 } | opcode];
+    match payload.len() {
+        len if len < 126 => frame.push(0x80 | len as u8),
+        len => {
+            frame.push(0x80 | 126);
+            frame.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(&mask);
+    frame.extend(payload.iter().enumerate().map(|(i, byte)| byte ^ mask[i % 4]));
+    frame
+}
+
+/// Starts `rocket` on a free port and returns the port
+async fn launch(rocket: Rocket<Build>) -> u16 {
+    let (port_sender, port) = oneshot::channel();
+    let figment = rocket.figment().clone().merge(("port", 0));
+    let rocket = rocket.configure(figment).attach(AdHoc::on_liftoff("Port", |rocket| {
+        Box::pin(async move {
+            let _ = port_sender.send(rocket.config().port);
+        })
+    }));
+    rocket::tokio::spawn(rocket.launch());
+    port.await.unwrap()
+}
+
+async fn register_on(port: u16, username: &str) -> String {
+    let response = reqwest::Client::new()
+        .post(format!("http://127.0.0.1:{}/api/auth/register", port))
+        .json(&serde_json::json!({
+            "username": username,
+            "email": format!("{}@example.com", username),
+            "password": "correct horse battery staple"
+        }))
+        .send()
+        .await
+        .unwrap();
+    let body: serde_json::Value = response.json().await.unwrap();
+    body["token"].as_str().unwrap().to_string()
+}
+
+/// Opens a socket and completes the handshake
+async fn open(port: u16, token: &str) -> TcpStream {
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+    let handshake = format!(
+        "GET /api/ws HTTP/1.1\r\nHost: 127.0.0.1:{}\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+         Sec-WebSocket-Version: 13\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nAuthorization: Bearer {}\r\n\r\n",
+        port, token
+    );
+    stream.write_all(handshake.as_bytes()).await.unwrap();
+
+    // Byte by byte, so no frame is read along with the headers
+    let mut head = Vec::new();
+    while !head.ends_with(b"\r\n\r\n") {
+        head.push(stream.read_u8().await.unwrap());
+    }
+    let head = String::from_utf8(head).unwrap().to_ascii_lowercase();
+    assert!(head.starts_with("http/1.1 101"), "{}", head);
+    assert!(head.contains("sec-websocket-accept: s3pplmbitxaq9kygzzhzrbk+xoo="));
+    stream
+}
+
+async fn send(stream: &mut TcpStream, message: serde_json::Value) {
+    stream.write_all(&frame(0x1, true, message.to_string().as_bytes())).await.unwrap();
+}
+
+/// The next frame the server sends other than a ping: its opcode and payload
+async fn next_frame(stream: &mut TcpStream) -> (u8, Vec<u8>) {
+    rocket::tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            let opcode = stream.read_u8().await.unwrap() & 0x0F;
+            let len = match stream.read_u8().await.unwrap() {
+                126 => usize::from(stream.read_u16().await.unwrap()),
+                len => usize::from(len),
+            };
+            let mut payload = vec![0; len];
+            stream.read_exact(&mut payload).await.unwrap();
+            if opcode != 0x9 {
+                return (opcode, payload);
+            }
+        }
+    })
+    .await
+    .expect("a frame within 5 seconds")
+}
+
+/// The next text message
+async fn receive(stream: &mut TcpStream) -> serde_json::Value {
+    let (opcode, payload) = next_frame(stream).await;
+    assert_eq!(opcode, 0x1, "expected a text message");
+    serde_json::from_slice(&payload).unwrap()
+}
+
+/// The code of the close frame the server sends next
+async fn receive_close(stream: &mut TcpStream) -> u16 {
+    let (opcode, payload) = next_frame(stream).await;
+    assert_eq!(opcode, 0x8, "expected a close frame");
+    u16::from_be_bytes([payload[0], payload[1]])
+}
+
+#[rocket::async_test]
+async fn frames_are_reassembled_and_checked() {
+    let port = launch(crate::app(test_figment().merge(("websocket.max_message_bytes", 64)))).await;
+    let token = register_on(port, "alice").await;
+
+    // A ping may arrive between the fragments of a message
+    let mut stream = open(port, &token).await;
+    let mut bytes = frame(0x1, false, br#"{"type": "subscribe", "#);
+    bytes.extend(frame(0x9, true, b"?"));
+    bytes.extend(frame(0x0, true, r#""ref": "wörld"}"#.as_bytes()));
+    stream.write_all(&bytes).await.unwrap();
+    assert_eq!(next_frame(&mut stream).await, (0xA, b"?".to_vec()));
+    assert_eq!(receive(&mut stream).await, serde_json::json!({ "type": "ok", "ref": "wörld" }));
+
+    let mut bytes = frame(0x1, false, &[b'a'; 40]);
+    bytes.extend(frame(0x0, true, &[b'a'; 40]));
+    stream.write_all(&bytes).await.unwrap();
+    assert_eq!(receive_close(&mut stream).await, 1009);
+
+    let mut stream = open(port, &token).await;
+    stream.write_all(&[0x81, 0x01, b'a']).await.unwrap();
+    assert_eq!(receive_close(&mut stream).await, 1002);
+
+    let mut stream = open(port, &token).await;
+    stream.write_all(&frame(0x1, true, &[0xFF])).await.unwrap();
+    assert_eq!(receive_close(&mut stream).await, 1007);
+}
+
+#[rocket::async_test]
+async fn handshakes_need_a_token_an_upgrade_and_the_same_origin() {
+    let client = untracked_client(test_figment()).await;
+    let registered = register(&client, "alice", "alice@example.com").await;
+    let token = registered["token"].as_str().unwrap();
+
+    let response = client.get("/api/ws").dispatch().await;
+    assert_eq!(response.status(), Status::Unauthorized);
+
+    let response = client.get("/api/ws").header(bearer(token)).dispatch().await;
+    assert_eq!(response.status(), Status::BadRequest);
+    let problem: serde_json::Value = response.into_json().await.unwrap();
+    assert_eq!(problem["detail"], "Expected a WebSocket version 13 upgrade request");
+
+    let upgrade = |version: &'static str| {
+        client
+            .get("/api/ws")
+            .header(Header::new("Upgrade", "websocket"))
+            .header(Header::new("Connection", "keep-alive, Upgrade"))
+            .header(Header::new("Sec-WebSocket-Version", version))
+            .header(Header::new("Sec-WebSocket-Key", "dGhlIHNhbXBsZSBub25jZQ=="))
+    };
+    let response = upgrade("8").header(bearer(token)).dispatch().await;
+    assert_eq!(response.status(), Status::BadRequest);
+    // Local clients do not switch protocols; `open` sees the 101
+    let accepted = |response: &rocket::local::asynchronous::LocalResponse<'_>| {
+        response.headers().get_one("Sec-WebSocket-Accept") == Some("s3pPLMBiTxaQ9kYGzzhZRbK+xOo=")
+    };
+    let response = upgrade("13").header(bearer(token)).dispatch().await;
+    assert!(accepted(&response), "{}", response.status());
+
+    // Any page can make the browser send the cookie; only this origin may
+    let cookie = rocket::http::Cookie::new("auth_token", token.to_string());
+    let response = upgrade("13")
+        .cookie(cookie.clone())
+        .header(Header::new("Origin", "https://evil.example"))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Forbidden);
+    let response = upgrade("13")
+        .cookie(cookie)
+        .header(Header::new("Origin", "http://localhost:8000"))
+        .header(Header::new("Host", "localhost:8000"))
+        .dispatch()
+        .await;
+    assert!(accepted(&response), "{}", response.status());
+}
+
+#[rocket::async_test]
+async fn commands_changes_and_presence_flow_over_sockets() {
+    let port = launch(crate::app(test_figment())).await;
+    let token = register_on(port, "alice").await;
+    let mut editor = open(port, &token).await;
+    let mut watcher = open(port, &token).await;
+
+    send(&mut watcher, serde_json::json!({ "type": "subscribe", "ref": "w1" })).await;
+    assert_eq!(receive(&mut watcher).await, serde_json::json!({ "type": "ok", "ref": "w1" }));
+
+    let create = serde_json::json!({ "type": "create", "ref": "e1", "todo": { "title": "  over the socket " } });
+    send(&mut editor, create).await;
+    let reply = receive(&mut editor).await;
+    assert_eq!(reply["type"], "ok");
+    assert_eq!(reply["ref"], "e1");
+    assert_eq!(reply["todo"]["title"], "over the socket");
+    let id = reply["todo"]["id"].clone();
+
+    let event = receive(&mut watcher).await;
+    assert_eq!(event["type"], "event");
+    assert_eq!(event["kind"], "created");
+    assert_eq!(event["todo_id"], id);
+
+    send(&mut editor, serde_json::json!({ "type": "presence", "todo_id": id, "state": "editing" })).await;
+    assert_eq!(receive(&mut editor).await["type"], "ok");
+    let presence = receive(&mut watcher).await;
+    assert_eq!(presence["type"], "presence");
+    assert_eq!(presence["username"], "alice");
+    assert_eq!(presence["state"], "editing");
+
+    let update = serde_json::json!({ "type": "update", "ref": "e2", "id": id, "changes": { "status": "completed" } });
+    send(&mut editor, update).await;
+    assert_eq!(receive(&mut editor).await["todo"]["status"], "completed");
+    assert_eq!(receive(&mut watcher).await["kind"], "updated");
+
+    // Errors are replies; the socket stays open
+    send(&mut editor, serde_json::json!({ "type": "create", "ref": "e3", "todo": { "title": " " } })).await;
+    let error = receive(&mut editor).await;
+    assert_eq!(error["code"], "validation_failed");
+    assert_eq!(error["errors"][0]["field"], "title");
+    send(&mut editor, serde_json::json!({ "type": "delete", "ref": "e4", "id": 9999 })).await;
+    assert_eq!(receive(&mut editor).await["code"], "not_found");
+    send(&mut editor, serde_json::json!({ "type": "launch" })).await;
+    assert_eq!(receive(&mut editor).await["code"], "invalid_message");
+
+    // Todos of other users cannot be watched
+    let other = register_on(port, "bob").await;
+    let mut intruder = open(port, &other).await;
+    send(&mut intruder, serde_json::json!({ "type": "subscribe", "todo_ids": [id] })).await;
+    assert_eq!(receive(&mut intruder).await["code"], "not_found");
+
+    drop(editor);
+    let left = receive(&mut watcher).await;
+    assert_eq!((left["type"].as_str(), left["state"].as_str()), (Some("presence"), Some("left")));
+}
+
+/// Holds maintenance mode on for a moment, as a restore does
+#[rocket::post("/maintenance")]
+async fn hold_maintenance(maintenance: &State<Maintenance>) -> Status {
+    let Some(_window) = maintenance.begin(Duration::from_secs(5)).await else {
+        return Status::ServiceUnavailable;
+    };
+    rocket::tokio::time::sleep(Duration::from_millis(200)).await;
+    Status::NoContent
+}
+
+#[rocket::async_test]
+async fn maintenance_closes_sockets() {
+    let port = launch(crate::app(test_figment()).mount("/test", rocket::routes![hold_maintenance])).await;
+    let token = register_on(port, "alice").await;
+    let mut stream = open(port, &token).await;
+    send(&mut stream, serde_json::json!({ "type": "subscribe" })).await;
+    assert_eq!(receive(&mut stream).await["type"], "ok");
+
+    let response = reqwest::Client::new()
+        .post(format!("http://127.0.0.1:{}/test/maintenance", port))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::NO_CONTENT);
+    assert_eq!(receive_close(&mut stream).await, 1013);
+
+    // Once it is over, clients reconnect
+    let mut stream = open(port, &token).await;
+    send(&mut stream, serde_json::json!({ "type": "subscribe" })).await;
+    assert_eq!(receive(&mut stream).await["type"], "ok");
+}
+
+#[rocket::async_test]
+async fn sockets_close_when_their_session_ends() {
+    let port = launch(crate::app(test_figment().merge(("websocket.ping_secs", 1)))).await;
+    let token = register_on(port, "alice").await;
+    let mut stream = open(port, &token).await;
+
+    let response = reqwest::Client::new()
+        .post(format!("http://127.0.0.1:{}/api/auth/logout", port))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .unwrap();
+    assert!(response.status().is_success());
+    assert_eq!(receive_close(&mut stream).await, 1008);
+}
+
+#[rocket::async_test]
+async fn invalid_websocket_settings_refuse_to_ignite() {
+    let figment = test_figment().merge(("websocket.send_buffer", 0));
+    let Err(error) = rocket::local::asynchronous::Client::tracked(crate::app(figment)).await else {
+        panic!("started without a send buffer");
+    };
+    assert!(matches!(error.kind(), rocket::error::ErrorKind::FailedFairings(_)));
+}
@@ -0,0 +1,153 @@
+use rocket::http::{ContentType, Header, Status};
+use rocket::local::asynchronous::Client;
+
+use super::{bearer, client, register, test_figment};
+
+/// Logs in through the tracked client, which keeps the cookies, and returns
+/// the response body
+async fn cookie_login(client: &Client, email: &str) -> serde_json::Value {
+    let response = client
+        .post("/api/auth/login")
+        .header(ContentType::JSON)
+        .body(serde_json::json!({ "email": email, "password": "correct horse battery staple" }).to_string())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    response.into_json().await.unwrap()
+}
+
+async fn create_todo(client: &Client, csrf: Option<&str>) -> (Status, serde_json::Value) {
+    let mut request = client
+        .post("/api/todos")
+        .header(ContentType::JSON)
+        .body(r#"{"title":"buy milk"}"#);
+    if let Some(csrf) = csrf {
+        request = request.header(Header::new("X-CSRF-Token", csrf.to_string()));
+    }
+    let response = request.dispatch().await;
+    (response.status(), response.into_json().await.unwrap())
+}
+
+#[rocket::async_test]
+async fn cookie_writes_need_the_csrf_token() {
+    let client = client(test_figment()).await;
+    let registered = register(&client, "alice", "alice@example.com").await;
+    let body = cookie_login(&client, "alice@example.com").await;
+
+    let csrf = body["csrf_token"].as_str().unwrap();
+    let cookie = client.cookies().get("csrf_token").unwrap().clone();
+    assert_eq!(cookie.value(), csrf);
+    assert_ne!(cookie.http_only(), Some(true), "scripts must be able to read it");
+
+    let (status, problem) = create_todo(&client, None).await;
+    assert_eq!(status, Status::Forbidden);
+    assert_eq!(problem["code"], "csrf_token_invalid");
+    let (status, _) = create_todo(&client, Some(&"0".repeat(64))).await;
+    assert_eq!(status, Status::Forbidden);
+    let (status, _) = create_todo(&client, Some("not hex")).await;
+    assert_eq!(status, Status::Forbidden);
+
+    let (status, _) = create_todo(&client, Some(csrf)).await;
+    assert_eq!(status, Status::Created);
+
+    // Reads need no token
+    let response = client.get("/api/todos").dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+
+    // Neither does a Bearer token, which the browser never sends on its own
+    let response = client
+        .post("/api/todos")
+        .header(ContentType::JSON)
+        .header(bearer(registered["token"].as_str().unwrap()))
+        .body(r#"{"title":"by header"}"#)
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Created);
+}
+
+#[rocket::async_test]
+async fn csrf_tokens_belong_to_their_session() {
+    let client = client(test_figment()).await;
+    register(&client, "alice", "alice@example.com").await;
+    let first = cookie_login(&client, "alice@example.com").await;
+    let second = cookie_login(&client, "alice@example.com").await;
+    assert_ne!(first["csrf_token"], second["csrf_token"]);
+
+    // The cookie now holds the second session
+    let (status, _) = create_todo(&client, first["csrf_token"].as_str()).await;
+    assert_eq!(status, Status::Forbidden);
+
+    let response = client.get("/api/auth/csrf").dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    let body: serde_json::Value = response.into_json().await.unwrap();
+    assert_eq!(body["csrf_token"], second["csrf_token"]);
+}
+
+#[rocket::async_test]
+async fn logout_clears_both_cookies() {
+    let client = client(test_figment()).await;
+    register(&client, "alice", "alice@example.com").await;
+    let body = cookie_login(&client, "alice@example.com").await;
+
+    let response = client
+        .post("/api/auth/logout")
+        .header(Header::new("X-CSRF-Token", body["csrf_token"].as_str().unwrap().to_string()))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    assert!(client.cookies().get("auth_token").is_none());
+    assert!(client.cookies().get("csrf_token").is_none());
+
+    let response = client.get("/api/auth/me").header(bearer(body["token"].as_str().unwrap())).dispatch().await;
+    assert_eq!(response.status(), Status::Unauthorized);
+}
+
+#[rocket::async_test]
+async fn logout_without_the_csrf_header_still_ends_the_session() {
+    let client = client(test_figment()).await;
+    register(&client, "alice", "alice@example.com").await;
+    let body = cookie_login(&client, "alice@example.com").await;
+
+    let response = client.post("/api/auth/logout").dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    assert!(client.cookies().get("auth_token").is_none());
+
+    let response = client.get("/api/auth/me").header(bearer(body["token"].as_str().unwrap())).dispatch().await;
+    assert_eq!(response.status(), Status::Unauthorized);
+}
+
+#[rocket::async_test]
+async fn responses_carry_security_headers() {
+    let client = client(test_figment()).await;
+
+    for uri in ["/api/todos", "/health", "/api/nowhere"] {
+        let response = client.get(uri).dispatch().await;
+        let headers = response.headers();
+        assert_eq!(headers.get_one("X-Content-Type-Options"), Some("nosniff"), "{}", uri);
+        assert_eq!(headers.get_one("X-Frame-Options"), Some("DENY"), "{}", uri);
+        assert_eq!(headers.get_one("Referrer-Policy"), Some("no-referrer"), "{}", uri);
+        assert!(headers.get_one("Content-Security-Policy").unwrap().starts_with("default-src 'none'"), "{}", uri);
+        // Off unless configured, since the test server is plain HTTP
+        assert_eq!(headers.get_one("Strict-Transport-Security"), None, "{}", uri);
+    }
+
+    let response = client.get("/docs/").dispatch().await;
+    let csp = response.headers().get_one("Content-Security-Policy").unwrap();
+    assert!(csp.contains("script-src 'self'"), "{}", csp);
+}
+
+#[rocket::async_test]
+async fn hsts_follows_the_config() {
+    let client = client(
+        test_figment()
+            .merge(("app.security_headers.hsts", true))
+            .merge(("app.security_headers.hsts_max_age_secs", 600))
+            .merge(("app.security_headers.frame_options", "sameorigin")),
+    )
+    .await;
+
+    let response = client.get("/live").dispatch().await;
+    let headers = response.headers();
+    assert_eq!(headers.get_one("Strict-Transport-Security"), Some("max-age=600; includeSubDomains"));
+    assert_eq!(headers.get_one("X-Frame-Options"), Some("SAMEORIGIN"));
+}
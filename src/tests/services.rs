@@ -0,0 +1,288 @@
+//! The todo and auth services against in-memory repositories, without
+//! Rocket or a database
+
+use std::sync::Arc;
+
+use rocket::http::{ContentType, Status};
+
+use super::{bearer, register, test_figment};
+use crate::config::JwtConfig;
+use crate::error::ApiError;
+use crate::handlers::{auth_handler, todo_handler};
+use crate::models::{
+    CreateTodoRequest, CreateUserRequest, LoginRequest, Priority, QuotaOverrides,
+    Status as TodoStatus, UpdateTodoRequest,
+};
+use crate::quota::{Limits, Quota, QuotaConfig};
+use crate::repository::{
+    InMemoryTodoRepository, InMemoryUserRepository, TodoRepository, UserRepository,
+};
+
+fn new_todo(title: &str, status: TodoStatus, priority: Priority) -> CreateTodoRequest {
+    CreateTodoRequest {
+        title: title.to_string(),
+        description: None,
+        status: Some(status),
+        priority: Some(priority),
+    }
+}
+
+/// The default quotas
+fn limits() -> Limits {
+    QuotaConfig::default().limits(&QuotaOverrides::default())
+}
+
+fn no_changes() -> UpdateTodoRequest {
+    UpdateTodoRequest {
+        title: None,
+        description: None,
+        status: None,
+        priority: None,
+    }
+}
+
+#[rocket::async_test]
+async fn todos_are_listed_newest_first_and_filtered() {
+    let todos = InMemoryTodoRepository::new();
+
+    let first = todo_handler::create_todo(
+        &todos,
+        &limits(),
+        1,
+        new_todo("first", TodoStatus::Pending, Priority::High),
+    )
+    .await
+    .unwrap();
+    let second = todo_handler::create_todo(
+        &todos,
+        &limits(),
+        1,
+        new_todo("second", TodoStatus::Blocked, Priority::High),
+    )
+    .await
+    .unwrap();
+    todo_handler::create_todo(
+        &todos,
+        &limits(),
+        1,
+        new_todo("third", TodoStatus::Blocked, Priority::Low),
+    )
+    .await
+    .unwrap();
+
+    let all = todo_handler::get_all_todos(&todos, 1).await.unwrap();
+    let titles: Vec<_> = all.iter().map(|todo| todo.title.as_str()).collect();
+    assert_eq!(titles, ["third", "second", "first"]);
+
+    let blocked = todo_handler::get_todos_by_status(&todos, 1, TodoStatus::Blocked)
+        .await
+        .unwrap();
+    assert_eq!(blocked.len(), 2);
+
+    let high = todo_handler::get_todos_by_priority(&todos, 1, Priority::High)
+        .await
+        .unwrap();
+    let ids: Vec<_> = high.iter().map(|todo| todo.id).collect();
+    assert_eq!(ids, [second.id, first.id]);
+}
+
+#[rocket::async_test]
+async fn create_applies_defaults() {
+    let todos = InMemoryTodoRepository::new();
+    let request = CreateTodoRequest {
+        title: "defaults".to_string(),
+        description: None,
+        status: None,
+        priority: None,
+    };
+
+    let todo = todo_handler::create_todo(&todos, &limits(), 1, request).await.unwrap();
+    assert_eq!(todo.status, TodoStatus::Pending);
+    assert_eq!(todo.priority, Priority::Medium);
+}
+
+#[rocket::async_test]
+async fn other_users_todos_are_not_found() {
+    let todos = InMemoryTodoRepository::new();
+    let todo = todo_handler::create_todo(
+        &todos,
+        &limits(),
+        1,
+        new_todo("mine", TodoStatus::Pending, Priority::Low),
+    )
+    .await
+    .unwrap();
+
+    let result = todo_handler::get_todo(&todos, 2, todo.id).await;
+    assert!(matches!(result, Err(ApiError::NotFound(_))));
+
+    let result = todo_handler::update_todo(&todos, &limits(), 2, todo.id, no_changes()).await;
+    assert!(matches!(result, Err(ApiError::NotFound(_))));
+
+    let result = todo_handler::delete_todo(&todos, 2, todo.id).await;
+    assert!(matches!(result, Err(ApiError::NotFound(_))));
+    assert!(todo_handler::get_all_todos(&todos, 2)
+        .await
+        .unwrap()
+        .is_empty());
+
+    todo_handler::delete_todo(&todos, 1, todo.id).await.unwrap();
+    let result = todo_handler::get_todo(&todos, 1, todo.id).await;
+    assert!(matches!(result, Err(ApiError::NotFound(_))));
+}
+
+#[rocket::async_test]
+async fn update_changes_only_the_given_fields() {
+    let todos = InMemoryTodoRepository::new();
+    let todo = todo_handler::create_todo(
+        &todos,
+        &limits(),
+        1,
+        new_todo("draft", TodoStatus::Pending, Priority::Low),
+    )
+    .await
+    .unwrap();
+
+    let unchanged = todo_handler::update_todo(&todos, &limits(), 1, todo.id, no_changes())
+        .await
+        .unwrap();
+    assert_eq!(unchanged.updated_at, todo.updated_at);
+
+    let changes = UpdateTodoRequest {
+        status: Some(TodoStatus::Completed),
+        ..no_changes()
+    };
+    let updated = todo_handler::update_todo(&todos, &limits(), 1, todo.id, changes)
+        .await
+        .unwrap();
+    assert_eq!(updated.title, "draft");
+    assert_eq!(updated.priority, Priority::Low);
+    assert_eq!(updated.status, TodoStatus::Completed);
+}
+
+#[rocket::async_test]
+async fn quotas_allow_shrinking_but_not_growing() {
+    let todos = InMemoryTodoRepository::new();
+    let limits = Limits {
+        max_todos: 2,
+        max_storage_bytes: 10,
+        ..limits()
+    };
+
+    let todo = todo_handler::create_todo(&todos, &limits, 1, new_todo("12345", TodoStatus::Pending, Priority::Low))
+        .await
+        .unwrap();
+    let result = todo_handler::create_todo(&todos, &limits, 1, new_todo("123456", TodoStatus::Pending, Priority::Low)).await;
+    assert!(matches!(result, Err(ApiError::QuotaExceeded(Quota::Storage, 10))));
+    todo_handler::create_todo(&todos, &limits, 1, new_todo("12345", TodoStatus::Pending, Priority::Low))
+        .await
+        .unwrap();
+    let result = todo_handler::create_todo(&todos, &limits, 1, new_todo("", TodoStatus::Pending, Priority::Low)).await;
+    assert!(matches!(result, Err(ApiError::QuotaExceeded(Quota::Todos, 2))));
+
+    // Full, so a longer title is refused but a shorter one is fine
+    let longer = UpdateTodoRequest { title: Some("123456".to_string()), ..no_changes() };
+    let result = todo_handler::update_todo(&todos, &limits, 1, todo.id, longer).await;
+    assert!(matches!(result, Err(ApiError::QuotaExceeded(Quota::Storage, 10))));
+    let shorter = UpdateTodoRequest { title: Some("1".to_string()), ..no_changes() };
+    todo_handler::update_todo(&todos, &limits, 1, todo.id, shorter).await.unwrap();
+
+    let usage = todo_handler::get_usage(&todos, &limits, 1).await.unwrap();
+    assert_eq!((usage.todos.used, usage.storage_bytes.used), (2, 6));
+    // Other users have their own
+    todo_handler::create_todo(&todos, &limits, 2, new_todo("12345", TodoStatus::Pending, Priority::Low))
+        .await
+        .unwrap();
+}
+
+#[rocket::async_test]
+async fn register_then_login() {
+    let users = InMemoryUserRepository::new();
+    let request = || CreateUserRequest {
+        username: "alice".to_string(),
+        email: "alice@example.com".to_string(),
+        password: "correct horse battery staple".to_string(),
+    };
+
+    let registered = auth_handler::register(&users, &JwtConfig::default(), request()).await.unwrap();
+    assert_eq!(registered.username, "alice");
+
+    let result = auth_handler::register(&users, &JwtConfig::default(), request()).await;
+    assert!(matches!(result, Err(ApiError::Conflict(_))));
+
+    let login = |password: &str| LoginRequest {
+        email: "alice@example.com".to_string(),
+        password: password.to_string(),
+    };
+
+    let result = auth_handler::login(&users, &JwtConfig::default(), login("wrong password")).await;
+    assert!(matches!(result, Err(ApiError::InvalidCredentials)));
+
+    let signed_in = auth_handler::login(&users, &JwtConfig::default(), login("correct horse battery staple"))
+        .await
+        .unwrap();
+    assert_eq!(signed_in.user_id, registered.user_id);
+    assert_ne!(signed_in.token, registered.token);
+}
+
+#[rocket::async_test]
+async fn disabled_users_cannot_log_in() {
+    let users = InMemoryUserRepository::new();
+    let request = CreateUserRequest {
+        username: "alice".to_string(),
+        email: "alice@example.com".to_string(),
+        password: "correct horse battery staple".to_string(),
+    };
+    let registered = auth_handler::register(&users, &JwtConfig::default(), request).await.unwrap();
+    let login = || LoginRequest {
+        email: "alice@example.com".to_string(),
+        password: "correct horse battery staple".to_string(),
+    };
+
+    assert!(users.set_disabled(registered.user_id, true).await.unwrap());
+    let result = auth_handler::login(&users, &JwtConfig::default(), login()).await;
+    assert!(matches!(result, Err(ApiError::Forbidden(_))));
+
+    // A wrong password still reads as bad credentials
+    let wrong = LoginRequest {
+        password: "wrong password".to_string(),
+        ..login()
+    };
+    let result = auth_handler::login(&users, &JwtConfig::default(), wrong).await;
+    assert!(matches!(result, Err(ApiError::InvalidCredentials)));
+
+    assert!(users.set_disabled(registered.user_id, false).await.unwrap());
+    auth_handler::login(&users, &JwtConfig::default(), login()).await.unwrap();
+
+    assert!(!users.set_disabled(42, true).await.unwrap());
+}
+
+/// Repositories put in managed state before ignite replace the SQL ones
+#[rocket::async_test]
+async fn managed_repository_is_used_by_routes() {
+    let todos = Arc::new(InMemoryTodoRepository::new());
+    let managed: Arc<dyn TodoRepository> = todos.clone();
+    let client =
+        rocket::local::asynchronous::Client::tracked(crate::app(test_figment()).manage(managed))
+            .await
+            .expect("valid rocket instance");
+
+    let registered = register(&client, "alice", "alice@example.com").await;
+    let token = registered["token"].as_str().unwrap();
+    let user_id = registered["user"]["id"].as_i64().unwrap();
+
+    let response = client
+        .post("/api/todos")
+        .header(bearer(token))
+        .header(ContentType::JSON)
+        .body(r#"{"title":"kept in memory"}"#)
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Created);
+
+    let stored = todo_handler::get_all_todos(todos.as_ref(), user_id)
+        .await
+        .unwrap();
+    assert_eq!(stored.len(), 1);
+    assert_eq!(stored[0].title, "kept in memory");
+}
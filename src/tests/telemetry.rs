@@ -0,0 +1,255 @@
+use std::time::{Duration, SystemTime};
+
+use rocket::http::Header;
+use rocket::local::asynchronous::Client;
+use tracing_subscriber::layer::SubscriberExt;
+
+use super::{bearer, register, test_figment};
+use crate::telemetry::trace::{
+    AttributeValue, FinishedSpan, InMemoryExporter, SpanKind, TraceContext, TraceLayer, otlp_json,
+};
+
+const CALLER_TRACE: &str = "4bf92f3577b34da6a3ce929d0e0e4736";
+const CALLER_SPAN: &str = "00f067aa0ba902b7";
+
+/// Runs `test` against a fresh instance with spans going to an in-memory
+/// exporter, and returns the spans that ended
+fn with_exporter<F>(test: impl FnOnce(Client) -> F) -> Vec<FinishedSpan>
+where
+    F: Future<Output = ()>,
+{
+    let exporter = InMemoryExporter::default();
+    let subscriber = tracing_subscriber::registry().with(TraceLayer::new(exporter.clone()));
+
+    tracing::subscriber::with_default(subscriber, || {
+        // One thread, so the scoped subscriber sees every span
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let client = Client::untracked(crate::app(test_figment())).await.unwrap();
+            test(client).await;
+        });
+    });
+    exporter.finished()
+}
+
+fn string<'a>(span: &'a FinishedSpan, key: &str) -> Option<&'a str> {
+    match span.attribute(key) {
+        Some(AttributeValue::String(s)) => Some(s),
+        _ => None,
+    }
+}
+
+#[test]
+fn request_span_continues_the_callers_trace() {
+    let spans = with_exporter(|client| async move {
+        let registered = register(&client, "alice", "alice@example.com").await;
+        let response = client
+            .get("/api/todos")
+            .header(bearer(registered["token"].as_str().unwrap()))
+            .header(Header::new("traceparent", format!("00-{}-{}-01", CALLER_TRACE, CALLER_SPAN)))
+            .header(Header::new("x-request-id", "req-123"))
+            .dispatch()
+            .await;
+        assert_eq!(response.headers().get_one("x-request-id"), Some("req-123"));
+        let traceresponse = response.headers().get_one("traceresponse").unwrap();
+        assert!(traceresponse.starts_with(&format!("00-{}-", CALLER_TRACE)));
+        assert!(traceresponse.ends_with("-01"));
+    });
+
+    let trace: Vec<_> = spans
+        .iter()
+        .filter(|s| s.context.trace_id_hex() == CALLER_TRACE)
+        .collect();
+    let request = trace.iter().find(|s| s.name == "http_request").expect("request span");
+    assert_eq!(request.kind, SpanKind::Server);
+    assert_eq!(request.parent_span_id.map(hex::encode).as_deref(), Some(CALLER_SPAN));
+    assert_eq!(string(request, "request_id"), Some("req-123"));
+    assert_eq!(string(request, "http.route"), Some("/api/todos"));
+    assert_eq!(request.attribute("http.status_code"), Some(&AttributeValue::Int(200)));
+
+    let handler = trace.iter().find(|s| s.name == "handler").expect("handler span");
+    assert_eq!(handler.parent_span_id, Some(request.context.span_id));
+    assert_eq!(string(handler, "code.function"), Some("get_all_todos"));
+
+    let query = trace.iter().find(|s| s.name == "db.query").expect("query span");
+    assert_eq!(query.parent_span_id, Some(handler.context.span_id));
+    assert_eq!(string(query, "db.operation"), Some("todos.list"));
+    assert!(query.start >= handler.start && query.end <= handler.end);
+}
+
+#[test]
+fn malformed_or_unsampled_context_is_not_continued() {
+    for malformed in [
+        "00-00000000000000000000000000000000-00f067aa0ba902b7-01",
+        "00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-01",
+        "01-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+        "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7",
+        "garbage",
+    ] {
+        assert_eq!(TraceContext::from_traceparent(malformed), None, "{}", malformed);
+    }
+
+    let spans = with_exporter(|client| async move {
+        let response = client
+            .get("/health")
+            .header(Header::new("traceparent", "garbage"))
+            .header(Header::new("x-request-id", "bad id\twith spaces"))
+            .dispatch()
+            .await;
+        let request_id = response.headers().get_one("x-request-id").unwrap();
+        assert_ne!(request_id, "bad id\twith spaces");
+
+        let response = client
+            .get("/live")
+            .header(Header::new("traceparent", format!("00-{}-{}-00", CALLER_TRACE, CALLER_SPAN)))
+            .dispatch()
+            .await;
+        assert!(response.headers().get_one("traceresponse").unwrap().ends_with("-00"));
+    });
+
+    let health = spans
+        .iter()
+        .find(|s| s.name == "http_request" && string(s, "http.route") == Some("/health"))
+        .expect("request span");
+    assert_eq!(health.parent_span_id, None);
+    assert_ne!(health.context.trace_id_hex(), CALLER_TRACE);
+    // The caller asked for the trace not to be recorded
+    assert!(spans.iter().all(|s| s.context.trace_id_hex() != CALLER_TRACE));
+}
+
+#[test]
+fn spans_are_encoded_as_otlp_json() {
+    let context = TraceContext::from_traceparent(&format!("00-{}-{}-01", CALLER_TRACE, CALLER_SPAN)).unwrap();
+    let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+    let span = FinishedSpan {
+        context: context.child(),
+        parent_span_id: Some(context.span_id),
+        name: "http_request".to_string(),
+        kind: SpanKind::Server,
+        start,
+        end: start + Duration::from_millis(5),
+        attributes: vec![
+            ("http.route".to_string(), AttributeValue::String("/api/todos".to_string())),
+            ("http.status_code".to_string(), AttributeValue::Int(503)),
+        ],
+    };
+
+    let body = otlp_json("todo-api", &[span]);
+    let resource = &body["resourceSpans"][0];
+    assert_eq!(resource["resource"]["attributes"][0]["value"]["stringValue"], "todo-api");
+    let encoded = &resource["scopeSpans"][0]["spans"][0];
+    assert_eq!(encoded["traceId"], CALLER_TRACE);
+    assert_eq!(encoded["parentSpanId"], CALLER_SPAN);
+    assert_eq!(encoded["kind"], 2);
+    assert_eq!(encoded["startTimeUnixNano"], "1700000000000000000");
+    assert_eq!(encoded["endTimeUnixNano"], "1700000000005000000");
+    assert_eq!(encoded["attributes"][1]["value"]["intValue"], "503");
+    assert_eq!(encoded["status"]["code"], 2);
+}
+
+/// Accepts one HTTP request and returns its path and body
+fn collector() -> (String, std::sync::mpsc::Receiver<(String, String)>) {
+    use std::io::{BufRead, BufReader, Read, Write};
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let endpoint = format!("http://{}", listener.local_addr().unwrap());
+    let (sender, receiver) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line).unwrap();
+        let mut length = 0;
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            if line.trim().is_empty() {
+                break;
+            }
+            if let Some((name, value)) = line.split_once(':')
+                && name.eq_ignore_ascii_case("content-length")
+            {
+                length = value.trim().parse().unwrap();
+            }
+        }
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body).unwrap();
+        reader
+            .get_mut()
+            .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+            .unwrap();
+
+        let path = request_line.split_whitespace().nth(1).unwrap_or_default().to_string();
+        sender.send((path, String::from_utf8(body).unwrap())).unwrap();
+    });
+    (endpoint, receiver)
+}
+
+#[test]
+fn otlp_exporter_posts_batches_to_the_collector() {
+    use crate::telemetry::trace::{OtlpExporter, SpanExporter, TracingConfig};
+
+    let (endpoint, received) = collector();
+    let config = TracingConfig {
+        otlp_endpoint: Some(endpoint.clone()),
+        flush_interval_ms: 50,
+        ..TracingConfig::default()
+    };
+    let exporter = OtlpExporter::spawn(&endpoint, &config).unwrap();
+
+    let context = TraceContext::root();
+    exporter.export(FinishedSpan {
+        context,
+        parent_span_id: None,
+        name: "handler".to_string(),
+        kind: SpanKind::Internal,
+        start: SystemTime::now(),
+        end: SystemTime::now(),
+        attributes: Vec::new(),
+    });
+
+    let (path, body) = received.recv_timeout(Duration::from_secs(10)).unwrap();
+    assert_eq!(path, "/v1/traces");
+    let body: serde_json::Value = serde_json::from_str(&body).unwrap();
+    let span = &body["resourceSpans"][0]["scopeSpans"][0]["spans"][0];
+    assert_eq!(span["traceId"], context.trace_id_hex());
+    assert_eq!(span["name"], "handler");
+}
+
+#[test]
+fn a_steady_trickle_of_spans_is_still_flushed_on_time() {
+    use crate::telemetry::trace::{OtlpExporter, SpanExporter, TracingConfig};
+
+    let (endpoint, received) = collector();
+    let config = TracingConfig {
+        otlp_endpoint: Some(endpoint.clone()),
+        flush_interval_ms: 200,
+        ..TracingConfig::default()
+    };
+    let exporter = OtlpExporter::spawn(&endpoint, &config).unwrap();
+
+    // A span every 20ms never leaves the exporter idle for a whole interval,
+    // and fills a batch of 256 only after five seconds
+    std::thread::spawn(move || {
+        for _ in 0..250 {
+            exporter.export(FinishedSpan {
+                context: TraceContext::root(),
+                parent_span_id: None,
+                name: "tick".to_string(),
+                kind: SpanKind::Internal,
+                start: SystemTime::now(),
+                end: SystemTime::now(),
+                attributes: Vec::new(),
+            });
+            std::thread::sleep(Duration::from_millis(20));
+        }
+    });
+
+    let (_, body) = received.recv_timeout(Duration::from_secs(2)).expect("a batch within the flush interval");
+    let body: serde_json::Value = serde_json::from_str(&body).unwrap();
+    let spans = body["resourceSpans"][0]["scopeSpans"][0]["spans"].as_array().unwrap();
+    assert!(spans.len() < 100, "sent {} spans in one batch", spans.len());
+}
@@ -0,0 +1,53 @@
+use rocket::http::{ContentType, Status};
+
+use super::{bearer, client, register, test_figment};
+
+#[rocket::async_test]
+async fn new_statuses_round_trip() {
+    let client = client(test_figment()).await;
+    let registered = register(&client, "alice", "alice@example.com").await;
+    let token = registered["token"].as_str().unwrap();
+
+    let response = client
+        .post("/api/todos")
+        .header(bearer(token))
+        .header(ContentType::JSON)
+        .body(r#"{"title":"waiting on review","status":"blocked","priority":"high"}"#)
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Created);
+    let todo: serde_json::Value = response.into_json().await.unwrap();
+    assert_eq!(todo["status"], "blocked");
+    assert_eq!(todo["priority"], "high");
+
+    let response = client
+        .put(format!("/api/todos/{}", todo["id"]))
+        .header(bearer(token))
+        .header(ContentType::JSON)
+        .body(r#"{"status":"cancelled"}"#)
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+
+    let response = client
+        .get("/api/todos/status/cancelled")
+        .header(bearer(token))
+        .dispatch()
+        .await;
+    let todos: serde_json::Value = response.into_json().await.unwrap();
+    assert_eq!(todos.as_array().unwrap().len(), 1);
+    assert_eq!(todos[0]["title"], "waiting on review");
+
+    let response = client
+        .get("/api/todos/priority/urgent")
+        .header(bearer(token))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+    let problem: serde_json::Value = response.into_json().await.unwrap();
+    assert_eq!(problem["errors"][0]["field"], "priority");
+    assert!(problem["errors"][0]["message"]
+        .as_str()
+        .unwrap()
+        .contains("`low`, `medium`, `high`"));
+}
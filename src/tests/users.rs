@@ -0,0 +1,322 @@
+use std::sync::Arc;
+
+use rocket::http::{ContentType, Status};
+use rocket::local::asynchronous::Client;
+use rocket_db_pools::Database;
+
+use super::{bearer, register, test_figment, untracked_client as client};
+use crate::auth::password::hash_token;
+use crate::database::Db;
+use crate::events::{EventKind, EventLog};
+use crate::repository::{SqlUserRepository, UserRepository};
+
+const PASSWORD: &str = "correct horse battery staple";
+
+async fn login(client: &Client, email: &str, password: &str) -> Status {
+    client
+        .post("/api/auth/login")
+        .header(ContentType::JSON)
+        .body(serde_json::json!({ "email": email, "password": password }).to_string())
+        .dispatch()
+        .await
+        .status()
+}
+
+async fn login_token(client: &Client, email: &str) -> String {
+    let response = client
+        .post("/api/auth/login")
+        .header(ContentType::JSON)
+        .body(serde_json::json!({ "email": email, "password": PASSWORD }).to_string())
+        .dispatch()
+        .await;
+    let body: serde_json::Value = response.into_json().await.unwrap();
+    body["token"].as_str().unwrap().to_string()
+}
+
+async fn me_status(client: &Client, token: &str) -> Status {
+    client
+        .get("/api/auth/me")
+        .header(bearer(token))
+        .dispatch()
+        .await
+        .status()
+}
+
+#[rocket::async_test]
+async fn password_change_revokes_other_sessions() {
+    let client = client(test_figment()).await;
+    let registered = register(&client, "alice", "alice@example.com").await;
+    let first = registered["token"].as_str().unwrap().to_string();
+    let second = login_token(&client, "alice@example.com").await;
+
+    let response = client
+        .post("/api/users/me/password")
+        .header(bearer(&second))
+        .header(ContentType::JSON)
+        .body(serde_json::json!({ "current_password": "wrong", "new_password": "n3w password" }).to_string())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Unauthorized);
+
+    let response = client
+        .post("/api/users/me/password")
+        .header(bearer(&second))
+        .header(ContentType::JSON)
+        .body(serde_json::json!({ "current_password": PASSWORD, "new_password": "n3w password" }).to_string())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+
+    assert_eq!(me_status(&client, &first).await, Status::Unauthorized);
+    assert_eq!(me_status(&client, &second).await, Status::Ok);
+    assert_eq!(login(&client, "alice@example.com", PASSWORD).await, Status::Unauthorized);
+    assert_eq!(login(&client, "alice@example.com", "n3w password").await, Status::Ok);
+}
+
+#[rocket::async_test]
+async fn logout_revokes_session() {
+    let client = client(test_figment()).await;
+    let registered = register(&client, "bob", "bob@example.com").await;
+    let token = registered["token"].as_str().unwrap();
+
+    let response = client.post("/api/auth/logout").header(bearer(token)).dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(me_status(&client, token).await, Status::Unauthorized);
+}
+
+#[rocket::async_test]
+async fn email_change_requires_verification() {
+    let client = client(test_figment()).await;
+    let registered = register(&client, "carol", "carol@example.com").await;
+    let token = registered["token"].as_str().unwrap();
+
+    let response = client
+        .patch("/api/users/me")
+        .header(bearer(token))
+        .header(ContentType::JSON)
+        .body(serde_json::json!({ "username": "carol2", "email": "carol@new.example" }).to_string())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    let body: serde_json::Value = response.into_json().await.unwrap();
+    assert_eq!(body["user"]["username"], "carol2");
+    assert_eq!(body["user"]["email"], "carol@example.com");
+    assert_eq!(body["user"]["pending_email"], "carol@new.example");
+
+    let response = client
+        .post("/api/users/me/email/verify")
+        .header(bearer(token))
+        .header(ContentType::JSON)
+        .body(serde_json::json!({ "token": "not-the-token" }).to_string())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::BadRequest);
+
+    // The real token only goes out by mail, so plant a known one
+    let db = Db::fetch(client.rocket()).unwrap();
+    sqlx::query("UPDATE users SET email_verification_hash = $1 WHERE username = 'carol2'")
+        .bind(hash_token("known-token"))
+        .execute(&**db)
+        .await
+        .unwrap();
+
+    let response = client
+        .post("/api/users/me/email/verify")
+        .header(bearer(token))
+        .header(ContentType::JSON)
+        .body(serde_json::json!({ "token": "known-token" }).to_string())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    let body: serde_json::Value = response.into_json().await.unwrap();
+    assert_eq!(body["user"]["email"], "carol@new.example");
+    assert!(body["user"]["pending_email"].is_null());
+}
+
+#[rocket::async_test]
+async fn delete_account_anonymizes_todos() {
+    let client = client(test_figment()).await;
+    let registered = register(&client, "dave", "dave@example.com").await;
+    let token = registered["token"].as_str().unwrap();
+
+    let response = client
+        .post("/api/todos")
+        .header(bearer(token))
+        .header(ContentType::JSON)
+        .body(r#"{"title":"keep me"}"#)
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Created);
+    let todo: serde_json::Value = response.into_json().await.unwrap();
+    let mut events = client.rocket().state::<Arc<EventLog>>().unwrap().subscribe();
+
+    let response = client
+        .delete("/api/users/me")
+        .header(bearer(token))
+        .header(ContentType::JSON)
+        .body(serde_json::json!({ "password": "wrong" }).to_string())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Unauthorized);
+
+    let response = client
+        .delete("/api/users/me")
+        .header(bearer(token))
+        .header(ContentType::JSON)
+        .body(serde_json::json!({ "password": PASSWORD, "todos": "anonymize" }).to_string())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::NoContent);
+
+    assert_eq!(me_status(&client, token).await, Status::Unauthorized);
+    assert_eq!(login(&client, "dave@example.com", PASSWORD).await, Status::Unauthorized);
+
+    let db = Db::fetch(client.rocket()).unwrap();
+    let orphaned: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM todos WHERE user_id IS NULL")
+        .fetch_one(&**db)
+        .await
+        .unwrap();
+    assert_eq!(orphaned, 1);
+
+    // The todo left the account, so its feeds and webhooks see it deleted
+    let event = events.recv().await.unwrap();
+    assert_eq!((event.kind, event.todo_id), (EventKind::Deleted, todo["id"].as_i64().unwrap()));
+    assert!(event.todo.is_none());
+    let logged: Vec<String> = sqlx::query_scalar("SELECT kind FROM todo_events WHERE user_id = $1")
+        .bind(registered["user"]["id"].as_i64().unwrap())
+        .fetch_all(&**db)
+        .await
+        .unwrap();
+    assert_eq!(logged, ["deleted"]);
+}
+
+#[rocket::async_test]
+async fn delete_account_cascades_todos() {
+    let client = client(test_figment()).await;
+    let registered = register(&client, "erin", "erin@example.com").await;
+    let token = registered["token"].as_str().unwrap();
+
+    let response = client
+        .post("/api/todos")
+        .header(bearer(token))
+        .header(ContentType::JSON)
+        .body(r#"{"title":"delete me"}"#)
+        .dispatch()
+        .await;
+    let todo: serde_json::Value = response.into_json().await.unwrap();
+    let mut events = client.rocket().state::<Arc<EventLog>>().unwrap().subscribe();
+
+    let response = client
+        .delete("/api/users/me")
+        .header(bearer(token))
+        .header(ContentType::JSON)
+        .body(serde_json::json!({ "password": PASSWORD }).to_string())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::NoContent);
+
+    let db = Db::fetch(client.rocket()).unwrap();
+    let remaining: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM todos")
+        .fetch_one(&**db)
+        .await
+        .unwrap();
+    assert_eq!(remaining, 0);
+
+    let event = events.recv().await.unwrap();
+    assert_eq!((event.kind, event.todo_id), (EventKind::Deleted, todo["id"].as_i64().unwrap()));
+}
+
+#[rocket::async_test]
+async fn disabled_account_is_locked_out() {
+    let client = client(test_figment()).await;
+    let registered = register(&client, "dave", "dave@example.com").await;
+    let token = registered["token"].as_str().unwrap();
+    let user_id = registered["user"]["id"].as_i64().unwrap();
+
+    let response = client
+        .post("/api/auth/api-keys")
+        .header(bearer(token))
+        .header(ContentType::JSON)
+        .body(r#"{"name": "ci"}"#)
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Created);
+    let body: serde_json::Value = response.into_json().await.unwrap();
+    let api_key = body["token"].as_str().unwrap().to_string();
+    assert_eq!(me_status(&client, &api_key).await, Status::Ok);
+
+    // What `todoctl user disable` does
+    let db = Db::fetch(client.rocket()).unwrap();
+    let users = SqlUserRepository::new((**db).clone());
+    assert!(users.set_disabled(user_id, true).await.unwrap());
+    users.revoke_all_sessions(user_id).await.unwrap();
+
+    assert_eq!(me_status(&client, token).await, Status::Unauthorized);
+    assert_eq!(me_status(&client, &api_key).await, Status::Unauthorized);
+    assert_eq!(login(&client, "dave@example.com", PASSWORD).await, Status::Forbidden);
+
+    assert!(users.set_disabled(user_id, false).await.unwrap());
+    assert_eq!(login(&client, "dave@example.com", PASSWORD).await, Status::Ok);
+    assert_eq!(me_status(&client, &api_key).await, Status::Ok);
+}
+
+#[rocket::async_test]
+async fn rejected_profile_update_changes_nothing() {
+    let client = client(test_figment()).await;
+    let registered = register(&client, "erin", "erin@example.com").await;
+    register(&client, "frank", "frank@example.com").await;
+
+    let response = client
+        .patch("/api/users/me")
+        .header(bearer(registered["token"].as_str().unwrap()))
+        .header(ContentType::JSON)
+        .body(serde_json::json!({ "username": "erin2", "email": "frank@example.com" }).to_string())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Conflict);
+
+    let db = Db::fetch(client.rocket()).unwrap();
+    let username = sqlx::query_scalar!("SELECT username FROM users WHERE email = $1", "erin@example.com")
+        .fetch_one(&**db)
+        .await
+        .unwrap();
+    assert_eq!(username, "erin");
+}
+
+/// The verification token goes to the outbox and nowhere else
+#[rocket::async_test]
+async fn verification_mail_lands_in_outbox() {
+    let figment = test_figment();
+    let outbox: std::path::PathBuf = figment.extract_inner("mailer.outbox_dir").unwrap();
+    let client = client(figment).await;
+    let registered = register(&client, "gina", "gina@example.com").await;
+    let token = registered["token"].as_str().unwrap();
+
+    let response = client
+        .patch("/api/users/me")
+        .header(bearer(token))
+        .header(ContentType::JSON)
+        .body(serde_json::json!({ "email": "gina@new.example" }).to_string())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+
+    let mut messages: Vec<_> = std::fs::read_dir(&outbox).unwrap().collect();
+    assert_eq!(messages.len(), 1);
+    let message = std::fs::read_to_string(messages.pop().unwrap().unwrap().path()).unwrap();
+    assert!(message.starts_with("To: gina@new.example\n"));
+    let verification = message
+        .split_whitespace()
+        .skip_while(|word| *word != "token")
+        .nth(1)
+        .unwrap();
+
+    let response = client
+        .post("/api/users/me/email/verify")
+        .header(bearer(token))
+        .header(ContentType::JSON)
+        .body(serde_json::json!({ "token": verification }).to_string())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+}
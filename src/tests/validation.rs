@@ -0,0 +1,226 @@
+use rocket::http::{ContentType, Status};
+use rocket::local::asynchronous::Client;
+use utoipa::OpenApi;
+
+use super::{bearer, client, register, test_figment};
+
+async fn post_todo(client: &Client, token: &str, body: serde_json::Value) -> (Status, serde_json::Value) {
+    let response = client
+        .post("/api/todos")
+        .header(bearer(token))
+        .header(ContentType::JSON)
+        .body(body.to_string())
+        .dispatch()
+        .await;
+    let status = response.status();
+    (status, response.into_json().await.unwrap())
+}
+
+fn field_codes(problem: &serde_json::Value) -> Vec<(String, String)> {
+    problem["errors"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|e| {
+            (
+                e["field"].as_str().unwrap().to_string(),
+                e["code"].as_str().unwrap().to_string(),
+            )
+        })
+        .collect()
+}
+
+#[rocket::async_test]
+async fn todo_payloads_are_trimmed_and_checked() {
+    let client = client(test_figment()).await;
+    let registered = register(&client, "alice", "alice@example.com").await;
+    let token = registered["token"].as_str().unwrap();
+
+    let (status, todo) = post_todo(
+        &client,
+        token,
+        serde_json::json!({ "title": "  buy milk  ", "description": "   ", "status": "in_progress" }),
+    )
+    .await;
+    assert_eq!(status, Status::Created);
+    assert_eq!(todo["title"], "buy milk");
+    assert!(todo["description"].is_null());
+    assert_eq!(todo["status"], "in_progress");
+
+    let (status, problem) = post_todo(&client, token, serde_json::json!({ "title": "   " })).await;
+    assert_eq!(status, Status::UnprocessableEntity);
+    assert_eq!(problem["code"], "validation_failed");
+    assert_eq!(field_codes(&problem), [("title".into(), "required".into())]);
+
+    let (status, problem) =
+        post_todo(&client, token, serde_json::json!({ "title": "x".repeat(100 * 1024) })).await;
+    assert_eq!(status, Status::UnprocessableEntity);
+    assert_eq!(field_codes(&problem), [("title".into(), "too_long".into())]);
+
+    let (status, problem) =
+        post_todo(&client, token, serde_json::json!({ "title": "t", "owner": "bob" })).await;
+    assert_eq!(status, Status::UnprocessableEntity);
+    assert_eq!(field_codes(&problem), [("owner".into(), "unknown_field".into())]);
+
+    let (status, problem) = post_todo(&client, token, serde_json::json!({})).await;
+    assert_eq!(status, Status::UnprocessableEntity);
+    assert_eq!(field_codes(&problem), [("title".into(), "required".into())]);
+
+    // Enum values are snake_case, matching what the API returns
+    let (status, problem) =
+        post_todo(&client, token, serde_json::json!({ "title": "t", "priority": "High" })).await;
+    assert_eq!(status, Status::UnprocessableEntity);
+    assert_eq!(field_codes(&problem), [("priority".into(), "invalid".into())]);
+}
+
+#[rocket::async_test]
+async fn unknown_status_filter_is_rejected() {
+    let client = client(test_figment()).await;
+    let registered = register(&client, "bob", "bob@example.com").await;
+    let token = registered["token"].as_str().unwrap();
+
+    let response = client
+        .get("/api/todos/status/in_progress")
+        .header(bearer(token))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+
+    let response = client
+        .get("/api/todos/status/done")
+        .header(bearer(token))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+    let problem: serde_json::Value = response.into_json().await.unwrap();
+    assert_eq!(field_codes(&problem), [("status".into(), "invalid_choice".into())]);
+}
+
+#[rocket::async_test]
+async fn registration_reports_every_invalid_field() {
+    let client = client(test_figment()).await;
+
+    let response = client
+        .post("/api/auth/register")
+        .header(ContentType::JSON)
+        .body(
+            serde_json::json!({ "username": "a b", "email": "not-an-email", "password": "short" })
+                .to_string(),
+        )
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+    let problem: serde_json::Value = response.into_json().await.unwrap();
+    assert_eq!(
+        field_codes(&problem),
+        [
+            ("username".into(), "invalid_format".into()),
+            ("email".into(), "invalid_format".into()),
+            ("password".into(), "too_short".into()),
+        ]
+    );
+}
+
+#[rocket::async_test]
+async fn profile_and_password_payloads_are_checked() {
+    let client = client(test_figment()).await;
+    let registered = register(&client, "carol", "carol@example.com").await;
+    let token = registered["token"].as_str().unwrap();
+
+    let send = |method: rocket::http::Method, uri: &'static str, body: serde_json::Value| {
+        let client = &client;
+        async move {
+            let response = client
+                .req(method, uri)
+                .header(bearer(token))
+                .header(ContentType::JSON)
+                .body(body.to_string())
+                .dispatch()
+                .await;
+            let status = response.status();
+            (status, response.into_json::<serde_json::Value>().await.unwrap())
+        }
+    };
+    use rocket::http::Method::{Delete, Patch, Post};
+
+    let (status, problem) =
+        send(Patch, "/api/users/me", serde_json::json!({ "username": "a b", "email": "  " })).await;
+    assert_eq!(status, Status::UnprocessableEntity);
+    assert_eq!(
+        field_codes(&problem),
+        [
+            ("username".into(), "invalid_format".into()),
+            ("email".into(), "required".into()),
+        ]
+    );
+
+    let (status, problem) =
+        send(Patch, "/api/users/me", serde_json::json!({ "role": "admin" })).await;
+    assert_eq!(status, Status::UnprocessableEntity);
+    assert_eq!(field_codes(&problem), [("role".into(), "unknown_field".into())]);
+
+    let (status, user) =
+        send(Patch, "/api/users/me", serde_json::json!({ "username": "  carol2  " })).await;
+    assert_eq!(status, Status::Ok);
+    assert_eq!(user["user"]["username"], "carol2");
+
+    let (status, problem) = send(
+        Post,
+        "/api/users/me/password",
+        serde_json::json!({ "current_password": "correct horse battery staple", "new_password": "x" }),
+    )
+    .await;
+    assert_eq!(status, Status::UnprocessableEntity);
+    assert_eq!(field_codes(&problem), [("new_password".into(), "too_short".into())]);
+
+    let (status, problem) =
+        send(Post, "/api/users/me/email/verify", serde_json::json!({ "token": " " })).await;
+    assert_eq!(status, Status::UnprocessableEntity);
+    assert_eq!(field_codes(&problem), [("token".into(), "required".into())]);
+
+    let (status, problem) =
+        send(Delete, "/api/users/me", serde_json::json!({ "password": "correct horse battery staple", "force": true })).await;
+    assert_eq!(status, Status::UnprocessableEntity);
+    assert_eq!(field_codes(&problem), [("force".into(), "unknown_field".into())]);
+}
+
+/// The `#[schema]` bounds are literals, so they are checked against the
+/// constants the validators use
+#[test]
+fn constraints_are_documented() {
+    use crate::models::*;
+
+    let doc = serde_json::to_value(crate::routes::ApiDoc::openapi()).unwrap();
+    let bounds = |schema: &str, property: &str| {
+        let property = &doc["components"]["schemas"][schema]["properties"][property];
+        (property["minLength"].as_u64(), property["maxLength"].as_u64())
+    };
+    let len = |n: usize| Some(n as u64);
+
+    let title = (len(1), len(TITLE_MAX_LEN));
+    assert_eq!(bounds("CreateTodoRequest", "title"), title);
+    assert_eq!(bounds("UpdateTodoRequest", "title"), title);
+    assert_eq!(bounds("CreateTodoRequest", "description"), (None, len(DESCRIPTION_MAX_LEN)));
+    assert_eq!(bounds("UpdateTodoRequest", "description"), (None, len(DESCRIPTION_MAX_LEN)));
+
+    let username = (len(USERNAME_MIN_LEN), len(USERNAME_MAX_LEN));
+    assert_eq!(bounds("CreateUserRequest", "username"), username);
+    assert_eq!(bounds("UpdateProfileRequest", "username"), username);
+    assert_eq!(bounds("CreateUserRequest", "email"), (None, len(EMAIL_MAX_LEN)));
+    assert_eq!(bounds("LoginRequest", "email"), (None, len(EMAIL_MAX_LEN)));
+    assert_eq!(bounds("UpdateProfileRequest", "email"), (None, len(EMAIL_MAX_LEN)));
+
+    let new_password = (len(PASSWORD_MIN_LEN), len(PASSWORD_MAX_LEN));
+    assert_eq!(bounds("CreateUserRequest", "password"), new_password);
+    assert_eq!(bounds("ChangePasswordRequest", "new_password"), new_password);
+    assert_eq!(bounds("LoginRequest", "password"), (len(1), len(PASSWORD_MAX_LEN)));
+    assert_eq!(bounds("ChangePasswordRequest", "current_password"), (len(1), len(PASSWORD_MAX_LEN)));
+    assert_eq!(bounds("DeleteAccountRequest", "password"), (None, len(PASSWORD_MAX_LEN)));
+    assert_eq!(bounds("VerifyEmailRequest", "token"), (len(1), len(VERIFICATION_TOKEN_MAX_LEN)));
+
+    let status = &doc["components"]["schemas"]["Status"]["enum"];
+    assert_eq!(
+        status,
+        &serde_json::json!(["pending", "in_progress", "blocked", "completed", "cancelled"])
+    );
+}
@@ -0,0 +1,380 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use rocket::http::{ContentType, Status};
+use rocket::local::asynchronous::Client;
+use rocket::tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use rocket::tokio::net::TcpListener;
+use rocket::tokio::sync::mpsc;
+
+use rocket_db_pools::Database;
+
+use super::{admin_token, bearer, register, test_figment, untracked_client};
+use crate::database::Db;
+use crate::webhooks::{self, sign, WebhookConfig};
+
+/// A request the receiver got: lowercased headers and the body
+struct Received {
+    headers: HashMap<String, String>,
+    body: String,
+}
+
+/// An endpoint answering each request with the next of `statuses`
+async fn receiver(statuses: Vec<u16>) -> (String, mpsc::UnboundedReceiver<Received>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/hook", listener.local_addr().unwrap());
+    let (sender, received) = mpsc::unbounded_channel();
+
+    rocket::tokio::spawn(async move {
+        for status in statuses {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut stream = BufReader::new(stream);
+            let mut line = String::new();
+            stream.read_line(&mut line).await.unwrap();
+            let mut headers = HashMap::new();
+            loop {
+                line.clear();
+                stream.read_line(&mut line).await.unwrap();
+                let Some((name, value)) = line.trim_end().split_once(':') else {
+                    break;
+                };
+                headers.insert(name.to_ascii_lowercase(), value.trim().to_string());
+            }
+            let mut body = vec![0; headers["content-length"].parse().unwrap()];
+            stream.read_exact(&mut body).await.unwrap();
+
+            let answer = format!("HTTP/1.1 {} Status\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", status);
+            stream.get_mut().write_all(answer.as_bytes()).await.unwrap();
+            let _ = sender.send(Received {
+                headers,
+                body: String::from_utf8(body).unwrap(),
+            });
+        }
+    });
+    (url, received)
+}
+
+async fn next(received: &mut mpsc::UnboundedReceiver<Received>) -> Received {
+    rocket::tokio::time::timeout(Duration::from_secs(10), received.recv())
+        .await
+        .expect("a delivery within 10 seconds")
+        .unwrap()
+}
+
+async fn create_webhook(client: &Client, token: &str, body: serde_json::Value) -> (Status, serde_json::Value) {
+    let response = client
+        .post("/api/webhooks")
+        .header(ContentType::JSON)
+        .header(bearer(token))
+        .body(body.to_string())
+        .dispatch()
+        .await;
+    (response.status(), response.into_json().await.unwrap_or_default())
+}
+
+async fn create_todo(client: &Client, token: &str, title: &str) -> serde_json::Value {
+    let response = client
+        .post("/api/todos")
+        .header(ContentType::JSON)
+        .header(bearer(token))
+        .body(serde_json::json!({ "title": title }).to_string())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Created);
+    response.into_json().await.unwrap()
+}
+
+async fn deliveries(client: &Client, token: &str, webhook_id: &serde_json::Value) -> serde_json::Value {
+    let response = client
+        .get(format!("/api/webhooks/{}/deliveries", webhook_id))
+        .header(bearer(token))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    response.into_json().await.unwrap()
+}
+
+#[rocket::async_test]
+async fn deliveries_are_signed_retried_and_logged() {
+    let figment = test_figment()
+        .merge(("webhooks.retry_base_secs", 1))
+        .merge(("webhooks.poll_secs", 1))
+        .merge(("webhooks.allow_private_networks", true));
+    let client = untracked_client(figment).await;
+    let alice = register(&client, "alice", "alice@example.com").await;
+    let token = alice["token"].as_str().unwrap();
+    let (url, mut received) = receiver(vec![500, 200, 200]).await;
+
+    let (status, created) = create_webhook(
+        &client,
+        token,
+        serde_json::json!({ "url": url, "event_types": ["todo.completed"] }),
+    )
+    .await;
+    assert_eq!(status, Status::Created);
+    let secret = created["secret"].as_str().unwrap();
+    assert!(secret.starts_with("whsec_"));
+    let webhook_id = created["webhook"]["id"].clone();
+
+    // Only completing the todo is subscribed to
+    let todo = create_todo(&client, token, "ship it").await;
+    let response = client
+        .put(format!("/api/todos/{}", todo["id"]))
+        .header(ContentType::JSON)
+        .header(bearer(token))
+        .body(r#"{"status":"completed"}"#)
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+
+    let first = next(&mut received).await;
+    assert_eq!(first.headers["x-webhook-event"], "todo.completed");
+    assert_eq!(first.headers["content-type"], "application/json");
+    let timestamp: i64 = first.headers["x-webhook-timestamp"].parse().unwrap();
+    assert!((chrono::Utc::now().timestamp() - timestamp).abs() < 60);
+    assert_eq!(first.headers["x-webhook-signature"], sign(secret, timestamp, &first.body));
+    let payload: serde_json::Value = serde_json::from_str(&first.body).unwrap();
+    assert_eq!(payload["type"], "todo.completed");
+    assert_eq!(payload["todo_id"], todo["id"]);
+    assert_eq!(payload["todo"]["status"], "completed");
+    assert_eq!(payload["previous_status"], "pending");
+
+    // The endpoint failed, so the same delivery is sent again
+    let retry = next(&mut received).await;
+    assert_eq!(retry.body, first.body);
+    assert_eq!(retry.headers["x-webhook-id"], first.headers["x-webhook-id"]);
+
+    let mut log = deliveries(&client, token, &webhook_id).await;
+    for _ in 0..50 {
+        if log[0]["status"] == "succeeded" {
+            break;
+        }
+        rocket::tokio::time::sleep(Duration::from_millis(100)).await;
+        log = deliveries(&client, token, &webhook_id).await;
+    }
+    assert_eq!(log.as_array().unwrap().len(), 1);
+    assert_eq!(log[0]["status"], "succeeded");
+    assert_eq!(log[0]["attempts"], 2);
+    assert_eq!(log[0]["response_status"], 200);
+    assert_eq!(log[0]["event_type"], "todo.completed");
+
+    let delivery_id = log[0]["id"].clone();
+    let response = client
+        .post(format!("/api/webhooks/{}/deliveries/{}/redeliver", webhook_id, delivery_id))
+        .header(bearer(token))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Accepted);
+    let redelivery: serde_json::Value = response.into_json().await.unwrap();
+    assert_eq!(redelivery["redelivery_of"], delivery_id);
+    assert_eq!(redelivery["status"], "pending");
+    assert_eq!(redelivery["attempts"], 0);
+
+    let again = next(&mut received).await;
+    assert_eq!(again.body, first.body);
+    assert_eq!(again.headers["x-webhook-id"], redelivery["id"].to_string());
+
+    let response = client
+        .post(format!("/api/webhooks/{}/deliveries/9999/redeliver", webhook_id))
+        .header(bearer(token))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+#[rocket::async_test]
+async fn webhooks_are_checked_and_kept_to_their_owner() {
+    let client = untracked_client(test_figment().merge(("webhooks.allow_private_networks", true))).await;
+    let alice = register(&client, "alice", "alice@example.com").await;
+    let bob = register(&client, "bob", "bob@example.com").await;
+    let alice_token = alice["token"].as_str().unwrap();
+    let bob_token = bob["token"].as_str().unwrap();
+
+    let (status, problem) =
+        create_webhook(&client, alice_token, serde_json::json!({ "url": "ftp://example.com/hook" })).await;
+    assert_eq!(status, Status::UnprocessableEntity);
+    assert_eq!(problem["errors"][0]["field"], "url");
+    let body = serde_json::json!({ "url": "https://example.com/hook", "event_types": ["todo.archived"] });
+    let (status, problem) = create_webhook(&client, alice_token, body).await;
+    assert_eq!(status, Status::UnprocessableEntity);
+    assert_eq!(problem["errors"][0]["field"], "event_types[0]");
+    let body = serde_json::json!({ "url": "https://example.com/hook", "all_users": true });
+    let (status, _) = create_webhook(&client, alice_token, body).await;
+    assert_eq!(status, Status::Forbidden);
+
+    let (status, created) =
+        create_webhook(&client, alice_token, serde_json::json!({ "url": "https://example.com/hook" })).await;
+    assert_eq!(status, Status::Created);
+    let id = created["webhook"]["id"].clone();
+    assert_eq!(created["webhook"]["event_types"].as_array().unwrap().len(), 4);
+
+    let response = client.get("/api/webhooks").header(bearer(alice_token)).dispatch().await;
+    let listed: serde_json::Value = response.into_json().await.unwrap();
+    assert_eq!(listed[0]["id"], id);
+    assert!(listed[0].get("secret").is_none());
+
+    // Bob cannot tell alice's webhook from a missing one
+    let response = client.get("/api/webhooks").header(bearer(bob_token)).dispatch().await;
+    assert_eq!(response.into_json::<serde_json::Value>().await.unwrap(), serde_json::json!([]));
+    let response = client
+        .get(format!("/api/webhooks/{}/deliveries", id))
+        .header(bearer(bob_token))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::NotFound);
+    let response = client.delete(format!("/api/webhooks/{}", id)).header(bearer(bob_token)).dispatch().await;
+    assert_eq!(response.status(), Status::NotFound);
+
+    // An administrator's all_users webhook receives everyone's events
+    let root = admin_token(&client, "root").await;
+    let (url, mut received) = receiver(vec![200]).await;
+    let body = serde_json::json!({ "url": url, "event_types": ["todo.created"], "all_users": true });
+    let (status, _) = create_webhook(&client, &root, body).await;
+    assert_eq!(status, Status::Created);
+    create_todo(&client, bob_token, "bob's todo").await;
+    let delivery = next(&mut received).await;
+    let payload: serde_json::Value = serde_json::from_str(&delivery.body).unwrap();
+    assert_eq!(payload["type"], "todo.created");
+    assert_eq!(payload["user_id"], bob["user"]["id"]);
+
+    let response = client.delete(format!("/api/webhooks/{}", id)).header(bearer(alice_token)).dispatch().await;
+    assert_eq!(response.status(), Status::NoContent);
+    let response = client.get("/api/webhooks").header(bearer(alice_token)).dispatch().await;
+    assert_eq!(response.into_json::<serde_json::Value>().await.unwrap(), serde_json::json!([]));
+}
+
+#[test]
+fn only_public_addresses_are_deliverable() {
+    for public in ["93.184.215.14", "2606:4700::6810:84e5", "::ffff:8.8.8.8", "64:ff9b::808:808"] {
+        assert!(webhooks::is_public(public.parse().unwrap()), "{}", public);
+    }
+    for private in [
+        "127.0.0.1",
+        "10.1.2.3",
+        "172.16.0.1",
+        "192.168.1.1",
+        "169.254.169.254",
+        "100.64.0.1",
+        "0.0.0.0",
+        "255.255.255.255",
+        "::1",
+        "::",
+        "fd00::1",
+        "fe80::1",
+        "::ffff:127.0.0.1",
+        "64:ff9b::a00:1",
+        "2002:c0a8:101::1",
+    ] {
+        assert!(!webhooks::is_public(private.parse().unwrap()), "{}", private);
+    }
+}
+
+#[rocket::async_test]
+async fn private_destinations_are_refused() {
+    let client = untracked_client(test_figment().merge(("webhooks.poll_secs", 1))).await;
+    let alice = register(&client, "alice", "alice@example.com").await;
+    let token = alice["token"].as_str().unwrap();
+    let (url, mut received) = receiver(vec![200]).await;
+    let by_name = url.replace("127.0.0.1", "localhost");
+
+    for url in [url.as_str(), by_name.as_str(), "http://[::1]/hook", "http://169.254.169.254/latest/meta-data"] {
+        let (status, problem) = create_webhook(&client, token, serde_json::json!({ "url": url })).await;
+        assert_eq!(status, Status::UnprocessableEntity, "{}", url);
+        assert_eq!(problem["errors"][0]["field"], "url");
+        assert_eq!(problem["errors"][0]["code"], "forbidden_destination");
+    }
+
+    // Webhooks that got in some other way (or whose name was pointed at a
+    // private address since) are refused when delivering
+    let db = Db::fetch(client.rocket()).unwrap();
+    let user_id = alice["user"]["id"].as_i64().unwrap();
+    let now = chrono::Utc::now().naive_utc();
+    for url in [&url, &by_name] {
+        sqlx::query(
+            "INSERT INTO webhooks (user_id, url, secret, event_types, all_users, created_at) VALUES ($1, $2, 'whsec_x', 'todo.created', FALSE, $3)",
+        )
+        .bind(user_id)
+        .bind(url)
+        .bind(now)
+        .execute(&**db)
+        .await
+        .unwrap();
+    }
+    create_todo(&client, token, "kept inside").await;
+
+    for id in [1, 2] {
+        let webhook_id = serde_json::json!(id);
+        let mut log = deliveries(&client, token, &webhook_id).await;
+        for _ in 0..50 {
+            if log[0]["attempts"] == 1 {
+                break;
+            }
+            rocket::tokio::time::sleep(Duration::from_millis(100)).await;
+            log = deliveries(&client, token, &webhook_id).await;
+        }
+        assert_eq!(log[0]["attempts"], 1);
+        assert_eq!(log[0]["response_status"], serde_json::Value::Null);
+        let error = log[0]["last_error"].as_str().unwrap();
+        assert!(error.contains("not a public address"), "{}", error);
+    }
+    assert!(received.try_recv().is_err());
+}
+
+#[rocket::async_test]
+async fn claimed_deliveries_are_sent_once() {
+    let figment = test_figment().merge(("webhooks.allow_private_networks", true));
+    let client = untracked_client(figment).await;
+    let alice = register(&client, "alice", "alice@example.com").await;
+    let token = alice["token"].as_str().unwrap();
+    let (url, mut received) = receiver(vec![200, 200]).await;
+    let (status, created) = create_webhook(&client, token, serde_json::json!({ "url": url })).await;
+    assert_eq!(status, Status::Created);
+    let webhook_id = created["webhook"]["id"].as_i64().unwrap();
+
+    // Queued directly, and claimed by some other instance for now
+    let db = Db::fetch(client.rocket()).unwrap();
+    let now = chrono::Utc::now().naive_utc();
+    let later = now + chrono::Duration::hours(1);
+    sqlx::query(
+        "INSERT INTO webhook_deliveries (webhook_id, event_id, event_type, payload, status, next_attempt_at, locked_until, created_at)
+        VALUES ($1, 1, 'todo.created', '{}', 'pending', $2, $3, $4)",
+    )
+    .bind(webhook_id)
+    .bind(now)
+    .bind(later)
+    .bind(now)
+    .execute(&**db)
+    .await
+    .unwrap();
+
+    let config = WebhookConfig {
+        allow_private_networks: true,
+        ..WebhookConfig::default()
+    };
+    let http = reqwest::Client::new();
+    assert_eq!(webhooks::deliver_due(db, &http, &config).await.unwrap(), 0);
+
+    // Once the claim lapses, racing workers still send it only once
+    sqlx::query("UPDATE webhook_deliveries SET locked_until = $1").bind(now).execute(&**db).await.unwrap();
+    let (first, second) = rocket::tokio::join!(
+        webhooks::deliver_due(db, &http, &config),
+        webhooks::deliver_due(db, &http, &config)
+    );
+    assert!(first.unwrap() + second.unwrap() <= 1);
+    next(&mut received).await;
+    rocket::tokio::time::sleep(Duration::from_millis(500)).await;
+    assert!(received.try_recv().is_err());
+
+    let log = deliveries(&client, token, &created["webhook"]["id"]).await;
+    assert_eq!(log[0]["status"], "succeeded");
+    assert_eq!(log[0]["attempts"], 1);
+}
+
+#[rocket::async_test]
+async fn invalid_webhook_settings_refuse_to_ignite() {
+    let figment = test_figment().merge(("webhooks.retry_max_secs", 1));
+    let Err(error) = Client::tracked(crate::app(figment)).await else {
+        panic!("started with a retry cap below the first retry");
+    };
+    assert!(matches!(error.kind(), rocket::error::ErrorKind::FailedFairings(_)));
+}
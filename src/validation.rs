@@ -0,0 +1,186 @@
+use std::ops::{Deref, DerefMut};
+
+use rocket::data::{self, Data, FromData, ToByteUnit};
+use rocket::http::Status;
+use rocket::Request;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Deserializer};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+/// One rejected field in a 422 response
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct FieldError {
+    /// Dotted path to the field, or `body` when the payload as a whole is wrong
+    #[schema(example = "title")]
+    pub field: String,
+    #[schema(example = "too_long")]
+    pub code: &'static str,
+    #[schema(example = "must be at most 200 characters")]
+    pub message: String,
+}
+
+impl FieldError {
+    pub fn new(field: &str, code: &'static str, message: impl Into<String>) -> Self {
+        FieldError {
+            field: field.to_string(),
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+/// Collects field errors while a payload is checked
+#[derive(Debug, Default)]
+pub struct Violations(Vec<FieldError>);
+
+impl Violations {
+    pub fn push(&mut self, field: &str, code: &'static str, message: impl Into<String>) {
+        self.0.push(FieldError::new(field, code, message));
+    }
+
+    /// Checks the length of an already trimmed value, counted in characters
+    pub fn length(&mut self, field: &str, value: &str, min: usize, max: usize) {
+        let len = value.chars().count();
+        if len < min {
+            if min == 1 {
+                self.push(field, "required", "must not be empty");
+            } else {
+                self.push(field, "too_short", format!("must be at least {} characters", min));
+            }
+        } else if len > max {
+            self.push(field, "too_long", format!("must be at most {} characters", max));
+        }
+    }
+
+    pub fn into_result(self) -> Result<(), Vec<FieldError>> {
+        if self.0.is_empty() {
+            Ok(())
+        } else {
+            Err(self.0)
+        }
+    }
+}
+
+/// Request payloads that normalize and check themselves after deserializing
+pub trait Validate {
+    /// Trims input in place and reports every invalid field
+    fn validate(&mut self) -> Result<(), Vec<FieldError>>;
+}
+
+pub fn trim(value: &mut String) {
+    let trimmed = value.trim();
+    if trimmed.len() != value.len() {
+        *value = trimmed.to_string();
+    }
+}
+
+/// Trims an optional value, turning blank input into `None`
+pub fn trim_optional(value: &mut Option<String>) {
+    if let Some(inner) = value {
+        trim(inner);
+        if inner.is_empty() {
+            *value = None;
+        }
+    }
+}
+
+/// Why a `Validated` guard failed; read by the 422 catcher
+#[derive(Debug, Clone)]
+pub struct ValidationFailure(pub Vec<FieldError>);
+
+/// A JSON body that has been deserialized and passed [`Validate`].
+/// Failures become 422 problem documents listing each field.
+#[derive(Debug)]
+pub struct Validated<T>(pub T);
+
+impl<T> Deref for Validated<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for Validated<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+/// Turns a serde error into a field error, naming the field where serde can
+fn parse_error(error: serde_path_to_error::Error<serde_json::Error>) -> FieldError {
+    let path = error.path().to_string();
+    let message = error.inner().to_string();
+
+    // The path already ends in an unknown field, but stops at the object
+    // that is missing one
+    if message.starts_with("unknown field `") {
+        return FieldError::new(&path, "unknown_field", "is not a recognised field");
+    }
+    if let Some(name) = message
+        .strip_prefix("missing field `")
+        .and_then(|rest| rest.split('`').next())
+    {
+        let field = match path.as_str() {
+            "." => name.to_string(),
+            parent => format!("{}.{}", parent, name),
+        };
+        return FieldError::new(&field, "required", "is required");
+    }
+
+    let field = if path == "." { "body" } else { path.as_str() };
+    let message = message
+        .split(" at line ")
+        .next()
+        .unwrap_or_default()
+        .to_string();
+    FieldError::new(field, "invalid", message)
+}
+
+/// Deserializes JSON, from a body or an already parsed value, and validates
+/// it, reporting errors as a `Validated` body would
+pub fn parse<'de, T, D>(deserializer: D) -> Result<T, Vec<FieldError>>
+where
+    T: Deserialize<'de> + Validate,
+    D: Deserializer<'de, Error = serde_json::Error>,
+{
+    let mut value: T = serde_path_to_error::deserialize(deserializer).map_err(|e| vec![parse_error(e)])?;
+    value.validate()?;
+    Ok(value)
+}
+
+fn fail<'r, T>(
+    request: &Request<'_>,
+    status: Status,
+    errors: Vec<FieldError>,
+) -> data::Outcome<'r, T, ValidationFailure> {
+    let failure = ValidationFailure(errors);
+    request.local_cache(|| Some(failure.clone()));
+    data::Outcome::Error((status, failure))
+}
+
+#[rocket::async_trait]
+impl<'r, T: DeserializeOwned + Validate + Send> FromData<'r> for Validated<T> {
+    type Error = ValidationFailure;
+
+    async fn from_data(request: &'r Request<'_>, data: Data<'r>) -> data::Outcome<'r, Self> {
+        let limit = request.limits().get("json").unwrap_or(1.mebibytes());
+        let body = match data.open(limit).into_string().await {
+            Ok(body) if body.is_complete() => body.into_inner(),
+            Ok(_) => {
+                let error = FieldError::new("body", "too_large", "request body is too large");
+                return fail(request, Status::UnprocessableEntity, vec![error]);
+            }
+            Err(e) => {
+                let error = FieldError::new("body", "unreadable", e.to_string());
+                return fail(request, Status::BadRequest, vec![error]);
+            }
+        };
+
+        match parse(&mut serde_json::Deserializer::from_str(&body)) {
+            Ok(value) => data::Outcome::Success(Validated(value)),
+            Err(errors) => fail(request, Status::UnprocessableEntity, errors),
+        }
+    }
+}
@@ -0,0 +1,583 @@
+//! Outgoing webhooks. Every todo event in the log is matched against the
+//! registered webhooks and queued in `webhook_deliveries`; a worker POSTs
+//! due deliveries, signed with the webhook's secret, and retries failures
+//! with exponential backoff. The queue is a table, so deliveries pending
+//! at shutdown are sent after the next start, and a worker claims each
+//! delivery before sending it, so instances sharing the database do not
+//! both send it.
+//!
+//! Endpoints must be public: a URL whose host is, or resolves to, a
+//! loopback, private, link-local or otherwise special-purpose address is
+//! refused when the webhook is created and again on every attempt, against
+//! the addresses the attempt actually connects to, so a DNS record changed
+//! in between does not get around the check.
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use rocket::fairing::AdHoc;
+use rocket::serde::Deserialize;
+use rocket::tokio::sync::broadcast::error::TryRecvError;
+use rocket::tokio::task::JoinSet;
+use rocket::Shutdown;
+use rocket_db_pools::Database;
+use sha2::Sha256;
+
+use crate::auth::password::generate_token;
+use crate::database::{Db, DbPool};
+use crate::events::{EventKind, EventLog, TodoEvent};
+use crate::health::Health;
+use crate::models::{DeliveryStatus, Status};
+
+/// Every type a webhook can subscribe to. `todo.completed` is sent
+/// alongside `todo.updated` when an update marks a todo completed.
+pub const EVENT_TYPES: [&str; 4] = ["todo.created", "todo.updated", "todo.completed", "todo.deleted"];
+
+/// Most events queued in one pass
+const EVENT_BATCH: usize = 500;
+
+/// Most deliveries attempted at once
+const DELIVERY_BATCH: i64 = 20;
+
+/// How long a claim outlasts the attempt's timeout. A worker that dies
+/// mid-attempt leaves its deliveries to be retried once the claim lapses.
+const CLAIM_MARGIN_SECS: u64 = 60;
+
+/// The `[webhooks]` section of Rocket.toml
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct WebhookConfig {
+    /// Attempts before a delivery is given up as failed
+    pub max_attempts: i64,
+    /// The wait before the first retry; it doubles with every failure
+    pub retry_base_secs: u64,
+    /// The longest wait between attempts
+    pub retry_max_secs: u64,
+    /// How long an endpoint has to answer
+    pub timeout_secs: u64,
+    /// How often the queue is checked for due retries
+    pub poll_secs: u64,
+    /// How long finished deliveries stay in the delivery log
+    pub retention_days: i64,
+    /// Lets webhooks reach loopback and private addresses, for development
+    /// against a local receiver. Never enable it where users are untrusted.
+    pub allow_private_networks: bool,
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        WebhookConfig {
+            max_attempts: 8,
+            retry_base_secs: 30,
+            retry_max_secs: 6 * 3600,
+            timeout_secs: 10,
+            poll_secs: 5,
+            retention_days: 30,
+            allow_private_networks: false,
+        }
+    }
+}
+
+impl WebhookConfig {
+    fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.max_attempts < 1 {
+            problems.push(format!("webhooks.max_attempts must be at least 1, got {}", self.max_attempts));
+        }
+        if self.retry_base_secs == 0 {
+            problems.push("webhooks.retry_base_secs must be at least 1".to_string());
+        }
+        if self.retry_max_secs < self.retry_base_secs {
+            problems.push(format!(
+                "webhooks.retry_max_secs ({}) must not be below webhooks.retry_base_secs ({})",
+                self.retry_max_secs, self.retry_base_secs
+            ));
+        }
+        if self.timeout_secs == 0 {
+            problems.push("webhooks.timeout_secs must be at least 1".to_string());
+        }
+        if self.poll_secs == 0 {
+            problems.push("webhooks.poll_secs must be at least 1".to_string());
+        }
+        if self.retention_days < 1 {
+            problems.push(format!("webhooks.retention_days must be at least 1, got {}", self.retention_days));
+        }
+        problems
+    }
+
+    /// The wait after a delivery has failed `attempts` times
+    pub fn backoff(&self, attempts: i64) -> Duration {
+        let doublings = attempts.saturating_sub(1).clamp(0, 32) as u32;
+        let secs = self.retry_base_secs.saturating_mul(1 << doublings);
+        Duration::from_secs(secs.min(self.retry_max_secs))
+    }
+}
+
+/// Whether a webhook may be delivered to `ip`. Everything reserved for a
+/// host or a private network is refused, as are IPv6 forms that embed such
+/// an IPv4 address.
+pub fn is_public(ip: IpAddr) -> bool {
+    match ip.to_canonical() {
+        IpAddr::V4(ip) => is_public_v4(ip),
+        IpAddr::V6(ip) => is_public_v6(ip),
+    }
+}
+
+fn is_public_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, c, _] = ip.octets();
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || ip.is_multicast()
+        || a == 0
+        // Shared address space (carrier-grade NAT)
+        || (a == 100 && (64..128).contains(&b))
+        // IETF protocol assignments
+        || (a == 192 && b == 0 && c == 0)
+        // Benchmarking
+        || (a == 198 && (18..20).contains(&b))
+        // Reserved
+        || a >= 240)
+}
+
+fn is_public_v6(ip: Ipv6Addr) -> bool {
+    let segments = ip.segments();
+    // NAT64 and 6to4 carry an IPv4 address, which has to be public too
+    if segments[..6] == [0x64, 0xff9b, 0, 0, 0, 0] {
+        let [a, b] = segments[6].to_be_bytes();
+        let [c, d] = segments[7].to_be_bytes();
+        return is_public_v4(Ipv4Addr::new(a, b, c, d));
+    }
+    if segments[0] == 0x2002 {
+        let [a, b] = segments[1].to_be_bytes();
+        let [c, d] = segments[2].to_be_bytes();
+        return is_public_v4(Ipv4Addr::new(a, b, c, d));
+    }
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_multicast()
+        // Unique local, fc00::/7
+        || (segments[0] & 0xfe00) == 0xfc00
+        // Link-local and the deprecated site-local, fe80::/10 and fec0::/10
+        || (segments[0] & 0xff80) == 0xfe80
+        || (segments[0] & 0xffc0) == 0xfec0
+        // Documentation, 2001:db8::/32
+        || (segments[0] == 0x2001 && segments[1] == 0x0db8)
+        // IPv4-compatible, ::/96
+        || segments[..6] == [0, 0, 0, 0, 0, 0])
+}
+
+/// The host of `url` when it is an IP address rather than a name
+fn literal_ip(url: &reqwest::Url) -> Option<IpAddr> {
+    url.host_str()?.trim_start_matches('[').trim_end_matches(']').parse().ok()
+}
+
+/// Checks that `url` points at public addresses only, resolving its host.
+/// The error says why not, for the caller to show.
+pub async fn check_destination(url: &str) -> Result<(), String> {
+    let url = reqwest::Url::parse(url).map_err(|_| "is not a valid URL".to_string())?;
+    let addresses: Vec<IpAddr> = match literal_ip(&url) {
+        Some(ip) => vec![ip],
+        None => {
+            let host = url.host_str().ok_or_else(|| "has no host".to_string())?;
+            let port = url.port_or_known_default().unwrap_or(80);
+            match tokio::net::lookup_host((host, port)).await {
+                Ok(resolved) => resolved.map(|address| address.ip()).collect(),
+                Err(_) => return Err(format!("host {} could not be resolved", host)),
+            }
+        }
+    };
+    if addresses.is_empty() {
+        return Err("host has no addresses".to_string());
+    }
+    match addresses.iter().find(|ip| !is_public(**ip)) {
+        Some(ip) => Err(format!("points at {}, which is not a public address", ip)),
+        None => Ok(()),
+    }
+}
+
+/// Resolves hosts for deliveries and refuses names with any address that is
+/// not public. The connection goes to the addresses checked here, so the
+/// name cannot be pointed elsewhere between the check and the request.
+struct PublicResolver;
+
+impl reqwest::dns::Resolve for PublicResolver {
+    fn resolve(&self, name: reqwest::dns::Name) -> reqwest::dns::Resolving {
+        Box::pin(async move {
+            let host = name.as_str();
+            let addresses: Vec<SocketAddr> = tokio::net::lookup_host((host, 0)).await?.collect();
+            if let Some(address) = addresses.iter().find(|address| !is_public(address.ip())) {
+                return Err(format!("{} resolves to {}, which is not a public address", host, address.ip()).into());
+            }
+            Ok(Box::new(addresses.into_iter()) as reqwest::dns::Addrs)
+        })
+    }
+}
+
+/// A new webhook secret
+pub fn generate_secret() -> String {
+    format!("whsec_{}", generate_token())
+}
+
+/// The `X-Webhook-Signature` of a delivery: HMAC-SHA256 of
+/// `"{timestamp}.{body}"`, so the timestamp cannot be swapped on replay
+pub fn sign(secret: &str, timestamp: i64, body: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC takes keys of any length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body.as_bytes());
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// The webhook event types an event is delivered as
+pub fn event_types(event: &TodoEvent) -> Vec<&'static str> {
+    let mut types = vec![event.kind.event_type()];
+    let completed = event.todo.as_ref().is_some_and(|todo| todo.status == Status::Completed);
+    if event.kind == EventKind::Updated && event.previous_status.is_some() && completed {
+        types.push("todo.completed");
+    }
+    types
+}
+
+/// The body POSTed for one event
+fn payload(event: &TodoEvent, event_type: &str) -> String {
+    serde_json::json!({
+        "type": event_type,
+        "event_id": event.id,
+        "user_id": event.user_id,
+        "todo_id": event.todo_id,
+        "todo": event.todo,
+        "previous_status": event.previous_status,
+        "created_at": event.created_at,
+    })
+    .to_string()
+}
+
+/// Queues a delivery to each matching webhook for the events after the
+/// cursor, and moves the cursor past them. Returns how many were queued.
+pub async fn enqueue(pool: &DbPool, log: &EventLog) -> Result<usize, sqlx::Error> {
+    let cursor = sqlx::query_scalar!("SELECT last_event_id FROM webhook_cursor WHERE id = 1")
+        .fetch_one(pool)
+        .await?;
+    let events = log.all_since(cursor, EVENT_BATCH).await?;
+    let Some(last) = events.last().map(|event| event.id) else {
+        return Ok(0);
+    };
+
+    let mut tx = pool.begin().await?;
+    // Claims the events, in case another instance shares the database
+    let claimed = sqlx::query!(
+        "UPDATE webhook_cursor SET last_event_id = $1 WHERE id = 1 AND last_event_id = $2",
+        last,
+        cursor
+    )
+    .execute(&mut *tx)
+    .await?;
+    if claimed.rows_affected() == 0 {
+        return Ok(0);
+    }
+
+    let now = Utc::now().naive_utc();
+    let mut queued = 0;
+    for event in &events {
+        let webhooks = sqlx::query!(
+            r#"SELECT w.id AS "id!", w.event_types FROM webhooks w JOIN users u ON u.id = w.user_id
+            WHERE w.user_id = $1 OR (w.all_users AND u.role = 'admin')"#,
+            event.user_id
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+
+        for event_type in event_types(event) {
+            let body = payload(event, event_type);
+            let subscribed = webhooks
+                .iter()
+                .filter(|webhook| webhook.event_types.split_whitespace().any(|t| t == event_type));
+            for webhook in subscribed {
+                sqlx::query!(
+                    "INSERT INTO webhook_deliveries (webhook_id, event_id, event_type, payload, status, next_attempt_at, created_at)
+                    VALUES ($1, $2, $3, $4, 'pending', $5, $6)",
+                    webhook.id,
+                    event.id,
+                    event_type,
+                    body,
+                    now,
+                    now
+                )
+                .execute(&mut *tx)
+                .await?;
+                queued += 1;
+            }
+        }
+    }
+
+    tx.commit().await?;
+    Ok(queued)
+}
+
+/// A queued delivery with where and how to send it
+struct Due {
+    id: i64,
+    webhook_id: i64,
+    event_type: String,
+    payload: String,
+    attempts: i64,
+    url: String,
+    secret: String,
+}
+
+/// The HTTP status the endpoint answered with, and why the attempt failed
+type AttemptResult = (Option<i64>, Option<String>);
+
+async fn attempt(http: &reqwest::Client, delivery: &Due, allow_private_networks: bool) -> AttemptResult {
+    // Names are checked as they are resolved; addresses never are
+    if !allow_private_networks
+        && let Ok(url) = reqwest::Url::parse(&delivery.url)
+        && let Some(ip) = literal_ip(&url)
+        && !is_public(ip)
+    {
+        return (None, Some(format!("refused: {} is not a public address", ip)));
+    }
+
+    let timestamp = Utc::now().timestamp();
+    let sent = http
+        .post(&delivery.url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .header("X-Webhook-Id", delivery.id.to_string())
+        .header("X-Webhook-Event", &delivery.event_type)
+        .header("X-Webhook-Timestamp", timestamp.to_string())
+        .header("X-Webhook-Signature", sign(&delivery.secret, timestamp, &delivery.payload))
+        .body(delivery.payload.clone())
+        .send()
+        .await;
+
+    match sent {
+        Ok(response) if response.status().is_success() => (Some(i64::from(response.status().as_u16())), None),
+        Ok(response) => (
+            Some(i64::from(response.status().as_u16())),
+            Some(format!("endpoint answered {}", response.status())),
+        ),
+        Err(e) => {
+            // reqwest's own message leaves out why, e.g. a refused address
+            let mut error = e.to_string();
+            let mut source = std::error::Error::source(&e);
+            while let Some(cause) = source {
+                error = format!("{}: {}", error, cause);
+                source = cause.source();
+            }
+            (None, Some(error))
+        }
+    }
+}
+
+/// Sends every due delivery, at most [`DELIVERY_BATCH`] at a time, and
+/// records the outcomes. Returns how many were attempted.
+pub async fn deliver_due(pool: &DbPool, http: &reqwest::Client, config: &WebhookConfig) -> Result<usize, sqlx::Error> {
+    let now = Utc::now().naive_utc();
+    let due = sqlx::query_as!(
+        Due,
+        r#"SELECT d.id AS "id!", d.webhook_id, d.event_type, d.payload, d.attempts, w.url, w.secret
+        FROM webhook_deliveries d JOIN webhooks w ON w.id = d.webhook_id
+        WHERE d.status = 'pending' AND d.next_attempt_at <= $1 AND (d.locked_until IS NULL OR d.locked_until <= $2)
+        ORDER BY d.next_attempt_at LIMIT $3"#,
+        now,
+        now,
+        DELIVERY_BATCH
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let claim = Duration::from_secs(config.timeout_secs.saturating_add(CLAIM_MARGIN_SECS));
+    let locked_until = chrono::Duration::from_std(claim)
+        .ok()
+        .and_then(|claim| now.checked_add_signed(claim))
+        .unwrap_or(chrono::NaiveDateTime::MAX);
+    let mut attempts = JoinSet::new();
+    for delivery in due {
+        // Another instance may have claimed it since it was read
+        let claimed = sqlx::query!(
+            "UPDATE webhook_deliveries SET locked_until = $1
+            WHERE id = $2 AND status = 'pending' AND (locked_until IS NULL OR locked_until <= $3)",
+            locked_until,
+            delivery.id,
+            now
+        )
+        .execute(pool)
+        .await?;
+        if claimed.rows_affected() == 0 {
+            continue;
+        }
+
+        let http = http.clone();
+        let allow_private_networks = config.allow_private_networks;
+        attempts.spawn(async move {
+            let result = attempt(&http, &delivery, allow_private_networks).await;
+            (delivery, result)
+        });
+    }
+
+    let mut attempted = 0;
+    while let Some(joined) = attempts.join_next().await {
+        let Ok((delivery, result)) = joined else {
+            continue;
+        };
+        record(pool, config, &delivery, result).await?;
+        attempted += 1;
+    }
+    Ok(attempted)
+}
+
+async fn record(
+    pool: &DbPool,
+    config: &WebhookConfig,
+    delivery: &Due,
+    (response_status, error): AttemptResult,
+) -> Result<(), sqlx::Error> {
+    let attempts = delivery.attempts + 1;
+    let now = Utc::now();
+    let (status, next_attempt_at) = match &error {
+        None => (DeliveryStatus::Succeeded, None),
+        Some(_) if attempts >= config.max_attempts => (DeliveryStatus::Failed, None),
+        Some(_) => {
+            let wait = chrono::Duration::from_std(config.backoff(attempts)).unwrap_or(chrono::Duration::MAX);
+            (DeliveryStatus::Pending, Some((now + wait).naive_utc()))
+        }
+    };
+
+    match &error {
+        None => tracing::info!(
+            event = "webhook_delivered",
+            delivery_id = delivery.id,
+            webhook_id = delivery.webhook_id,
+            attempts
+        ),
+        Some(e) => tracing::warn!(
+            event = "webhook_delivery_failed",
+            delivery_id = delivery.id,
+            webhook_id = delivery.webhook_id,
+            attempts,
+            retry = next_attempt_at.is_some(),
+            error = %e
+        ),
+    }
+
+    let last_attempt_at = now.naive_utc();
+    sqlx::query!(
+        "UPDATE webhook_deliveries SET status = $1, attempts = $2, next_attempt_at = $3, last_attempt_at = $4,
+        response_status = $5, last_error = $6, locked_until = NULL WHERE id = $7",
+        status as _,
+        attempts,
+        next_attempt_at,
+        last_attempt_at,
+        response_status,
+        error,
+        delivery.id
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Deletes finished deliveries older than `retention_days`
+pub async fn purge(pool: &DbPool, retention_days: i64) -> Result<u64, sqlx::Error> {
+    let cutoff = (Utc::now() - chrono::Duration::days(retention_days)).naive_utc();
+    let result = sqlx::query!(
+        "DELETE FROM webhook_deliveries WHERE status <> 'pending' AND created_at < $1",
+        cutoff
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+/// Queues and sends deliveries whenever an event is logged, and checks
+/// for due retries every `poll_secs`
+async fn run(pool: DbPool, log: Arc<EventLog>, http: reqwest::Client, config: WebhookConfig, mut shutdown: Shutdown) {
+    let mut events = log.subscribe();
+    let mut poll = tokio::time::interval(Duration::from_secs(config.poll_secs));
+    let mut cleanup = tokio::time::interval(Duration::from_secs(3600));
+
+    loop {
+        tokio::select! {
+            _ = &mut shutdown => break,
+            _ = cleanup.tick() => {
+                if let Err(e) = purge(&pool, config.retention_days).await {
+                    tracing::warn!(error = %e, "failed to purge old webhook deliveries");
+                }
+                continue;
+            }
+            _ = events.recv() => {
+                // One pass covers every event logged so far
+                while !matches!(events.try_recv(), Err(TryRecvError::Empty | TryRecvError::Closed)) {}
+            }
+            _ = poll.tick() => {}
+        }
+
+        if let Err(e) = enqueue(&pool, &log).await {
+            tracing::warn!(error = %e, "failed to queue webhook deliveries");
+        }
+        if let Err(e) = deliver_due(&pool, &http, &config).await {
+            tracing::warn!(error = %e, "failed to send webhook deliveries");
+        }
+    }
+}
+
+/// Reads `[webhooks]` and starts the delivery worker
+pub fn stage() -> AdHoc {
+    AdHoc::try_on_ignite("Webhooks", |rocket| async {
+        let config: WebhookConfig = match rocket.figment().extract_inner("webhooks") {
+            Ok(config) => config,
+            Err(e) if e.missing() => WebhookConfig::default(),
+            Err(e) => {
+                tracing::error!(event = "invalid_config", problem = %e);
+                return Err(rocket);
+            }
+        };
+        let problems = config.problems();
+        if !problems.is_empty() {
+            for problem in &problems {
+                tracing::error!(event = "invalid_config", problem = %problem);
+            }
+            return Err(rocket);
+        }
+
+        // A redirect would resend the signed body somewhere else, and a
+        // proxy would resolve the host where it cannot be checked
+        let mut http = reqwest::Client::builder()
+            .timeout(Duration::from_secs(config.timeout_secs))
+            .redirect(reqwest::redirect::Policy::none())
+            .no_proxy()
+            .user_agent(concat!("todo-api-webhooks/", env!("CARGO_PKG_VERSION")));
+        if !config.allow_private_networks {
+            http = http.dns_resolver(Arc::new(PublicResolver));
+        }
+        let http = match http.build() {
+            Ok(http) => http,
+            Err(e) => {
+                tracing::error!(error = %e, "failed to build the webhook HTTP client");
+                return Err(rocket);
+            }
+        };
+
+        Ok(rocket
+            .manage(config.clone())
+            .attach(AdHoc::on_liftoff("Webhook Delivery", move |rocket| {
+                Box::pin(async move {
+                    let (Some(db), Some(log)) = (Db::fetch(rocket), rocket.state::<Arc<EventLog>>().cloned()) else {
+                        return;
+                    };
+                    let job = tokio::spawn(run((**db).clone(), log, http, config, rocket.shutdown()));
+                    if let Some(health) = rocket.state::<Health>() {
+                        health.watch("webhook_delivery", job);
+                    }
+                })
+            })))
+    })
+}